@@ -1,6 +1,30 @@
 use anyhow::{Context, Result};
 use std::net::SocketAddr;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use tracing::warn;
+use uuid::Uuid;
+
+use crate::http::webui::UiTokenKey;
+use crate::query_log::QueryTextMode;
+use crate::search::encryption::EncryptionKey;
+use crate::search::models::{
+    BulkCommitMode, DuplicateContentMode, DuplicateIdMode, LimitOverflowMode, SearchBodyMode,
+};
+use crate::search::schema::{CreatedAtMode, CreatedAtPolicy, CreatedAtSource, EmptyTagsMode, TagDedupMode, SNIPPET_SOURCE_FIELDS};
+use crate::search::text::InvalidTextMode;
+
+/// Largest preview length a client may request
+pub const MAX_PREVIEW_CHARS: usize = 10_000;
+
+/// Most retries a bulk index may make; each can wait the full writer lock timeout
+pub const MAX_BULK_RETRY_ATTEMPTS: u32 = 5;
+
+/// Most commits `INDEX_HISTORY_MAX` may keep per user
+pub const MAX_INDEX_HISTORY: usize = 10_000;
+
+/// Smallest writer heap Tantivy accepts for a single indexing thread
+pub const MIN_WRITER_HEAP_BYTES: usize = 15_000_000;
 
 /// Application configuration loaded from environment variables
 #[derive(Debug, Clone)]
@@ -8,14 +32,356 @@ pub struct Config {
     /// Address to bind the HTTP server to
     pub bind_addr: SocketAddr,
 
+    /// Accept cleartext HTTP/2 (h2c with prior knowledge) next to HTTP/1.1
+    pub http2_enabled: bool,
+
+    /// Keep HTTP/1.1 connections open for further requests
+    pub http1_keep_alive: bool,
+
+    /// Seconds between HTTP/2 keep-alive pings on a connection (0 = no pings)
+    pub http2_keep_alive_interval_secs: u64,
+
+    /// Seconds to wait for a ping acknowledgement before closing the HTTP/2 connection
+    pub http2_keep_alive_timeout_secs: u64,
+
+    /// Requests one HTTP/2 connection may have in flight at once
+    pub http2_max_concurrent_streams: u32,
+
     /// Base directory for storing per-user indexes
     pub data_dir: PathBuf,
 
     /// Log level for tracing
     pub log_level: String,
 
+    /// Log a line when each enabled tracing span closes, with its fields and timings
+    pub log_span_close: bool,
+
     /// Enable web UI for testing (binds on localhost only)
     pub web_ui_enabled: bool,
+
+    /// Key verifying signed `/ui?token=` links (`None` disables them)
+    pub web_ui_token_key: Option<UiTokenKey>,
+
+    /// How long a signed web UI link stays valid after it is issued
+    pub web_ui_token_ttl_secs: u64,
+
+    /// Maximum time a write waits for a user's index writer before failing as busy
+    pub writer_lock_timeout_ms: u64,
+
+    /// How titles/bodies containing invalid characters are handled
+    pub invalid_text_mode: InvalidTextMode,
+
+    /// Maximum number of IDs accepted by a single bulk delete request
+    pub bulk_delete_max_ids: usize,
+
+    /// Seconds a bulk delete remembers the IDs it deleted, so a retry reports them deleted again (0 disables)
+    pub delete_retry_window_secs: u64,
+
+    /// Maximum number of documents accepted by a single bulk index request
+    pub bulk_index_max_docs: usize,
+
+    /// How repeated document IDs within a bulk index request are handled
+    pub bulk_duplicate_ids: DuplicateIdMode,
+
+    /// Whether a bulk index larger than the writer heap is committed once or in chunks
+    pub bulk_commit_mode: BulkCommitMode,
+
+    /// Extra attempts a bulk index makes when the writer is busy (0 disables retries)
+    pub bulk_retry_attempts: u32,
+
+    /// Wait before the first bulk retry; doubled for each further retry
+    pub bulk_retry_backoff_ms: u64,
+
+    /// Documents a user may index per minute, counted per document in bulk requests (0 = unlimited)
+    pub max_index_rate_per_minute: u32,
+
+    /// Commits kept per user for `/v1/index/history` (0 disables the history)
+    pub index_history_max: usize,
+
+    /// Open `/v1/changes` streams allowed across all users (0 disables the endpoint)
+    pub changes_max_connections: usize,
+
+    /// Open `/v1/changes` streams allowed per user
+    pub changes_max_connections_per_user: usize,
+
+    /// Segment count above which `/v1/index/health` reports that an index needs optimizing
+    pub optimize_max_segments: usize,
+
+    /// Share of deleted documents above which `/v1/index/health` reports that an index needs optimizing
+    pub optimize_max_deleted_ratio: f64,
+
+    /// Whether duplicate tags within a document are compared case-insensitively
+    pub tag_dedup: TagDedupMode,
+
+    /// Whether empty or whitespace-only tags are dropped or fail the document
+    pub empty_tags: EmptyTagsMode,
+
+    /// How a search `limit` above the cap of 100 is handled
+    pub search_limit_overflow: LimitOverflowMode,
+
+    /// Shared secret required by `/v1/admin/*` endpoints; admin API is disabled when unset
+    pub admin_token: Option<String>,
+
+    /// Most users one `POST /v1/admin/stats` request may list
+    pub admin_stats_max_users: usize,
+
+    /// Shared secret required by `/health`; health checks are open when unset
+    pub health_auth_token: Option<String>,
+
+    /// User assumed by API requests without an `X-User-Id` header (`None` requires the header)
+    pub single_tenant_user_id: Option<Uuid>,
+
+    /// Indexing memory of each user's writer, in bytes
+    pub writer_heap_bytes: usize,
+
+    /// Minimum number of segments in a layer before they are merged
+    pub merge_min_segments: usize,
+
+    /// Segments with more documents than this are never merged
+    pub merge_max_docs: usize,
+
+    /// Segments smaller than this many documents are grouped into the lowest merge layer
+    pub merge_min_layer_size: u32,
+
+    /// Upper bound applied to the per-request `X-Timeout-Ms` search budget
+    pub search_timeout_max_ms: u64,
+
+    /// Run the index/search self-test before binding the listener
+    pub selftest_on_start: bool,
+
+    /// Master key for encrypting indexes at rest (`None` stores plaintext)
+    pub index_encryption_key: Option<EncryptionKey>,
+
+    /// Fields tried in order for a search result snippet; the first with a highlight wins
+    pub snippet_fields: Vec<String>,
+
+    /// Largest `offset` accepted by search and browse requests
+    pub max_result_offset: usize,
+
+    /// Most document IDs one search may list in `exclude_ids`
+    pub search_exclude_ids_max: usize,
+
+    /// Budget for the serialized results of one search or browse response
+    pub max_response_bytes: usize,
+
+    /// Largest `limit` accepted by `/v1/terms/top`
+    pub top_terms_max_limit: usize,
+
+    /// Body characters returned by the preview endpoint when `chars` is omitted, and by previewed search results
+    pub preview_default_chars: usize,
+
+    /// How much of each body search results return unless the request says otherwise
+    pub search_body_mode: SearchBodyMode,
+
+    /// Handling of client-supplied `created_at` values outside the accepted window
+    pub created_at_mode: CreatedAtMode,
+
+    /// Whether `created_at` is the client's value or always the server's ingestion time
+    pub created_at_source: CreatedAtSource,
+
+    /// Source given to documents indexed without one; a user's config can override it
+    pub default_source: Option<String>,
+
+    /// How a write whose content another document already has is handled; a user's config can override it
+    pub duplicate_content: DuplicateContentMode,
+
+    /// How far into the future `created_at` may be, in seconds
+    pub created_at_max_future_secs: i64,
+
+    /// How far into the past `created_at` may be, in days
+    pub created_at_max_past_days: i64,
+
+    /// How long a failed index open is remembered before retrying (0 disables)
+    pub index_open_retry_ms: u64,
+
+    /// Add a redacted summary of the request to error responses
+    pub echo_request_on_error: bool,
+
+    /// Echo full request body values instead of lengths (requires `echo_request_on_error`)
+    pub echo_request_bodies: bool,
+
+    /// Hot-path log lines emitted per event kind per second (0 disables sampling)
+    pub log_sample_per_sec: u32,
+
+    /// How often suppressed log lines are reported in a summary line
+    pub log_summary_interval_secs: u64,
+
+    /// Maximum number of unpinned indexes kept open; the least recently used is closed first (0 = unbounded)
+    pub index_cache_capacity: usize,
+
+    /// Users whose indexes are never evicted from the cache
+    pub pinned_users: Vec<Uuid>,
+
+    /// Accept title-only documents whose body is empty or whitespace
+    pub allow_empty_body: bool,
+
+    /// Tenants that may hold their index writer at once; further writes queue (0 = unlimited)
+    pub max_concurrent_writers: usize,
+
+    /// Order search hits with equal scores by document ID so pagination is stable
+    pub tie_break_by_id: bool,
+
+    /// Accept queries that scan every document or much of the term dictionary:
+    /// the `*` wildcard, open-ended ranges and short prefixes
+    pub allow_expensive_queries: bool,
+
+    /// Run the `warmup_queries` of a user's config when their index is opened
+    pub warmup_queries_enabled: bool,
+
+    /// HTTP status for searches and browses with no results: 200 (empty list) or 404
+    pub empty_result_status: u16,
+
+    /// Record searches in the query log (off by default for privacy)
+    pub query_log_enabled: bool,
+
+    /// File the query log is appended to
+    pub query_log_path: PathBuf,
+
+    /// Size at which the query log rolls over to `<path>.1`
+    pub query_log_max_bytes: u64,
+
+    /// How query text is written to the query log
+    pub query_log_query_mode: QueryTextMode,
+
+    /// Salt mixed into query log hashes
+    pub query_log_salt: Option<String>,
+
+    /// What startup does when `data_dir` is on a network filesystem
+    pub network_fs_check: NetworkFsCheck,
+
+    /// Whether unknown top-level fields in JSON request bodies are ignored or rejected
+    pub unknown_fields: UnknownFieldsMode,
+}
+
+/// Startup behavior when the data directory is on a network filesystem
+///
+/// Every write commits and fsyncs, which on NFS and similar can take hundreds
+/// of milliseconds per request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NetworkFsCheck {
+    /// Log a warning and start anyway (default)
+    #[default]
+    Warn,
+    /// Refuse to start
+    Fail,
+    /// Skip detection
+    Off,
+}
+
+impl FromStr for NetworkFsCheck {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "warn" => Ok(NetworkFsCheck::Warn),
+            "fail" => Ok(NetworkFsCheck::Fail),
+            "off" => Ok(NetworkFsCheck::Off),
+            other => Err(format!("expected \"warn\", \"fail\" or \"off\", got \"{}\"", other)),
+        }
+    }
+}
+
+/// Handling of unknown top-level fields in JSON request bodies
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum UnknownFieldsMode {
+    /// Skip them, so older servers accept requests from newer clients (default)
+    #[default]
+    Ignore,
+    /// Fail the request with a 422 naming the field, to catch typos such as `lmit`
+    Reject,
+}
+
+impl FromStr for UnknownFieldsMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "ignore" => Ok(UnknownFieldsMode::Ignore),
+            "reject" => Ok(UnknownFieldsMode::Reject),
+            other => Err(format!("expected \"ignore\" or \"reject\", got \"{}\"", other)),
+        }
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            bind_addr: "127.0.0.1:8080".parse().expect("default bind address is valid"),
+            http2_enabled: false,
+            http1_keep_alive: true,
+            http2_keep_alive_interval_secs: 0,
+            http2_keep_alive_timeout_secs: 20,
+            http2_max_concurrent_streams: 200,
+            data_dir: PathBuf::from("./data"),
+            log_level: "info".to_string(),
+            log_span_close: false,
+            web_ui_enabled: false,
+            web_ui_token_key: None,
+            web_ui_token_ttl_secs: 900,
+            writer_lock_timeout_ms: 5_000,
+            invalid_text_mode: InvalidTextMode::Reject,
+            bulk_delete_max_ids: 1_000,
+            delete_retry_window_secs: 60,
+            bulk_index_max_docs: 1_000,
+            bulk_duplicate_ids: DuplicateIdMode::Reject,
+            bulk_commit_mode: BulkCommitMode::Atomic,
+            bulk_retry_attempts: 0,
+            bulk_retry_backoff_ms: 200,
+            max_index_rate_per_minute: 0,
+            index_history_max: 100,
+            changes_max_connections: 100,
+            changes_max_connections_per_user: 5,
+            optimize_max_segments: 20,
+            optimize_max_deleted_ratio: 0.2,
+            tag_dedup: TagDedupMode::CaseInsensitive,
+            empty_tags: EmptyTagsMode::Drop,
+            search_limit_overflow: LimitOverflowMode::Reject,
+            admin_token: None,
+            admin_stats_max_users: 100,
+            health_auth_token: None,
+            single_tenant_user_id: None,
+            writer_heap_bytes: 50_000_000,
+            merge_min_segments: 8,
+            merge_max_docs: 10_000_000,
+            merge_min_layer_size: 10_000,
+            search_timeout_max_ms: 10_000,
+            selftest_on_start: false,
+            index_encryption_key: None,
+            snippet_fields: vec!["body".to_string(), "title".to_string()],
+            max_result_offset: 10_000,
+            search_exclude_ids_max: 1_000,
+            max_response_bytes: 10_000_000,
+            top_terms_max_limit: 1_000,
+            preview_default_chars: 200,
+            search_body_mode: SearchBodyMode::Full,
+            created_at_mode: CreatedAtMode::Clamp,
+            created_at_source: CreatedAtSource::Client,
+            default_source: None,
+            duplicate_content: DuplicateContentMode::Allow,
+            created_at_max_future_secs: 86_400,
+            created_at_max_past_days: 18_250,
+            index_open_retry_ms: 30_000,
+            echo_request_on_error: false,
+            echo_request_bodies: false,
+            log_sample_per_sec: 100,
+            log_summary_interval_secs: 10,
+            index_cache_capacity: 0,
+            pinned_users: Vec::new(),
+            allow_empty_body: false,
+            max_concurrent_writers: 0,
+            tie_break_by_id: true,
+            allow_expensive_queries: false,
+            warmup_queries_enabled: true,
+            empty_result_status: 200,
+            query_log_enabled: false,
+            query_log_path: PathBuf::from("./logs/queries.jsonl"),
+            query_log_max_bytes: 100 * 1024 * 1024,
+            query_log_query_mode: QueryTextMode::Hashed,
+            query_log_salt: None,
+            network_fs_check: NetworkFsCheck::Warn,
+            unknown_fields: UnknownFieldsMode::Ignore,
+        }
+    }
 }
 
 impl Config {
@@ -23,9 +389,79 @@ impl Config {
     ///
     /// Expected environment variables:
     /// - `BIND_ADDR`: Socket address (default: "127.0.0.1:8080")
+    /// - `HTTP2_ENABLED`: Accept cleartext HTTP/2 next to HTTP/1.1 (default: "false")
+    /// - `HTTP1_KEEP_ALIVE`: Keep HTTP/1.1 connections open between requests (default: "true")
+    /// - `HTTP2_KEEP_ALIVE_INTERVAL_SECS`: Seconds between HTTP/2 keep-alive pings (default: 0, no pings)
+    /// - `HTTP2_KEEP_ALIVE_TIMEOUT_SECS`: Wait for a ping acknowledgement before closing (default: 20)
+    /// - `HTTP2_MAX_CONCURRENT_STREAMS`: Requests in flight per HTTP/2 connection (default: 200)
     /// - `DATA_DIR`: Base directory for indexes (required)
     /// - `LOG_LEVEL`: Logging level (default: "info")
+    /// - `LOG_SPAN_CLOSE`: Log span durations when spans close (default: "false")
     /// - `WEB_UI_ENABLED`: Enable web UI (default: "false")
+    /// - `WEB_UI_TOKEN_KEY`: Secret of at least 32 bytes for signed web UI links (default: unset, disabled)
+    /// - `WEB_UI_TOKEN_TTL_SECS`: Lifetime of a signed web UI link (default: 900)
+    /// - `WRITER_LOCK_TIMEOUT_MS`: Writer lock wait before returning 503 (default: 5000)
+    /// - `INVALID_TEXT_MODE`: "reject" or "replace" invalid characters in document text (default: "reject")
+    /// - `BULK_DELETE_MAX_IDS`: Maximum IDs per bulk delete request (default: 1000)
+    /// - `DELETE_RETRY_WINDOW_SECS`: Seconds a retried bulk delete still reports deleted IDs as deleted (default: 60, 0 disables)
+    /// - `BULK_INDEX_MAX_DOCS`: Maximum documents per bulk index request (default: 1000)
+    /// - `BULK_DUPLICATE_IDS`: "reject" or "last_wins" for repeated IDs in a bulk index (default: "reject")
+    /// - `BULK_COMMIT_MODE`: "atomic" or "chunked" commits for bulk indexes larger than the writer heap (default: "atomic")
+    /// - `BULK_RETRY_ATTEMPTS`: Retries of a bulk index while the writer is busy (default: 0, at most 5)
+    /// - `BULK_RETRY_BACKOFF_MS`: Wait before the first bulk retry, doubled per retry (default: 200)
+    /// - `MAX_INDEX_RATE_PER_MINUTE`: Documents a user may index per minute (default: 0, unlimited)
+    /// - `INDEX_HISTORY_MAX`: Commits kept per user for /v1/index/history (default: 100, 0 disables)
+    /// - `CHANGES_MAX_CONNECTIONS`: Open /v1/changes streams across all users (default: 100, 0 disables)
+    /// - `CHANGES_MAX_CONNECTIONS_PER_USER`: Open /v1/changes streams per user (default: 5)
+    /// - `OPTIMIZE_MAX_SEGMENTS`: Segments above which an index needs optimizing (default: 20)
+    /// - `OPTIMIZE_MAX_DELETED_RATIO`: Deleted-document share above which an index needs optimizing (default: 0.2)
+    /// - `TAG_DEDUP`: "case_insensitive" or "case_sensitive" tag deduplication (default: "case_insensitive")
+    /// - `EMPTY_TAGS`: "drop" or "reject" empty and whitespace-only tags (default: "drop")
+    /// - `SEARCH_LIMIT_OVERFLOW`: "reject" or "clamp" search limits above 100 (default: "reject")
+    /// - `ADMIN_TOKEN`: Bearer token for admin endpoints (default: unset, admin API disabled)
+    /// - `ADMIN_STATS_MAX_USERS`: Maximum users per /v1/admin/stats request (default: 100)
+    /// - `HEALTH_AUTH_TOKEN`: Token required by `/health` (default: unset, open health checks)
+    /// - `SINGLE_TENANT_USER_ID`: UUID used when `X-User-Id` is absent (default: unset, header required)
+    /// - `WRITER_HEAP_BYTES`: Indexing memory per user writer (default: 50000000, minimum 15000000)
+    /// - `MERGE_MIN_SEGMENTS`: Segments per layer before merging (default: 8)
+    /// - `MERGE_MAX_DOCS`: Largest segment eligible for merging (default: 10000000)
+    /// - `MERGE_MIN_LAYER_SIZE`: Document count of the smallest merge layer (default: 10000)
+    /// - `SEARCH_TIMEOUT_MAX_MS`: Maximum honored `X-Timeout-Ms` value (default: 10000)
+    /// - `SELFTEST_ON_START`: Run the index/search self-test before serving (default: "false")
+    /// - `INDEX_ENCRYPTION_KEY`: 64 hex character master key for encryption at rest (default: unset, plaintext)
+    /// - `SNIPPET_FIELDS`: Comma-separated snippet source priority (default: "body,title")
+    /// - `MAX_RESULT_OFFSET`: Maximum search/browse offset (default: 10000)
+    /// - `SEARCH_EXCLUDE_IDS_MAX`: Maximum IDs in a search's `exclude_ids` (default: 1000)
+    /// - `MAX_RESPONSE_BYTES`: Byte budget for the results of a search/browse response (default: 10000000)
+    /// - `TOP_TERMS_MAX_LIMIT`: Maximum number of terms from /v1/terms/top (default: 1000)
+    /// - `PREVIEW_DEFAULT_CHARS`: Default preview length in characters (default: 200)
+    /// - `SEARCH_BODY_MODE`: "full", "preview" or "none" result bodies by default (default: "full")
+    /// - `CREATED_AT_SOURCE`: "client" or "server" (always the ingestion time) `created_at` (default: "client")
+    /// - `DEFAULT_SOURCE`: Source of documents indexed without one (default: unset)
+    /// - `DUPLICATE_CONTENT`: "allow", "reject" or "alias" writes duplicating another document's content (default: "allow")
+    /// - `CREATED_AT_MODE`: "clamp", "reject" or "allow" out-of-window `created_at` values (default: "clamp")
+    /// - `CREATED_AT_MAX_FUTURE_SECS`: Allowed `created_at` lead over now (default: 86400)
+    /// - `CREATED_AT_MAX_PAST_DAYS`: Allowed `created_at` age (default: 18250)
+    /// - `INDEX_OPEN_RETRY_MS`: Cooldown after an index fails to open (default: 30000, 0 disables)
+    /// - `ECHO_REQUEST_ON_ERROR`: Include a redacted request summary in error details (default: "false")
+    /// - `ECHO_REQUEST_BODIES`: Echo full body values in that summary (default: "false")
+    /// - `LOG_SAMPLE_PER_SEC`: Hot-path log lines per kind per second (default: 100, 0 disables sampling)
+    /// - `LOG_SUMMARY_INTERVAL_SECS`: Interval between log sampling summaries (default: 10)
+    /// - `INDEX_CACHE_CAPACITY`: Unpinned indexes kept open before LRU eviction (default: 0, unbounded)
+    /// - `PINNED_USERS`: Comma-separated user UUIDs whose indexes are never evicted (default: none)
+    /// - `ALLOW_EMPTY_BODY`: Accept title-only documents (default: "false")
+    /// - `MAX_CONCURRENT_WRITERS`: Tenants writing at once across the service (default: 0, unlimited)
+    /// - `TIE_BREAK_BY_ID`: Order equal-score search hits by document ID (default: "true")
+    /// - `ALLOW_EXPENSIVE_QUERIES`: Accept `*`, open-ended ranges and short prefixes in queries (default: "false")
+    /// - `WARMUP_QUERIES_ENABLED`: Run per-user warmup queries when an index is opened (default: "true")
+    /// - `EMPTY_RESULT_STATUS`: Status for searches and browses with no results, 200 or 404 (default: 200)
+    /// - `QUERY_LOG_ENABLED`: Record searches in the query log (default: "false")
+    /// - `QUERY_LOG_PATH`: Query log file (default: ./logs/queries.jsonl)
+    /// - `QUERY_LOG_MAX_BYTES`: Size at which the query log rolls over (default: 104857600)
+    /// - `QUERY_LOG_QUERY_MODE`: "hashed", "redacted", "plain" or "omit" (default: "hashed")
+    /// - `QUERY_LOG_SALT`: Salt for query log hashes (default: unset)
+    /// - `NETWORK_FS_CHECK`: "warn", "fail" or "off" when DATA_DIR is on a network filesystem (default: "warn")
+    /// - `UNKNOWN_FIELDS`: "ignore" or "reject" unknown top-level fields in JSON request bodies (default: "ignore")
     pub fn from_env() -> Result<Self> {
         // Load .env file if it exists (development only)
         let _ = dotenvy::dotenv();
@@ -42,19 +478,256 @@ impl Config {
         let log_level = std::env::var("LOG_LEVEL")
             .unwrap_or_else(|_| "info".to_string());
 
+        let log_span_close = std::env::var("LOG_SPAN_CLOSE")
+            .unwrap_or_else(|_| "false".to_string())
+            .to_lowercase()
+            == "true";
+
         let web_ui_enabled = std::env::var("WEB_UI_ENABLED")
             .unwrap_or_else(|_| "false".to_string())
             .to_lowercase()
             == "true";
 
+        let defaults = Config::default();
+
+        let web_ui_token_key = std::env::var("WEB_UI_TOKEN_KEY")
+            .ok()
+            .filter(|key| !key.is_empty())
+            .map(|key| key.parse())
+            .transpose()
+            .map_err(|e| anyhow::anyhow!("Failed to parse WEB_UI_TOKEN_KEY: {}", e))?;
+        let web_ui_token_ttl_secs = env_parse("WEB_UI_TOKEN_TTL_SECS", defaults.web_ui_token_ttl_secs)?;
+        let http2_enabled = std::env::var("HTTP2_ENABLED")
+            .unwrap_or_else(|_| "false".to_string())
+            .to_lowercase()
+            == "true";
+        let http1_keep_alive = std::env::var("HTTP1_KEEP_ALIVE")
+            .unwrap_or_else(|_| "true".to_string())
+            .to_lowercase()
+            == "true";
+        let http2_keep_alive_interval_secs =
+            env_parse("HTTP2_KEEP_ALIVE_INTERVAL_SECS", defaults.http2_keep_alive_interval_secs)?;
+        let http2_keep_alive_timeout_secs =
+            env_parse("HTTP2_KEEP_ALIVE_TIMEOUT_SECS", defaults.http2_keep_alive_timeout_secs)?;
+        let http2_max_concurrent_streams =
+            env_parse("HTTP2_MAX_CONCURRENT_STREAMS", defaults.http2_max_concurrent_streams)?;
+        let writer_lock_timeout_ms = env_parse("WRITER_LOCK_TIMEOUT_MS", defaults.writer_lock_timeout_ms)?;
+        let invalid_text_mode = env_parse("INVALID_TEXT_MODE", defaults.invalid_text_mode)?;
+        let bulk_delete_max_ids = env_parse("BULK_DELETE_MAX_IDS", defaults.bulk_delete_max_ids)?;
+        let delete_retry_window_secs = env_parse("DELETE_RETRY_WINDOW_SECS", defaults.delete_retry_window_secs)?;
+        let bulk_index_max_docs = env_parse("BULK_INDEX_MAX_DOCS", defaults.bulk_index_max_docs)?;
+        let bulk_duplicate_ids = env_parse("BULK_DUPLICATE_IDS", defaults.bulk_duplicate_ids)?;
+        let bulk_commit_mode = env_parse("BULK_COMMIT_MODE", defaults.bulk_commit_mode)?;
+        let bulk_retry_attempts = env_parse("BULK_RETRY_ATTEMPTS", defaults.bulk_retry_attempts)?;
+        let bulk_retry_backoff_ms = env_parse("BULK_RETRY_BACKOFF_MS", defaults.bulk_retry_backoff_ms)?;
+        let max_index_rate_per_minute = env_parse("MAX_INDEX_RATE_PER_MINUTE", defaults.max_index_rate_per_minute)?;
+        let index_history_max = env_parse("INDEX_HISTORY_MAX", defaults.index_history_max)?;
+        let changes_max_connections = env_parse("CHANGES_MAX_CONNECTIONS", defaults.changes_max_connections)?;
+        let changes_max_connections_per_user =
+            env_parse("CHANGES_MAX_CONNECTIONS_PER_USER", defaults.changes_max_connections_per_user)?;
+        let optimize_max_segments = env_parse("OPTIMIZE_MAX_SEGMENTS", defaults.optimize_max_segments)?;
+        let optimize_max_deleted_ratio = env_parse("OPTIMIZE_MAX_DELETED_RATIO", defaults.optimize_max_deleted_ratio)?;
+        let tag_dedup = env_parse("TAG_DEDUP", defaults.tag_dedup)?;
+        let empty_tags = env_parse("EMPTY_TAGS", defaults.empty_tags)?;
+        let search_limit_overflow = env_parse("SEARCH_LIMIT_OVERFLOW", defaults.search_limit_overflow)?;
+        let admin_token = std::env::var("ADMIN_TOKEN")
+            .ok()
+            .filter(|token| !token.trim().is_empty());
+        let admin_stats_max_users = env_parse("ADMIN_STATS_MAX_USERS", defaults.admin_stats_max_users)?;
+        let health_auth_token = std::env::var("HEALTH_AUTH_TOKEN")
+            .ok()
+            .filter(|token| !token.trim().is_empty());
+        let single_tenant_user_id = std::env::var("SINGLE_TENANT_USER_ID")
+            .ok()
+            .filter(|id| !id.trim().is_empty())
+            .map(|id| {
+                id.trim()
+                    .parse()
+                    .with_context(|| format!("Failed to parse SINGLE_TENANT_USER_ID '{}' as a UUID", id))
+            })
+            .transpose()?;
+        let writer_heap_bytes = env_parse("WRITER_HEAP_BYTES", defaults.writer_heap_bytes)?;
+        let merge_min_segments = env_parse("MERGE_MIN_SEGMENTS", defaults.merge_min_segments)?;
+        let merge_max_docs = env_parse("MERGE_MAX_DOCS", defaults.merge_max_docs)?;
+        let merge_min_layer_size = env_parse("MERGE_MIN_LAYER_SIZE", defaults.merge_min_layer_size)?;
+        let search_timeout_max_ms = env_parse("SEARCH_TIMEOUT_MAX_MS", defaults.search_timeout_max_ms)?;
+        let selftest_on_start = std::env::var("SELFTEST_ON_START")
+            .unwrap_or_else(|_| "false".to_string())
+            .to_lowercase()
+            == "true";
+        let index_encryption_key = std::env::var("INDEX_ENCRYPTION_KEY")
+            .ok()
+            .filter(|key| !key.trim().is_empty())
+            .map(|key| key.parse())
+            .transpose()
+            .map_err(|e| anyhow::anyhow!("Failed to parse INDEX_ENCRYPTION_KEY: {}", e))?;
+
+        let snippet_fields = match std::env::var("SNIPPET_FIELDS") {
+            Ok(value) => value
+                .split(',')
+                .map(|field| field.trim().to_lowercase())
+                .filter(|field| !field.is_empty())
+                .collect(),
+            Err(_) => defaults.snippet_fields,
+        };
+        let max_result_offset = env_parse("MAX_RESULT_OFFSET", defaults.max_result_offset)?;
+        let search_exclude_ids_max = env_parse("SEARCH_EXCLUDE_IDS_MAX", defaults.search_exclude_ids_max)?;
+        let max_response_bytes = env_parse("MAX_RESPONSE_BYTES", defaults.max_response_bytes)?;
+        let top_terms_max_limit = env_parse("TOP_TERMS_MAX_LIMIT", defaults.top_terms_max_limit)?;
+        let preview_default_chars = env_parse("PREVIEW_DEFAULT_CHARS", defaults.preview_default_chars)?;
+        let search_body_mode = env_parse("SEARCH_BODY_MODE", defaults.search_body_mode)?;
+        let created_at_mode = env_parse("CREATED_AT_MODE", defaults.created_at_mode)?;
+        let created_at_source = env_parse("CREATED_AT_SOURCE", defaults.created_at_source)?;
+        let default_source = std::env::var("DEFAULT_SOURCE")
+            .ok()
+            .filter(|source| !source.trim().is_empty());
+        let duplicate_content = env_parse("DUPLICATE_CONTENT", defaults.duplicate_content)?;
+        let created_at_max_future_secs = env_parse("CREATED_AT_MAX_FUTURE_SECS", defaults.created_at_max_future_secs)?;
+        let created_at_max_past_days = env_parse("CREATED_AT_MAX_PAST_DAYS", defaults.created_at_max_past_days)?;
+        let index_open_retry_ms = env_parse("INDEX_OPEN_RETRY_MS", defaults.index_open_retry_ms)?;
+        let echo_request_on_error = std::env::var("ECHO_REQUEST_ON_ERROR")
+            .unwrap_or_else(|_| "false".to_string())
+            .to_lowercase()
+            == "true";
+        let echo_request_bodies = std::env::var("ECHO_REQUEST_BODIES")
+            .unwrap_or_else(|_| "false".to_string())
+            .to_lowercase()
+            == "true";
+        let log_sample_per_sec = env_parse("LOG_SAMPLE_PER_SEC", defaults.log_sample_per_sec)?;
+        let log_summary_interval_secs = env_parse("LOG_SUMMARY_INTERVAL_SECS", defaults.log_summary_interval_secs)?;
+        let index_cache_capacity = env_parse("INDEX_CACHE_CAPACITY", defaults.index_cache_capacity)?;
+        let pinned_users = match std::env::var("PINNED_USERS") {
+            Ok(value) => value
+                .split(',')
+                .map(str::trim)
+                .filter(|id| !id.is_empty())
+                .map(|id| {
+                    id.parse()
+                        .with_context(|| format!("Failed to parse PINNED_USERS entry '{}' as a UUID", id))
+                })
+                .collect::<Result<_>>()?,
+            Err(_) => defaults.pinned_users,
+        };
+        let allow_empty_body = std::env::var("ALLOW_EMPTY_BODY")
+            .unwrap_or_else(|_| "false".to_string())
+            .to_lowercase()
+            == "true";
+        let max_concurrent_writers = env_parse("MAX_CONCURRENT_WRITERS", defaults.max_concurrent_writers)?;
+        let tie_break_by_id = std::env::var("TIE_BREAK_BY_ID")
+            .unwrap_or_else(|_| "true".to_string())
+            .to_lowercase()
+            == "true";
+        let allow_expensive_queries = std::env::var("ALLOW_EXPENSIVE_QUERIES")
+            .unwrap_or_else(|_| "false".to_string())
+            .to_lowercase()
+            == "true";
+        let warmup_queries_enabled = std::env::var("WARMUP_QUERIES_ENABLED")
+            .unwrap_or_else(|_| "true".to_string())
+            .to_lowercase()
+            == "true";
+        let empty_result_status = env_parse("EMPTY_RESULT_STATUS", defaults.empty_result_status)?;
+        let query_log_enabled = std::env::var("QUERY_LOG_ENABLED")
+            .unwrap_or_else(|_| "false".to_string())
+            .to_lowercase()
+            == "true";
+        let query_log_path = std::env::var("QUERY_LOG_PATH")
+            .map(PathBuf::from)
+            .unwrap_or(defaults.query_log_path);
+        let query_log_max_bytes = env_parse("QUERY_LOG_MAX_BYTES", defaults.query_log_max_bytes)?;
+        let query_log_query_mode = env_parse("QUERY_LOG_QUERY_MODE", defaults.query_log_query_mode)?;
+        let query_log_salt = std::env::var("QUERY_LOG_SALT")
+            .ok()
+            .filter(|salt| !salt.is_empty());
+        let network_fs_check = env_parse("NETWORK_FS_CHECK", defaults.network_fs_check)?;
+        let unknown_fields = env_parse("UNKNOWN_FIELDS", defaults.unknown_fields)?;
+
         Ok(Config {
             bind_addr,
+            http2_enabled,
+            http1_keep_alive,
+            http2_keep_alive_interval_secs,
+            http2_keep_alive_timeout_secs,
+            http2_max_concurrent_streams,
             data_dir,
             log_level,
+            log_span_close,
             web_ui_enabled,
+            web_ui_token_key,
+            web_ui_token_ttl_secs,
+            writer_lock_timeout_ms,
+            invalid_text_mode,
+            bulk_delete_max_ids,
+            delete_retry_window_secs,
+            bulk_index_max_docs,
+            bulk_duplicate_ids,
+            bulk_commit_mode,
+            bulk_retry_attempts,
+            bulk_retry_backoff_ms,
+            max_index_rate_per_minute,
+            index_history_max,
+            changes_max_connections,
+            changes_max_connections_per_user,
+            optimize_max_segments,
+            optimize_max_deleted_ratio,
+            tag_dedup,
+            empty_tags,
+            search_limit_overflow,
+            admin_token,
+            admin_stats_max_users,
+            health_auth_token,
+            single_tenant_user_id,
+            writer_heap_bytes,
+            merge_min_segments,
+            merge_max_docs,
+            merge_min_layer_size,
+            search_timeout_max_ms,
+            selftest_on_start,
+            index_encryption_key,
+            snippet_fields,
+            max_result_offset,
+            search_exclude_ids_max,
+            max_response_bytes,
+            top_terms_max_limit,
+            preview_default_chars,
+            search_body_mode,
+            created_at_mode,
+            created_at_source,
+            default_source,
+            duplicate_content,
+            created_at_max_future_secs,
+            created_at_max_past_days,
+            index_open_retry_ms,
+            echo_request_on_error,
+            echo_request_bodies,
+            log_sample_per_sec,
+            log_summary_interval_secs,
+            index_cache_capacity,
+            pinned_users,
+            allow_empty_body,
+            max_concurrent_writers,
+            tie_break_by_id,
+            allow_expensive_queries,
+            warmup_queries_enabled,
+            empty_result_status,
+            query_log_enabled,
+            query_log_path,
+            query_log_max_bytes,
+            query_log_query_mode,
+            query_log_salt,
+            network_fs_check,
+            unknown_fields,
         })
     }
 
+    /// Accepted window for client-supplied `created_at` values
+    pub fn created_at_policy(&self) -> CreatedAtPolicy {
+        CreatedAtPolicy {
+            mode: self.created_at_mode,
+            max_future: chrono::Duration::seconds(self.created_at_max_future_secs),
+            max_past: chrono::Duration::days(self.created_at_max_past_days),
+        }
+    }
+
     /// Validate configuration and create necessary directories
     pub fn validate(&self) -> Result<()> {
         // Create data directory if it doesn't exist
@@ -68,10 +741,173 @@ impl Config {
         std::fs::remove_file(&test_file)
             .context("Failed to clean up write test file")?;
 
+        if self.network_fs_check != NetworkFsCheck::Off {
+            if let Some(fs_type) = network_filesystem(&self.data_dir) {
+                if self.network_fs_check == NetworkFsCheck::Fail {
+                    anyhow::bail!(
+                        "DATA_DIR {:?} is on a network filesystem ({}); set NETWORK_FS_CHECK=warn to start anyway",
+                        self.data_dir,
+                        fs_type
+                    );
+                }
+                warn!(
+                    data_dir = ?self.data_dir,
+                    fs_type = fs_type,
+                    "DATA_DIR is on a network filesystem. Every write fsyncs on commit, which can take \
+                     hundreds of milliseconds here; prefer local disk, or batch writes through \
+                     POST /v1/documents/bulk so many documents share one commit"
+                );
+            }
+        }
+
+        if self.writer_heap_bytes < MIN_WRITER_HEAP_BYTES {
+            anyhow::bail!("WRITER_HEAP_BYTES must be at least {}", MIN_WRITER_HEAP_BYTES);
+        }
+
+        if self.merge_min_segments < 2 {
+            anyhow::bail!("MERGE_MIN_SEGMENTS must be at least 2");
+        }
+
+        if self.max_response_bytes == 0 {
+            anyhow::bail!("MAX_RESPONSE_BYTES must be greater than 0");
+        }
+
+        if self.web_ui_token_ttl_secs == 0 {
+            anyhow::bail!("WEB_UI_TOKEN_TTL_SECS must be greater than 0");
+        }
+
+        if self.bulk_retry_attempts > MAX_BULK_RETRY_ATTEMPTS {
+            anyhow::bail!("BULK_RETRY_ATTEMPTS cannot exceed {}", MAX_BULK_RETRY_ATTEMPTS);
+        }
+
+        if self.index_history_max > MAX_INDEX_HISTORY {
+            anyhow::bail!("INDEX_HISTORY_MAX cannot exceed {}", MAX_INDEX_HISTORY);
+        }
+
+        if self.http2_keep_alive_interval_secs > 0 && self.http2_keep_alive_timeout_secs == 0 {
+            anyhow::bail!("HTTP2_KEEP_ALIVE_TIMEOUT_SECS must be at least 1 when keep-alive pings are enabled");
+        }
+
+        if self.http2_max_concurrent_streams == 0 {
+            anyhow::bail!("HTTP2_MAX_CONCURRENT_STREAMS must be at least 1");
+        }
+
+        if self.changes_max_connections_per_user == 0 {
+            anyhow::bail!("CHANGES_MAX_CONNECTIONS_PER_USER must be at least 1");
+        }
+
+        if self.optimize_max_segments == 0 {
+            anyhow::bail!("OPTIMIZE_MAX_SEGMENTS must be at least 1");
+        }
+
+        if !(0.0..=1.0).contains(&self.optimize_max_deleted_ratio) {
+            anyhow::bail!("OPTIMIZE_MAX_DELETED_RATIO must be between 0 and 1");
+        }
+
+        if self.admin_stats_max_users == 0 {
+            anyhow::bail!("ADMIN_STATS_MAX_USERS must be at least 1");
+        }
+
+        if self.top_terms_max_limit == 0 {
+            anyhow::bail!("TOP_TERMS_MAX_LIMIT must be at least 1");
+        }
+
+        if self.preview_default_chars == 0 || self.preview_default_chars > MAX_PREVIEW_CHARS {
+            anyhow::bail!("PREVIEW_DEFAULT_CHARS must be between 1 and {}", MAX_PREVIEW_CHARS);
+        }
+
+        if self.created_at_max_future_secs < 0 || self.created_at_max_past_days < 0 {
+            anyhow::bail!("CREATED_AT_MAX_FUTURE_SECS and CREATED_AT_MAX_PAST_DAYS cannot be negative");
+        }
+
+        if self.log_summary_interval_secs == 0 {
+            anyhow::bail!("LOG_SUMMARY_INTERVAL_SECS must be at least 1");
+        }
+
+        if self.query_log_max_bytes == 0 {
+            anyhow::bail!("QUERY_LOG_MAX_BYTES must be at least 1");
+        }
+
+        if !matches!(self.empty_result_status, 200 | 404) {
+            anyhow::bail!("EMPTY_RESULT_STATUS must be 200 or 404");
+        }
+
+        if self.snippet_fields.is_empty() {
+            anyhow::bail!("SNIPPET_FIELDS must list at least one field");
+        }
+        if let Some(field) = self
+            .snippet_fields
+            .iter()
+            .find(|field| !SNIPPET_SOURCE_FIELDS.contains(&field.as_str()))
+        {
+            anyhow::bail!(
+                "SNIPPET_FIELDS contains unknown field '{}'. Valid fields: {}",
+                field,
+                SNIPPET_SOURCE_FIELDS.join(", ")
+            );
+        }
+
         Ok(())
     }
 }
 
+/// Name of the network filesystem `path` is on, or `None` for local or unknown filesystems
+///
+/// Uses `statfs(2)` and matches the filesystem magic number, so it only works
+/// on Linux. FUSE mounts are not reported because the magic number doesn't
+/// say whether sshfs, s3fs or a local FUSE filesystem is behind them.
+#[cfg(target_os = "linux")]
+pub fn network_filesystem(path: &Path) -> Option<&'static str> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let c_path = std::ffi::CString::new(path.as_os_str().as_bytes()).ok()?;
+    // SAFETY: `statfs` is plain old data, so all-zero is a valid value to be overwritten
+    let mut stat: libc::statfs = unsafe { std::mem::zeroed() };
+    // SAFETY: `c_path` is NUL-terminated and `stat` is a valid out-pointer for the call
+    if unsafe { libc::statfs(c_path.as_ptr(), &mut stat) } != 0 {
+        return None;
+    }
+    network_fs_name(stat.f_type as u32)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn network_filesystem(_path: &Path) -> Option<&'static str> {
+    None
+}
+
+/// Network filesystem named by a `statfs` magic number (see `statfs(2)`)
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn network_fs_name(magic: u32) -> Option<&'static str> {
+    match magic {
+        0x6969 => Some("nfs"),
+        0x517b => Some("smb"),
+        0xff53_4d42 => Some("cifs"),
+        0xfe53_4d42 => Some("smb2"),
+        0x00c3_6400 => Some("ceph"),
+        0x5346_414f | 0x6b41_4653 => Some("afs"),
+        0x7375_7245 => Some("coda"),
+        0x0102_1997 => Some("9p"),
+        0x0bd0_0bd0 => Some("lustre"),
+        0x4750_4653 => Some("gpfs"),
+        _ => None,
+    }
+}
+
+/// Parse an optional environment variable, falling back to a default when unset
+fn env_parse<T>(name: &str, default: T) -> Result<T>
+where
+    T: std::str::FromStr,
+    T::Err: std::fmt::Display,
+{
+    match std::env::var(name) {
+        Ok(value) => value
+            .trim()
+            .parse()
+            .map_err(|e| anyhow::anyhow!("Failed to parse {}: {}", name, e)),
+        Err(_) => Ok(default),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -84,8 +920,26 @@ mod tests {
             data_dir: temp_dir.path().to_path_buf(),
             log_level: "info".to_string(),
             web_ui_enabled: false,
+            ..Config::default()
         };
 
         assert!(config.validate().is_ok());
+
+        let config = Config {
+            snippet_fields: vec!["body".to_string(), "checksum".to_string()],
+            ..config
+        };
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_network_fs_names() {
+        assert_eq!(network_fs_name(0x6969), Some("nfs"));
+        assert_eq!(network_fs_name(0xff53_4d42), Some("cifs"));
+        // ext4 and tmpfs
+        assert_eq!(network_fs_name(0xef53), None);
+        assert_eq!(network_fs_name(0x0102_1994), None);
+        assert_eq!("FAIL".parse::<NetworkFsCheck>(), Ok(NetworkFsCheck::Fail));
+        assert!("strict".parse::<NetworkFsCheck>().is_err());
     }
 }
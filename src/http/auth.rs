@@ -7,10 +7,13 @@ use axum::{
 use serde::Serialize;
 use uuid::Uuid;
 
+use super::routes::AppState;
+
 /// Represents an authenticated user
 ///
 /// This extractor reads the X-User-Id header and validates it as a UUID.
-/// In a production system, this would validate a JWT or session token.
+/// Without the header, the `SingleTenantUser` request extension is used if
+/// present. In a production system, this would validate a JWT or session token.
 #[derive(Debug, Clone, Copy)]
 pub struct CurrentUser {
     pub user_id: Uuid,
@@ -23,6 +26,12 @@ impl CurrentUser {
     }
 }
 
+/// Fallback user for requests without `X-User-Id`, from `SINGLE_TENANT_USER_ID`
+///
+/// Added as a request extension by the router only when configured.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SingleTenantUser(pub Uuid);
+
 /// Error response for authentication failures
 #[derive(Debug, Serialize)]
 pub struct AuthError {
@@ -32,11 +41,17 @@ pub struct AuthError {
 
 impl IntoResponse for AuthError {
     fn into_response(self) -> Response {
+        let status = if self.error == "admin_disabled" {
+            StatusCode::FORBIDDEN
+        } else {
+            StatusCode::UNAUTHORIZED
+        };
+
         let body = serde_json::to_string(&self).unwrap_or_else(|_| {
             r#"{"error":"internal_error","message":"Failed to serialize error"}"#.to_string()
         });
 
-        (StatusCode::UNAUTHORIZED, body).into_response()
+        (status, body).into_response()
     }
 }
 
@@ -48,14 +63,16 @@ where
     type Rejection = AuthError;
 
     async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
-        // Extract X-User-Id header
-        let user_id_header = parts
-            .headers
-            .get("X-User-Id")
-            .ok_or_else(|| AuthError {
-                error: "missing_auth".to_string(),
-                message: "X-User-Id header is required".to_string(),
-            })?;
+        // Extract X-User-Id header; single-tenant deployments fall back to their fixed user
+        let Some(user_id_header) = parts.headers.get("X-User-Id") else {
+            return match parts.extensions.get::<SingleTenantUser>() {
+                Some(SingleTenantUser(user_id)) => Ok(CurrentUser { user_id: *user_id }),
+                None => Err(AuthError {
+                    error: "missing_auth".to_string(),
+                    message: "X-User-Id header is required".to_string(),
+                }),
+            };
+        };
 
         // Convert header value to string
         let user_id_str = user_id_header.to_str().map_err(|_| AuthError {
@@ -73,6 +90,87 @@ where
     }
 }
 
+/// Marker extractor for operator-only endpoints
+///
+/// Requires `Authorization: Bearer <ADMIN_TOKEN>`. When no admin token is
+/// configured every admin request is refused with 403.
+#[derive(Debug, Clone, Copy)]
+pub struct AdminAuth;
+
+#[async_trait]
+impl FromRequestParts<AppState> for AdminAuth {
+    type Rejection = AuthError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &AppState) -> Result<Self, Self::Rejection> {
+        let expected = state.config.admin_token.as_deref().ok_or_else(|| AuthError {
+            error: "admin_disabled".to_string(),
+            message: "Admin API is disabled; set ADMIN_TOKEN to enable it".to_string(),
+        })?;
+
+        let provided = parts
+            .headers
+            .get("Authorization")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .ok_or_else(|| AuthError {
+                error: "missing_auth".to_string(),
+                message: "Authorization: Bearer <admin token> header is required".to_string(),
+            })?;
+
+        if !constant_time_eq(provided.as_bytes(), expected.as_bytes()) {
+            return Err(AuthError {
+                error: "invalid_auth".to_string(),
+                message: "Invalid admin token".to_string(),
+            });
+        }
+
+        Ok(AdminAuth)
+    }
+}
+
+/// Extractor guarding the health endpoint
+///
+/// Health checks are open unless `HEALTH_AUTH_TOKEN` is set. Then the
+/// `Authorization` header must carry the token, either bare or as
+/// `Bearer <token>`, or the request is refused with 401.
+#[derive(Debug, Clone, Copy)]
+pub struct HealthAuth;
+
+#[async_trait]
+impl FromRequestParts<AppState> for HealthAuth {
+    type Rejection = AuthError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &AppState) -> Result<Self, Self::Rejection> {
+        let Some(expected) = state.config.health_auth_token.as_deref() else {
+            return Ok(HealthAuth);
+        };
+
+        let provided = parts
+            .headers
+            .get("Authorization")
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.strip_prefix("Bearer ").unwrap_or(value))
+            .ok_or_else(|| AuthError {
+                error: "missing_auth".to_string(),
+                message: "Authorization header with the health token is required".to_string(),
+            })?;
+
+        if !constant_time_eq(provided.as_bytes(), expected.as_bytes()) {
+            return Err(AuthError {
+                error: "invalid_auth".to_string(),
+                message: "Invalid health token".to_string(),
+            });
+        }
+
+        Ok(HealthAuth)
+    }
+}
+
+/// Compare two secrets without short-circuiting on the first mismatch
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -109,6 +207,38 @@ mod tests {
         assert!(result.is_err());
     }
 
+    #[tokio::test]
+    async fn test_single_tenant_user() {
+        let tenant = Uuid::new_v4();
+
+        // No header: the configured tenant
+        let mut req = Request::builder().body(Body::empty()).unwrap();
+        req.extensions_mut().insert(SingleTenantUser(tenant));
+        let (mut parts, _body) = req.into_parts();
+        let current_user = CurrentUser::from_request_parts(&mut parts, &()).await.unwrap();
+        assert_eq!(current_user.user_id, tenant);
+
+        // An explicit header still wins
+        let user_id = Uuid::new_v4();
+        let mut req = Request::builder()
+            .header("X-User-Id", user_id.to_string())
+            .body(Body::empty())
+            .unwrap();
+        req.extensions_mut().insert(SingleTenantUser(tenant));
+        let (mut parts, _body) = req.into_parts();
+        let current_user = CurrentUser::from_request_parts(&mut parts, &()).await.unwrap();
+        assert_eq!(current_user.user_id, user_id);
+
+        // A malformed header is not replaced by the fallback
+        let mut req = Request::builder()
+            .header("X-User-Id", "not-a-uuid")
+            .body(Body::empty())
+            .unwrap();
+        req.extensions_mut().insert(SingleTenantUser(tenant));
+        let (mut parts, _body) = req.into_parts();
+        assert!(CurrentUser::from_request_parts(&mut parts, &()).await.is_err());
+    }
+
     #[tokio::test]
     async fn test_current_user_invalid_uuid() {
         let req = Request::builder()
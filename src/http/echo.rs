@@ -0,0 +1,146 @@
+//! Request echo for error responses
+//!
+//! When `ECHO_REQUEST_ON_ERROR` is enabled, failed API requests carry a
+//! redacted summary of what the client sent in the error's `details`, so
+//! support tickets include enough to reproduce the call. String values are
+//! reduced to their length unless they are identifiers or the query text;
+//! full values are only echoed with `ECHO_REQUEST_BODIES`.
+
+use axum::{
+    body::Body,
+    extract::{OriginalUri, Request, State},
+    http::StatusCode,
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde_json::{json, Map, Value};
+
+use super::error::ErrorResponse;
+use super::routes::AppState;
+
+/// Largest request body buffered for the summary (matches the JSON extractor limit)
+const MAX_BUFFERED_BODY: usize = 2 * 1024 * 1024;
+
+/// Longest echoed string value, in characters
+const MAX_ECHOED_CHARS: usize = 256;
+
+/// Body keys whose string values identify the request rather than carry content
+const ECHOED_KEYS: &[&str] = &["id", "ids", "query", "source"];
+
+/// Middleware adding a request summary to error responses produced by `AppError`
+pub async fn echo_request_on_error(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let (parts, body) = request.into_parts();
+    let bytes = match axum::body::to_bytes(body, MAX_BUFFERED_BODY).await {
+        Ok(bytes) => bytes,
+        Err(_) => return StatusCode::PAYLOAD_TOO_LARGE.into_response(),
+    };
+
+    // Nesting strips the `/v1` prefix from the request URI; report what the client sent
+    let uri = parts
+        .extensions
+        .get::<OriginalUri>()
+        .map_or_else(|| parts.uri.clone(), |original| original.0.clone());
+    let mut summary = json!({
+        "method": parts.method.as_str(),
+        "path": uri.path(),
+    });
+    if let Some(query) = uri.query() {
+        summary["query_string"] = Value::String(query.to_string());
+    }
+    if !bytes.is_empty() {
+        summary["body"] = match serde_json::from_slice::<Value>(&bytes) {
+            Ok(value) => redact(None, value, state.config.echo_request_bodies),
+            Err(_) => json!({ "bytes": bytes.len() }),
+        };
+    }
+
+    let response = next.run(Request::from_parts(parts, Body::from(bytes))).await;
+
+    let error = match response.extensions().get::<ErrorResponse>() {
+        Some(error) if response.status().is_client_error() || response.status().is_server_error() => {
+            error.clone()
+        }
+        _ => return response,
+    };
+
+    let mut details = Map::new();
+    if let Some(previous) = error.details.clone() {
+        details.insert("error".to_string(), previous);
+    }
+    details.insert("request".to_string(), summary);
+
+    let (mut parts, _) = response.into_parts();
+    // The body is replaced, so the old length no longer applies
+    parts.headers.remove(axum::http::header::CONTENT_LENGTH);
+    let body = ErrorResponse {
+        details: Some(Value::Object(details)),
+        ..error
+    };
+    let new_response = Json(body).into_response();
+    Response::from_parts(parts, new_response.into_body())
+}
+
+/// Reduce a JSON value to a summary that does not reveal document content
+fn redact(key: Option<&str>, value: Value, full: bool) -> Value {
+    if full {
+        return value;
+    }
+
+    let echoed = key.is_some_and(|k| ECHOED_KEYS.contains(&k));
+    match value {
+        Value::String(s) if echoed => Value::String(s.chars().take(MAX_ECHOED_CHARS).collect()),
+        Value::String(s) => json!({ "length": s.chars().count() }),
+        Value::Array(items) if echoed => Value::Array(
+            items
+                .into_iter()
+                .take(10)
+                .map(|item| redact(key, item, false))
+                .collect(),
+        ),
+        Value::Array(items) => json!({ "count": items.len() }),
+        Value::Object(map) => Value::Object(
+            map.into_iter()
+                .map(|(k, v)| {
+                    let redacted = redact(Some(&k), v, false);
+                    (k, redacted)
+                })
+                .collect(),
+        ),
+        other => other,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_redact_hides_content() {
+        let request = json!({
+            "id": "doc-1",
+            "title": "Secret title",
+            "body": "Sensitive body text",
+            "metadata": { "tags": ["a", "b"], "source": "upload" },
+            "limit": 10
+        });
+
+        let redacted = redact(None, request.clone(), false);
+        assert_eq!(
+            redacted,
+            json!({
+                "id": "doc-1",
+                "title": { "length": 12 },
+                "body": { "length": 19 },
+                "metadata": { "tags": { "count": 2 }, "source": "upload" },
+                "limit": 10
+            })
+        );
+
+        assert_eq!(redact(None, request.clone(), true), request);
+    }
+}
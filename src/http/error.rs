@@ -1,11 +1,13 @@
 use axum::{
-    http::StatusCode,
+    http::{header, StatusCode},
     response::{IntoResponse, Response},
     Json,
 };
 use serde::Serialize;
 use thiserror::Error;
 
+use crate::search::IndexError;
+
 /// Application error types
 #[derive(Error, Debug)]
 pub enum AppError {
@@ -23,30 +25,54 @@ pub enum AppError {
     #[error("Validation error: {0}")]
     Validation(String),
 
+    #[error("Feature disabled: {0}")]
+    FeatureDisabled(String),
+
+    #[error("Conflict: {0}")]
+    Conflict(String),
+
     #[error("Search error: {0}")]
     Search(String),
 
     #[error("Index error: {0}")]
     Index(String),
+
+    #[error("Service busy: {message}")]
+    Busy { message: String, retry_after_secs: u64 },
+
+    #[error("Index unavailable: {message}")]
+    Unavailable { message: String, retry_after_secs: u64 },
+
+    #[error("Rate limited: {message}")]
+    RateLimited { message: String, retry_after_secs: u64 },
 }
 
 /// Error response body
-#[derive(Debug, Serialize)]
-struct ErrorResponse {
-    error: String,
-    message: String,
+///
+/// A copy is stored in the response extensions so middleware can enrich it.
+#[derive(Debug, Clone, Serialize)]
+pub struct ErrorResponse {
+    pub error: String,
+    pub message: String,
     #[serde(skip_serializing_if = "Option::is_none")]
-    details: Option<String>,
+    pub details: Option<serde_json::Value>,
 }
 
 impl IntoResponse for AppError {
     fn into_response(self) -> Response {
+        let retry_after = match &self {
+            AppError::Busy { retry_after_secs, .. }
+            | AppError::Unavailable { retry_after_secs, .. }
+            | AppError::RateLimited { retry_after_secs, .. } => Some(*retry_after_secs),
+            _ => None,
+        };
+
         let (status, error_type, message, details) = match self {
             AppError::Internal(err) => (
                 StatusCode::INTERNAL_SERVER_ERROR,
                 "internal_error".to_string(),
                 "An internal error occurred".to_string(),
-                Some(err.to_string()),
+                Some(serde_json::Value::String(err.to_string())),
             ),
             AppError::BadRequest(msg) => (
                 StatusCode::BAD_REQUEST,
@@ -66,6 +92,18 @@ impl IntoResponse for AppError {
                 msg,
                 None,
             ),
+            AppError::FeatureDisabled(feature) => (
+                StatusCode::FORBIDDEN,
+                "feature_disabled".to_string(),
+                format!("Feature '{}' is not enabled for this account", feature),
+                None,
+            ),
+            AppError::Conflict(msg) => (
+                StatusCode::CONFLICT,
+                "conflict".to_string(),
+                msg,
+                None,
+            ),
             AppError::Search(msg) => (
                 StatusCode::BAD_REQUEST,
                 "search_error".to_string(),
@@ -78,6 +116,24 @@ impl IntoResponse for AppError {
                 msg,
                 None,
             ),
+            AppError::Busy { message, .. } => (
+                StatusCode::SERVICE_UNAVAILABLE,
+                "index_busy".to_string(),
+                message,
+                None,
+            ),
+            AppError::Unavailable { message, .. } => (
+                StatusCode::SERVICE_UNAVAILABLE,
+                "index_unavailable".to_string(),
+                message,
+                None,
+            ),
+            AppError::RateLimited { message, .. } => (
+                StatusCode::TOO_MANY_REQUESTS,
+                "rate_limited".to_string(),
+                message,
+                None,
+            ),
         };
 
         let body = ErrorResponse {
@@ -86,7 +142,52 @@ impl IntoResponse for AppError {
             details,
         };
 
-        (status, Json(body)).into_response()
+        let mut response = (status, Json(body.clone())).into_response();
+        response.extensions_mut().insert(body);
+        if let Some(secs) = retry_after {
+            response
+                .headers_mut()
+                .insert(header::RETRY_AFTER, header::HeaderValue::from(secs));
+        }
+        response
+    }
+}
+
+impl From<IndexError> for AppError {
+    fn from(err: IndexError) -> Self {
+        match err {
+            IndexError::Busy { retry_after_secs } => AppError::Busy {
+                message: "Index is busy, please retry".to_string(),
+                retry_after_secs,
+            },
+            IndexError::Rebuilding { retry_after_secs } => AppError::Busy {
+                message: "Index is being rebuilt, please retry".to_string(),
+                retry_after_secs,
+            },
+            // The reason names paths inside DATA_DIR; it was logged when the open failed
+            IndexError::Unavailable { retry_after_secs, .. } => AppError::Unavailable {
+                message: "Index failed to open recently, please retry later".to_string(),
+                retry_after_secs,
+            },
+            IndexError::RateLimited { retry_after_secs } => AppError::RateLimited {
+                message: "Documents are being indexed faster than the allowed rate, please retry".to_string(),
+                retry_after_secs,
+            },
+            IndexError::InvalidQuery(msg) => AppError::Validation(msg),
+            IndexError::Unsupported(msg) => AppError::Validation(msg),
+            IndexError::Conflict(msg) => AppError::Conflict(msg),
+        }
+    }
+}
+
+impl AppError {
+    /// Convert an error from the index layer, preserving typed `IndexError`s
+    /// and falling back to the handler-specific mapping for everything else
+    pub fn from_index(err: anyhow::Error, fallback: impl FnOnce(anyhow::Error) -> AppError) -> Self {
+        match err.downcast::<IndexError>() {
+            Ok(index_err) => index_err.into(),
+            Err(err) => fallback(err),
+        }
     }
 }
 
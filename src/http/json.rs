@@ -0,0 +1,128 @@
+//! JSON request bodies with optional rejection of unknown fields
+//!
+//! Request models ignore fields they don't know, so an older server accepts
+//! requests from newer clients. With `UNKNOWN_FIELDS=reject`, a body whose
+//! top level has a field the model doesn't declare fails with a 422 naming
+//! it instead, so a typo such as `lmit` doesn't silently fall back to the
+//! default. Nested objects are not checked; `metadata` in particular keeps
+//! collecting custom fields.
+
+use axum::{
+    async_trait,
+    body::{Body, Bytes},
+    extract::{FromRequest, Request},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::de::{self, DeserializeOwned, Visitor};
+use serde::forward_to_deserialize_any;
+
+use super::error::AppError;
+use super::routes::AppState;
+use crate::config::UnknownFieldsMode;
+
+/// JSON request body, checked for unknown top-level fields when configured
+pub struct ApiJson<T>(pub T);
+
+#[async_trait]
+impl<T: DeserializeOwned> FromRequest<AppState> for ApiJson<T> {
+    type Rejection = Response;
+
+    async fn from_request(request: Request, state: &AppState) -> Result<Self, Response> {
+        if state.config.unknown_fields == UnknownFieldsMode::Reject {
+            let (parts, body) = request.into_parts();
+            let bytes = Bytes::from_request(Request::from_parts(parts.clone(), body), state)
+                .await
+                .map_err(IntoResponse::into_response)?;
+            if let Some(fields) = struct_fields::<T>() {
+                check_fields(&bytes, fields).map_err(IntoResponse::into_response)?;
+            }
+            let request = Request::from_parts(parts, Body::from(bytes));
+            let Json(value) = Json::<T>::from_request(request, state)
+                .await
+                .map_err(IntoResponse::into_response)?;
+            return Ok(ApiJson(value));
+        }
+
+        let Json(value) = Json::<T>::from_request(request, state)
+            .await
+            .map_err(IntoResponse::into_response)?;
+        Ok(ApiJson(value))
+    }
+}
+
+/// Fail on the first top-level key of a JSON object body that isn't in `fields`
+///
+/// Bodies that aren't JSON objects are left to the JSON extractor to reject.
+fn check_fields(bytes: &[u8], fields: &[&str]) -> Result<(), AppError> {
+    let Ok(serde_json::Value::Object(object)) = serde_json::from_slice(bytes) else {
+        return Ok(());
+    };
+    match object.keys().find(|key| !fields.contains(&key.as_str())) {
+        Some(unknown) => Err(AppError::Validation(format!(
+            "Unknown field '{}'. Valid fields: {}",
+            unknown,
+            fields.join(", ")
+        ))),
+        None => Ok(()),
+    }
+}
+
+/// Field names of the struct `T` deserializes as, or `None` if it isn't a plain struct
+///
+/// Serde's derive hands the list to the deserializer, so a deserializer
+/// that records it and then fails reads it without a sample value. Structs
+/// with flattened fields are deserialized as maps and yield `None`.
+pub fn struct_fields<T: DeserializeOwned>() -> Option<&'static [&'static str]> {
+    let mut fields = None;
+    let _ = T::deserialize(FieldRecorder { fields: &mut fields });
+    fields
+}
+
+/// Deserializer that only records the field list it is asked for
+struct FieldRecorder<'a> {
+    fields: &'a mut Option<&'static [&'static str]>,
+}
+
+impl<'de> de::Deserializer<'de> for FieldRecorder<'_> {
+    type Error = de::value::Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, Self::Error> {
+        Err(de::Error::custom("not a struct"))
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        _visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        *self.fields = Some(fields);
+        Err(de::Error::custom("field names recorded"))
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct map enum identifier ignored_any
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::search::models::{IndexDocumentInput, SearchQuery};
+
+    #[test]
+    fn test_struct_fields() {
+        let fields = struct_fields::<SearchQuery>().unwrap();
+        assert!(fields.contains(&"query") && fields.contains(&"limit"));
+        assert!(struct_fields::<IndexDocumentInput>().unwrap().contains(&"metadata"));
+        assert_eq!(struct_fields::<serde_json::Value>(), None);
+
+        assert!(check_fields(br#"{"query": "refund", "limit": 5}"#, fields).is_ok());
+        let err = check_fields(br#"{"query": "refund", "lmit": 5}"#, fields).unwrap_err();
+        assert!(err.to_string().contains("'lmit'"));
+        assert!(check_fields(b"[1, 2]", fields).is_ok());
+    }
+}
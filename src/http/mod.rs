@@ -1,11 +1,15 @@
 pub mod auth;
+pub mod echo;
 pub mod error;
+pub mod json;
 pub mod routes;
+pub mod server;
 pub mod webui;
 
 use axum::{
+    middleware,
     routing::{delete, get, post, put},
-    Router,
+    Extension, Router,
 };
 use std::time::Duration;
 use tower_http::{
@@ -20,12 +24,45 @@ use routes::AppState;
 /// Build the Axum router with all routes and middleware
 pub fn build_router(state: AppState, web_ui_enabled: bool) -> Router {
     // API v1 routes - all require authentication
-    let api_v1 = Router::new()
+    let mut api_v1 = Router::new()
         .route("/documents", put(routes::index_document))
+        .route("/documents", post(routes::create_document))
         .route("/documents", delete(routes::delete_document))
+        .route("/documents/bulk", post(routes::bulk_index_documents))
+        .route("/documents/bulk-delete", post(routes::bulk_delete_documents))
+        .route("/documents/:id/preview", get(routes::preview_document))
+        .route("/documents/:id/refresh", post(routes::refresh_document))
+        .route("/documents/:id/similar", post(routes::similar_documents))
         .route("/search", post(routes::search_documents))
+        .route("/query/validate", post(routes::validate_query))
+        .route("/analyze", post(routes::analyze_text))
+        .route("/similarity", post(routes::compare_similarity))
         .route("/browse", post(routes::browse_documents))
-        .route("/stats", get(routes::get_stats));
+        .route("/stats", get(routes::get_stats))
+        .route("/analytics", get(routes::get_analytics))
+        .route("/terms/top", get(routes::top_terms))
+        .route("/tags/facets", get(routes::tag_facets))
+        .route("/index/warm", post(routes::warm_index))
+        .route("/index/flush", post(routes::flush_index))
+        .route("/index/history", get(routes::index_history))
+        .route("/index/health", get(routes::index_health))
+        .route("/changes", get(routes::stream_changes))
+        .route("/reindex", post(routes::reindex))
+        .route("/admin/repair/:user_id", post(routes::repair_index))
+        .route("/admin/stats", post(routes::admin_stats))
+        .route("/admin/pins", get(routes::list_pinned_indexes))
+        .route("/admin/pins/:user_id", put(routes::pin_index).delete(routes::unpin_index));
+
+    if let Some(user_id) = state.config.single_tenant_user_id {
+        api_v1 = api_v1.layer(Extension(auth::SingleTenantUser(user_id)));
+    }
+
+    if state.config.echo_request_on_error {
+        api_v1 = api_v1.layer(middleware::from_fn_with_state(
+            state.clone(),
+            echo::echo_request_on_error,
+        ));
+    }
 
     // Main router with health check and API routes
     let mut router = Router::new()
@@ -57,13 +94,18 @@ mod tests {
     use tempfile::TempDir;
     use tower::ServiceExt;
 
+    use crate::config::Config;
     use crate::search::IndexManager;
 
     #[tokio::test]
     async fn test_health_check() {
         let temp_dir = TempDir::new().unwrap();
         let index_manager = Arc::new(IndexManager::new(temp_dir.path().to_path_buf()));
-        let state = AppState { index_manager };
+        let state = AppState {
+            index_manager,
+            config: Arc::new(Config::default()),
+            query_log: None,
+        };
         let app = build_router(state, false);
 
         let response = app
@@ -79,11 +121,79 @@ mod tests {
         assert_eq!(response.status(), StatusCode::OK);
     }
 
+    #[tokio::test]
+    async fn test_single_tenant_requests_without_header() {
+        let temp_dir = TempDir::new().unwrap();
+        let index_manager = Arc::new(IndexManager::new(temp_dir.path().to_path_buf()));
+        let state = AppState {
+            index_manager,
+            config: Arc::new(Config {
+                single_tenant_user_id: Some(uuid::Uuid::new_v4()),
+                ..Config::default()
+            }),
+            query_log: None,
+        };
+        let app = build_router(state, false);
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/v1/search")
+                    .header("content-type", "application/json")
+                    .body(Body::from(r#"{"query":"test"}"#))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_ui_token_prefills_user() {
+        let temp_dir = TempDir::new().unwrap();
+        let index_manager = Arc::new(IndexManager::new(temp_dir.path().to_path_buf()));
+        let key: webui::UiTokenKey = "0123456789abcdef0123456789abcdef".parse().unwrap();
+        let state = AppState {
+            index_manager,
+            config: Arc::new(Config {
+                web_ui_token_key: Some(key.clone()),
+                ..Config::default()
+            }),
+            query_log: None,
+        };
+        let app = build_router(state, true);
+
+        let user_id = uuid::Uuid::new_v4();
+        let now = chrono::Utc::now().timestamp();
+        let get_ui = |token: String| {
+            app.clone().oneshot(
+                Request::builder()
+                    .uri(format!("/ui?token={}", token))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+        };
+
+        let response = get_ui(key.sign(user_id, now)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert!(String::from_utf8_lossy(&body).contains(&format!("const presetUserId = '{}';", user_id)));
+
+        let response = get_ui(key.sign(user_id, now - 3600)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+
     #[tokio::test]
     async fn test_missing_auth() {
         let temp_dir = TempDir::new().unwrap();
         let index_manager = Arc::new(IndexManager::new(temp_dir.path().to_path_buf()));
-        let state = AppState { index_manager };
+        let state = AppState {
+            index_manager,
+            config: Arc::new(Config::default()),
+            query_log: None,
+        };
         let app = build_router(state, false);
 
         let response = app
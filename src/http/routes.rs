@@ -1,30 +1,66 @@
 use axum::{
-    extract::State,
-    http::StatusCode,
+    extract::{Path, Query, State},
+    http::{HeaderMap, StatusCode},
+    response::sse::{Event, KeepAlive, Sse},
     response::IntoResponse,
     Json,
 };
+use std::collections::HashMap;
 use std::sync::Arc;
-use tracing::{info, error};
+use std::time::Duration;
+use tracing::{info, error, warn};
+use uuid::Uuid;
 
+use crate::config::{Config, MAX_PREVIEW_CHARS};
+use crate::logging;
+use crate::query_log::QueryLog;
+use crate::search::schema::{tag_facet, CreatedAtSource, EmptyTagsMode};
+use crate::search::text::{normalize_text, validate_document_id, MAX_DOCUMENT_ID_BYTES};
+use crate::search::user_config::Feature;
 use crate::search::{
-    DeleteDocumentInput, HealthResponse, IndexDocumentInput, IndexManager, SearchQuery,
-    BrowseDocumentsQuery,
+    SearchOptions, BulkDeleteInput, DeleteDocumentInput, HealthResponse, IndexDocumentInput, IndexManager, SearchQuery,
+    BrowseDocumentsQuery, DocumentPreviewQuery, PinIndexResponse, PinnedIndexesResponse, AdminStatsRequest, AdminStatsResponse, AdminUserStats, QueryValidationResponse,
+    SearchFilters, BulkIndexInput, DuplicateIdMode, AnalyzeRequest, LimitOverflowMode, TopTermsQuery, TagFacetsQuery,
+    SimilarityRequest, SimilarityResponse, SimilarityTarget, SimilarDocumentsRequest, IndexHistoryQuery, ScoreMode, SearchFormat, SortBy, MAX_BROWSE_LIMIT, MAX_SEARCH_LIMIT,
 };
+use crate::search::changes::ChangeMessage;
+use crate::search::similarity;
+use crate::search::language::{Language, StopWords};
 
-use super::auth::CurrentUser;
+use super::auth::{AdminAuth, CurrentUser, HealthAuth};
 use super::error::{AppError, AppResult};
+use super::json::ApiJson;
+
+/// Most tag levels one `/v1/tags/facets` request counts; each level is a pass over the index
+const MAX_TAG_FACET_DEPTH: usize = 5;
+
+/// Most seed terms one `/v1/documents/:id/similar` request searches for
+const MAX_SIMILAR_QUERY_TERMS: usize = 100;
+
+/// Users whose stats one `/v1/admin/stats` request reads at a time
+const ADMIN_STATS_CONCURRENCY: usize = 4;
+
+/// Retry-After for a `/v1/changes` request turned away at a connection cap
+const CHANGES_RETRY_AFTER_SECS: u64 = 5;
+
+/// Longest text accepted by `/v1/analyze`; it is a debugging aid, not a bulk tokenizer
+const MAX_ANALYZE_CHARS: usize = 10_000;
 
 /// Application state shared across handlers
 #[derive(Clone)]
 pub struct AppState {
     pub index_manager: Arc<IndexManager>,
+    pub config: Arc<Config>,
+    /// Search query log; `None` unless `QUERY_LOG_ENABLED` is set
+    pub query_log: Option<Arc<QueryLog>>,
 }
 
 /// Health check endpoint
 ///
 /// GET /health
-pub async fn health_check() -> Json<HealthResponse> {
+///
+/// Open by default; requires the health token when `HEALTH_AUTH_TOKEN` is set.
+pub async fn health_check(_auth: HealthAuth) -> Json<HealthResponse> {
     Json(HealthResponse {
         status: "ok".to_string(),
         version: Some(env!("CARGO_PKG_VERSION").to_string()),
@@ -40,22 +76,17 @@ pub async fn health_check() -> Json<HealthResponse> {
 pub async fn index_document(
     State(state): State<AppState>,
     current_user: CurrentUser,
-    Json(input): Json<IndexDocumentInput>,
+    ApiJson(input): ApiJson<IndexDocumentInput>,
 ) -> AppResult<impl IntoResponse> {
-    info!(
-        user_id = %current_user.user_id,
-        doc_id = ?input.id,
-        "Indexing document"
-    );
-
-    // Validate input
-    if input.title.trim().is_empty() {
-        return Err(AppError::Validation("Title cannot be empty".to_string()));
+    if logging::sample("index_request") {
+        info!(
+            user_id = %current_user.user_id,
+            doc_id = ?input.id,
+            "Indexing document"
+        );
     }
 
-    if input.body.trim().is_empty() {
-        return Err(AppError::Validation("Body cannot be empty".to_string()));
-    }
+    let input = prepare_document(&state.config, current_user.user_id, input)?;
 
     // Index the document using the authenticated user's ID
     let response = state
@@ -64,12 +95,128 @@ pub async fn index_document(
         .await
         .map_err(|e| {
             error!(error = %e, "Failed to index document");
-            AppError::Index(format!("Failed to index document: {}", e))
+            AppError::from_index(e, |e| AppError::Index(format!("Failed to index document: {}", e)))
         })?;
 
     Ok((StatusCode::OK, Json(response)))
 }
 
+/// Create a document, failing if its ID is already taken
+///
+/// POST /v1/documents
+///
+/// Create-only counterpart of `PUT /v1/documents`: returns 201 when the
+/// document is inserted and 409 if a document with the same ID exists. A
+/// write skipped as an alias of a document with the same content returns 200.
+pub async fn create_document(
+    State(state): State<AppState>,
+    current_user: CurrentUser,
+    ApiJson(input): ApiJson<IndexDocumentInput>,
+) -> AppResult<impl IntoResponse> {
+    if logging::sample("create_request") {
+        info!(
+            user_id = %current_user.user_id,
+            doc_id = ?input.id,
+            "Creating document"
+        );
+    }
+
+    let input = prepare_document(&state.config, current_user.user_id, input)?;
+
+    let response = state
+        .index_manager
+        .create_document(current_user.user_id, input)
+        .await
+        .map_err(|e| {
+            AppError::from_index(e, |e| {
+                error!(error = %e, "Failed to create document");
+                AppError::Index(format!("Failed to create document: {}", e))
+            })
+        })?;
+
+    let status = if response.alias_of.is_some() { StatusCode::OK } else { StatusCode::CREATED };
+    Ok((status, Json(response)))
+}
+
+/// Validate and normalize a document before it is written
+fn prepare_document(
+    config: &Config,
+    user_id: Uuid,
+    mut input: IndexDocumentInput,
+) -> AppResult<IndexDocumentInput> {
+    // Validate input
+    if let Some(id) = &input.id {
+        validate_document_id(id).map_err(AppError::Validation)?;
+    }
+
+    if input.title.trim().is_empty() {
+        return Err(AppError::Validation("Title cannot be empty".to_string()));
+    }
+
+    if let Some(external_id) = &input.external_id {
+        if external_id.trim().is_empty() {
+            return Err(AppError::Validation("external_id cannot be empty".to_string()));
+        }
+        if external_id.len() > MAX_DOCUMENT_ID_BYTES {
+            return Err(AppError::Validation(format!(
+                "external_id is {} bytes; the maximum is {}",
+                external_id.len(),
+                MAX_DOCUMENT_ID_BYTES
+            )));
+        }
+    }
+
+    if input.body.trim().is_empty() {
+        if !config.allow_empty_body {
+            return Err(AppError::Validation("Body cannot be empty".to_string()));
+        }
+        // Title-only document; store a canonical empty body rather than whitespace
+        input.body.clear();
+    }
+
+    // Dropped tags are left to `doc_from_input`, which trims every tag
+    if config.empty_tags == EmptyTagsMode::Reject && input.metadata.tags.iter().any(|tag| tag.trim().is_empty()) {
+        return Err(AppError::Validation("Tags cannot be empty".to_string()));
+    }
+
+    if let Some(boost) = input.boost {
+        if !boost.is_finite() || boost <= 0.0 {
+            return Err(AppError::Validation("boost must be a positive number".to_string()));
+        }
+    }
+
+    // NFC-normalize text and apply the configured invalid-character policy
+    let mode = config.invalid_text_mode;
+    input.title = normalize_text("title", &input.title, mode).map_err(AppError::Validation)?;
+    input.body = normalize_text("body", &input.body, mode).map_err(AppError::Validation)?;
+    input.external_id = input
+        .external_id
+        .map(|external_id| normalize_text("external_id", &external_id, mode))
+        .transpose()
+        .map_err(AppError::Validation)?;
+
+    // Keep out-of-range timestamps from skewing recency ranking and date filters; a
+    // server-stamped index only records the client's value, so it is kept verbatim
+    if let (CreatedAtSource::Client, Some(created_at)) = (config.created_at_source, input.metadata.created_at) {
+        let accepted = config
+            .created_at_policy()
+            .apply(created_at, chrono::Utc::now())
+            .map_err(AppError::Validation)?;
+        if accepted != created_at {
+            warn!(
+                user_id = %user_id,
+                doc_id = ?input.id,
+                supplied = %created_at.to_rfc3339(),
+                clamped = %accepted.to_rfc3339(),
+                "Clamped out-of-range created_at"
+            );
+            input.metadata.created_at = Some(accepted);
+        }
+    }
+
+    Ok(input)
+}
+
 /// Delete a document
 ///
 /// DELETE /v1/documents
@@ -78,25 +225,219 @@ pub async fn index_document(
 pub async fn delete_document(
     State(state): State<AppState>,
     current_user: CurrentUser,
-    Json(input): Json<DeleteDocumentInput>,
+    ApiJson(input): ApiJson<DeleteDocumentInput>,
+) -> AppResult<impl IntoResponse> {
+    if logging::sample("delete_request") {
+        info!(
+            user_id = %current_user.user_id,
+            doc_id = %input.id,
+            "Deleting document"
+        );
+    }
+
+    validate_document_id(&input.id).map_err(AppError::Validation)?;
+
+    let response = state
+        .index_manager
+        .delete_document(current_user.user_id, input.id)
+        .await
+        .map_err(|e| {
+            error!(error = %e, "Failed to delete document");
+            AppError::from_index(e, |e| AppError::Index(format!("Failed to delete document: {}", e)))
+        })?;
+
+    Ok((StatusCode::OK, Json(response)))
+}
+
+/// Preview a document
+///
+/// GET /v1/documents/:id/preview?chars=N
+///
+/// Returns the title and the first N characters of the body, trimmed back to
+/// a word boundary, so list views don't transfer whole documents.
+pub async fn preview_document(
+    State(state): State<AppState>,
+    current_user: CurrentUser,
+    Path(id): Path<String>,
+    Query(params): Query<DocumentPreviewQuery>,
+) -> AppResult<impl IntoResponse> {
+    validate_document_id(&id).map_err(AppError::Validation)?;
+
+    let chars = params.chars.unwrap_or(state.config.preview_default_chars);
+    if chars == 0 || chars > MAX_PREVIEW_CHARS {
+        return Err(AppError::Validation(format!(
+            "chars must be between 1 and {}",
+            MAX_PREVIEW_CHARS
+        )));
+    }
+
+    let preview = state
+        .index_manager
+        .preview_document(current_user.user_id, &id, chars)
+        .await
+        .map_err(|e| {
+            error!(error = %e, "Failed to preview document");
+            AppError::from_index(e, |e| AppError::Index(format!("Failed to preview document: {}", e)))
+        })?
+        .ok_or_else(|| AppError::NotFound(format!("Document '{}' not found", id)))?;
+
+    Ok(Json(preview))
+}
+
+/// Re-tokenize a stored document
+///
+/// POST /v1/documents/:id/refresh
+///
+/// Re-adds the document from its stored values so it picks up the current
+/// analyzers. The content is not changed.
+pub async fn refresh_document(
+    State(state): State<AppState>,
+    current_user: CurrentUser,
+    Path(id): Path<String>,
+) -> AppResult<impl IntoResponse> {
+    info!(
+        user_id = %current_user.user_id,
+        doc_id = %id,
+        "Refreshing document"
+    );
+
+    validate_document_id(&id).map_err(AppError::Validation)?;
+
+    let response = state
+        .index_manager
+        .refresh_document(current_user.user_id, &id)
+        .await
+        .map_err(|e| {
+            error!(error = %e, "Failed to refresh document");
+            AppError::from_index(e, |e| AppError::Index(format!("Failed to refresh document: {}", e)))
+        })?
+        .ok_or_else(|| AppError::NotFound(format!("Document '{}' not found", id)))?;
+
+    Ok(Json(response))
+}
+
+/// Index or replace many documents in one request
+///
+/// POST /v1/documents/bulk
+///
+/// Every document is validated like a single index request before anything
+/// is written; all writes then share a single writer lock and commit.
+/// Repeated IDs are rejected or resolved according to `BULK_DUPLICATE_IDS`.
+pub async fn bulk_index_documents(
+    State(state): State<AppState>,
+    current_user: CurrentUser,
+    ApiJson(input): ApiJson<BulkIndexInput>,
 ) -> AppResult<impl IntoResponse> {
+    require_feature(&state, current_user.user_id, Feature::Bulk).await?;
+
     info!(
         user_id = %current_user.user_id,
-        doc_id = %input.id,
-        "Deleting document"
+        count = input.documents.len(),
+        "Bulk indexing documents"
     );
 
-    if input.id.trim().is_empty() {
-        return Err(AppError::Validation("Document ID cannot be empty".to_string()));
+    if input.documents.is_empty() {
+        return Err(AppError::Validation("documents cannot be empty".to_string()));
+    }
+
+    if input.documents.len() > state.config.bulk_index_max_docs {
+        return Err(AppError::Validation(format!(
+            "Cannot index more than {} documents per request",
+            state.config.bulk_index_max_docs
+        )));
+    }
+
+    let documents = input
+        .documents
+        .into_iter()
+        .enumerate()
+        .map(|(i, document)| {
+            prepare_document(&state.config, current_user.user_id, document).map_err(|e| match e {
+                AppError::Validation(message) => AppError::Validation(format!("documents[{}]: {}", i, message)),
+                other => other,
+            })
+        })
+        .collect::<AppResult<Vec<_>>>()?;
+
+    if state.config.bulk_duplicate_ids == DuplicateIdMode::Reject {
+        let duplicates = duplicate_ids(&documents);
+        if !duplicates.is_empty() {
+            return Err(AppError::Validation(format!(
+                "Duplicate document IDs in request: {}",
+                duplicates.join(", ")
+            )));
+        }
     }
 
     let response = state
         .index_manager
-        .delete_document(current_user.user_id, input.id)
+        .index_documents_bulk(current_user.user_id, documents)
         .await
         .map_err(|e| {
-            error!(error = %e, "Failed to delete document");
-            AppError::Index(format!("Failed to delete document: {}", e))
+            error!(error = %e, "Failed to bulk index documents");
+            AppError::from_index(e, |e| AppError::Index(format!("Failed to index documents: {}", e)))
+        })?;
+
+    Ok((StatusCode::OK, Json(response)))
+}
+
+/// IDs supplied more than once, in order of first appearance
+fn duplicate_ids(documents: &[IndexDocumentInput]) -> Vec<&str> {
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for id in documents.iter().filter_map(|document| document.id.as_deref()) {
+        *counts.entry(id).or_default() += 1;
+    }
+
+    let mut duplicates: Vec<&str> = Vec::new();
+    for id in documents.iter().filter_map(|document| document.id.as_deref()) {
+        if counts[id] > 1 && !duplicates.contains(&id) {
+            duplicates.push(id);
+        }
+    }
+    duplicates
+}
+
+/// Delete many documents in one request
+///
+/// POST /v1/documents/bulk-delete
+///
+/// All deletes share a single writer lock and commit. The response reports
+/// whether each ID was deleted or not found.
+pub async fn bulk_delete_documents(
+    State(state): State<AppState>,
+    current_user: CurrentUser,
+    ApiJson(input): ApiJson<BulkDeleteInput>,
+) -> AppResult<impl IntoResponse> {
+    require_feature(&state, current_user.user_id, Feature::Bulk).await?;
+
+    info!(
+        user_id = %current_user.user_id,
+        count = input.ids.len(),
+        "Bulk deleting documents"
+    );
+
+    if input.ids.is_empty() {
+        return Err(AppError::Validation("ids cannot be empty".to_string()));
+    }
+
+    if input.ids.len() > state.config.bulk_delete_max_ids {
+        return Err(AppError::Validation(format!(
+            "Cannot delete more than {} documents per request",
+            state.config.bulk_delete_max_ids
+        )));
+    }
+
+    for (i, id) in input.ids.iter().enumerate() {
+        validate_document_id(id).map_err(|e| AppError::Validation(format!("ids[{}]: {}", i, e)))?;
+    }
+
+    let response = state
+        .index_manager
+        .delete_documents_bulk(current_user.user_id, input.ids)
+        .await
+        .map_err(|e| {
+            error!(error = %e, "Failed to bulk delete documents");
+            AppError::from_index(e, |e| AppError::Index(format!("Failed to delete documents: {}", e)))
         })?;
 
     Ok((StatusCode::OK, Json(response)))
@@ -108,16 +449,23 @@ pub async fn delete_document(
 ///
 /// This endpoint allows users to search within their personal index.
 /// Users can only search their own documents - multi-tenant isolation is enforced.
+///
+/// An optional `X-Timeout-Ms` header caps how long the search may run
+/// (clamped to `SEARCH_TIMEOUT_MAX_MS`). When the budget is exceeded the
+/// results collected so far are returned with `timed_out: true`.
 pub async fn search_documents(
     State(state): State<AppState>,
     current_user: CurrentUser,
-    Json(query): Json<SearchQuery>,
+    headers: HeaderMap,
+    ApiJson(mut query): ApiJson<SearchQuery>,
 ) -> AppResult<impl IntoResponse> {
-    info!(
-        user_id = %current_user.user_id,
-        query = %query.query,
-        "Searching documents"
-    );
+    if logging::sample("search_request") {
+        info!(
+            user_id = %current_user.user_id,
+            query = %query.query,
+            "Searching documents"
+        );
+    }
 
     if query.query.trim().is_empty() {
         return Err(AppError::Validation("Query cannot be empty".to_string()));
@@ -127,17 +475,563 @@ pub async fn search_documents(
         return Err(AppError::Validation("Limit must be greater than 0".to_string()));
     }
 
-    if query.limit > 100 {
-        return Err(AppError::Validation("Limit cannot exceed 100".to_string()));
+    let capped = query.limit > MAX_SEARCH_LIMIT;
+    if capped {
+        match state.config.search_limit_overflow {
+            LimitOverflowMode::Reject => {
+                return Err(AppError::Validation(format!("Limit cannot exceed {}", MAX_SEARCH_LIMIT)));
+            }
+            LimitOverflowMode::Clamp => query.limit = MAX_SEARCH_LIMIT,
+        }
     }
 
-    let response = state
+    validate_offset(query.offset, state.config.max_result_offset)?;
+
+    if let Some(message) = filter_error(&query.filters) {
+        return Err(AppError::Validation(message.to_string()));
+    }
+
+    if let Some(boost) = query.recency_boost {
+        if !boost.is_finite() || boost < 0.0 {
+            return Err(AppError::Validation(
+                "recency_boost must be a non-negative number".to_string(),
+            ));
+        }
+    }
+
+    // Length-sorted results aren't ranked, so there is no score to boost or rescale
+    if matches!(query.sort, SortBy::ByLength { .. })
+        && (query.recency_boost.is_some_and(|boost| boost > 0.0) || query.score_mode != ScoreMode::Raw)
+    {
+        return Err(AppError::Validation(
+            "Sorting by length cannot be combined with recency_boost or score_mode".to_string(),
+        ));
+    }
+
+    if query.exclude_ids.len() > state.config.search_exclude_ids_max {
+        return Err(AppError::Validation(format!(
+            "exclude_ids cannot list more than {} IDs",
+            state.config.search_exclude_ids_max
+        )));
+    }
+    for (i, id) in query.exclude_ids.iter().enumerate() {
+        validate_document_id(id).map_err(|e| AppError::Validation(format!("exclude_ids[{}]: {}", i, e)))?;
+    }
+
+    if let Some(chars) = query.body_max_chars {
+        if chars == 0 || chars > MAX_PREVIEW_CHARS {
+            return Err(AppError::Validation(format!(
+                "body_max_chars must be between 1 and {}",
+                MAX_PREVIEW_CHARS
+            )));
+        }
+    }
+
+    // Columns hold only IDs, titles, scores and timestamps, so there is nowhere to put the rest
+    if query.format == SearchFormat::Columnar && (query.ids_only || query.highlight || query.return_matched_fields) {
+        return Err(AppError::Validation(
+            "format \"columnar\" cannot be combined with ids_only, highlight or return_matched_fields".to_string(),
+        ));
+    }
+
+    let timeout = match headers.get("X-Timeout-Ms") {
+        Some(value) => {
+            let ms: u64 = value
+                .to_str()
+                .ok()
+                .and_then(|v| v.trim().parse().ok())
+                .ok_or_else(|| {
+                    AppError::Validation("X-Timeout-Ms must be a non-negative integer".to_string())
+                })?;
+            Some(Duration::from_millis(ms.min(state.config.search_timeout_max_ms)))
+        }
+        None => None,
+    };
+
+    let detailed_timings = match headers.get("X-Timing") {
+        Some(value) if value.to_str().is_ok_and(|v| v.trim().eq_ignore_ascii_case("detailed")) => true,
+        Some(_) => return Err(AppError::Validation("X-Timing must be \"detailed\"".to_string())),
+        None => false,
+    };
+
+    let offset = query.offset;
+    let mut response = state
         .index_manager
-        .search(current_user.user_id, query)
+        .search_with_options(current_user.user_id, query, SearchOptions { timeout, detailed_timings })
         .await
         .map_err(|e| {
             error!(error = %e, "Search failed");
-            AppError::Search(format!("Search failed: {}", e))
+            AppError::from_index(e, |e| AppError::Search(format!("Search failed: {}", e)))
+        })?;
+    response.capped = capped;
+
+    // Logged before the empty-result check: zero-hit queries show where the corpus is thin
+    if let Some(query_log) = &state.query_log {
+        query_log.record(current_user.user_id, &response.query, response.matched, response.took_ms);
+    }
+
+    // A timed-out search may have stopped before reaching any match, so it is never a 404;
+    // a later page past the last match is an empty page, not a missing resource. Hits the
+    // byte budget trimmed away still matched, so only an empty page before the budget counts
+    if response.matched == 0 && !response.timed_out && offset == 0 {
+        reject_empty_result(&state.config, "No documents match the query")?;
+    }
+
+    Ok(Json(response))
+}
+
+/// Get summary analytics for the user's documents
+///
+/// GET /v1/analytics
+pub async fn get_analytics(
+    State(state): State<AppState>,
+    current_user: CurrentUser,
+) -> AppResult<impl IntoResponse> {
+    require_feature(&state, current_user.user_id, Feature::Analytics).await?;
+
+    info!(
+        user_id = %current_user.user_id,
+        "Computing analytics"
+    );
+
+    let analytics = state
+        .index_manager
+        .analytics(current_user.user_id)
+        .await
+        .map_err(|e| {
+            error!(error = %e, "Failed to compute analytics");
+            AppError::from_index(e, AppError::Internal)
+        })?;
+
+    Ok(Json(analytics))
+}
+
+/// List the most frequent terms of a field
+///
+/// GET /v1/terms/top?field=body&limit=20&stopwords=en
+///
+/// Ranks terms by the number of documents containing them. Stop words of
+/// `stopwords` (a language code, or `none`) are left out.
+pub async fn top_terms(
+    State(state): State<AppState>,
+    current_user: CurrentUser,
+    Query(query): Query<TopTermsQuery>,
+) -> AppResult<impl IntoResponse> {
+    require_feature(&state, current_user.user_id, Feature::Analytics).await?;
+
+    let limit = query.limit.unwrap_or(20);
+    let max_limit = state.config.top_terms_max_limit;
+    if limit == 0 || limit > max_limit {
+        return Err(AppError::Validation(format!("limit must be between 1 and {}", max_limit)));
+    }
+
+    let stop_words = match query.stopwords.as_deref().unwrap_or("en") {
+        "none" => None,
+        code => Some(StopWords::new(Language::from_code(code).ok_or_else(|| {
+            AppError::Validation(format!("Unknown stopwords language '{}'", code))
+        })?)),
+    };
+
+    let field = query.field.as_deref().unwrap_or("body");
+    let response = state
+        .index_manager
+        .top_terms(current_user.user_id, field, limit, stop_words)
+        .await
+        .map_err(|e| {
+            error!(error = %e, "Failed to list top terms");
+            AppError::from_index(e, AppError::Internal)
+        })?;
+
+    Ok(Json(response))
+}
+
+/// Count documents at each level of the user's hierarchical tags
+///
+/// GET /v1/tags/facets?path=finance/tax&depth=2
+///
+/// Tags are split into levels at `/`; a document counts toward every level
+/// above each of its tags, once per path.
+pub async fn tag_facets(
+    State(state): State<AppState>,
+    current_user: CurrentUser,
+    Query(query): Query<TagFacetsQuery>,
+) -> AppResult<impl IntoResponse> {
+    require_feature(&state, current_user.user_id, Feature::Facets).await?;
+
+    let depth = query.depth.unwrap_or(1);
+    if depth == 0 || depth > MAX_TAG_FACET_DEPTH {
+        return Err(AppError::Validation(format!("depth must be between 1 and {}", MAX_TAG_FACET_DEPTH)));
+    }
+    let prefix = query.path.as_deref().and_then(tag_facet);
+
+    let response = state
+        .index_manager
+        .tag_facets(current_user.user_id, prefix, depth)
+        .await
+        .map_err(|e| {
+            error!(error = %e, "Failed to count tag facets");
+            AppError::from_index(e, AppError::Internal)
+        })?;
+
+    Ok(Json(response))
+}
+
+/// Compare two documents or texts by their terms
+///
+/// POST /v1/similarity
+///
+/// Each side is a stored document (`id`) or inline `text`. Returns the cosine
+/// and Jaccard similarity of their term vectors, both from 0 to 1.
+pub async fn compare_similarity(
+    State(state): State<AppState>,
+    current_user: CurrentUser,
+    ApiJson(request): ApiJson<SimilarityRequest>,
+) -> AppResult<impl IntoResponse> {
+    let start = std::time::Instant::now();
+
+    let left = similarity_text(&state, current_user.user_id, "left", &request.left).await?;
+    let right = similarity_text(&state, current_user.user_id, "right", &request.right).await?;
+
+    // Compare by the terms this tenant's index would hold
+    let mut analyzer = state
+        .index_manager
+        .text_analyzer(current_user.user_id)
+        .await
+        .map_err(|e| {
+            error!(error = %e, "Failed to load analyzer for similarity");
+            AppError::from_index(e, AppError::Internal)
+        })?;
+    let left = similarity::term_vector(&mut analyzer, &left);
+    let right = similarity::term_vector(&mut analyzer, &right);
+
+    Ok(Json(SimilarityResponse {
+        cosine: similarity::cosine(&left, &right),
+        jaccard: similarity::jaccard(&left, &right),
+        left_terms: left.len(),
+        right_terms: right.len(),
+        took_ms: start.elapsed().as_millis() as u64,
+    }))
+}
+
+/// Text of one side of a similarity request, loading the document when given by ID
+async fn similarity_text(state: &AppState, user_id: Uuid, side: &str, target: &SimilarityTarget) -> AppResult<String> {
+    match (&target.id, &target.text) {
+        (Some(id), None) => {
+            validate_document_id(id).map_err(|e| AppError::Validation(format!("{}.id: {}", side, e)))?;
+            state
+                .index_manager
+                .document_text(user_id, id)
+                .await
+                .map_err(|e| {
+                    error!(error = %e, "Failed to load document for similarity");
+                    AppError::from_index(e, AppError::Internal)
+                })?
+                .ok_or_else(|| AppError::NotFound(format!("Document '{}' not found", id)))
+        }
+        (None, Some(text)) if text.trim().is_empty() => {
+            Err(AppError::Validation(format!("{}.text cannot be empty", side)))
+        }
+        (None, Some(text)) => Ok(text.clone()),
+        _ => Err(AppError::Validation(format!("{} needs exactly one of id or text", side))),
+    }
+}
+
+/// Find documents similar to a stored document
+///
+/// POST /v1/documents/:id/similar
+///
+/// Searches for the most significant terms of the document's body by tf-idf
+/// and returns the best matches, excluding the document itself.
+pub async fn similar_documents(
+    State(state): State<AppState>,
+    current_user: CurrentUser,
+    Path(id): Path<String>,
+    ApiJson(request): ApiJson<SimilarDocumentsRequest>,
+) -> AppResult<impl IntoResponse> {
+    validate_document_id(&id).map_err(AppError::Validation)?;
+
+    let limit = request.limit.unwrap_or(10);
+    if limit == 0 || limit > MAX_SEARCH_LIMIT {
+        return Err(AppError::Validation(format!("limit must be between 1 and {}", MAX_SEARCH_LIMIT)));
+    }
+    let max_query_terms = request.max_query_terms.unwrap_or(25);
+    if max_query_terms == 0 || max_query_terms > MAX_SIMILAR_QUERY_TERMS {
+        return Err(AppError::Validation(format!(
+            "max_query_terms must be between 1 and {}",
+            MAX_SIMILAR_QUERY_TERMS
+        )));
+    }
+    let min_term_freq = request.min_term_freq.unwrap_or(1);
+    if min_term_freq == 0 {
+        return Err(AppError::Validation("min_term_freq must be at least 1".to_string()));
+    }
+
+    let response = state
+        .index_manager
+        .similar_documents(current_user.user_id, &id, limit, max_query_terms, min_term_freq)
+        .await
+        .map_err(|e| {
+            error!(error = %e, "Failed to find similar documents");
+            AppError::from_index(e, AppError::Internal)
+        })?
+        .ok_or_else(|| AppError::NotFound(format!("Document '{}' not found", id)))?;
+
+    Ok(Json(response))
+}
+
+/// Validate a search query without executing it
+///
+/// POST /v1/query/validate
+///
+/// Parses the query and checks its filters against the user's index so
+/// query builders can give feedback while the user types. Invalid queries
+/// return 200 with `valid: false`.
+pub async fn validate_query(
+    State(state): State<AppState>,
+    current_user: CurrentUser,
+    ApiJson(query): ApiJson<SearchQuery>,
+) -> AppResult<impl IntoResponse> {
+    let rejected = if query.query.trim().is_empty() {
+        Some("Query cannot be empty")
+    } else {
+        filter_error(&query.filters)
+    };
+
+    if let Some(message) = rejected {
+        return Ok(Json(QueryValidationResponse {
+            valid: false,
+            error: Some(message.to_string()),
+            interpreted_fields: Vec::new(),
+        }));
+    }
+
+    let response = state
+        .index_manager
+        .validate_query(current_user.user_id, &query)
+        .await
+        .map_err(|e| {
+            error!(error = %e, "Query validation failed");
+            AppError::from_index(e, AppError::Internal)
+        })?;
+
+    Ok(Json(response))
+}
+
+/// Fail with 404 for an empty result when `EMPTY_RESULT_STATUS` asks for it
+fn reject_empty_result(config: &Config, message: &str) -> AppResult<()> {
+    if config.empty_result_status == 404 {
+        return Err(AppError::NotFound(message.to_string()));
+    }
+    Ok(())
+}
+
+/// Request-level filter checks shared by search and query validation
+fn filter_error(filters: &SearchFilters) -> Option<&'static str> {
+    if filters.id_prefix.as_deref().is_some_and(str::is_empty) {
+        return Some("id_prefix cannot be empty");
+    }
+
+    if filters.external_id.as_deref().is_some_and(|external_id| external_id.trim().is_empty()) {
+        return Some("external_id filter cannot be empty");
+    }
+
+    if filters.title_exact.as_deref().is_some_and(|title| title.trim().is_empty()) {
+        return Some("title_exact filter cannot be empty");
+    }
+
+    if filters.tags.values.iter().any(|tag| tag.trim().is_empty()) {
+        return Some("Tag filters cannot contain empty tags");
+    }
+
+    None
+}
+
+/// Open and warm the user's index ahead of use
+///
+/// POST /v1/index/warm
+///
+/// Lets a login flow hide the cost of opening a cold index by warming it
+/// while other work runs; no search is performed.
+pub async fn warm_index(
+    State(state): State<AppState>,
+    current_user: CurrentUser,
+) -> AppResult<impl IntoResponse> {
+    let response = state
+        .index_manager
+        .warm_index(current_user.user_id)
+        .await
+        .map_err(|e| {
+            error!(error = %e, "Failed to warm index");
+            AppError::from_index(e, AppError::Internal)
+        })?;
+
+    Ok(Json(response))
+}
+
+/// Wait for the user's queued writes and make them visible
+///
+/// POST /v1/index/flush
+///
+/// Lets a client that sent many writes concurrently make them all visible
+/// at once before it starts querying.
+pub async fn flush_index(
+    State(state): State<AppState>,
+    current_user: CurrentUser,
+) -> AppResult<impl IntoResponse> {
+    let response = state
+        .index_manager
+        .flush(current_user.user_id)
+        .await
+        .map_err(|e| {
+            error!(error = %e, "Failed to flush index");
+            AppError::from_index(e, AppError::Internal)
+        })?;
+
+    Ok(Json(response))
+}
+
+/// List the latest commits of the user's index
+///
+/// GET /v1/index/history?limit=20
+///
+/// Each commit carries the generation token its writes returned, so a client
+/// can see when a write became searchable. The history is in memory and
+/// starts empty after a restart.
+pub async fn index_history(
+    State(state): State<AppState>,
+    current_user: CurrentUser,
+    Query(query): Query<IndexHistoryQuery>,
+) -> AppResult<impl IntoResponse> {
+    let max_limit = state.config.index_history_max.max(1);
+    let limit = query.limit.unwrap_or(max_limit);
+    if limit == 0 || limit > max_limit {
+        return Err(AppError::Validation(format!("limit must be between 1 and {}", max_limit)));
+    }
+
+    let response = state
+        .index_manager
+        .history(current_user.user_id, limit)
+        .await
+        .map_err(|e| {
+            error!(error = %e, "Failed to list index history");
+            AppError::from_index(e, AppError::Internal)
+        })?;
+
+    Ok(Json(response))
+}
+
+/// Stream the user's indexed and deleted documents as Server-Sent Events
+///
+/// GET /v1/changes
+///
+/// Live only: each event is a change committed while the stream is open,
+/// and nothing is replayed after a reconnect. A subscriber that falls too
+/// far behind gets a `lagged` event counting the changes it missed.
+pub async fn stream_changes(
+    State(state): State<AppState>,
+    current_user: CurrentUser,
+) -> AppResult<impl IntoResponse> {
+    if state.config.changes_max_connections == 0 {
+        return Err(AppError::NotFound("Change streams are disabled".to_string()));
+    }
+
+    let subscription = state
+        .index_manager
+        .subscribe_changes(current_user.user_id)
+        .map_err(|e| AppError::RateLimited {
+            message: e.to_string(),
+            retry_after_secs: CHANGES_RETRY_AFTER_SECS,
+        })?;
+
+    // The stream owns the subscription, so a client disconnect unsubscribes
+    let events = futures_util::stream::unfold(subscription, |mut subscription| async move {
+        let event = match subscription.next().await? {
+            ChangeMessage::Change(change) => Event::default().event(change.kind.as_str()).json_data(&change),
+            ChangeMessage::Lagged(skipped) => {
+                Event::default().event("lagged").json_data(serde_json::json!({ "skipped": skipped }))
+            }
+        };
+        Some((event, subscription))
+    });
+
+    Ok(Sse::new(events).keep_alive(KeepAlive::default()))
+}
+
+/// Report whether the user's index needs optimizing
+///
+/// GET /v1/index/health
+///
+/// Lets a scheduler rebuild only the indexes whose segment count or share
+/// of deleted documents exceeds the configured thresholds.
+pub async fn index_health(
+    State(state): State<AppState>,
+    current_user: CurrentUser,
+) -> AppResult<impl IntoResponse> {
+    let response = state
+        .index_manager
+        .index_health(current_user.user_id)
+        .await
+        .map_err(|e| {
+            error!(error = %e, "Failed to check index health");
+            AppError::from_index(e, AppError::Internal)
+        })?;
+
+    Ok(Json(response))
+}
+
+/// Rebuild the user's index from its stored documents
+///
+/// POST /v1/reindex
+///
+/// Applies the current schema and analyzers to every document. Searches keep
+/// working during the rebuild; writes get a 503 with `Retry-After` until it ends.
+pub async fn reindex(
+    State(state): State<AppState>,
+    current_user: CurrentUser,
+) -> AppResult<impl IntoResponse> {
+    info!(user_id = %current_user.user_id, "Rebuilding index");
+
+    let response = state
+        .index_manager
+        .reindex(current_user.user_id)
+        .await
+        .map_err(|e| {
+            error!(error = %e, "Reindex failed");
+            AppError::from_index(e, AppError::Internal)
+        })?;
+
+    Ok(Json(response))
+}
+
+/// Show how text is tokenized by one of the user's analyzers
+///
+/// POST /v1/analyze
+///
+/// Takes `{text, field}` or `{text, analyzer}` and returns the token stream,
+/// for debugging why a document does or does not match a query.
+pub async fn analyze_text(
+    State(state): State<AppState>,
+    current_user: CurrentUser,
+    ApiJson(request): ApiJson<AnalyzeRequest>,
+) -> AppResult<impl IntoResponse> {
+    if request.field.is_some() == request.analyzer.is_some() {
+        return Err(AppError::Validation("Exactly one of field or analyzer is required".to_string()));
+    }
+
+    if request.text.chars().count() > MAX_ANALYZE_CHARS {
+        return Err(AppError::Validation(format!(
+            "text cannot exceed {} characters",
+            MAX_ANALYZE_CHARS
+        )));
+    }
+
+    let response = state
+        .index_manager
+        .analyze(current_user.user_id, &request)
+        .await
+        .map_err(|e| {
+            error!(error = %e, "Failed to analyze text");
+            AppError::from_index(e, AppError::Internal)
         })?;
 
     Ok(Json(response))
@@ -152,10 +1046,12 @@ pub async fn get_stats(
     State(state): State<AppState>,
     current_user: CurrentUser,
 ) -> AppResult<impl IntoResponse> {
-    info!(
-        user_id = %current_user.user_id,
-        "Getting user stats"
-    );
+    if logging::sample("stats_request") {
+        info!(
+            user_id = %current_user.user_id,
+            "Getting user stats"
+        );
+    }
 
     let stats = state
         .index_manager
@@ -163,7 +1059,7 @@ pub async fn get_stats(
         .await
         .map_err(|e| {
             error!(error = %e, "Failed to get stats");
-            AppError::Internal(e)
+            AppError::from_index(e, AppError::Internal)
         })?;
 
     #[derive(serde::Serialize)]
@@ -188,21 +1084,34 @@ pub async fn get_stats(
 pub async fn browse_documents(
     State(state): State<AppState>,
     current_user: CurrentUser,
-    Json(query): Json<BrowseDocumentsQuery>,
+    ApiJson(query): ApiJson<BrowseDocumentsQuery>,
 ) -> AppResult<impl IntoResponse> {
-    info!(
-        user_id = %current_user.user_id,
-        limit = query.limit,
-        offset = query.offset,
-        "Browsing documents"
-    );
+    require_feature(&state, current_user.user_id, Feature::Browse).await?;
+
+    if logging::sample("browse_request") {
+        info!(
+            user_id = %current_user.user_id,
+            limit = query.limit,
+            offset = query.offset,
+            "Browsing documents"
+        );
+    }
 
     if query.limit == 0 {
         return Err(AppError::Validation("Limit must be greater than 0".to_string()));
     }
 
-    if query.limit > 1000 {
-        return Err(AppError::Validation("Limit cannot exceed 1000".to_string()));
+    if query.limit > MAX_BROWSE_LIMIT {
+        return Err(AppError::Validation(format!("Limit cannot exceed {}", MAX_BROWSE_LIMIT)));
+    }
+
+    // Browse has no query to narrow, so point clients at filtered search instead
+    if query.offset > state.config.max_result_offset {
+        return Err(AppError::Validation(format!(
+            "Offset cannot exceed {}; browse is not meant for full dumps, \
+             use /v1/search with filters (tags, source, id_prefix) to reach specific documents",
+            state.config.max_result_offset
+        )));
     }
 
     let response = state
@@ -211,12 +1120,178 @@ pub async fn browse_documents(
         .await
         .map_err(|e| {
             error!(error = %e, "Browse failed");
-            AppError::Internal(e)
+            AppError::from_index(e, AppError::Internal)
         })?;
 
+    // Only an empty index is a 404; a page past the last document is an empty page, as in search
+    if response.total == 0 {
+        reject_empty_result(&state.config, "No documents found")?;
+    }
+
     Ok(Json(response))
 }
 
+/// Check and repair a user's index (admin)
+///
+/// POST /v1/admin/repair/{user_id}
+///
+/// Drops segments that fail to open so the index can be served again. Returns
+/// the repair report; an unrecoverable index is reported with status 500.
+pub async fn repair_index(
+    State(state): State<AppState>,
+    _admin: AdminAuth,
+    Path(user_id): Path<Uuid>,
+) -> AppResult<impl IntoResponse> {
+    warn!(user_id = %user_id, "Admin index repair requested");
+
+    let report = state
+        .index_manager
+        .repair_index(user_id)
+        .await
+        .map_err(|e| {
+            error!(error = %e, "Index repair failed");
+            AppError::from_index(e, |e| AppError::Index(format!("Index repair failed: {}", e)))
+        })?;
+
+    let status = if report.status == "unrecoverable" {
+        StatusCode::INTERNAL_SERVER_ERROR
+    } else {
+        StatusCode::OK
+    };
+
+    Ok((status, Json(report)))
+}
+
+/// Get index statistics for several users (admin)
+///
+/// POST /v1/admin/stats
+///
+/// Users are read a few at a time. One whose index fails to open gets an
+/// `error` entry instead of failing the whole request.
+pub async fn admin_stats(
+    State(state): State<AppState>,
+    _admin: AdminAuth,
+    ApiJson(request): ApiJson<AdminStatsRequest>,
+) -> AppResult<impl IntoResponse> {
+    let max_users = state.config.admin_stats_max_users;
+    if request.user_ids.is_empty() || request.user_ids.len() > max_users {
+        return Err(AppError::Validation(format!("user_ids must list between 1 and {} users", max_users)));
+    }
+
+    let stats = state
+        .index_manager
+        .get_users_stats(&request.user_ids, ADMIN_STATS_CONCURRENCY)
+        .await;
+
+    let users = request
+        .user_ids
+        .iter()
+        .zip(stats)
+        .map(|(user_id, stats)| match stats {
+            Ok(stats) => AdminUserStats {
+                user_id: user_id.to_string(),
+                num_documents: Some(stats.num_documents),
+                disk_bytes: Some(stats.disk_bytes),
+                num_segments: Some(stats.num_segments),
+                error: None,
+            },
+            Err(e) => {
+                warn!(user_id = %user_id, error = %e, "Failed to get stats");
+                AdminUserStats {
+                    user_id: user_id.to_string(),
+                    num_documents: None,
+                    disk_bytes: None,
+                    num_segments: None,
+                    error: Some(e.to_string()),
+                }
+            }
+        })
+        .collect();
+
+    Ok(Json(AdminStatsResponse { users }))
+}
+
+/// List users whose indexes are pinned in the cache (admin)
+///
+/// GET /v1/admin/pins
+pub async fn list_pinned_indexes(
+    State(state): State<AppState>,
+    _admin: AdminAuth,
+) -> AppResult<impl IntoResponse> {
+    let pinned = state
+        .index_manager
+        .pinned_indexes()
+        .iter()
+        .map(Uuid::to_string)
+        .collect();
+
+    Ok(Json(PinnedIndexesResponse { pinned }))
+}
+
+/// Pin a user's index so it is never evicted from the cache (admin)
+///
+/// PUT /v1/admin/pins/{user_id}
+///
+/// Runtime pins are not persisted; use `PINNED_USERS` for pins that survive restarts.
+pub async fn pin_index(
+    State(state): State<AppState>,
+    _admin: AdminAuth,
+    Path(user_id): Path<Uuid>,
+) -> AppResult<impl IntoResponse> {
+    info!(user_id = %user_id, "Admin index pin requested");
+
+    let changed = state.index_manager.pin_index(user_id);
+
+    Ok(Json(PinIndexResponse {
+        user_id: user_id.to_string(),
+        pinned: true,
+        changed,
+    }))
+}
+
+/// Unpin a user's index, making it evictable again (admin)
+///
+/// DELETE /v1/admin/pins/{user_id}
+pub async fn unpin_index(
+    State(state): State<AppState>,
+    _admin: AdminAuth,
+    Path(user_id): Path<Uuid>,
+) -> AppResult<impl IntoResponse> {
+    info!(user_id = %user_id, "Admin index unpin requested");
+
+    let changed = state.index_manager.unpin_index(user_id).await;
+
+    Ok(Json(PinIndexResponse {
+        user_id: user_id.to_string(),
+        pinned: false,
+        changed,
+    }))
+}
+
+/// Reject the request with 403 when the user's config disables `feature`
+async fn require_feature(state: &AppState, user_id: Uuid, feature: Feature) -> AppResult<()> {
+    let enabled = state
+        .index_manager
+        .feature_enabled(user_id, feature)
+        .await
+        .map_err(|e| AppError::from_index(e, AppError::Internal))?;
+    if !enabled {
+        return Err(AppError::FeatureDisabled(feature.as_str().to_string()));
+    }
+    Ok(())
+}
+
+/// Reject offsets deep enough to make the collector materialize huge result sets
+fn validate_offset(offset: usize, max_offset: usize) -> AppResult<()> {
+    if offset > max_offset {
+        return Err(AppError::Validation(format!(
+            "Offset cannot exceed {}; narrow the query or add filters to reach deeper results",
+            max_offset
+        )));
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -227,6 +1302,8 @@ mod tests {
         let index_manager = Arc::new(IndexManager::new(temp_dir.path().to_path_buf()));
         let state = AppState {
             index_manager: index_manager.clone(),
+            config: Arc::new(Config::default()),
+            query_log: None,
         };
 
         let cloned = state.clone();
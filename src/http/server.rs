@@ -0,0 +1,104 @@
+//! HTTP connection handling
+//!
+//! `axum::serve` accepts HTTP/1.1 only and offers no connection settings, so
+//! connections are served with hyper's automatic builder instead. HTTP/1.1 is
+//! always accepted; with `HTTP2_ENABLED`, a client that opens the connection
+//! with the HTTP/2 preface (h2c with prior knowledge) is served over HTTP/2.
+//! There is no TLS here: HTTP/2 over TLS is negotiated by the proxy that
+//! terminates it, which can then speak h2c to this service.
+
+use std::io;
+use std::time::Duration;
+
+use axum::Router;
+use hyper_util::rt::{TokioExecutor, TokioIo, TokioTimer};
+use hyper_util::server::conn::auto::Builder;
+use hyper_util::service::TowerToHyperService;
+use tokio::net::TcpListener;
+use tracing::{debug, error};
+
+use crate::config::Config;
+
+/// Connection settings taken from the configuration
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServerOptions {
+    /// Accept HTTP/2 connections next to HTTP/1.1
+    pub http2: bool,
+
+    /// Keep HTTP/1.1 connections open for further requests
+    pub http1_keep_alive: bool,
+
+    /// Interval of HTTP/2 keep-alive pings; `None` sends none
+    pub http2_keep_alive_interval: Option<Duration>,
+
+    /// Wait for a ping acknowledgement before the HTTP/2 connection is closed
+    pub http2_keep_alive_timeout: Duration,
+
+    /// Requests one HTTP/2 connection may have in flight at once
+    pub http2_max_concurrent_streams: u32,
+}
+
+impl ServerOptions {
+    pub fn from_config(config: &Config) -> Self {
+        ServerOptions {
+            http2: config.http2_enabled,
+            http1_keep_alive: config.http1_keep_alive,
+            http2_keep_alive_interval: (config.http2_keep_alive_interval_secs > 0)
+                .then(|| Duration::from_secs(config.http2_keep_alive_interval_secs)),
+            http2_keep_alive_timeout: Duration::from_secs(config.http2_keep_alive_timeout_secs),
+            http2_max_concurrent_streams: config.http2_max_concurrent_streams,
+        }
+    }
+
+    fn builder(&self) -> Builder<TokioExecutor> {
+        let mut builder = Builder::new(TokioExecutor::new());
+        builder.http1().keep_alive(self.http1_keep_alive);
+        builder
+            .http2()
+            .timer(TokioTimer::new())
+            .keep_alive_interval(self.http2_keep_alive_interval)
+            .keep_alive_timeout(self.http2_keep_alive_timeout)
+            .max_concurrent_streams(self.http2_max_concurrent_streams);
+        if self.http2 {
+            builder
+        } else {
+            builder.http1_only()
+        }
+    }
+}
+
+/// Serve `app` on every connection accepted from `listener`
+///
+/// Runs until the process stops. Each connection is served on its own task;
+/// a connection that fails only ends that connection.
+pub async fn serve(listener: TcpListener, app: Router, options: &ServerOptions) -> io::Result<()> {
+    let builder = options.builder();
+    loop {
+        let (stream, remote_addr) = match listener.accept().await {
+            Ok(connection) => connection,
+            // The client gave up before the connection was accepted
+            Err(e) if is_connection_error(&e) => continue,
+            Err(e) => {
+                // Typically out of file descriptors; give open connections a moment to close
+                error!(error = %e, "Failed to accept connection");
+                tokio::time::sleep(Duration::from_secs(1)).await;
+                continue;
+            }
+        };
+
+        let builder = builder.clone();
+        let service = TowerToHyperService::new(app.clone());
+        tokio::spawn(async move {
+            if let Err(e) = builder.serve_connection(TokioIo::new(stream), service).await {
+                debug!(remote_addr = %remote_addr, error = %e, "Connection ended with an error");
+            }
+        });
+    }
+}
+
+fn is_connection_error(e: &io::Error) -> bool {
+    matches!(
+        e.kind(),
+        io::ErrorKind::ConnectionRefused | io::ErrorKind::ConnectionAborted | io::ErrorKind::ConnectionReset
+    )
+}
@@ -1,15 +1,168 @@
 use axum::{
-    http::StatusCode,
-    response::{Html, IntoResponse},
+    extract::{Query, State},
+    http::{header, StatusCode},
+    response::{Html, IntoResponse, Response},
 };
+use hmac::{Hmac, Mac};
+use serde::Deserialize;
+use sha2::Sha256;
+use std::fmt;
+use std::str::FromStr;
+use tracing::{debug, warn};
+use uuid::Uuid;
+
+use super::routes::AppState;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Shortest accepted `WEB_UI_TOKEN_KEY`, in bytes
+pub const MIN_TOKEN_KEY_BYTES: usize = 32;
+
+/// How far in the future a token's issue time may be, to absorb clock drift between hosts
+const CLOCK_SKEW_SECS: i64 = 60;
+
+/// Placeholder in the page replaced with the user ID of a verified token
+const PRESET_USER_ID: &str = "__PRESET_USER_ID__";
+
+/// Secret shared with the backend that signs web UI links
+#[derive(Clone, PartialEq, Eq)]
+pub struct UiTokenKey(Vec<u8>);
+
+impl FromStr for UiTokenKey {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.len() < MIN_TOKEN_KEY_BYTES {
+            return Err(format!("must be at least {} bytes", MIN_TOKEN_KEY_BYTES));
+        }
+        Ok(UiTokenKey(s.as_bytes().to_vec()))
+    }
+}
+
+impl fmt::Debug for UiTokenKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("UiTokenKey(<redacted>)")
+    }
+}
+
+impl UiTokenKey {
+    fn mac(&self, user_id: Uuid, issued_at: i64) -> HmacSha256 {
+        let mut mac = HmacSha256::new_from_slice(&self.0).expect("HMAC accepts any key length");
+        mac.update(format!("{}.{}", user_id, issued_at).as_bytes());
+        mac
+    }
+
+    /// Sign a link token for `user_id`, issued at `issued_at` (Unix seconds)
+    ///
+    /// The token is `<user_id>.<issued_at>.<signature>`, where the signature
+    /// is the lowercase hex HMAC-SHA256 of `<user_id>.<issued_at>`.
+    pub fn sign(&self, user_id: Uuid, issued_at: i64) -> String {
+        let signature: String = self
+            .mac(user_id, issued_at)
+            .finalize()
+            .into_bytes()
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect();
+        format!("{}.{}.{}", user_id, issued_at, signature)
+    }
+
+    /// User ID of a token signed with this key and issued at most `ttl_secs` before `now`
+    pub fn verify(&self, token: &str, ttl_secs: u64, now: i64) -> Result<Uuid, &'static str> {
+        let mut parts = token.split('.');
+        let (Some(user_id), Some(issued_at), Some(signature), None) =
+            (parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+            return Err("malformed token");
+        };
+        let user_id = Uuid::parse_str(user_id).map_err(|_| "malformed token")?;
+        let issued_at: i64 = issued_at.parse().map_err(|_| "malformed token")?;
+        let signature = decode_hex(signature).ok_or("malformed token")?;
+
+        self.mac(user_id, issued_at)
+            .verify_slice(&signature)
+            .map_err(|_| "bad signature")?;
+
+        if issued_at > now + CLOCK_SKEW_SECS {
+            return Err("issued in the future");
+        }
+        if now.saturating_sub(issued_at) > ttl_secs as i64 {
+            return Err("expired");
+        }
+        Ok(user_id)
+    }
+}
+
+fn decode_hex(s: &str) -> Option<Vec<u8>> {
+    // `from_str_radix` alone would also accept a sign, such as "+f"
+    if s.len() % 2 != 0 || !s.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&s[i..i + 2], 16).ok())
+        .collect()
+}
+
+/// Query string of the web UI page
+#[derive(Debug, Deserialize)]
+pub struct UiQuery {
+    /// Signed link token naming the user to prefill
+    pub token: Option<String>,
+}
 
 /// Serve the web UI HTML page
 ///
 /// GET /ui
-pub async fn serve_ui() -> impl IntoResponse {
-    (StatusCode::OK, Html(HTML_CONTENT))
+///
+/// With `?token=`, the token is verified and its user ID is filled into the
+/// page's user fields; an invalid or expired token gets a 401 page instead.
+pub async fn serve_ui(State(state): State<AppState>, Query(query): Query<UiQuery>) -> Response {
+    let Some(token) = query.token else {
+        return (StatusCode::OK, Html(HTML_CONTENT.replace(PRESET_USER_ID, ""))).into_response();
+    };
+
+    let verified = match &state.config.web_ui_token_key {
+        Some(key) => key.verify(&token, state.config.web_ui_token_ttl_secs, chrono::Utc::now().timestamp()),
+        None => Err("WEB_UI_TOKEN_KEY is not set"),
+    };
+
+    match verified {
+        Ok(user_id) => (
+            StatusCode::OK,
+            // Keep the token out of caches and Referer headers of outgoing requests
+            [(header::CACHE_CONTROL, "no-store"), (header::REFERRER_POLICY, "no-referrer")],
+            Html(HTML_CONTENT.replace(PRESET_USER_ID, &user_id.to_string())),
+        )
+            .into_response(),
+        Err(reason) => {
+            if state.config.web_ui_token_key.is_none() {
+                warn!("Rejected web UI token: {}", reason);
+            } else {
+                debug!(reason = reason, "Rejected web UI token");
+            }
+            (
+                StatusCode::UNAUTHORIZED,
+                [(header::CACHE_CONTROL, "no-store")],
+                Html(INVALID_TOKEN_CONTENT),
+            )
+                .into_response()
+        }
+    }
 }
 
+const INVALID_TOKEN_CONTENT: &str = r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta charset="UTF-8">
+    <title>Tax2Go Search - Web UI</title>
+</head>
+<body>
+    <p>This link is invalid or has expired. Ask for a new one.</p>
+</body>
+</html>
+"#;
+
 const HTML_CONTENT: &str = r#"<!DOCTYPE html>
 <html lang="en">
 <head>
@@ -409,7 +562,10 @@ const HTML_CONTENT: &str = r#"<!DOCTYPE html>
         // Browse Documents
         document.getElementById('browseForm').addEventListener('submit', async (e) => {
             e.preventDefault();
+            await browsePage(0);
+        });
 
+        async function browsePage(offset) {
             const userId = document.getElementById('browseUserId').value.trim();
             const limit = parseInt(document.getElementById('browseLimit').value);
 
@@ -422,7 +578,7 @@ const HTML_CONTENT: &str = r#"<!DOCTYPE html>
                     },
                     body: JSON.stringify({
                         limit,
-                        offset: 0
+                        offset
                     })
                 });
 
@@ -433,11 +589,13 @@ const HTML_CONTENT: &str = r#"<!DOCTYPE html>
 
                 const result = await response.json();
                 displayBrowseResults(result);
-                showMessage(`Loaded ${result.total} document(s) in ${result.took_ms}ms`, 'success');
+                const shown = result.documents.length;
+                const range = shown > 0 ? `${result.offset + 1}-${result.offset + shown}` : 'none';
+                showMessage(`Showing ${range} of ${result.total} document(s) in ${result.took_ms}ms`, 'success');
             } catch (error) {
                 showMessage(`Error: ${error.message}`, 'error');
             }
-        });
+        }
 
         // Delete Document
         document.getElementById('deleteForm').addEventListener('submit', async (e) => {
@@ -497,11 +655,22 @@ const HTML_CONTENT: &str = r#"<!DOCTYPE html>
             const resultsEl = document.getElementById('results');
 
             if (!result.documents || result.documents.length === 0) {
-                resultsEl.innerHTML = '<div class="no-results">No documents found in this index.</div>';
+                const message = result.total > 0
+                    ? `No documents on this page; the index has ${result.total} document(s).`
+                    : 'No documents found in this index.';
+                resultsEl.innerHTML = `<div class="no-results">${message}</div>`;
                 return;
             }
 
-            resultsEl.innerHTML = result.documents.map(doc => `
+            const pager = `
+                <div class="result-meta" style="margin-bottom: 15px;">
+                    <span>Documents ${result.offset + 1}-${result.offset + result.documents.length} of ${result.total}</span>
+                    ${result.offset > 0 ? `<button type="button" onclick="browsePage(${Math.max(0, result.offset - result.limit)})">Previous</button>` : ''}
+                    ${result.has_more ? `<button type="button" onclick="browsePage(${result.offset + result.limit})">Next</button>` : ''}
+                </div>
+            `;
+
+            resultsEl.innerHTML = pager + result.documents.map(doc => `
                 <div class="result-item">
                     <div class="result-title">${escapeHtml(doc.title)}</div>
                     <div class="result-body" style="white-space: pre-wrap;">${escapeHtml(doc.body)}</div>
@@ -526,7 +695,62 @@ const HTML_CONTENT: &str = r#"<!DOCTYPE html>
             document.getElementById('browseUserId').value = e.target.value;
             document.getElementById('deleteUserId').value = e.target.value;
         });
+
+        // Prefill the user from a signed link (filled in by the server)
+        const presetUserId = '__PRESET_USER_ID__';
+        if (presetUserId) {
+            for (const id of ['userId', 'searchUserId', 'browseUserId', 'deleteUserId']) {
+                document.getElementById(id).value = presetUserId;
+            }
+        }
     </script>
 </body>
 </html>
 "#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key() -> UiTokenKey {
+        "0123456789abcdef0123456789abcdef".parse().unwrap()
+    }
+
+    #[test]
+    fn test_ui_token_valid_and_expired() {
+        let user_id = Uuid::new_v4();
+        let issued_at = 1_700_000_000;
+        let token = key().sign(user_id, issued_at);
+
+        assert_eq!(key().verify(&token, 900, issued_at + 60), Ok(user_id));
+        assert_eq!(key().verify(&token, 900, issued_at + 900), Ok(user_id));
+        assert_eq!(key().verify(&token, 900, issued_at + 901), Err("expired"));
+        assert_eq!(key().verify(&token, 900, issued_at - 3600), Err("issued in the future"));
+    }
+
+    #[test]
+    fn test_ui_token_rejects_tampering() {
+        let user_id = Uuid::new_v4();
+        let token = key().sign(user_id, 1_700_000_000);
+
+        // Another user's ID with the original signature
+        let (_, rest) = token.split_once('.').unwrap();
+        let forged = format!("{}.{}", Uuid::new_v4(), rest);
+        assert_eq!(key().verify(&forged, 900, 1_700_000_000), Err("bad signature"));
+
+        let other_key: UiTokenKey = "fedcba9876543210fedcba9876543210".parse().unwrap();
+        assert_eq!(other_key.verify(&token, 900, 1_700_000_000), Err("bad signature"));
+
+        assert_eq!(key().verify("not-a-token", 900, 1_700_000_000), Err("malformed token"));
+
+        // A signature byte written as "+f" instead of "0f" is not the same signature
+        let (issued_at, token) = (1_700_000_000..)
+            .map(|issued_at| (issued_at, key().sign(user_id, issued_at)))
+            .find(|(_, token)| token.rsplit('.').next().unwrap().starts_with('0'))
+            .unwrap();
+        let (signed, signature) = token.rsplit_once('.').unwrap();
+        let signed_with_sign = format!("{}.+{}", signed, &signature[1..]);
+        assert_eq!(key().verify(&signed_with_sign, 900, issued_at), Err("malformed token"));
+        assert!("too short".parse::<UiTokenKey>().is_err());
+    }
+}
@@ -2,4 +2,7 @@
 
 pub mod config;
 pub mod http;
+pub mod logging;
+pub mod query_log;
 pub mod search;
+pub mod selftest;
@@ -0,0 +1,234 @@
+//! Sampling for high-frequency log lines
+//!
+//! Per-request and per-search logs are useful at low volume but flood the log
+//! pipeline under load. Call sites on hot paths ask the sampler whether to
+//! emit; each event kind may log at most `LOG_SAMPLE_PER_SEC` lines per
+//! second, and everything beyond that is folded into a periodic summary line
+//! with counts and latency percentiles. Warnings and errors are never sampled.
+
+use std::collections::BTreeMap;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use tracing::info;
+
+use crate::config::Config;
+
+/// Latencies kept per kind and summary interval, bounding memory under load
+const MAX_LATENCY_SAMPLES: usize = 10_000;
+
+/// How often the summary timer checks whether an interval has ended
+const SUMMARY_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+static SAMPLER: OnceLock<LogSampler> = OnceLock::new();
+
+/// Install the process-wide sampler from the configuration
+///
+/// Until this is called (e.g. in tests) every event is logged.
+pub fn init_sampler(config: &Config) {
+    let _ = SAMPLER.set(LogSampler::new(
+        config.log_sample_per_sec,
+        Duration::from_secs(config.log_summary_interval_secs),
+    ));
+}
+
+/// Emit summaries from a background task, so the last interval before a
+/// quiet period is reported without waiting for the next event
+///
+/// Does nothing until [`init_sampler`] has installed a sampler. Must be
+/// called from within a Tokio runtime.
+pub fn spawn_summary_timer() {
+    let Some(sampler) = SAMPLER.get() else {
+        return;
+    };
+    if sampler.per_second == 0 {
+        return;
+    }
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(SUMMARY_CHECK_INTERVAL);
+        loop {
+            ticker.tick().await;
+            sampler.flush(Instant::now());
+        }
+    });
+}
+
+/// Whether a hot-path log line of this kind should be emitted
+pub fn sample(kind: &'static str) -> bool {
+    sample_with_latency(kind, None)
+}
+
+/// Like [`sample`], also recording the operation's latency for the summary
+pub fn sample_with_latency(kind: &'static str, latency_ms: Option<u64>) -> bool {
+    match SAMPLER.get() {
+        Some(sampler) => sampler.sample(kind, latency_ms),
+        None => true,
+    }
+}
+
+/// Counts and latencies for one event kind within a summary interval
+#[derive(Debug, Default)]
+struct KindStats {
+    /// Events seen in the interval
+    total: u64,
+    /// Events not logged because the per-second budget was spent
+    suppressed: u64,
+    /// Events logged in the current second
+    emitted_this_second: u32,
+    latencies_ms: Vec<u64>,
+}
+
+#[derive(Debug)]
+struct SamplerState {
+    second_start: Instant,
+    interval_start: Instant,
+    kinds: BTreeMap<&'static str, KindStats>,
+}
+
+/// Per-kind rate limiter for log lines with periodic summaries
+#[derive(Debug)]
+pub struct LogSampler {
+    /// Lines per kind per second; 0 disables sampling
+    per_second: u32,
+    summary_interval: Duration,
+    state: Mutex<SamplerState>,
+}
+
+impl LogSampler {
+    pub fn new(per_second: u32, summary_interval: Duration) -> Self {
+        let now = Instant::now();
+        LogSampler {
+            per_second,
+            summary_interval,
+            state: Mutex::new(SamplerState {
+                second_start: now,
+                interval_start: now,
+                kinds: BTreeMap::new(),
+            }),
+        }
+    }
+
+    /// Record an event and decide whether it is logged
+    pub fn sample(&self, kind: &'static str, latency_ms: Option<u64>) -> bool {
+        if self.per_second == 0 {
+            return true;
+        }
+
+        let now = Instant::now();
+        let mut state = self.state.lock().unwrap();
+
+        self.end_interval_if_due(&mut state, now);
+        if now.duration_since(state.second_start) >= Duration::from_secs(1) {
+            state.second_start = now;
+            for stats in state.kinds.values_mut() {
+                stats.emitted_this_second = 0;
+            }
+        }
+
+        let stats = state.kinds.entry(kind).or_default();
+        stats.total += 1;
+        if let Some(latency_ms) = latency_ms {
+            if stats.latencies_ms.len() < MAX_LATENCY_SAMPLES {
+                stats.latencies_ms.push(latency_ms);
+            }
+        }
+
+        if stats.emitted_this_second < self.per_second {
+            stats.emitted_this_second += 1;
+            true
+        } else {
+            stats.suppressed += 1;
+            false
+        }
+    }
+
+    /// Emit and reset the summary if the interval has ended by `now`
+    pub fn flush(&self, now: Instant) {
+        self.end_interval_if_due(&mut self.state.lock().unwrap(), now);
+    }
+
+    fn end_interval_if_due(&self, state: &mut SamplerState, now: Instant) {
+        if now.duration_since(state.interval_start) >= self.summary_interval {
+            emit_summary(&state.kinds, now.duration_since(state.interval_start));
+            state.kinds.clear();
+            state.interval_start = now;
+        }
+    }
+}
+
+/// Log one summary line per kind that had suppressed events
+fn emit_summary(kinds: &BTreeMap<&'static str, KindStats>, elapsed: Duration) {
+    for (kind, stats) in kinds.iter().filter(|(_, stats)| stats.suppressed > 0) {
+        let mut latencies = stats.latencies_ms.clone();
+        latencies.sort_unstable();
+        info!(
+            kind = kind,
+            total = stats.total,
+            suppressed = stats.suppressed,
+            interval_secs = elapsed.as_secs(),
+            p50_ms = percentile(&latencies, 50),
+            p99_ms = percentile(&latencies, 99),
+            "Log sampling summary"
+        );
+    }
+}
+
+/// Nearest-rank percentile of sorted values
+fn percentile(sorted: &[u64], pct: usize) -> Option<u64> {
+    if sorted.is_empty() {
+        return None;
+    }
+    let rank = (sorted.len() * pct).div_ceil(100).max(1);
+    Some(sorted[rank - 1])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sampler_limits_per_kind() {
+        let sampler = LogSampler::new(3, Duration::from_secs(60));
+
+        let emitted = (0..10).filter(|_| sampler.sample("search", Some(5))).count();
+        assert_eq!(emitted, 3);
+        // Kinds have independent budgets
+        assert!(sampler.sample("index", None));
+
+        let state = sampler.state.lock().unwrap();
+        assert_eq!(state.kinds["search"].total, 10);
+        assert_eq!(state.kinds["search"].suppressed, 7);
+        assert_eq!(state.kinds["search"].latencies_ms.len(), 10);
+    }
+
+    #[test]
+    fn test_flush_ends_interval_without_events() {
+        let sampler = LogSampler::new(1, Duration::from_secs(60));
+        let start = sampler.state.lock().unwrap().interval_start;
+        for _ in 0..3 {
+            sampler.sample("search", Some(5));
+        }
+
+        sampler.flush(start + Duration::from_secs(59));
+        assert_eq!(sampler.state.lock().unwrap().kinds["search"].suppressed, 2);
+
+        sampler.flush(start + Duration::from_secs(60));
+        let state = sampler.state.lock().unwrap();
+        assert!(state.kinds.is_empty());
+        assert_eq!(state.interval_start, start + Duration::from_secs(60));
+    }
+
+    #[test]
+    fn test_sampling_disabled() {
+        let sampler = LogSampler::new(0, Duration::from_secs(60));
+        assert!((0..1000).all(|_| sampler.sample("search", None)));
+    }
+
+    #[test]
+    fn test_percentile() {
+        let values: Vec<u64> = (1..=100).collect();
+        assert_eq!(percentile(&values, 50), Some(50));
+        assert_eq!(percentile(&values, 99), Some(99));
+        assert_eq!(percentile(&[7], 99), Some(7));
+        assert_eq!(percentile(&[], 50), None);
+    }
+}
@@ -1,15 +1,20 @@
 mod config;
 mod http;
+mod logging;
+mod query_log;
 mod search;
+mod selftest;
 
 use anyhow::{Context, Result};
 use std::sync::Arc;
-use tracing::info;
-use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
+use tracing::{error, info, warn};
+use tracing_subscriber::{fmt::format::FmtSpan, layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
 
 use crate::config::Config;
 use crate::http::build_router;
 use crate::http::routes::AppState;
+use crate::http::server::{self, ServerOptions};
+use crate::query_log::QueryLog;
 use crate::search::IndexManager;
 
 #[tokio::main]
@@ -17,8 +22,25 @@ async fn main() -> Result<()> {
     // Load configuration
     let config = Config::from_env().context("Failed to load configuration")?;
 
+    // `--ui-token <user_id>` prints a signed web UI link token and exits,
+    // before logging starts so stdout holds only the token
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Some(pos) = args.iter().position(|arg| arg == "--ui-token") {
+        let user_id = args
+            .get(pos + 1)
+            .context("--ui-token requires a user ID")?
+            .parse()
+            .context("--ui-token requires a valid UUID")?;
+        let key = config
+            .web_ui_token_key
+            .as_ref()
+            .context("WEB_UI_TOKEN_KEY must be set to issue web UI tokens")?;
+        println!("{}", key.sign(user_id, chrono::Utc::now().timestamp()));
+        return Ok(());
+    }
+
     // Initialize tracing/logging
-    init_tracing(&config.log_level)?;
+    init_tracing(&config.log_level, config.log_span_close)?;
 
     info!("Starting tax2go-search service");
     info!("Version: {}", env!("CARGO_PKG_VERSION"));
@@ -27,13 +49,44 @@ async fn main() -> Result<()> {
 
     // Validate configuration
     config.validate().context("Configuration validation failed")?;
+    logging::init_sampler(&config);
+    logging::spawn_summary_timer();
+
+    // `--selftest` runs the round trip and exits; SELFTEST_ON_START runs it before serving
+    let selftest_only = std::env::args().skip(1).any(|arg| arg == "--selftest");
+    if selftest_only || config.selftest_on_start {
+        if let Err(e) = selftest::run(&config).await {
+            error!("Self-test failed: {:#}", e);
+            std::process::exit(1);
+        }
+        info!("Self-test passed");
+
+        if selftest_only {
+            return Ok(());
+        }
+    }
+
+    if let Some(user_id) = config.single_tenant_user_id {
+        warn!(user_id = %user_id, "Single-tenant mode: requests without X-User-Id act as this user");
+    }
 
     // Initialize index manager
-    let index_manager = Arc::new(IndexManager::new(config.data_dir.clone()));
+    let index_manager = Arc::new(IndexManager::from_config(&config));
     info!("Index manager initialized");
 
+    let query_log = QueryLog::from_config(&config)
+        .context("Failed to open query log")?
+        .map(Arc::new);
+    if query_log.is_some() {
+        info!("Query log enabled at {:?}", config.query_log_path);
+    }
+
     // Build application state
-    let state = AppState { index_manager };
+    let state = AppState {
+        index_manager,
+        config: Arc::new(config.clone()),
+        query_log,
+    };
 
     // Build router
     let app = build_router(state, config.web_ui_enabled);
@@ -51,8 +104,12 @@ async fn main() -> Result<()> {
     info!("Health check available at http://{}/health", config.bind_addr);
     info!("API endpoints available at http://{}/v1/*", config.bind_addr);
 
+    if config.http2_enabled {
+        info!("HTTP/2 enabled (h2c with prior knowledge)");
+    }
+
     // Start server
-    axum::serve(listener, app)
+    server::serve(listener, app, &ServerOptions::from_config(&config))
         .await
         .context("Server error")?;
 
@@ -60,13 +117,18 @@ async fn main() -> Result<()> {
 }
 
 /// Initialize tracing subscriber for logging
-fn init_tracing(log_level: &str) -> Result<()> {
+///
+/// With `span_close`, a line is also logged whenever an enabled span closes,
+/// carrying its fields (such as search phase durations) and busy/idle time.
+fn init_tracing(log_level: &str, span_close: bool) -> Result<()> {
     let env_filter = EnvFilter::try_from_default_env()
         .unwrap_or_else(|_| EnvFilter::new(log_level));
 
+    let span_events = if span_close { FmtSpan::CLOSE } else { FmtSpan::NONE };
+
     tracing_subscriber::registry()
         .with(env_filter)
-        .with(tracing_subscriber::fmt::layer())
+        .with(tracing_subscriber::fmt::layer().with_span_events(span_events))
         .try_init()
         .map_err(|e| anyhow::anyhow!("Failed to initialize tracing: {}", e))?;
 
@@ -0,0 +1,263 @@
+//! Opt-in log of search queries for corpus analytics
+//!
+//! Each search appends one JSON line with a hashed user ID, the query text
+//! (hashed, redacted, omitted or verbatim depending on `QUERY_LOG_QUERY_MODE`),
+//! the number of results and the latency. Nothing is recorded unless
+//! `QUERY_LOG_ENABLED` is set. Entries are handed to a background writer, so
+//! the search never waits on the disk. Write failures are logged and never
+//! fail the search.
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::thread::JoinHandle;
+use tokio::sync::mpsc;
+use tracing::warn;
+use uuid::Uuid;
+
+use crate::config::Config;
+
+/// How the query text is written to the log
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum QueryTextMode {
+    /// SHA-256 of the trimmed, lowercased query; equal queries share a hash (default)
+    #[default]
+    Hashed,
+    /// The query with every digit replaced by `#`, hiding IDs and account numbers
+    Redacted,
+    /// The query exactly as sent
+    Plain,
+    /// No query text at all
+    Omit,
+}
+
+impl FromStr for QueryTextMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "hashed" => Ok(QueryTextMode::Hashed),
+            "redacted" => Ok(QueryTextMode::Redacted),
+            "plain" => Ok(QueryTextMode::Plain),
+            "omit" => Ok(QueryTextMode::Omit),
+            other => Err(format!(
+                "expected \"hashed\", \"redacted\", \"plain\" or \"omit\", got \"{}\"",
+                other
+            )),
+        }
+    }
+}
+
+/// One logged search
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct QueryLogEntry {
+    /// Salted SHA-256 of the user ID
+    pub user: String,
+
+    /// Query text as shaped by the configured mode; absent with `omit`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub query: Option<String>,
+
+    /// Number of results returned
+    pub result_count: usize,
+
+    /// Search time in milliseconds
+    pub took_ms: u64,
+
+    /// When the search ran (RFC 3339)
+    pub timestamp: String,
+}
+
+/// Search query log shared by the HTTP handlers
+pub struct QueryLog {
+    sender: mpsc::UnboundedSender<QueryLogEntry>,
+    query_mode: QueryTextMode,
+    salt: String,
+}
+
+impl QueryLog {
+    /// Open the configured log file, or return `None` when query logging is disabled
+    pub fn from_config(config: &Config) -> Result<Option<Self>> {
+        if !config.query_log_enabled {
+            return Ok(None);
+        }
+
+        let file = RotatingFile::open(config.query_log_path.clone(), config.query_log_max_bytes)?;
+        let salt = config.query_log_salt.clone().unwrap_or_default();
+        let (log, entries) = QueryLog::channel(config.query_log_query_mode, &salt);
+        spawn_writer(file, entries)?;
+        Ok(Some(log))
+    }
+
+    /// Send entries to an in-process channel instead of a file
+    pub fn channel(query_mode: QueryTextMode, salt: &str) -> (Self, mpsc::UnboundedReceiver<QueryLogEntry>) {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let log = QueryLog {
+            sender,
+            query_mode,
+            salt: salt.to_string(),
+        };
+        (log, receiver)
+    }
+
+    /// Record one search; the entry is queued and written in the background
+    pub fn record(&self, user_id: Uuid, query: &str, result_count: usize, took_ms: u64) {
+        let entry = QueryLogEntry {
+            user: self.hash(&user_id.to_string()),
+            query: self.shape_query(query),
+            result_count,
+            took_ms,
+            timestamp: chrono::Utc::now().to_rfc3339(),
+        };
+
+        // A dropped receiver just means nobody is listening any more
+        let _ = self.sender.send(entry);
+    }
+
+    fn shape_query(&self, query: &str) -> Option<String> {
+        match self.query_mode {
+            QueryTextMode::Hashed => Some(self.hash(&query.trim().to_lowercase())),
+            QueryTextMode::Redacted => Some(
+                query
+                    .chars()
+                    .map(|c| if c.is_numeric() { '#' } else { c })
+                    .collect(),
+            ),
+            QueryTextMode::Plain => Some(query.to_string()),
+            QueryTextMode::Omit => None,
+        }
+    }
+
+    /// Salted SHA-256, hex encoded; the salt keeps hashes of known values from being looked up
+    fn hash(&self, value: &str) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(self.salt.as_bytes());
+        hasher.update([0x1f]);
+        hasher.update(value.as_bytes());
+        format!("{:x}", hasher.finalize())
+    }
+}
+
+/// Write queued entries to `file` on a dedicated thread until every sender is dropped
+fn spawn_writer(mut file: RotatingFile, mut entries: mpsc::UnboundedReceiver<QueryLogEntry>) -> Result<JoinHandle<()>> {
+    std::thread::Builder::new()
+        .name("query-log".to_string())
+        .spawn(move || {
+            while let Some(entry) = entries.blocking_recv() {
+                if let Err(e) = file.append(&entry) {
+                    warn!(error = %e, "Failed to write query log entry");
+                }
+            }
+        })
+        .context("Failed to start query log writer")
+}
+
+/// Append-only JSON lines file that rolls over to `<path>.1` at a size limit
+struct RotatingFile {
+    path: PathBuf,
+    max_bytes: u64,
+    file: File,
+    size: u64,
+}
+
+impl RotatingFile {
+    fn open(path: PathBuf, max_bytes: u64) -> Result<Self> {
+        if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create query log directory: {:?}", parent))?;
+        }
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .with_context(|| format!("Failed to open query log: {:?}", path))?;
+        let size = file.metadata()?.len();
+        Ok(RotatingFile { path, max_bytes, file, size })
+    }
+
+    fn append(&mut self, entry: &QueryLogEntry) -> Result<()> {
+        let mut line = serde_json::to_vec(entry)?;
+        line.push(b'\n');
+
+        if self.size > 0 && self.size + line.len() as u64 > self.max_bytes {
+            self.rotate()?;
+        }
+
+        self.file.write_all(&line)?;
+        self.size += line.len() as u64;
+        Ok(())
+    }
+
+    /// Move the current file to `<path>.1`, replacing the previous backup
+    fn rotate(&mut self) -> Result<()> {
+        let mut backup = self.path.clone().into_os_string();
+        backup.push(".1");
+        std::fs::rename(&self.path, &backup)?;
+        self.file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        self.size = 0;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_query_text_modes() {
+        let user_id = Uuid::new_v4();
+        let record = |mode| {
+            let (log, mut entries) = QueryLog::channel(mode, "salt");
+            log.record(user_id, "  SSN 123-45-6789 ", 2, 5);
+            entries.try_recv().unwrap()
+        };
+
+        let hashed = record(QueryTextMode::Hashed);
+        assert_eq!(hashed.query.as_deref().unwrap().len(), 64);
+        assert_ne!(hashed.user, user_id.to_string());
+        assert_eq!(hashed.result_count, 2);
+
+        // Equal queries share a hash, so popular searches can still be counted
+        let (log, mut entries) = QueryLog::channel(QueryTextMode::Hashed, "salt");
+        log.record(user_id, "ssn 123-45-6789", 0, 1);
+        assert_eq!(entries.try_recv().unwrap().query, hashed.query);
+
+        // A different salt gives unrelated hashes
+        let (log, mut entries) = QueryLog::channel(QueryTextMode::Hashed, "other");
+        log.record(user_id, "ssn 123-45-6789", 0, 1);
+        assert_ne!(entries.try_recv().unwrap().user, hashed.user);
+
+        assert_eq!(record(QueryTextMode::Redacted).query.as_deref(), Some("  SSN ###-##-#### "));
+        assert_eq!(record(QueryTextMode::Plain).query.as_deref(), Some("  SSN 123-45-6789 "));
+        assert_eq!(record(QueryTextMode::Omit).query, None);
+    }
+
+    #[test]
+    fn test_file_rotates_at_size_limit() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("logs").join("queries.jsonl");
+        let (log, entries) = QueryLog::channel(QueryTextMode::Omit, "");
+        let writer = spawn_writer(RotatingFile::open(path.clone(), 400).unwrap(), entries).unwrap();
+
+        for _ in 0..3 {
+            log.record(Uuid::new_v4(), "receipts", 1, 1);
+        }
+
+        // Dropping the last sender lets the writer drain the queue and exit
+        drop(log);
+        writer.join().unwrap();
+
+        let current = std::fs::read_to_string(&path).unwrap();
+        let backup = std::fs::read_to_string(temp_dir.path().join("logs").join("queries.jsonl.1")).unwrap();
+        assert_eq!(current.lines().count() + backup.lines().count(), 3);
+        assert!(current.len() <= 400 && backup.len() <= 400);
+
+        let entry: serde_json::Value = serde_json::from_str(current.lines().next().unwrap()).unwrap();
+        assert_eq!(entry["result_count"], 1);
+        assert!(entry.get("query").is_none());
+    }
+}
@@ -0,0 +1,179 @@
+//! Live feed of each user's document changes
+//!
+//! Writes publish one event per indexed or deleted document once their
+//! commit succeeds; `GET /v1/changes` streams them to subscribers. Only
+//! changes committed while a subscriber is connected are delivered: nothing
+//! is stored, so there is no replay after a reconnect. A user's channel
+//! exists only while someone subscribes to it. Open subscriptions are
+//! capped across all users and per user; a subscription is released when
+//! it is dropped, which happens however its connection ends.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use thiserror::Error;
+use tokio::sync::broadcast::{self, error::RecvError};
+use uuid::Uuid;
+
+use super::models::{ChangeEvent, ChangeKind};
+
+/// Events buffered per user for subscribers that fall behind
+const CHANNEL_CAPACITY: usize = 1024;
+
+/// Broadcast channels of the users that currently have subscribers
+pub struct ChangeFeed {
+    max_subscribers: usize,
+    max_subscribers_per_user: usize,
+    state: Mutex<FeedState>,
+}
+
+#[derive(Default)]
+struct FeedState {
+    channels: HashMap<Uuid, broadcast::Sender<ChangeEvent>>,
+    subscribers: usize,
+}
+
+/// Why a subscription was refused
+#[derive(Error, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SubscribeError {
+    /// The cap across all users is reached
+    #[error("Too many open change streams; at most {0} are allowed")]
+    TooManyTotal(usize),
+
+    /// The user's own cap is reached
+    #[error("Too many open change streams for this user; at most {0} are allowed")]
+    TooManyForUser(usize),
+}
+
+/// What a subscriber receives next
+#[derive(Debug, Clone, PartialEq)]
+pub enum ChangeMessage {
+    /// A document was indexed or deleted
+    Change(ChangeEvent),
+    /// The subscriber fell behind and this many events were dropped
+    Lagged(u64),
+}
+
+impl ChangeFeed {
+    /// Allow up to `max_subscribers` open subscriptions, `max_subscribers_per_user` of them per user
+    pub fn new(max_subscribers: usize, max_subscribers_per_user: usize) -> Self {
+        ChangeFeed {
+            max_subscribers,
+            max_subscribers_per_user,
+            state: Mutex::new(FeedState::default()),
+        }
+    }
+
+    /// Subscribe to a user's changes unless a subscription cap is reached
+    pub fn subscribe(self: &Arc<Self>, user_id: Uuid) -> Result<ChangeSubscription, SubscribeError> {
+        let mut state = self.state.lock().unwrap();
+        if state.subscribers >= self.max_subscribers {
+            return Err(SubscribeError::TooManyTotal(self.max_subscribers));
+        }
+        let user_subscribers = state.channels.get(&user_id).map_or(0, |sender| sender.receiver_count());
+        if user_subscribers >= self.max_subscribers_per_user {
+            return Err(SubscribeError::TooManyForUser(self.max_subscribers_per_user));
+        }
+        state.subscribers += 1;
+        let receiver = state
+            .channels
+            .entry(user_id)
+            .or_insert_with(|| broadcast::channel(CHANNEL_CAPACITY).0)
+            .subscribe();
+        Ok(ChangeSubscription {
+            feed: Arc::clone(self),
+            user_id,
+            receiver,
+        })
+    }
+
+    /// Publish the documents of one commit to the user's subscribers, if any
+    pub fn publish(&self, user_id: Uuid, kind: ChangeKind, ids: &[String], generation: u64) {
+        let state = self.state.lock().unwrap();
+        let Some(sender) = state.channels.get(&user_id) else {
+            return;
+        };
+        for id in ids {
+            // Sending only fails without receivers, which the subscription's drop cleans up
+            let _ = sender.send(ChangeEvent {
+                kind,
+                id: id.clone(),
+                generation,
+            });
+        }
+    }
+}
+
+/// One subscriber's view of a user's changes; dropping it unsubscribes
+pub struct ChangeSubscription {
+    feed: Arc<ChangeFeed>,
+    user_id: Uuid,
+    receiver: broadcast::Receiver<ChangeEvent>,
+}
+
+impl ChangeSubscription {
+    /// Wait for the next change
+    pub async fn next(&mut self) -> Option<ChangeMessage> {
+        match self.receiver.recv().await {
+            Ok(event) => Some(ChangeMessage::Change(event)),
+            Err(RecvError::Lagged(skipped)) => Some(ChangeMessage::Lagged(skipped)),
+            Err(RecvError::Closed) => None,
+        }
+    }
+}
+
+impl Drop for ChangeSubscription {
+    fn drop(&mut self) {
+        let mut state = self.feed.state.lock().unwrap();
+        state.subscribers -= 1;
+        // This subscription's receiver is still alive, so it is the last one at a count of 1
+        if state
+            .channels
+            .get(&self.user_id)
+            .is_some_and(|sender| sender.receiver_count() <= 1)
+        {
+            state.channels.remove(&self.user_id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_change_feed() {
+        let feed = Arc::new(ChangeFeed::new(3, 2));
+        let (user_id, other_user) = (Uuid::new_v4(), Uuid::new_v4());
+
+        // Nothing is kept for users without subscribers
+        feed.publish(user_id, ChangeKind::Indexed, &["early".to_string()], 1);
+
+        let mut subscription = feed.subscribe(user_id).unwrap();
+        let second = feed.subscribe(user_id).unwrap();
+        assert_eq!(feed.subscribe(user_id).err(), Some(SubscribeError::TooManyForUser(2)));
+        let _other = feed.subscribe(other_user).unwrap();
+        assert_eq!(feed.subscribe(Uuid::new_v4()).err(), Some(SubscribeError::TooManyTotal(3)));
+        drop(second);
+
+        feed.publish(other_user, ChangeKind::Indexed, &["other".to_string()], 2);
+        feed.publish(user_id, ChangeKind::Deleted, &["doc1".to_string()], 3);
+        assert_eq!(
+            subscription.next().await,
+            Some(ChangeMessage::Change(ChangeEvent {
+                kind: ChangeKind::Deleted,
+                id: "doc1".to_string(),
+                generation: 3,
+            }))
+        );
+
+        let ids: Vec<String> = (0..CHANNEL_CAPACITY + 5).map(|i| i.to_string()).collect();
+        feed.publish(user_id, ChangeKind::Indexed, &ids, 4);
+        assert_eq!(subscription.next().await, Some(ChangeMessage::Lagged(5)));
+
+        drop(subscription);
+        assert_eq!(feed.state.lock().unwrap().subscribers, 1);
+        assert!(!feed.state.lock().unwrap().channels.contains_key(&user_id));
+        assert!(feed.subscribe(user_id).is_ok());
+    }
+}
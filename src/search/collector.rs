@@ -0,0 +1,466 @@
+use std::cmp::Ordering as CmpOrdering;
+use std::collections::BinaryHeap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+use tantivy::collector::{Collector, SegmentCollector};
+use tantivy::columnar::StrColumn;
+use tantivy::query::Weight;
+use tantivy::{DocAddress, DocId, DocSet, Score, SegmentOrdinal, SegmentReader, TERMINATED};
+
+/// How many matches are collected between deadline checks
+const DEADLINE_CHECK_INTERVAL: u32 = 1024;
+
+/// Wraps a collector so that collection stops once a deadline passes
+///
+/// Segments are scored manually so iteration can stop mid-segment. Whatever
+/// was collected before the deadline is still merged and returned, and
+/// `timed_out()` reports whether any matches were skipped.
+pub struct TimeBudgetCollector<C> {
+    inner: C,
+    deadline: Instant,
+    timed_out: Arc<AtomicBool>,
+}
+
+impl<C: Collector> TimeBudgetCollector<C> {
+    pub fn new(inner: C, deadline: Instant) -> Self {
+        TimeBudgetCollector {
+            inner,
+            deadline,
+            timed_out: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Flag shared with the collector; set when the deadline cut collection short
+    pub fn timed_out_flag(&self) -> Arc<AtomicBool> {
+        Arc::clone(&self.timed_out)
+    }
+}
+
+impl<C: Collector> Collector for TimeBudgetCollector<C> {
+    type Fruit = C::Fruit;
+    type Child = C::Child;
+
+    fn for_segment(
+        &self,
+        segment_local_id: SegmentOrdinal,
+        segment: &SegmentReader,
+    ) -> tantivy::Result<Self::Child> {
+        self.inner.for_segment(segment_local_id, segment)
+    }
+
+    fn requires_scoring(&self) -> bool {
+        self.inner.requires_scoring()
+    }
+
+    fn merge_fruits(
+        &self,
+        segment_fruits: Vec<<Self::Child as SegmentCollector>::Fruit>,
+    ) -> tantivy::Result<Self::Fruit> {
+        self.inner.merge_fruits(segment_fruits)
+    }
+
+    fn collect_segment(
+        &self,
+        weight: &dyn Weight,
+        segment_ord: u32,
+        reader: &SegmentReader,
+    ) -> tantivy::Result<<Self::Child as SegmentCollector>::Fruit> {
+        let mut segment_collector = self.inner.for_segment(segment_ord, reader)?;
+
+        if Instant::now() >= self.deadline {
+            self.timed_out.store(true, Ordering::Relaxed);
+            return Ok(segment_collector.harvest());
+        }
+
+        let alive_bitset = reader.alive_bitset();
+        let mut scorer = weight.scorer(reader, 1.0)?;
+        let mut doc = scorer.doc();
+        let mut since_check = 0;
+
+        while doc != TERMINATED {
+            if alive_bitset.map_or(true, |bitset| bitset.is_alive(doc)) {
+                segment_collector.collect(doc, scorer.score());
+            }
+
+            since_check += 1;
+            if since_check == DEADLINE_CHECK_INTERVAL {
+                since_check = 0;
+                if Instant::now() >= self.deadline {
+                    self.timed_out.store(true, Ordering::Relaxed);
+                    break;
+                }
+            }
+
+            doc = scorer.advance();
+        }
+
+        Ok(segment_collector.harvest())
+    }
+}
+
+/// Per-segment score adjustment applied before ranking
+pub type SegmentScoreTweak = fn(DocId, Score) -> Score;
+
+/// Top-k collector with a deterministic order for equal scores
+///
+/// Hits are ranked by score descending, then by the value of a fast string
+/// key field (the document ID) ascending, so tied documents keep the same
+/// order across reader reloads and merges and pagination doesn't flicker.
+/// Indexes whose key field isn't fast, such as ones created before the ID was
+/// made a fast field, fall back to the internal doc address.
+/// The fruit has the same shape as `TopDocs`.
+pub struct StableTopDocs<T> {
+    limit: usize,
+    key_field: &'static str,
+    tweak: T,
+}
+
+fn unadjusted(_: &SegmentReader) -> SegmentScoreTweak {
+    |_, score| score
+}
+
+impl StableTopDocs<fn(&SegmentReader) -> SegmentScoreTweak> {
+    pub fn with_limit(limit: usize, key_field: &'static str) -> Self {
+        StableTopDocs {
+            limit,
+            key_field,
+            tweak: unadjusted,
+        }
+    }
+}
+
+impl<T> StableTopDocs<T> {
+    /// Rank by a per-segment adjusted score, like `TopDocs::tweak_score`
+    pub fn tweak_score<U, F>(self, tweak: U) -> StableTopDocs<U>
+    where
+        U: Fn(&SegmentReader) -> F,
+        F: FnMut(DocId, Score) -> Score,
+    {
+        StableTopDocs {
+            limit: self.limit,
+            key_field: self.key_field,
+            tweak,
+        }
+    }
+}
+
+/// A candidate hit; orders best first (higher score, then lower key)
+#[derive(Debug)]
+pub struct RankedHit {
+    score: Score,
+    key: String,
+    address: DocAddress,
+}
+
+impl Ord for RankedHit {
+    fn cmp(&self, other: &Self) -> CmpOrdering {
+        other
+            .score
+            .total_cmp(&self.score)
+            .then_with(|| self.key.cmp(&other.key))
+            .then_with(|| self.address.cmp(&other.address))
+    }
+}
+
+impl PartialOrd for RankedHit {
+    fn partial_cmp(&self, other: &Self) -> Option<CmpOrdering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for RankedHit {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == CmpOrdering::Equal
+    }
+}
+
+impl Eq for RankedHit {}
+
+impl<T, F> Collector for StableTopDocs<T>
+where
+    T: Fn(&SegmentReader) -> F + Send + Sync,
+    F: FnMut(DocId, Score) -> Score + 'static,
+{
+    type Fruit = Vec<(Score, DocAddress)>;
+    type Child = StableTopSegmentCollector<F>;
+
+    fn for_segment(
+        &self,
+        segment_local_id: SegmentOrdinal,
+        segment: &SegmentReader,
+    ) -> tantivy::Result<Self::Child> {
+        // Tantivy fails rather than returning no column for a field that isn't fast
+        let key_is_fast = segment
+            .schema()
+            .get_field(self.key_field)
+            .is_ok_and(|field| segment.schema().get_field_entry(field).is_fast());
+        let keys = if key_is_fast {
+            segment.fast_fields().str(self.key_field)?
+        } else {
+            None
+        };
+        Ok(StableTopSegmentCollector {
+            limit: self.limit,
+            segment_ord: segment_local_id,
+            keys,
+            tweak: (self.tweak)(segment),
+            heap: BinaryHeap::with_capacity(self.limit),
+        })
+    }
+
+    fn requires_scoring(&self) -> bool {
+        true
+    }
+
+    fn merge_fruits(&self, segment_fruits: Vec<Vec<RankedHit>>) -> tantivy::Result<Self::Fruit> {
+        let mut hits: Vec<RankedHit> = segment_fruits.into_iter().flatten().collect();
+        hits.sort_unstable();
+        hits.truncate(self.limit);
+        Ok(hits.into_iter().map(|hit| (hit.score, hit.address)).collect())
+    }
+}
+
+/// Segment half of [`StableTopDocs`]
+///
+/// Keeps the `limit` best hits in a heap whose top is the worst kept hit, so
+/// most non-competitive documents are rejected on score alone without
+/// reading their key.
+pub struct StableTopSegmentCollector<F> {
+    limit: usize,
+    segment_ord: SegmentOrdinal,
+    keys: Option<StrColumn>,
+    tweak: F,
+    heap: BinaryHeap<RankedHit>,
+}
+
+impl<F> StableTopSegmentCollector<F> {
+    fn key(&self, doc: DocId) -> String {
+        let mut key = String::new();
+        if let Some(column) = &self.keys {
+            if let Some(ord) = column.term_ords(doc).next() {
+                // An unreadable key only loses the tie-break for this hit
+                let _ = column.ord_to_str(ord, &mut key);
+            }
+        }
+        key
+    }
+}
+
+impl<F> SegmentCollector for StableTopSegmentCollector<F>
+where
+    F: FnMut(DocId, Score) -> Score + 'static,
+{
+    type Fruit = Vec<RankedHit>;
+
+    fn collect(&mut self, doc: DocId, score: Score) {
+        if self.limit == 0 {
+            return;
+        }
+
+        let score = (self.tweak)(doc, score);
+        if self.heap.len() == self.limit {
+            let worst = self.heap.peek().expect("heap is full");
+            if score.total_cmp(&worst.score) == CmpOrdering::Less {
+                return;
+            }
+        }
+
+        let hit = RankedHit {
+            score,
+            key: self.key(doc),
+            address: DocAddress::new(self.segment_ord, doc),
+        };
+        if self.heap.len() < self.limit {
+            self.heap.push(hit);
+        } else if let Some(mut worst) = self.heap.peek_mut() {
+            if hit < *worst {
+                *worst = hit;
+            }
+        }
+    }
+
+    fn harvest(self) -> Self::Fruit {
+        self.heap.into_vec()
+    }
+}
+
+/// Buckets in a score histogram; percentiles resolve to 1/1000 of the top score
+const SCORE_HISTOGRAM_BUCKETS: usize = 1000;
+
+/// Counts the adjusted score of every match into fixed-width buckets over `[0, max_score]`
+///
+/// Lets returned hits be ranked against the whole matching set with memory
+/// that does not grow with the number of matches. Scores above `max_score`
+/// land in the last bucket.
+pub struct ScoreHistogram<T> {
+    max_score: Score,
+    tweak: T,
+}
+
+impl<T> ScoreHistogram<T> {
+    /// `tweak` must be the adjustment used to rank the hits being compared
+    pub fn new(max_score: Score, tweak: T) -> Self {
+        ScoreHistogram { max_score, tweak }
+    }
+}
+
+/// Score distribution of all matches, collected by [`ScoreHistogram`]
+#[derive(Debug)]
+pub struct ScoreDistribution {
+    max_score: Score,
+    buckets: Vec<u64>,
+}
+
+impl ScoreDistribution {
+    fn bucket(max_score: Score, score: Score) -> usize {
+        if max_score <= 0.0 {
+            return SCORE_HISTOGRAM_BUCKETS - 1;
+        }
+        // Negative and NaN ratios saturate to bucket 0
+        ((score / max_score * SCORE_HISTOGRAM_BUCKETS as Score) as usize).min(SCORE_HISTOGRAM_BUCKETS - 1)
+    }
+
+    /// Percentage of matches scoring at or below `score`, from 0 to 100
+    pub fn percentile(&self, score: Score) -> f32 {
+        let total: u64 = self.buckets.iter().sum();
+        if total == 0 {
+            return 100.0;
+        }
+        let at_or_below: u64 = self.buckets[..=Self::bucket(self.max_score, score)].iter().sum();
+        (at_or_below as f64 * 100.0 / total as f64) as f32
+    }
+}
+
+impl<T, F> Collector for ScoreHistogram<T>
+where
+    T: Fn(&SegmentReader) -> F + Send + Sync,
+    F: FnMut(DocId, Score) -> Score + 'static,
+{
+    type Fruit = ScoreDistribution;
+    type Child = ScoreHistogramSegmentCollector<F>;
+
+    fn for_segment(
+        &self,
+        _segment_local_id: SegmentOrdinal,
+        segment: &SegmentReader,
+    ) -> tantivy::Result<Self::Child> {
+        Ok(ScoreHistogramSegmentCollector {
+            max_score: self.max_score,
+            tweak: (self.tweak)(segment),
+            buckets: vec![0; SCORE_HISTOGRAM_BUCKETS],
+        })
+    }
+
+    fn requires_scoring(&self) -> bool {
+        true
+    }
+
+    fn merge_fruits(&self, segment_fruits: Vec<Vec<u64>>) -> tantivy::Result<Self::Fruit> {
+        let mut buckets = vec![0; SCORE_HISTOGRAM_BUCKETS];
+        for fruit in segment_fruits {
+            for (total, count) in buckets.iter_mut().zip(fruit) {
+                *total += count;
+            }
+        }
+        Ok(ScoreDistribution {
+            max_score: self.max_score,
+            buckets,
+        })
+    }
+}
+
+/// Segment half of [`ScoreHistogram`]
+pub struct ScoreHistogramSegmentCollector<F> {
+    max_score: Score,
+    tweak: F,
+    buckets: Vec<u64>,
+}
+
+impl<F> SegmentCollector for ScoreHistogramSegmentCollector<F>
+where
+    F: FnMut(DocId, Score) -> Score + 'static,
+{
+    type Fruit = Vec<u64>;
+
+    fn collect(&mut self, doc: DocId, score: Score) {
+        let score = (self.tweak)(doc, score);
+        self.buckets[ScoreDistribution::bucket(self.max_score, score)] += 1;
+    }
+
+    fn harvest(self) -> Self::Fruit {
+        self.buckets
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use tantivy::collector::Count;
+    use tantivy::query::AllQuery;
+    use tantivy::schema::{Schema, Value, FAST, STORED, STRING};
+    use tantivy::{doc, Index};
+
+    #[test]
+    fn test_stable_top_docs_breaks_ties_by_key() {
+        let mut schema_builder = Schema::builder();
+        let id_field = schema_builder.add_text_field("id", STRING | FAST | STORED);
+        let index = Index::create_in_ram(schema_builder.build());
+        let mut writer = index.writer(15_000_000).unwrap();
+        // Keys arrive out of order and spread over several segments
+        for batch in [["d", "b"], ["e", "a"], ["c", "f"]] {
+            for key in batch {
+                writer.add_document(doc!(id_field => key)).unwrap();
+            }
+            writer.commit().unwrap();
+        }
+        let searcher = index.reader().unwrap().searcher();
+
+        let keys = |hits: Vec<(Score, DocAddress)>| -> Vec<String> {
+            hits.into_iter()
+                .map(|(_, address)| {
+                    let doc: tantivy::TantivyDocument = searcher.doc(address).unwrap();
+                    doc.get_first(id_field).and_then(|v| v.as_str()).unwrap().to_string()
+                })
+                .collect()
+        };
+
+        let top = searcher.search(&AllQuery, &StableTopDocs::with_limit(4, "id")).unwrap();
+        assert_eq!(keys(top), vec!["a", "b", "c", "d"]);
+
+        // Scores still dominate the key
+        let boosted = StableTopDocs::with_limit(2, "id").tweak_score(|segment: &SegmentReader| {
+            let ids = segment.fast_fields().str("id").unwrap().unwrap();
+            move |doc: DocId, score: Score| {
+                let mut id = String::new();
+                ids.ord_to_str(ids.term_ords(doc).next().unwrap(), &mut id).unwrap();
+                if id == "f" { score + 1.0 } else { score }
+            }
+        });
+        assert_eq!(keys(searcher.search(&AllQuery, &boosted).unwrap()), vec!["f", "a"]);
+    }
+
+    #[test]
+    fn test_deadline_stops_collection() {
+        let mut schema_builder = Schema::builder();
+        let field = schema_builder.add_text_field("id", STRING);
+        let index = Index::create_in_ram(schema_builder.build());
+        let mut writer = index.writer(15_000_000).unwrap();
+        for i in 0..10 {
+            writer.add_document(doc!(field => i.to_string())).unwrap();
+        }
+        writer.commit().unwrap();
+        let searcher = index.reader().unwrap().searcher();
+
+        let generous = TimeBudgetCollector::new(Count, Instant::now() + Duration::from_secs(60));
+        let flag = generous.timed_out_flag();
+        assert_eq!(searcher.search(&AllQuery, &generous).unwrap(), 10);
+        assert!(!flag.load(Ordering::Relaxed));
+
+        let expired = TimeBudgetCollector::new(Count, Instant::now());
+        let flag = expired.timed_out_flag();
+        assert_eq!(searcher.search(&AllQuery, &expired).unwrap(), 0);
+        assert!(flag.load(Ordering::Relaxed));
+    }
+}
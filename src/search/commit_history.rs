@@ -0,0 +1,81 @@
+//! Recent commits of each user's index
+//!
+//! Every commit is recorded with its generation token, time and the number
+//! of operations it applied, so a client can tell when a write became
+//! searchable. Each user keeps the latest `capacity` commits in memory; the
+//! history survives index eviction and rebuilds but not a restart.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+
+use chrono::Utc;
+use uuid::Uuid;
+
+use super::models::CommitRecord;
+
+/// Bounded commit history of every user who committed since startup
+pub struct CommitHistory {
+    capacity: usize,
+    commits: Mutex<HashMap<Uuid, VecDeque<CommitRecord>>>,
+}
+
+impl CommitHistory {
+    /// Keep up to `capacity` commits per user; 0 records nothing
+    pub fn new(capacity: usize) -> Self {
+        CommitHistory {
+            capacity,
+            commits: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Record a commit of `operations` writes that produced `generation`
+    pub fn record(&self, user_id: Uuid, generation: u64, operations: u64) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut commits = self.commits.lock().unwrap();
+        let history = commits.entry(user_id).or_default();
+        if history.len() == self.capacity {
+            history.pop_front();
+        }
+        history.push_back(CommitRecord {
+            generation,
+            committed_at: Utc::now().to_rfc3339(),
+            operations,
+        });
+    }
+
+    /// The user's latest `limit` commits, newest first
+    pub fn recent(&self, user_id: Uuid, limit: usize) -> Vec<CommitRecord> {
+        let commits = self.commits.lock().unwrap();
+        commits
+            .get(&user_id)
+            .map(|history| history.iter().rev().take(limit).cloned().collect())
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_commit_history_is_bounded() {
+        let history = CommitHistory::new(3);
+        let user_id = Uuid::new_v4();
+        for generation in 1..=5 {
+            history.record(user_id, generation, generation * 10);
+        }
+
+        let recent = history.recent(user_id, 10);
+        let generations: Vec<u64> = recent.iter().map(|commit| commit.generation).collect();
+        assert_eq!(generations, [5, 4, 3]);
+        assert_eq!(recent[0].operations, 50);
+        assert_eq!(history.recent(user_id, 1).len(), 1);
+        assert!(history.recent(Uuid::new_v4(), 10).is_empty());
+
+        let disabled = CommitHistory::new(0);
+        disabled.record(user_id, 1, 1);
+        assert!(disabled.recent(user_id, 10).is_empty());
+    }
+}
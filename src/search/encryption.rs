@@ -0,0 +1,343 @@
+//! Encryption at rest for tenant indexes
+//!
+//! [`EncryptedDirectory`] wraps a Tantivy [`Directory`] and encrypts every file
+//! it writes with ChaCha20 under a per-user key derived from the master key in
+//! `INDEX_ENCRYPTION_KEY`. Each file starts with a short header (magic, key
+//! fingerprint, random nonce); the rest is ciphertext of the same length as the
+//! plaintext, so Tantivy's random-access reads map directly onto keystream
+//! offsets.
+//!
+//! This provides confidentiality only. Integrity is left to Tantivy's own
+//! per-file checksums, which are verified on the decrypted bytes.
+
+use std::fmt;
+use std::io::{self, Write};
+use std::ops::Range;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use chacha20::cipher::{KeyIvInit, StreamCipher, StreamCipherSeek};
+use chacha20::ChaCha20;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use tantivy::directory::error::{DeleteError, LockError, OpenReadError, OpenWriteError};
+use tantivy::directory::{
+    AntiCallToken, DirectoryLock, FileHandle, Lock, OwnedBytes, TerminatingWrite, WatchCallback,
+    WatchHandle, WritePtr,
+};
+use tantivy::{Directory, HasLen};
+use uuid::Uuid;
+
+const MAGIC: &[u8; 4] = b"T2GE";
+const FINGERPRINT_LEN: usize = 8;
+const NONCE_LEN: usize = 12;
+const HEADER_LEN: usize = MAGIC.len() + FINGERPRINT_LEN + NONCE_LEN;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// 256-bit master key, configured as 64 hex characters
+#[derive(Clone, PartialEq, Eq)]
+pub struct EncryptionKey([u8; 32]);
+
+impl EncryptionKey {
+    /// Derive the key for a single user's index
+    ///
+    /// Keys are independent per tenant, so one leaked index key exposes no
+    /// other tenant's data.
+    pub fn for_user(&self, user_id: Uuid) -> EncryptionKey {
+        let mut mac = HmacSha256::new_from_slice(&self.0).expect("HMAC accepts any key length");
+        mac.update(b"tax2go-index-key:");
+        mac.update(user_id.as_bytes());
+        EncryptionKey(mac.finalize().into_bytes().into())
+    }
+
+    /// Short value stored in each file header to detect a wrong key
+    fn fingerprint(&self) -> [u8; FINGERPRINT_LEN] {
+        let mut mac = HmacSha256::new_from_slice(&self.0).expect("HMAC accepts any key length");
+        mac.update(b"tax2go-key-fingerprint");
+        let digest = mac.finalize().into_bytes();
+        let mut fingerprint = [0u8; FINGERPRINT_LEN];
+        fingerprint.copy_from_slice(&digest[..FINGERPRINT_LEN]);
+        fingerprint
+    }
+
+    fn cipher(&self, nonce: &[u8; NONCE_LEN]) -> ChaCha20 {
+        ChaCha20::new(&self.0.into(), nonce.into())
+    }
+}
+
+impl FromStr for EncryptionKey {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let invalid = || "must be 64 hex characters (a 256-bit key)".to_string();
+        // `from_str_radix` alone would also accept a sign, such as "+f"
+        if s.len() != 64 || !s.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(invalid());
+        }
+
+        let mut key = [0u8; 32];
+        for (i, byte) in key.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&s[i * 2..i * 2 + 2], 16).map_err(|_| invalid())?;
+        }
+        Ok(EncryptionKey(key))
+    }
+}
+
+impl fmt::Debug for EncryptionKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("EncryptionKey(<redacted>)")
+    }
+}
+
+/// Directory wrapper that encrypts file contents with a per-user key
+#[derive(Clone, Debug)]
+pub struct EncryptedDirectory<D> {
+    inner: D,
+    key: EncryptionKey,
+}
+
+impl<D: Directory + Clone> EncryptedDirectory<D> {
+    pub fn new(inner: D, key: EncryptionKey) -> Self {
+        EncryptedDirectory { inner, key }
+    }
+
+    fn new_header(&self) -> io::Result<([u8; HEADER_LEN], [u8; NONCE_LEN])> {
+        let mut nonce = [0u8; NONCE_LEN];
+        getrandom::getrandom(&mut nonce).map_err(io::Error::other)?;
+
+        let mut header = [0u8; HEADER_LEN];
+        header[..MAGIC.len()].copy_from_slice(MAGIC);
+        header[MAGIC.len()..MAGIC.len() + FINGERPRINT_LEN].copy_from_slice(&self.key.fingerprint());
+        header[MAGIC.len() + FINGERPRINT_LEN..].copy_from_slice(&nonce);
+        Ok((header, nonce))
+    }
+
+    /// Validate a file header and return its nonce
+    fn parse_header(&self, header: &[u8], path: &Path) -> io::Result<[u8; NONCE_LEN]> {
+        if header.len() < HEADER_LEN || &header[..MAGIC.len()] != MAGIC {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{:?} is not encrypted; plaintext indexes must be reindexed", path),
+            ));
+        }
+        if header[MAGIC.len()..MAGIC.len() + FINGERPRINT_LEN] != self.key.fingerprint() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{:?} was encrypted with a different key", path),
+            ));
+        }
+
+        let mut nonce = [0u8; NONCE_LEN];
+        nonce.copy_from_slice(&header[MAGIC.len() + FINGERPRINT_LEN..HEADER_LEN]);
+        Ok(nonce)
+    }
+}
+
+impl<D: Directory + Clone> Directory for EncryptedDirectory<D> {
+    fn get_file_handle(&self, path: &Path) -> Result<Arc<dyn FileHandle>, OpenReadError> {
+        let wrap = |e: io::Error| OpenReadError::wrap_io_error(e, path.to_path_buf());
+
+        let inner = self.inner.get_file_handle(path)?;
+        let header = inner.read_bytes(0..HEADER_LEN.min(inner.len())).map_err(wrap)?;
+        let nonce = self.parse_header(header.as_slice(), path).map_err(wrap)?;
+
+        Ok(Arc::new(EncryptedFileHandle {
+            inner,
+            key: self.key.clone(),
+            nonce,
+        }))
+    }
+
+    fn delete(&self, path: &Path) -> Result<(), DeleteError> {
+        self.inner.delete(path)
+    }
+
+    fn exists(&self, path: &Path) -> Result<bool, OpenReadError> {
+        self.inner.exists(path)
+    }
+
+    fn open_write(&self, path: &Path) -> Result<WritePtr, OpenWriteError> {
+        let wrap = |e: io::Error| OpenWriteError::wrap_io_error(e, path.to_path_buf());
+
+        let mut inner = self.inner.open_write(path)?;
+        let (header, nonce) = self.new_header().map_err(wrap)?;
+        inner.write_all(&header).map_err(wrap)?;
+
+        Ok(io::BufWriter::new(Box::new(EncryptingWriter {
+            inner,
+            cipher: self.key.cipher(&nonce),
+            buffer: Vec::new(),
+        })))
+    }
+
+    fn atomic_read(&self, path: &Path) -> Result<Vec<u8>, OpenReadError> {
+        let mut data = self.inner.atomic_read(path)?;
+        let nonce = self
+            .parse_header(&data, path)
+            .map_err(|e| OpenReadError::wrap_io_error(e, path.to_path_buf()))?;
+
+        let mut plaintext = data.split_off(HEADER_LEN);
+        self.key.cipher(&nonce).apply_keystream(&mut plaintext);
+        Ok(plaintext)
+    }
+
+    fn atomic_write(&self, path: &Path, data: &[u8]) -> io::Result<()> {
+        let (header, nonce) = self.new_header()?;
+        let mut buffer = Vec::with_capacity(HEADER_LEN + data.len());
+        buffer.extend_from_slice(&header);
+        buffer.extend_from_slice(data);
+        self.key.cipher(&nonce).apply_keystream(&mut buffer[HEADER_LEN..]);
+        self.inner.atomic_write(path, &buffer)
+    }
+
+    fn sync_directory(&self) -> io::Result<()> {
+        self.inner.sync_directory()
+    }
+
+    fn acquire_lock(&self, lock: &Lock) -> Result<DirectoryLock, LockError> {
+        // Lock files carry no data; keep the inner directory's locking semantics
+        self.inner.acquire_lock(lock)
+    }
+
+    fn watch(&self, watch_callback: WatchCallback) -> tantivy::Result<WatchHandle> {
+        self.inner.watch(watch_callback)
+    }
+}
+
+/// Read side of an encrypted file, decrypting requested ranges on demand
+struct EncryptedFileHandle {
+    inner: Arc<dyn FileHandle>,
+    key: EncryptionKey,
+    nonce: [u8; NONCE_LEN],
+}
+
+impl fmt::Debug for EncryptedFileHandle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EncryptedFileHandle")
+            .field("inner", &self.inner)
+            .finish_non_exhaustive()
+    }
+}
+
+impl HasLen for EncryptedFileHandle {
+    fn len(&self) -> usize {
+        self.inner.len() - HEADER_LEN
+    }
+}
+
+impl FileHandle for EncryptedFileHandle {
+    fn read_bytes(&self, range: Range<usize>) -> io::Result<OwnedBytes> {
+        let ciphertext = self
+            .inner
+            .read_bytes(range.start + HEADER_LEN..range.end + HEADER_LEN)?;
+
+        let mut plaintext = ciphertext.as_slice().to_vec();
+        let mut cipher = self.key.cipher(&self.nonce);
+        cipher.seek(range.start as u64);
+        cipher.apply_keystream(&mut plaintext);
+        Ok(OwnedBytes::new(plaintext))
+    }
+}
+
+/// Write side of an encrypted file; the keystream advances with each write
+struct EncryptingWriter {
+    inner: WritePtr,
+    cipher: ChaCha20,
+    buffer: Vec<u8>,
+}
+
+impl Write for EncryptingWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buffer.clear();
+        self.buffer.extend_from_slice(buf);
+        self.cipher.apply_keystream(&mut self.buffer);
+        // Write everything so the keystream position always matches the file offset
+        self.inner.write_all(&self.buffer)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl TerminatingWrite for EncryptingWriter {
+    fn terminate_ref(&mut self, token: AntiCallToken) -> io::Result<()> {
+        self.inner.terminate_ref(token)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tantivy::directory::RamDirectory;
+
+    fn key(byte: u8) -> EncryptionKey {
+        EncryptionKey([byte; 32])
+    }
+
+    #[test]
+    fn test_key_parsing() {
+        let hex = "00112233445566778899aabbccddeeff00112233445566778899aabbccddeeff";
+        let parsed: EncryptionKey = hex.parse().unwrap();
+        assert_eq!(parsed.0[1], 0x11);
+        assert_eq!(format!("{:?}", parsed), "EncryptionKey(<redacted>)");
+
+        assert!("abcd".parse::<EncryptionKey>().is_err());
+        assert!("zz".repeat(32).parse::<EncryptionKey>().is_err());
+        assert!("+f".repeat(32).parse::<EncryptionKey>().is_err());
+    }
+
+    #[test]
+    fn test_user_keys_differ() {
+        let master = key(7);
+        assert_ne!(master.for_user(Uuid::new_v4()), master.for_user(Uuid::new_v4()));
+    }
+
+    #[test]
+    fn test_round_trip_and_random_access() {
+        let ram = RamDirectory::create();
+        let dir = EncryptedDirectory::new(ram.clone(), key(1));
+        let path = Path::new("segment.idx");
+        let plaintext: Vec<u8> = (0..10_000u32).map(|i| (i % 251) as u8).collect();
+
+        let mut writer = dir.open_write(path).unwrap();
+        writer.write_all(&plaintext[..3_000]).unwrap();
+        writer.write_all(&plaintext[3_000..]).unwrap();
+        writer.terminate().unwrap();
+
+        // Nothing readable leaks through the inner directory
+        let raw = ram.open_read(path).unwrap().read_bytes().unwrap();
+        assert_eq!(raw.len(), plaintext.len() + HEADER_LEN);
+        assert_ne!(&raw.as_slice()[HEADER_LEN..], plaintext.as_slice());
+
+        let file = dir.open_read(path).unwrap();
+        assert_eq!(file.len(), plaintext.len());
+        assert_eq!(file.read_bytes().unwrap().as_slice(), plaintext.as_slice());
+        assert_eq!(
+            file.slice(4_321..5_000).read_bytes().unwrap().as_slice(),
+            &plaintext[4_321..5_000]
+        );
+
+        dir.atomic_write(Path::new("meta.json"), b"{\"segments\":[]}").unwrap();
+        assert_eq!(dir.atomic_read(Path::new("meta.json")).unwrap(), b"{\"segments\":[]}");
+    }
+
+    #[test]
+    fn test_wrong_key_and_plaintext_rejected() {
+        let ram = RamDirectory::create();
+        EncryptedDirectory::new(ram.clone(), key(1))
+            .atomic_write(Path::new("meta.json"), b"{}")
+            .unwrap();
+        ram.atomic_write(Path::new("plain.json"), b"{}").unwrap();
+
+        let other = EncryptedDirectory::new(ram, key(2));
+        let err = other.atomic_read(Path::new("meta.json")).unwrap_err();
+        assert!(err.to_string().contains("different key"));
+        let err = other.atomic_read(Path::new("plain.json")).unwrap_err();
+        assert!(err.to_string().contains("not encrypted"));
+    }
+}
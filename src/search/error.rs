@@ -0,0 +1,34 @@
+use thiserror::Error;
+
+/// Typed failures raised by the index layer that callers may want to
+/// surface with a specific HTTP status rather than a generic 500
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum IndexError {
+    /// The user's index writer is held by another operation
+    #[error("Index is busy, retry after {retry_after_secs}s")]
+    Busy { retry_after_secs: u64 },
+
+    /// The query string is well-formed but cannot be executed against this index
+    #[error("{0}")]
+    InvalidQuery(String),
+
+    /// The user's index is being rebuilt by a reindex and cannot take writes
+    #[error("Index is being rebuilt, retry after {retry_after_secs}s")]
+    Rebuilding { retry_after_secs: u64 },
+
+    /// The user's index recently failed to open and is in its retry cooldown
+    #[error("Index is unavailable, retry after {retry_after_secs}s: {reason}")]
+    Unavailable { retry_after_secs: u64, reason: String },
+
+    /// The operation is not possible with this index's configuration
+    #[error("{0}")]
+    Unsupported(String),
+
+    /// The user is indexing documents faster than their configured rate
+    #[error("Indexing rate exceeded, retry after {retry_after_secs}s")]
+    RateLimited { retry_after_secs: u64 },
+
+    /// The operation conflicts with the index's current contents
+    #[error("{0}")]
+    Conflict(String),
+}
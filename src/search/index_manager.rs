@@ -1,21 +1,140 @@
 use anyhow::{Context, Result};
-use std::collections::HashMap;
-use std::path::PathBuf;
+use std::cmp::Reverse;
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeMap, BTreeSet, BinaryHeap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
 use std::sync::Arc;
-use std::time::Instant;
-use tantivy::collector::TopDocs;
-use tantivy::query::QueryParser;
-use tantivy::schema::{Schema, Value};
-use tantivy::{Index, IndexReader, IndexWriter, Term, TantivyDocument};
-use tokio::sync::RwLock;
-use tracing::{debug, info};
+use std::time::{Duration, Instant};
+use tantivy::collector::{Collector, Count, DocSetCollector, FacetCollector, TopDocs};
+use tantivy::directory::MmapDirectory;
+use tantivy::merge_policy::LogMergePolicy;
+use tantivy::query::{AllQuery, BooleanQuery, BoostQuery, Occur, Query, QueryParserError, TermQuery};
+use tantivy::schema::{Facet, Field, FieldType, IndexRecordOption, Schema, Value};
+use tantivy::snippet::{Snippet, SnippetGenerator};
+use tantivy::tokenizer::TextAnalyzer;
+use tantivy::{
+    DocAddress, DocId, DocSet, Directory, Index, IndexReader, IndexSettings, IndexWriter, Order, Score, Searcher, SegmentReader, Term,
+    TantivyDocument,
+};
+use tokio::sync::{MutexGuard, OwnedSemaphorePermit, RwLock, Semaphore};
+use tracing::{debug, info, warn};
+use serde::Serialize;
 use uuid::Uuid;
 
+use crate::config::Config;
+use crate::logging;
+
 use super::models::{
-    DeleteDocumentResponse, IndexDocumentInput, IndexDocumentResponse, SearchQuery, SearchResponse,
-    SearchResult, BrowseDocumentsQuery, BrowseDocumentsResponse, DocumentDetail,
+    BulkCommitMode, DocumentPreview, IndexAnalytics, RepairReport, ScoredId, BulkDeleteResponse, BulkIndexResponse, BulkIndexResult, BulkDeleteResult, DeleteDocumentResponse, IndexDocumentInput, IndexDocumentResponse, ScoreMode, SearchQuery, SearchResponse,
+    SearchResult, BrowseDocumentsQuery, BrowseDocumentsResponse, DocumentDetail, QueryValidationResponse,
+    WarmIndexResponse, AnalyzeRequest, AnalyzeResponse, AnalyzedToken, ReindexResponse, TermFrequency,
+    TopTermsResponse, FlushResponse, TagFacetCount, TagFacetsResponse, SimilarDocumentsResponse, SearchBodyMode, SearchTimings,
+    ChangeKind, IndexHealthResponse, IndexHistoryResponse, LengthField, SortBy, SortOrder, DuplicateContentMode,
+    SearchColumns, SearchFormat, MatchMode, MAX_BROWSE_LIMIT, MAX_SEARCH_LIMIT,
 };
-use super::schema::{build_schema, doc_from_input, FieldNames};
+use super::changes::{ChangeFeed, ChangeSubscription, SubscribeError};
+use super::collector::{ScoreHistogram, StableTopDocs, TimeBudgetCollector};
+use super::encryption::{EncryptedDirectory, EncryptionKey};
+use super::error::IndexError;
+use super::language::{self, StopWords, LANGUAGES};
+use super::ngram;
+use super::tokenizer;
+use super::query::{apply_filters, describe_query, exclude_ids, parse_search_query, QUERYABLE_FIELDS};
+use super::text::truncate_at_word_boundary;
+use super::user_config::{Feature, UserConfig};
+use super::recent_deletes::RecentDeletes;
+use super::write_rate::WriteRateLimiter;
+use super::commit_history::CommitHistory;
+use super::similarity;
+use super::schema::{
+    body_capped, build_schema, doc_from_input, highlight_fields, input_checksum, refresh_doc, stored_body,
+    DocumentOptions, FieldNames,
+};
+
+/// Run a search phase inside a `debug`-level span of the same name, recording
+/// its wall time as the span's `duration_us` field
+///
+/// With `debug` disabled the span is a no-op, so this costs one level check
+/// and a clock read. The body runs in the caller's scope, so `?` propagates.
+/// Given a `SearchTimings` field, the wall time is also added to it in
+/// milliseconds.
+macro_rules! phase {
+    ($name:literal, $body:expr) => {{
+        let span = tracing::debug_span!($name, duration_us = tracing::field::Empty);
+        let started = Instant::now();
+        let entered = span.enter();
+        let result = $body;
+        drop(entered);
+        span.record("duration_us", started.elapsed().as_micros() as u64);
+        result
+    }};
+    ($name:literal, $total_ms:expr, $body:expr) => {{
+        let started = Instant::now();
+        let result = phase!($name, $body);
+        $total_ms += started.elapsed().as_secs_f64() * 1000.0;
+        result
+    }};
+}
+
+/// Per-request search options that do not come from the query body
+#[derive(Debug, Clone, Default)]
+pub struct SearchOptions {
+    /// Time budget for the search; when exceeded, partial results are returned
+    /// with `timed_out` set instead of failing the request
+    pub timeout: Option<Duration>,
+
+    /// Report the time spent in each phase in the response's `timings`
+    pub detailed_timings: bool,
+}
+
+/// Settings applied when opening a user's index
+#[derive(Debug, Clone)]
+struct HandleOptions {
+    writer_heap_bytes: usize,
+    merge_min_segments: usize,
+    merge_max_docs: usize,
+    merge_min_layer_size: u32,
+    encryption_key: Option<EncryptionKey>,
+}
+
+impl HandleOptions {
+    fn from_config(config: &Config) -> Self {
+        HandleOptions {
+            writer_heap_bytes: config.writer_heap_bytes,
+            merge_min_segments: config.merge_min_segments,
+            merge_max_docs: config.merge_max_docs,
+            merge_min_layer_size: config.merge_min_layer_size,
+            encryption_key: config.index_encryption_key.clone(),
+        }
+    }
+
+    /// Open the on-disk directory backing a user's index
+    ///
+    /// With an encryption key configured, files are transparently encrypted
+    /// under a key derived for this user.
+    fn open_directory(&self, index_path: &Path, user_id: Uuid) -> Result<Box<dyn Directory>> {
+        let mmap = MmapDirectory::open(index_path)
+            .with_context(|| format!("Failed to open index directory {:?}", index_path))?;
+
+        Ok(match &self.encryption_key {
+            Some(key) => Box::new(EncryptedDirectory::new(mmap, key.for_user(user_id))),
+            None => Box::new(mmap),
+        })
+    }
+
+    /// Build the writer's merge policy
+    ///
+    /// Fewer segments per merge keeps read-heavy indexes compact at the cost of
+    /// more merge IO; larger values suit write-heavy tenants.
+    fn merge_policy(&self) -> LogMergePolicy {
+        let mut policy = LogMergePolicy::default();
+        policy.set_min_num_segments(self.merge_min_segments);
+        policy.set_max_docs_before_merge(self.merge_max_docs);
+        policy.set_min_layer_size(self.merge_min_layer_size);
+        policy
+    }
+}
 
 /// Handle to a user's index with reader and writer
 struct IndexHandle {
@@ -23,41 +142,110 @@ struct IndexHandle {
     writer: Arc<tokio::sync::Mutex<IndexWriter>>,
     reader: IndexReader,
     schema: Schema,
+    /// Opstamp of the latest commit, exposed to clients as an opaque generation token
+    generation: AtomicU64,
+    /// Last computed analytics, valid while the generation is unchanged
+    analytics_cache: std::sync::Mutex<Option<IndexAnalytics>>,
+    /// Tick of the most recent access, used to pick eviction victims
+    last_used: AtomicU64,
+    /// Features switched off in the user's config
+    disabled_features: BTreeSet<Feature>,
+    /// Query field aliases from the user's config
+    field_aliases: BTreeMap<String, String>,
+    /// Indexing rate from the user's config, overriding the global one
+    max_index_rate_per_minute: Option<u32>,
+    /// Source of documents indexed without one, overriding the global default
+    default_source: Option<String>,
+    /// Duplicate content handling from the user's config, overriding the global one
+    duplicate_content: Option<DuplicateContentMode>,
+    /// Bytes of each body stored when the index caps stored bodies
+    stored_body_max_bytes: Option<usize>,
+    /// Searches from the user's config to run when the index is opened
+    warmup_queries: Vec<String>,
+    /// Warmup queries that ran without error
+    warmup_queries_run: AtomicUsize,
+    /// Set when a reindex replaced this handle; writes through it must be retried on the new one
+    retired: AtomicBool,
 }
 
 impl IndexHandle {
     /// Create a new index handle for a user
-    fn new(index_path: PathBuf) -> Result<Self> {
-        let schema = build_schema();
-
+    fn new(
+        index_path: PathBuf,
+        user_id: Uuid,
+        options: &HandleOptions,
+        user_config: &UserConfig,
+    ) -> Result<Self> {
         // Create or open the index
         let index = if index_path.exists() {
-            Index::open_in_dir(&index_path)
+            Index::open(options.open_directory(&index_path, user_id)?)
                 .with_context(|| format!("Failed to open index at {:?}", index_path))?
         } else {
             std::fs::create_dir_all(&index_path)
                 .with_context(|| format!("Failed to create index directory: {:?}", index_path))?;
-            Index::create_in_dir(&index_path, schema.clone())
-                .with_context(|| format!("Failed to create index at {:?}", index_path))?
+            Index::create(
+                options.open_directory(&index_path, user_id)?,
+                build_schema(&user_config.schema_options()),
+                IndexSettings::default(),
+            )
+            .with_context(|| format!("Failed to create index at {:?}", index_path))?
         };
 
-        // Create writer with 50MB heap
+        // Per-language and n-gram fields name their analyzer; it must exist before the writer does
+        language::register_analyzers(index.tokenizers());
+        tokenizer::register_analyzers(&index.schema(), index.tokenizers());
+        ngram::register_analyzers(&index.schema(), index.tokenizers());
+
+        // Use the on-disk schema so indexes created before a field was added keep working
+        let schema = index.schema();
+
         let writer = index
-            .writer(50_000_000)
+            .writer(options.writer_heap_bytes)
             .context("Failed to create index writer")?;
+        writer.set_merge_policy(Box::new(options.merge_policy()));
 
         // Create reader - will reload automatically or manually as needed
         let reader = index
             .reader()
             .context("Failed to create index reader")?;
 
+        // Seed the generation from the last persisted commit so it stays monotonic across restarts
+        let generation = index
+            .load_metas()
+            .context("Failed to load index metadata")?
+            .opstamp;
+
         Ok(IndexHandle {
             index,
             writer: Arc::new(tokio::sync::Mutex::new(writer)),
             reader,
             schema,
+            generation: AtomicU64::new(generation),
+            analytics_cache: std::sync::Mutex::new(None),
+            last_used: AtomicU64::new(0),
+            disabled_features: user_config.disabled_features.clone(),
+            field_aliases: user_config.field_aliases.clone(),
+            max_index_rate_per_minute: user_config.max_index_rate_per_minute,
+            default_source: user_config.default_source.clone(),
+            duplicate_content: user_config.duplicate_content,
+            stored_body_max_bytes: user_config.stored_body_max_bytes,
+            warmup_queries: user_config.warmup_queries.clone(),
+            warmup_queries_run: AtomicUsize::new(0),
+            retired: AtomicBool::new(false),
         })
     }
+
+    /// Commit pending writes and advance the generation token
+    fn commit(&self, writer: &mut IndexWriter) -> Result<u64> {
+        let opstamp = writer.commit()?;
+        self.generation.fetch_max(opstamp, Ordering::SeqCst);
+        Ok(opstamp)
+    }
+
+    /// Current generation token
+    fn generation(&self) -> u64 {
+        self.generation.load(Ordering::SeqCst)
+    }
 }
 
 /// Manages per-user Tantivy indexes with strong isolation
@@ -71,15 +259,450 @@ pub struct IndexManager {
 
     /// Cache of opened indexes, keyed by user ID
     indexes: Arc<RwLock<HashMap<Uuid, Arc<IndexHandle>>>>,
+
+    /// Maximum time a write waits to acquire a user's writer
+    writer_lock_timeout: Duration,
+
+    /// Options applied when converting inputs into documents
+    document_options: DocumentOptions,
+
+    /// Options applied when opening indexes
+    handle_options: HandleOptions,
+
+    /// Snippet source fields in priority order
+    snippet_fields: Vec<String>,
+
+    /// Budget for the serialized results of one search or browse response
+    max_response_bytes: usize,
+
+    /// Result bodies returned by searches that don't choose
+    search_body_mode: SearchBodyMode,
+
+    /// Length of previewed result bodies
+    preview_default_chars: usize,
+
+    /// Recent index open failures, so a broken index isn't reopened on every request
+    open_failures: std::sync::Mutex<HashMap<Uuid, OpenFailure>>,
+
+    /// How long an open failure is remembered
+    open_retry_cooldown: Duration,
+
+    /// Maximum number of unpinned indexes kept open (0 = unbounded)
+    cache_capacity: usize,
+
+    /// Users whose indexes are never evicted and don't count toward the capacity
+    pinned: std::sync::RwLock<HashSet<Uuid>>,
+
+    /// Monotonic counter stamped on handles as they are used
+    access_clock: AtomicU64,
+
+    /// Global limit on tenants holding their writer at once (`None` = unlimited)
+    writer_permits: Option<Arc<Semaphore>>,
+
+    /// Order equally scored search hits by document ID
+    tie_break_by_id: bool,
+
+    /// Accept queries that scan every document or much of the term dictionary
+    allow_expensive_queries: bool,
+
+    /// Run the warmup queries of a user's config when their index is opened
+    warmup_queries_enabled: bool,
+
+    /// Whether bulk indexes are committed once or in heap-sized chunks
+    bulk_commit_mode: BulkCommitMode,
+
+    /// How writes duplicating another document's content are handled, unless the user's config says
+    duplicate_content: DuplicateContentMode,
+
+    /// Extra attempts a bulk index makes while the writer is busy
+    bulk_retry_attempts: u32,
+
+    /// Wait before the first bulk retry, doubled for each further one
+    bulk_retry_backoff: Duration,
+
+    /// Users whose index is being rebuilt; their writes are rejected until the swap
+    reindexing: std::sync::Mutex<HashSet<Uuid>>,
+
+    /// Users whose old index a reindex has taken out of the cache to swap;
+    /// opens wait for the new one instead of reopening the old directory
+    swapping: std::sync::Mutex<HashSet<Uuid>>,
+
+    /// Documents a user may index per minute unless their config says otherwise (0 = unlimited)
+    max_index_rate_per_minute: u32,
+
+    /// Per-user indexing rate buckets
+    write_rate: WriteRateLimiter,
+
+    /// Recent commits of each user's index
+    commit_history: CommitHistory,
+
+    /// Segment count above which an index needs optimizing
+    optimize_max_segments: usize,
+
+    /// Deleted-document share above which an index needs optimizing
+    optimize_max_deleted_ratio: f64,
+
+    /// Live feed of indexed and deleted documents for `/v1/changes`
+    changes: Arc<ChangeFeed>,
+
+    /// IDs recently removed by bulk deletes, reported deleted again on a retry
+    recent_deletes: RecentDeletes,
+}
+
+/// A user's index writer, held together with a global writer permit
+///
+/// Dropping the guard releases both.
+struct WriterGuard<'a> {
+    writer: MutexGuard<'a, IndexWriter>,
+    _permit: Option<OwnedSemaphorePermit>,
+}
+
+impl std::ops::Deref for WriterGuard<'_> {
+    type Target = IndexWriter;
+
+    fn deref(&self) -> &IndexWriter {
+        &self.writer
+    }
+}
+
+impl std::ops::DerefMut for WriterGuard<'_> {
+    fn deref_mut(&mut self) -> &mut IndexWriter {
+        &mut self.writer
+    }
+}
+
+/// Hits collected per warmup query, as many as a default search returns
+const WARMUP_QUERY_LIMIT: usize = 10;
+
+/// Directory a reindex builds the new index in, next to `index`
+const REBUILD_DIR: &str = "index.reindex";
+
+/// Where the replaced index is moved during the swap before being deleted
+const RETIRED_DIR: &str = "index.old";
+
+/// Removes a user from a set of in-progress operations when it ends, however it ends
+struct ReindexGuard<'a> {
+    users: &'a std::sync::Mutex<HashSet<Uuid>>,
+    user_id: Uuid,
+}
+
+impl Drop for ReindexGuard<'_> {
+    fn drop(&mut self) {
+        self.users.lock().unwrap().remove(&self.user_id);
+    }
+}
+
+/// Finish or undo a reindex swap that a crash interrupted
+///
+/// The swap moves `index` to `index.old` and then `index.reindex` to
+/// `index`. Without `index` the rebuild may be incomplete, so the old index
+/// is moved back; with it the swap finished and `index.old` is left over.
+fn recover_interrupted_swap(user_id: Uuid, user_dir: &Path) -> Result<()> {
+    let index_path = user_dir.join("index");
+    let retired_path = user_dir.join(RETIRED_DIR);
+    if !retired_path.exists() {
+        return Ok(());
+    }
+
+    if index_path.exists() {
+        warn!(user_id = %user_id, "Removing index left over from an interrupted reindex");
+        std::fs::remove_dir_all(&retired_path)
+            .with_context(|| format!("Failed to remove {:?}", retired_path))?;
+    } else {
+        warn!(user_id = %user_id, "Restoring index moved aside by an interrupted reindex");
+        std::fs::rename(&retired_path, &index_path)
+            .with_context(|| format!("Failed to restore {:?}", retired_path))?;
+    }
+    Ok(())
+}
+
+/// Inputs of an index rebuild, owned so it can run on a blocking thread
+struct IndexRebuild {
+    user_id: Uuid,
+    /// Searcher over the index being rebuilt
+    searcher: Searcher,
+    old_schema: Schema,
+    /// Directory the new index is built in
+    path: PathBuf,
+    schema: Schema,
+    document_options: DocumentOptions,
+    handle_options: HandleOptions,
+    /// The new index's opstamp is raised above this generation
+    previous_generation: u64,
+}
+
+impl IndexRebuild {
+    /// Copy every live document of `searcher` into a new index at `path`
+    ///
+    /// Fields are matched by name, so documents pick up the current schema;
+    /// fields it no longer has are dropped. Returns the number of documents copied.
+    fn run(self) -> Result<u64> {
+        // Left over from an interrupted rebuild
+        if self.path.exists() {
+            std::fs::remove_dir_all(&self.path)
+                .with_context(|| format!("Failed to clear {:?}", self.path))?;
+        }
+        std::fs::create_dir_all(&self.path)
+            .with_context(|| format!("Failed to create rebuild directory: {:?}", self.path))?;
+
+        let index = Index::create(
+            self.handle_options.open_directory(&self.path, self.user_id)?,
+            self.schema.clone(),
+            IndexSettings::default(),
+        )
+        .with_context(|| format!("Failed to create index at {:?}", self.path))?;
+        language::register_analyzers(index.tokenizers());
+        tokenizer::register_analyzers(&self.schema, index.tokenizers());
+        ngram::register_analyzers(&self.schema, index.tokenizers());
+        let mut writer: IndexWriter = index
+            .writer(self.handle_options.writer_heap_bytes)
+            .context("Failed to create index writer")?;
+
+        let mut documents = 0;
+        for (segment_ord, segment_reader) in self.searcher.segment_readers().iter().enumerate() {
+            for doc_id in segment_reader.doc_ids_alive() {
+                let stored: TantivyDocument = self.searcher.doc(DocAddress::new(segment_ord as u32, doc_id))?;
+                let mut doc = TantivyDocument::new();
+                for field_value in stored.field_values() {
+                    let name = self.old_schema.get_field_name(field_value.field());
+                    if let Ok(field) = self.schema.get_field(name) {
+                        doc.add_field_value(field, field_value.value().clone());
+                    }
+                }
+                writer.add_document(refresh_doc(&self.schema, doc, &self.document_options))?;
+                documents += 1;
+            }
+        }
+        writer.commit()?;
+        writer.wait_merging_threads()?;
+
+        let mut metas = index.load_metas().context("Failed to load index metadata")?;
+        metas.opstamp = metas.opstamp.max(self.previous_generation + 1);
+        let mut buffer = serde_json::to_vec_pretty(&metas)?;
+        buffer.push(b'\n');
+        index
+            .directory()
+            .atomic_write(Path::new("meta.json"), &buffer)
+            .context("Failed to write rebuilt index metadata")?;
+
+        Ok(documents)
+    }
+}
+
+/// A failed attempt to open a user's index
+struct OpenFailure {
+    retry_at: Instant,
+    reason: String,
 }
 
 impl IndexManager {
-    /// Create a new IndexManager
+    /// Create a new IndexManager with default settings, for tests
+    #[cfg(test)]
     pub fn new(base_dir: PathBuf) -> Self {
+        Self::from_config(&Config {
+            data_dir: base_dir,
+            ..Config::default()
+        })
+    }
+
+    /// Create a new IndexManager using the settings from the application config
+    pub fn from_config(config: &Config) -> Self {
         IndexManager {
-            base_dir,
+            base_dir: config.data_dir.clone(),
             indexes: Arc::new(RwLock::new(HashMap::new())),
+            writer_lock_timeout: Duration::from_millis(config.writer_lock_timeout_ms),
+            document_options: DocumentOptions {
+                tag_dedup: config.tag_dedup,
+                created_at_source: config.created_at_source,
+                default_source: config.default_source.clone(),
+                stored_body_max_bytes: None,
+            },
+            handle_options: HandleOptions::from_config(config),
+            snippet_fields: config.snippet_fields.clone(),
+            max_response_bytes: config.max_response_bytes,
+            search_body_mode: config.search_body_mode,
+            preview_default_chars: config.preview_default_chars,
+            open_failures: std::sync::Mutex::new(HashMap::new()),
+            open_retry_cooldown: Duration::from_millis(config.index_open_retry_ms),
+            cache_capacity: config.index_cache_capacity,
+            pinned: std::sync::RwLock::new(config.pinned_users.iter().copied().collect()),
+            access_clock: AtomicU64::new(0),
+            writer_permits: (config.max_concurrent_writers > 0)
+                .then(|| Arc::new(Semaphore::new(config.max_concurrent_writers))),
+            tie_break_by_id: config.tie_break_by_id,
+            allow_expensive_queries: config.allow_expensive_queries,
+            warmup_queries_enabled: config.warmup_queries_enabled,
+            bulk_commit_mode: config.bulk_commit_mode,
+            duplicate_content: config.duplicate_content,
+            bulk_retry_attempts: config.bulk_retry_attempts,
+            bulk_retry_backoff: Duration::from_millis(config.bulk_retry_backoff_ms),
+            reindexing: std::sync::Mutex::new(HashSet::new()),
+            swapping: std::sync::Mutex::new(HashSet::new()),
+            max_index_rate_per_minute: config.max_index_rate_per_minute,
+            write_rate: WriteRateLimiter::default(),
+            commit_history: CommitHistory::new(config.index_history_max),
+            optimize_max_segments: config.optimize_max_segments,
+            optimize_max_deleted_ratio: config.optimize_max_deleted_ratio,
+            changes: Arc::new(ChangeFeed::new(
+                config.changes_max_connections,
+                config.changes_max_connections_per_user,
+            )),
+            recent_deletes: RecentDeletes::new(Duration::from_secs(config.delete_retry_window_secs)),
+        }
+    }
+
+    /// Commit a user's pending writes and record the commit in their history
+    fn commit(&self, user_id: Uuid, handle: &IndexHandle, writer: &mut IndexWriter, operations: u64) -> Result<u64> {
+        let generation = handle.commit(writer)?;
+        self.commit_history.record(user_id, generation, operations);
+        Ok(generation)
+    }
+
+    /// Acquire a user's index writer and a global writer permit for a write
+    ///
+    /// The per-user lock is taken first so that writes queued behind the same
+    /// tenant don't tie up permits other tenants could use. Permits are handed
+    /// out in FIFO order, so a burst of writes queues fairly instead of failing
+    /// or growing indexing memory without bound.
+    async fn lock_writer<'a>(&self, user_id: Uuid, handle: &'a IndexHandle) -> Result<WriterGuard<'a>> {
+        // Fail fast rather than queue behind a rebuild that holds the writer
+        if self.reindexing.lock().unwrap().contains(&user_id) {
+            return Err(IndexError::Rebuilding { retry_after_secs: self.retry_after_secs() }.into());
+        }
+
+        let writer = self.wait_for_writer(user_id, handle).await?;
+
+        // A reindex swapped the index while this write was queued
+        if handle.retired.load(Ordering::SeqCst) {
+            return Err(IndexError::Rebuilding { retry_after_secs: 1 }.into());
+        }
+
+        let permit = match &self.writer_permits {
+            Some(permits) => Some(match Arc::clone(permits).try_acquire_owned() {
+                Ok(permit) => permit,
+                Err(_) => {
+                    debug!(user_id = %user_id, "Waiting for a global writer permit");
+                    Arc::clone(permits)
+                        .acquire_owned()
+                        .await
+                        .context("Writer permits closed")?
+                }
+            }),
+            None => None,
+        };
+
+        Ok(WriterGuard { writer, _permit: permit })
+    }
+
+    /// `lock_writer` for bulk indexes, retrying `IndexError::Busy` with doubling backoff
+    ///
+    /// Nothing is written before the writer is held, so retrying can't apply
+    /// part of a batch twice. Any other error is returned at once. Also returns
+    /// the number of retries made.
+    async fn lock_writer_for_bulk<'a>(&self, user_id: Uuid, handle: &'a IndexHandle) -> Result<(WriterGuard<'a>, u32)> {
+        let mut backoff = self.bulk_retry_backoff;
+        let mut retries = 0;
+        loop {
+            match self.lock_writer(user_id, handle).await {
+                Err(e)
+                    if retries < self.bulk_retry_attempts
+                        && matches!(e.downcast_ref::<IndexError>(), Some(IndexError::Busy { .. })) =>
+                {
+                    retries += 1;
+                    warn!(
+                        user_id = %user_id,
+                        retry = retries,
+                        backoff_ms = backoff.as_millis() as u64,
+                        "Index writer busy, retrying bulk index"
+                    );
+                    tokio::time::sleep(backoff).await;
+                    backoff *= 2;
+                }
+                result => return result.map(|writer| (writer, retries)),
+            }
+        }
+    }
+
+    /// Acquire a user's index writer, failing with `IndexError::Busy` if it
+    /// cannot be obtained within the configured lock timeout
+    ///
+    /// This keeps interactive writes from queueing behind a long-running
+    /// operation until the HTTP timeout fires.
+    async fn wait_for_writer<'a>(
+        &self,
+        user_id: Uuid,
+        handle: &'a IndexHandle,
+    ) -> Result<MutexGuard<'a, IndexWriter>> {
+        match tokio::time::timeout(self.writer_lock_timeout, handle.writer.lock()).await {
+            Ok(guard) => Ok(guard),
+            Err(_) => {
+                warn!(
+                    user_id = %user_id,
+                    timeout_ms = self.writer_lock_timeout.as_millis() as u64,
+                    "Timed out waiting for index writer"
+                );
+                Err(IndexError::Busy { retry_after_secs: self.retry_after_secs() }.into())
+            }
+        }
+    }
+
+    /// Document options with the user's default source in place of the global one
+    fn document_options_for(&self, handle: &IndexHandle) -> DocumentOptions {
+        DocumentOptions {
+            default_source: handle.default_source.clone().or_else(|| self.document_options.default_source.clone()),
+            stored_body_max_bytes: handle.stored_body_max_bytes,
+            ..self.document_options.clone()
+        }
+    }
+
+    /// Duplicate content handling of a user's index; the user's config overrides the global one
+    fn duplicate_content_for(&self, handle: &IndexHandle) -> DuplicateContentMode {
+        handle.duplicate_content.unwrap_or(self.duplicate_content)
+    }
+
+    /// Documents a user may index per minute (0 = unlimited)
+    fn index_rate_for(&self, handle: &IndexHandle) -> u32 {
+        handle.max_index_rate_per_minute.unwrap_or(self.max_index_rate_per_minute)
+    }
+
+    /// Reject a batch larger than a whole minute's allowance, which could never be admitted
+    ///
+    /// Checked before waiting for the writer; takes nothing from the allowance.
+    fn check_batch_within_rate(&self, handle: &IndexHandle, documents: usize) -> Result<()> {
+        let per_minute = self.index_rate_for(handle);
+        if per_minute != 0 && documents > per_minute as usize {
+            return Err(IndexError::InvalidQuery(format!(
+                "{} documents exceed the indexing rate of {} per minute; split the request into smaller batches",
+                documents, per_minute
+            ))
+            .into());
+        }
+        Ok(())
+    }
+
+    /// Count `documents` against the user's indexing rate
+    ///
+    /// Called under the writer lock once every other check has passed, so a
+    /// write rejected as busy, conflicting or duplicate takes nothing. Fails
+    /// with `IndexError::RateLimited` when the user's bucket doesn't hold
+    /// enough tokens. The batch must already have passed `check_batch_within_rate`.
+    fn take_index_rate(&self, user_id: Uuid, handle: &IndexHandle, documents: usize) -> Result<()> {
+        let per_minute = self.index_rate_for(handle);
+        if per_minute == 0 {
+            return Ok(());
         }
+
+        self.write_rate
+            .acquire(user_id, documents as u32, per_minute, Instant::now())
+            .map_err(|retry_after_secs| {
+                warn!(user_id = %user_id, documents = documents, per_minute = per_minute, "Indexing rate exceeded");
+                IndexError::RateLimited { retry_after_secs }.into()
+            })
+    }
+
+    /// Suggested client backoff when a write cannot get the writer
+    fn retry_after_secs(&self) -> u64 {
+        self.writer_lock_timeout.as_secs_f64().ceil().max(1.0) as u64
     }
 
     /// Get or create an index handle for a user
@@ -88,6 +711,7 @@ impl IndexManager {
         {
             let indexes = self.indexes.read().await;
             if let Some(handle) = indexes.get(&user_id) {
+                self.touch(handle);
                 return Ok(Arc::clone(handle));
             }
         }
@@ -95,11 +719,33 @@ impl IndexManager {
         // Slow path: create new index
         let mut indexes = self.indexes.write().await;
 
+        // A reindex is swapping directories; wait for it to cache the new index.
+        // The swap is bounded by the writer lock timeout.
+        while self.swapping.lock().unwrap().contains(&user_id) {
+            drop(indexes);
+            tokio::time::sleep(Duration::from_millis(1)).await;
+            indexes = self.indexes.write().await;
+        }
+
         // Double-check in case another task created it
         if let Some(handle) = indexes.get(&user_id) {
+            self.touch(handle);
             return Ok(Arc::clone(handle));
         }
 
+        // Fail fast while a recent open failure is cooling down
+        if let Some(failure) = self.open_failures.lock().unwrap().get(&user_id) {
+            let now = Instant::now();
+            if failure.retry_at > now {
+                let remaining = failure.retry_at - now;
+                return Err(IndexError::Unavailable {
+                    retry_after_secs: remaining.as_secs_f64().ceil().max(1.0) as u64,
+                    reason: failure.reason.clone(),
+                }
+                .into());
+            }
+        }
+
         // Create index directory path: base_dir/{user_id}/index
         let user_dir = self.base_dir.join(user_id.to_string());
         let index_path = user_dir.join("index");
@@ -110,32 +756,267 @@ impl IndexManager {
             "Creating new index for user"
         );
 
-        let handle = Arc::new(IndexHandle::new(index_path)?);
+        let opened = recover_interrupted_swap(user_id, &user_dir)
+            .and_then(|_| UserConfig::load(&user_dir))
+            .and_then(|user_config| IndexHandle::new(index_path, user_id, &self.handle_options, &user_config));
+        let handle = match opened {
+            Ok(handle) => Arc::new(handle),
+            Err(e) => {
+                self.record_open_failure(user_id, &e);
+                return Err(e);
+            }
+        };
+        self.open_failures.lock().unwrap().remove(&user_id);
+        self.touch(&handle);
         indexes.insert(user_id, Arc::clone(&handle));
+        self.evict_idle(&mut indexes);
+        // Other users' indexes stay reachable while this one warms up
+        drop(indexes);
+
+        if self.warmup_queries_enabled {
+            self.run_warmup_queries(user_id, &handle);
+        }
 
         Ok(handle)
     }
 
+    /// Run the warmup queries of a freshly opened index
+    ///
+    /// Only their postings matter, so hits are collected but no documents are
+    /// read. A query that fails to parse or run is logged and skipped; it never
+    /// fails the open.
+    fn run_warmup_queries(&self, user_id: Uuid, handle: &IndexHandle) {
+        if handle.warmup_queries.is_empty() {
+            return;
+        }
+
+        let start = Instant::now();
+        let searcher = handle.reader.searcher();
+        for query in &handle.warmup_queries {
+            let searched = parse_search_query(
+                &handle.index,
+                &handle.schema,
+                query,
+                MatchMode::Standard,
+                &handle.field_aliases,
+                self.allow_expensive_queries,
+            )
+            .and_then(|parsed| Ok(searcher.search(&*parsed, &TopDocs::with_limit(WARMUP_QUERY_LIMIT))?));
+            match searched {
+                Ok(_) => {
+                    handle.warmup_queries_run.fetch_add(1, Ordering::Relaxed);
+                }
+                Err(e) => warn!(user_id = %user_id, query = %query, error = %e, "Skipping failed warmup query"),
+            }
+        }
+
+        info!(
+            user_id = %user_id,
+            queries = handle.warmup_queries_run.load(Ordering::Relaxed),
+            took_ms = start.elapsed().as_millis() as u64,
+            "Ran warmup queries"
+        );
+    }
+
+    /// Whether the user has an index, open or on disk
+    ///
+    /// Reads by users without one are answered as empty instead of creating
+    /// the index, so a never-seen user's search does no disk writes. A user
+    /// whose index recently failed to open counts as having one, so reads keep
+    /// reporting the failure during its cooldown.
+    async fn index_exists(&self, user_id: Uuid) -> bool {
+        self.indexes.read().await.contains_key(&user_id)
+            || self.open_failures.lock().unwrap().contains_key(&user_id)
+            || self.base_dir.join(user_id.to_string()).join("index").exists()
+            || self.base_dir.join(user_id.to_string()).join(RETIRED_DIR).exists()
+    }
+
+    /// Validate a search the way the user's index would, for a user without one
+    ///
+    /// Uses an in-memory index with the schema the user's config would create,
+    /// so invalid queries and filters are still rejected.
+    fn check_query_without_index(&self, user_id: Uuid, query: &SearchQuery) -> Result<Option<String>> {
+        let user_config = UserConfig::load(&self.base_dir.join(user_id.to_string()))?;
+        let index = Index::create_in_ram(build_schema(&user_config.schema_options()));
+        language::register_analyzers(index.tokenizers());
+        let schema = index.schema();
+        tokenizer::register_analyzers(&schema, index.tokenizers());
+        let parsed = parse_search_query(
+            &index,
+            &schema,
+            &query.query,
+            query.match_mode,
+            &user_config.field_aliases,
+            self.allow_expensive_queries,
+        )?;
+        let described = query.return_parsed_query.then(|| describe_query(&schema, &*parsed));
+        apply_filters(&schema, parsed, &query.filters)?;
+        Ok(described)
+    }
+
+    /// Mark a handle as most recently used
+    fn touch(&self, handle: &IndexHandle) {
+        let tick = self.access_clock.fetch_add(1, Ordering::Relaxed) + 1;
+        handle.last_used.store(tick, Ordering::Relaxed);
+    }
+
+    /// Close least recently used unpinned indexes until the cache is within capacity
+    ///
+    /// Handles still referenced by an in-flight request are skipped; closing
+    /// them would release nothing, and reopening the index while the old
+    /// writer is alive would fail on the writer lock.
+    fn evict_idle(&self, indexes: &mut HashMap<Uuid, Arc<IndexHandle>>) {
+        if self.cache_capacity == 0 {
+            return;
+        }
+
+        let pinned = self.pinned.read().unwrap();
+        loop {
+            let unpinned = indexes.keys().filter(|id| !pinned.contains(id)).count();
+            if unpinned <= self.cache_capacity {
+                return;
+            }
+
+            let victim = indexes
+                .iter()
+                .filter(|(id, handle)| !pinned.contains(id) && Arc::strong_count(handle) == 1)
+                .min_by_key(|(_, handle)| handle.last_used.load(Ordering::Relaxed))
+                .map(|(id, _)| *id);
+            let Some(victim) = victim else {
+                return;
+            };
+
+            indexes.remove(&victim);
+            debug!(user_id = %victim, "Evicted idle index from cache");
+        }
+    }
+
+    /// Keep a user's index resident regardless of recency
+    ///
+    /// Returns `false` if the user was already pinned.
+    pub fn pin_index(&self, user_id: Uuid) -> bool {
+        self.pinned.write().unwrap().insert(user_id)
+    }
+
+    /// Make a user's index evictable again
+    ///
+    /// Returns `false` if the user was not pinned. The cache is trimmed
+    /// immediately in case the index now pushes it over capacity.
+    pub async fn unpin_index(&self, user_id: Uuid) -> bool {
+        if !self.pinned.write().unwrap().remove(&user_id) {
+            return false;
+        }
+        let mut indexes = self.indexes.write().await;
+        self.evict_idle(&mut indexes);
+        true
+    }
+
+    /// Currently pinned users
+    pub fn pinned_indexes(&self) -> Vec<Uuid> {
+        let mut pinned: Vec<Uuid> = self.pinned.read().unwrap().iter().copied().collect();
+        pinned.sort();
+        pinned
+    }
+
+    /// Remember that a user's index failed to open so requests during the
+    /// cooldown fail fast instead of retrying the expensive open
+    fn record_open_failure(&self, user_id: Uuid, error: &anyhow::Error) {
+        if self.open_retry_cooldown.is_zero() {
+            return;
+        }
+
+        warn!(
+            user_id = %user_id,
+            error = %format!("{:#}", error),
+            cooldown_ms = self.open_retry_cooldown.as_millis() as u64,
+            "Index failed to open; failing fast until the cooldown expires"
+        );
+        self.open_failures.lock().unwrap().insert(
+            user_id,
+            OpenFailure {
+                retry_at: Instant::now() + self.open_retry_cooldown,
+                reason: format!("{:#}", error),
+            },
+        );
+    }
+
     /// Index or update a document for a user
     ///
     /// If a document with the same ID exists, it will be deleted and re-added.
+    /// The delete and the add are committed together: readers see either the
+    /// old version or the new one, never both or neither, and a crash before
+    /// the commit leaves the old version in place. Content another document
+    /// already has is rejected or skipped as an alias when configured.
     pub async fn index_document(
         &self,
         user_id: Uuid,
         input: IndexDocumentInput,
+    ) -> Result<IndexDocumentResponse> {
+        self.write_document(user_id, input, false).await
+    }
+
+    /// Index a document only if no document with its ID exists
+    ///
+    /// Fails with `IndexError::Conflict` instead of replacing an existing document.
+    pub async fn create_document(
+        &self,
+        user_id: Uuid,
+        input: IndexDocumentInput,
+    ) -> Result<IndexDocumentResponse> {
+        self.write_document(user_id, input, true).await
+    }
+
+    async fn write_document(
+        &self,
+        user_id: Uuid,
+        mut input: IndexDocumentInput,
+        create_only: bool,
     ) -> Result<IndexDocumentResponse> {
         let handle = self.get_or_create_index(user_id).await?;
+        check_input_storable(&handle.schema, &input)?;
+        self.check_batch_within_rate(&handle, 1)?;
 
-        let doc = doc_from_input(&handle.schema, &input)
-            .context("Failed to create document from input")?;
+        // Generated before the document is built, so the stored ID is the one reported
+        let doc_id = input.id.get_or_insert_with(|| Uuid::new_v4().to_string()).clone();
 
-        let doc_id = input.id.clone().unwrap_or_else(|| Uuid::new_v4().to_string());
+        let doc = doc_from_input(&handle.schema, &input, &self.document_options_for(&handle))
+            .context("Failed to create document from input")?;
 
         let id_field = handle.schema
             .get_field(FieldNames::ID)
             .context("ID field not found in schema")?;
 
-        let mut writer = handle.writer.lock().await;
+        let mut writer = self.lock_writer(user_id, &handle).await?;
+
+        // Check existence under the writer lock so a concurrent write can't slip in between
+        if create_only {
+            handle.reader.reload()?;
+            if document_exists(&handle.reader.searcher(), id_field, &doc_id)? {
+                return Err(IndexError::Conflict(format!("Document '{}' already exists", doc_id)).into());
+            }
+        }
+
+        // Also under the lock, so two concurrent writes of the same content can't both pass
+        let duplicate_content = self.duplicate_content_for(&handle);
+        if duplicate_content != DuplicateContentMode::Allow {
+            handle.reader.reload()?;
+            let checksum = input_checksum(&input, self.document_options.tag_dedup);
+            let existing = find_same_content(&handle.reader.searcher(), &handle.schema, &checksum, |id| id == doc_id)?;
+            if let Some(existing) = existing {
+                if duplicate_content == DuplicateContentMode::Reject {
+                    return Err(IndexError::Conflict(duplicate_content_message(&doc_id, &existing)).into());
+                }
+                return Ok(IndexDocumentResponse {
+                    id: doc_id,
+                    status: "alias".to_string(),
+                    message: format!("Document '{}' has the same content; nothing was indexed", existing),
+                    generation: handle.generation(),
+                    alias_of: Some(existing),
+                });
+            }
+        }
+
+        self.take_index_rate(user_id, &handle, 1)?;
 
         // Delete existing document with the same ID (if any)
         let term = Term::from_field_text(id_field, &doc_id);
@@ -145,202 +1026,676 @@ impl IndexManager {
         writer.add_document(doc)?;
 
         // Commit changes
-        writer.commit()?;
+        let generation = self.commit(user_id, &handle, &mut writer, 1)?;
+        self.changes.publish(user_id, ChangeKind::Indexed, std::slice::from_ref(&doc_id), generation);
 
-        debug!(
-            user_id = %user_id,
-            doc_id = %doc_id,
-            "Document indexed successfully"
-        );
+        if logging::sample("document_indexed") {
+            debug!(
+                user_id = %user_id,
+                doc_id = %doc_id,
+                "Document indexed successfully"
+            );
+        }
 
         Ok(IndexDocumentResponse {
             id: doc_id,
             status: "success".to_string(),
             message: "Document indexed successfully".to_string(),
+            generation,
+            alias_of: None,
         })
     }
 
-    /// Delete a document by ID for a user
-    pub async fn delete_document(
+    /// Index or replace many documents for a user
+    ///
+    /// All writes are issued under a single writer lock and, by default,
+    /// committed once, so readers see either none or all of them. A batch that
+    /// outgrows the writer heap is flushed to disk in uncommitted segments,
+    /// which stays atomic. With `BulkCommitMode::Chunked`, a commit is made
+    /// instead each time the text written since the last one reaches a quarter
+    /// of the heap, and `partial_commits` counts them. A failure rolls back
+    /// whatever is not yet committed. Documents without an ID get a generated
+    /// UUID. When an ID repeats, only its last occurrence is indexed and the
+    /// earlier ones are reported as `overwritten`. With duplicate content
+    /// rejected, a document whose content another one has fails the whole
+    /// batch before anything is written; with aliases, it is skipped as `alias`.
+    pub async fn index_documents_bulk(
         &self,
         user_id: Uuid,
-        document_id: String,
-    ) -> Result<DeleteDocumentResponse> {
+        documents: Vec<IndexDocumentInput>,
+    ) -> Result<BulkIndexResponse> {
+        let start = Instant::now();
+
         let handle = self.get_or_create_index(user_id).await?;
 
         let id_field = handle.schema
             .get_field(FieldNames::ID)
             .context("ID field not found in schema")?;
 
-        let mut writer = handle.writer.lock().await;
+        for input in &documents {
+            check_input_storable(&handle.schema, input)?;
+        }
+        self.check_batch_within_rate(&handle, documents.len())?;
 
-        let term = Term::from_field_text(id_field, &document_id);
-        writer.delete_term(term);
-        writer.commit()?;
+        let documents: Vec<IndexDocumentInput> = documents
+            .into_iter()
+            .map(|mut input| {
+                input.id.get_or_insert_with(|| Uuid::new_v4().to_string());
+                input
+            })
+            .collect();
 
-        debug!(
-            user_id = %user_id,
-            doc_id = %document_id,
-            "Document deleted successfully"
-        );
+        // Position of the last occurrence of each ID; earlier ones are skipped
+        let last_seen: HashMap<&str, usize> = documents
+            .iter()
+            .enumerate()
+            .filter_map(|(i, input)| input.id.as_deref().map(|id| (id, i)))
+            .collect();
 
-        Ok(DeleteDocumentResponse {
-            id: document_id,
-            status: "success".to_string(),
-            message: "Document deleted successfully".to_string(),
-        })
-    }
+        // Indexing memory runs several times the raw text, so chunks stay well inside the heap
+        let chunk_bytes = self.handle_options.writer_heap_bytes / 4;
 
-    /// Search documents for a user
-    ///
-    /// This method ensures that only the user's own documents are searched.
-    pub async fn search(
+        let (mut writer, retries) = self.lock_writer_for_bulk(user_id, &handle).await?;
+
+        // Content already in the index or earlier in the batch, decided before anything is written
+        let mut alias_of: Vec<Option<String>> = vec![None; documents.len()];
+        let duplicate_content = self.duplicate_content_for(&handle);
+        if duplicate_content != DuplicateContentMode::Allow {
+            handle.reader.reload()?;
+            let searcher = handle.reader.searcher();
+            let mut batch_content: HashMap<String, &str> = HashMap::new();
+            for (i, input) in documents.iter().enumerate() {
+                let id = input.id.as_deref().unwrap_or_default();
+                if last_seen.get(id) != Some(&i) {
+                    continue;
+                }
+                let checksum = input_checksum(input, self.document_options.tag_dedup);
+                // Documents of this batch are being replaced, so their stored content doesn't count
+                let existing = match batch_content.get(&checksum) {
+                    Some(earlier) => Some(earlier.to_string()),
+                    None => find_same_content(&searcher, &handle.schema, &checksum, |existing| {
+                        last_seen.contains_key(existing)
+                    })?,
+                };
+                match existing {
+                    Some(existing) if duplicate_content == DuplicateContentMode::Reject => {
+                        let message = duplicate_content_message(id, &existing);
+                        return Err(IndexError::Conflict(format!("documents[{}]: {}", i, message)).into());
+                    }
+                    Some(existing) => alias_of[i] = Some(existing),
+                    None => {
+                        batch_content.insert(checksum, id);
+                    }
+                }
+            }
+        }
+
+        self.take_index_rate(user_id, &handle, documents.len())?;
+
+        let mut results = Vec::with_capacity(documents.len());
+        let mut indexed = 0;
+        let mut indexed_bytes = 0u64;
+        let mut partial_commits = 0u32;
+        let document_options = self.document_options_for(&handle);
+        let written = (|| -> Result<u64> {
+            let mut pending_bytes = 0;
+            let mut pending_docs = 0;
+            let mut pending_ids = Vec::new();
+            for (i, input) in documents.iter().enumerate() {
+                let id = input.id.clone().unwrap_or_default();
+                let status = if last_seen.get(id.as_str()) != Some(&i) {
+                    "overwritten"
+                } else if alias_of[i].is_some() {
+                    "alias"
+                } else {
+                    if self.bulk_commit_mode == BulkCommitMode::Chunked && pending_bytes >= chunk_bytes {
+                        let generation = self.commit(user_id, &handle, &mut writer, pending_docs)?;
+                        self.changes.publish(user_id, ChangeKind::Indexed, &pending_ids, generation);
+                        partial_commits += 1;
+                        pending_bytes = 0;
+                        pending_docs = 0;
+                        pending_ids.clear();
+                    }
+                    let doc = doc_from_input(&handle.schema, input, &document_options)
+                        .context("Failed to create document from input")?;
+                    writer.delete_term(Term::from_field_text(id_field, &id));
+                    writer.add_document(doc)?;
+                    pending_bytes += input.title.len() + input.body.len();
+                    pending_docs += 1;
+                    pending_ids.push(id.clone());
+                    indexed += 1;
+                    indexed_bytes += input_bytes(input);
+                    "indexed"
+                };
+                results.push(BulkIndexResult {
+                    id,
+                    status: status.to_string(),
+                    alias_of: alias_of[i].clone(),
+                });
+            }
+            let generation = self.commit(user_id, &handle, &mut writer, pending_docs)?;
+            self.changes.publish(user_id, ChangeKind::Indexed, &pending_ids, generation);
+            Ok(generation)
+        })();
+
+        let generation = match written {
+            Ok(generation) => generation,
+            Err(e) => {
+                // Pending writes would otherwise be committed by the next unrelated write
+                if let Err(rollback_error) = writer.rollback() {
+                    warn!(user_id = %user_id, error = %rollback_error, "Failed to roll back bulk index");
+                }
+                return Err(e);
+            }
+        };
+
+        let took_ms = start.elapsed().as_millis() as u64;
+        let overwritten = results.iter().filter(|result| result.status == "overwritten").count();
+
+        debug!(
+            user_id = %user_id,
+            indexed = indexed,
+            indexed_bytes = indexed_bytes,
+            overwritten = overwritten,
+            partial_commits = partial_commits,
+            retries = retries,
+            took_ms = took_ms,
+            "Bulk index completed"
+        );
+
+        Ok(BulkIndexResponse {
+            results,
+            indexed,
+            indexed_bytes,
+            overwritten,
+            partial_commits,
+            retries,
+            generation,
+            took_ms,
+        })
+    }
+
+    /// Re-add a stored document so it is tokenized with the current analyzers
+    ///
+    /// The document's stored values are reused unchanged. Returns `None` if the
+    /// user has no document with this ID.
+    pub async fn refresh_document(
         &self,
         user_id: Uuid,
-        query: SearchQuery,
-    ) -> Result<SearchResponse> {
-        let start = Instant::now();
-
+        document_id: &str,
+    ) -> Result<Option<IndexDocumentResponse>> {
         let handle = self.get_or_create_index(user_id).await?;
 
-        // Reload the reader to see latest commits
+        let id_field = handle.schema
+            .get_field(FieldNames::ID)
+            .context("ID field not found in schema")?;
+        let body_field = handle.schema
+            .get_field(FieldNames::BODY)
+            .context("Body field not found in schema")?;
+
+        // Re-adding a document whose body isn't stored would drop its body terms
+        if !handle.schema.get_field_entry(body_field).is_stored() {
+            return Err(IndexError::Unsupported(
+                "Documents cannot be refreshed unless whole bodies are stored; re-index them instead".to_string(),
+            )
+            .into());
+        }
+
+        let mut writer = self.lock_writer(user_id, &handle).await?;
+
+        // Read under the writer lock so a concurrent update isn't overwritten with stale values
         handle.reader.reload()?;
         let searcher = handle.reader.searcher();
+        let term = Term::from_field_text(id_field, document_id);
+        let query = TermQuery::new(term.clone(), IndexRecordOption::Basic);
+        let doc_address = match searcher.search(&query, &TopDocs::with_limit(1))?.first() {
+            Some((_, doc_address)) => *doc_address,
+            None => return Ok(None),
+        };
+        let stored: TantivyDocument = searcher.doc(doc_address)?;
 
-        // Build query parser for title and body fields
-        let title_field = handle.schema
-            .get_field(FieldNames::TITLE)
-            .context("Title field not found")?;
-        let body_field = handle.schema
-            .get_field(FieldNames::BODY)
-            .context("Body field not found")?;
+        writer.delete_term(term);
+        writer.add_document(refresh_doc(&handle.schema, stored, &self.document_options_for(&handle)))?;
+        let generation = self.commit(user_id, &handle, &mut writer, 1)?;
 
-        let query_parser = QueryParser::for_index(&handle.index, vec![title_field, body_field]);
+        debug!(
+            user_id = %user_id,
+            doc_id = %document_id,
+            "Document refreshed successfully"
+        );
 
-        // Parse the query
-        let parsed_query = query_parser
-            .parse_query(&query.query)
-            .context("Failed to parse search query")?;
+        Ok(Some(IndexDocumentResponse {
+            id: document_id.to_string(),
+            status: "success".to_string(),
+            message: "Document refreshed successfully".to_string(),
+            generation,
+            alias_of: None,
+        }))
+    }
 
-        // Execute search
-        let limit = query.limit.min(100); // Cap at 100 results
-        let offset = query.offset;
-        let top_docs = searcher.search(&parsed_query, &TopDocs::with_limit(limit + offset))?;
+    /// Delete a document by ID for a user
+    pub async fn delete_document(
+        &self,
+        user_id: Uuid,
+        document_id: String,
+    ) -> Result<DeleteDocumentResponse> {
+        let handle = self.get_or_create_index(user_id).await?;
 
-        // Convert results
-        let mut results = Vec::new();
-        let id_field = handle.schema.get_field(FieldNames::ID).context("ID field not found")?;
-        let created_at_field = handle.schema.get_field(FieldNames::CREATED_AT).ok();
+        let id_field = handle.schema
+            .get_field(FieldNames::ID)
+            .context("ID field not found in schema")?;
 
-        for (_score, doc_address) in top_docs.into_iter().skip(offset).take(limit) {
-            let retrieved_doc: TantivyDocument = searcher.doc(doc_address)?;
+        let mut writer = self.lock_writer(user_id, &handle).await?;
 
-            let id = retrieved_doc
-                .get_first(id_field)
-                .and_then(|v| v.as_str())
-                .unwrap_or("unknown")
-                .to_string();
+        let term = Term::from_field_text(id_field, &document_id);
+        writer.delete_term(term);
+        let generation = self.commit(user_id, &handle, &mut writer, 1)?;
+        self.changes.publish(user_id, ChangeKind::Deleted, std::slice::from_ref(&document_id), generation);
 
-            let title = retrieved_doc
-                .get_first(title_field)
-                .and_then(|v| v.as_str())
-                .unwrap_or("")
-                .to_string();
+        if logging::sample("document_deleted") {
+            debug!(
+                user_id = %user_id,
+                doc_id = %document_id,
+                "Document deleted successfully"
+            );
+        }
 
-            let body = retrieved_doc
-                .get_first(body_field)
-                .and_then(|v| v.as_str())
-                .unwrap_or("")
-                .to_string();
+        Ok(DeleteDocumentResponse {
+            id: document_id,
+            status: "success".to_string(),
+            message: "Document deleted successfully".to_string(),
+            generation,
+        })
+    }
 
-            let created_at = created_at_field
-                .and_then(|f| retrieved_doc.get_first(f))
-                .and_then(|v| v.as_str())
-                .map(|s| s.to_string());
+    /// Delete many documents by ID for a user
+    ///
+    /// All deletes are issued under a single writer lock and committed once,
+    /// which is much cheaper than one commit per document. Each ID is reported
+    /// as `deleted` or `not_found` based on the committed state before the call.
+    /// An ID a bulk delete removed within the retry window is reported
+    /// `deleted` again, so a retried request gets the original outcome.
+    /// Repeated IDs are reported and counted once, at their first position.
+    pub async fn delete_documents_bulk(
+        &self,
+        user_id: Uuid,
+        document_ids: Vec<String>,
+    ) -> Result<BulkDeleteResponse> {
+        let start = Instant::now();
 
-            results.push(SearchResult {
+        let handle = self.get_or_create_index(user_id).await?;
+
+        let id_field = handle.schema
+            .get_field(FieldNames::ID)
+            .context("ID field not found in schema")?;
+
+        let mut writer = self.lock_writer(user_id, &handle).await?;
+
+        // Check existence under the writer lock so no write can interleave
+        handle.reader.reload()?;
+        let searcher = handle.reader.searcher();
+
+        let now = Instant::now();
+        let mut results = Vec::with_capacity(document_ids.len());
+        let mut deleted_ids = Vec::new();
+        let mut deleted = 0;
+        let mut seen = HashSet::with_capacity(document_ids.len());
+        for id in document_ids {
+            if !seen.insert(id.clone()) {
+                continue;
+            }
+            let status = if document_exists(&searcher, id_field, &id)? {
+                writer.delete_term(Term::from_field_text(id_field, &id));
+                deleted_ids.push(id.clone());
+                "deleted"
+            } else if self.recent_deletes.contains(user_id, &id, now) {
+                // Already gone because of an earlier attempt; nothing to commit
+                "deleted"
+            } else {
+                "not_found"
+            };
+            if status == "deleted" {
+                deleted += 1;
+            }
+            results.push(BulkDeleteResult {
                 id,
-                title,
-                body, // Complete body, not truncated
-                score: _score,
-                created_at,
-                snippet: None, // TODO: Implement snippet generation
+                status: status.to_string(),
             });
         }
 
+        if !deleted_ids.is_empty() {
+            let generation = self.commit(user_id, &handle, &mut writer, deleted_ids.len() as u64)?;
+            self.changes.publish(user_id, ChangeKind::Deleted, &deleted_ids, generation);
+            self.recent_deletes.record(user_id, &deleted_ids, Instant::now());
+        }
+
         let took_ms = start.elapsed().as_millis() as u64;
-        let total = results.len();
+        let not_found = results.len() - deleted;
 
         debug!(
             user_id = %user_id,
-            query = %query.query,
-            results = total,
+            deleted = deleted,
+            not_found = not_found,
             took_ms = took_ms,
-            "Search completed"
+            "Bulk delete completed"
         );
 
-        Ok(SearchResponse {
+        Ok(BulkDeleteResponse {
             results,
-            total,
-            query: query.query,
+            deleted,
+            not_found,
             took_ms,
         })
     }
 
-    /// Get statistics about a user's index
-    pub async fn get_user_stats(&self, user_id: Uuid) -> Result<UserIndexStats> {
-        let handle = self.get_or_create_index(user_id).await?;
-
-        // Reload the reader to see latest commits
-        handle.reader.reload()?;
-        let searcher = handle.reader.searcher();
-
-        let num_docs = searcher.num_docs() as usize;
-
-        Ok(UserIndexStats {
-            user_id,
-            num_documents: num_docs,
-        })
+    /// Search documents for a user
+    ///
+    /// This method ensures that only the user's own documents are searched.
+    pub async fn search(
+        &self,
+        user_id: Uuid,
+        query: SearchQuery,
+    ) -> Result<SearchResponse> {
+        self.search_with_options(user_id, query, SearchOptions::default()).await
     }
 
-    /// Browse/list all documents for a user
+    /// Search documents for a user with per-request options
     ///
-    /// Returns complete documents without requiring a search query.
-    pub async fn browse_documents(
+    /// The reader is reloaded first, so every write that has returned is
+    /// visible. With `query.consistent`, a commit that finishes during the
+    /// reload triggers a second one, so the search reflects every commit made
+    /// before it runs. Neither waits for writes still holding the writer.
+    pub async fn search_with_options(
         &self,
         user_id: Uuid,
-        query: BrowseDocumentsQuery,
-    ) -> Result<BrowseDocumentsResponse> {
+        query: SearchQuery,
+        options: SearchOptions,
+    ) -> Result<SearchResponse> {
         let start = Instant::now();
+        let mut timings = SearchTimings::default();
+        let deadline = options.timeout.map(|timeout| start + timeout);
+
+        if !self.index_exists(user_id).await {
+            let parsed_query = self.check_query_without_index(user_id, &query)?;
+            return Ok(SearchResponse {
+                results: Vec::new(),
+                ids: query.ids_only.then(Vec::new),
+                columns: (query.format == SearchFormat::Columnar).then(SearchColumns::default),
+                total: 0,
+                matched: 0,
+                query: query.query,
+                took_ms: start.elapsed().as_millis() as u64,
+                generation: 0,
+                timed_out: false,
+                limit: query.limit.min(MAX_SEARCH_LIMIT),
+                capped: false,
+                truncated_by_size: false,
+                timings: options.detailed_timings.then_some(timings),
+                parsed_query,
+            });
+        }
 
         let handle = self.get_or_create_index(user_id).await?;
 
+        // Read the generation before reloading: the searcher reflects at least this commit
+        let mut generation = handle.generation();
+
         // Reload the reader to see latest commits
-        handle.reader.reload()?;
+        phase!("reload", timings.reload_ms, handle.reader.reload())?;
+        if query.consistent && handle.generation() > generation {
+            generation = handle.generation();
+            phase!("reload", timings.reload_ms, handle.reader.reload())?;
+        }
         let searcher = handle.reader.searcher();
 
-        // Get field handles
-        let id_field = handle.schema.get_field(FieldNames::ID).context("ID field not found")?;
-        let title_field = handle.schema.get_field(FieldNames::TITLE).context("Title field not found")?;
-        let body_field = handle.schema.get_field(FieldNames::BODY).context("Body field not found")?;
-        let created_at_field = handle.schema.get_field(FieldNames::CREATED_AT).ok();
-        let tags_field = handle.schema.get_field(FieldNames::TAGS).ok();
+        let title_field = handle.schema
+            .get_field(FieldNames::TITLE)
+            .context("Title field not found")?;
 
-        // Use a match-all query to get all documents
-        use tantivy::query::AllQuery;
-        let all_query = AllQuery;
+        // Parse the query against the title and body fields
+        let (parsed_query, described_query, query_terms) = phase!("parse", timings.parse_ms, {
+            let parsed_query = parse_search_query(
+                &handle.index,
+                &handle.schema,
+                &query.query,
+                query.match_mode,
+                &handle.field_aliases,
+                self.allow_expensive_queries,
+            )?;
+            let described_query = query.return_parsed_query.then(|| describe_query(&handle.schema, &*parsed_query));
+            // Taken before filtering: filter terms select documents but aren't what matched
+            let query_terms = query.return_matched_fields.then(|| {
+                let mut terms = Vec::new();
+                parsed_query.query_terms(&mut |term, _| terms.push(term.clone()));
+                terms
+            });
+            let filtered = apply_filters(&handle.schema, parsed_query, &query.filters)?;
+            (exclude_ids(&handle.schema, filtered, &query.exclude_ids)?, described_query, query_terms)
+        });
 
-        // Get all documents, limited by the query parameters
-        let limit = query.limit.min(1000); // Cap at 1000 documents
+        // Execute search
+        let limit = query.limit.min(MAX_SEARCH_LIMIT);
         let offset = query.offset;
-        let top_docs = searcher.search(&all_query, &TopDocs::with_limit(limit + offset))?;
+        let recency_boost = query.recency_boost.unwrap_or(0.0);
+        let now_secs = chrono::Utc::now().timestamp();
+        let adjust = move |segment_reader: &SegmentReader| adjust_score(segment_reader, recency_boost, now_secs);
+        let (top_docs, mut timed_out) = phase!("search", timings.search_ms, match query.sort {
+            SortBy::ByLength { field, order } => {
+                let field_name = length_field_name(&handle.schema, field)?;
+                let order = match order {
+                    SortOrder::Asc => Order::Asc,
+                    SortOrder::Desc => Order::Desc,
+                };
+                let (hits, timed_out) = search_within(
+                    &searcher,
+                    &parsed_query,
+                    TopDocs::with_limit(limit + offset).order_by_fast_field::<u64>(field_name, order),
+                    deadline,
+                )?;
+                (hits.into_iter().map(|(_, doc_address)| (0.0, doc_address)).collect(), timed_out)
+            }
+            SortBy::Relevance if self.tie_break_by_id => search_within(
+                &searcher,
+                &parsed_query,
+                StableTopDocs::with_limit(limit + offset, FieldNames::ID).tweak_score(adjust),
+                deadline,
+            )?,
+            SortBy::Relevance => search_within(
+                &searcher,
+                &parsed_query,
+                TopDocs::with_limit(limit + offset).tweak_score(adjust),
+                deadline,
+            )?,
+        });
+
+        let id_field = handle.schema.get_field(FieldNames::ID).context("ID field not found")?;
+        let max_score = top_docs.first().map_or(0.0, |(score, _)| *score);
+        let mut page: Vec<(Score, DocAddress)> = top_docs.into_iter().skip(offset).take(limit).collect();
+
+        match query.score_mode {
+            ScoreMode::Raw => {}
+            ScoreMode::Normalized => {
+                if max_score > 0.0 {
+                    for (score, _) in &mut page {
+                        *score /= max_score;
+                    }
+                }
+            }
+            // A second pass over every match; only the histogram is kept, not the hits
+            ScoreMode::Percentile if !page.is_empty() => {
+                let (distribution, histogram_timed_out) = phase!(
+                    "score_distribution",
+                    timings.search_ms,
+                    search_within(&searcher, &parsed_query, ScoreHistogram::new(max_score, adjust), deadline)?
+                );
+                timed_out |= histogram_timed_out;
+                for (score, _) in &mut page {
+                    *score = distribution.percentile(*score);
+                }
+            }
+            ScoreMode::Percentile => {}
+        }
+
+        if query.ids_only {
+            let ids = phase!("fetch_docs", timings.fetch_ms, scored_ids(&searcher, id_field, &page))?;
+            let took_ms = start.elapsed().as_millis() as u64;
+            let total = ids.len();
+
+            if logging::sample_with_latency("search", Some(took_ms)) {
+                debug!(
+                    user_id = %user_id,
+                    query = %query.query,
+                    results = total,
+                    took_ms = took_ms,
+                    "ID-only search completed"
+                );
+            }
+
+            return Ok(SearchResponse {
+                results: Vec::new(),
+                ids: Some(ids),
+                columns: None,
+                total,
+                matched: total,
+                query: query.query,
+                took_ms,
+                generation,
+                timed_out,
+                limit,
+                capped: false,
+                truncated_by_size: false,
+                timings: options.detailed_timings.then_some(timings),
+                parsed_query: described_query,
+            });
+        }
+
+        let docs = phase!("fetch_docs", timings.fetch_ms, {
+            page.iter()
+                .map(|(score, doc_address)| Ok((*score, searcher.doc::<TantivyDocument>(*doc_address)?)))
+                .collect::<Result<Vec<_>>>()?
+        });
+
+        if query.format == SearchFormat::Columnar {
+            let (columns, truncated_by_size) =
+                search_columns(&handle.schema, docs, ResponseBudget::new(self.max_response_bytes));
+            let took_ms = start.elapsed().as_millis() as u64;
+            let total = columns.ids.len();
+
+            if logging::sample_with_latency("search", Some(took_ms)) {
+                debug!(
+                    user_id = %user_id,
+                    query = %query.query,
+                    results = total,
+                    took_ms = took_ms,
+                    timed_out = timed_out,
+                    truncated_by_size = truncated_by_size,
+                    "Columnar search completed"
+                );
+            }
+
+            return Ok(SearchResponse {
+                results: Vec::new(),
+                ids: None,
+                columns: Some(columns),
+                total,
+                matched: page.len(),
+                query: query.query,
+                took_ms,
+                generation,
+                timed_out,
+                limit,
+                capped: false,
+                truncated_by_size,
+                timings: options.detailed_timings.then_some(timings),
+                parsed_query: described_query,
+            });
+        }
+
+        let highlighted = phase!("highlight", timings.highlight_ms, {
+            // Snippet generators in priority order; fields missing from older schemas are skipped
+            let mut snippet_generators = Vec::with_capacity(self.snippet_fields.len());
+            for name in &self.snippet_fields {
+                if let Ok(field) = handle.schema.get_field(name) {
+                    let generator = SnippetGenerator::create(&searcher, &*parsed_query, field)?;
+                    snippet_generators.push((name.as_str(), generator));
+                }
+            }
+
+            // A capped body is stored only as its prefix, which the body's generators highlight
+            let body_prefix_field = handle.schema.get_field(FieldNames::BODY_PREFIX).ok();
+
+            let mut highlight_generators = Vec::new();
+            if query.highlight {
+                for (field, name) in highlight_fields(&handle.schema) {
+                    let generator = SnippetGenerator::create(&searcher, &*parsed_query, field)?;
+                    highlight_generators.push((name, field, generator));
+                }
+            }
 
-        // Convert results
-        let mut documents = Vec::new();
+            docs.iter()
+                .map(|(_, retrieved_doc)| {
+                    // Take the first field in priority order with a highlighted fragment
+                    let (snippet_field, snippet) = snippet_generators
+                        .iter()
+                        .map(|(name, generator)| {
+                            let snippet = match body_prefix_field.filter(|_| *name == FieldNames::BODY) {
+                                Some(prefix_field) => retrieved_doc
+                                    .get_first(prefix_field)
+                                    .and_then(|value| value.as_str())
+                                    .map_or_else(Snippet::empty, |text| generator.snippet(text)),
+                                None => generator.snippet_from_doc(retrieved_doc),
+                            };
+                            (*name, snippet)
+                        })
+                        .find(|(_, snippet)| !snippet.is_empty())
+                        .map(|(name, snippet)| (Some(name.to_string()), Some(snippet.to_html())))
+                        .unwrap_or((None, None));
 
-        for (_score, doc_address) in top_docs.into_iter().skip(offset).take(limit) {
-            let retrieved_doc: TantivyDocument = searcher.doc(doc_address)?;
+                    // One fragment per matching value, so multi-valued fields like tags list each hit
+                    let mut highlights = HashMap::new();
+                    for (name, field, generator) in &highlight_generators {
+                        let text_field = match body_prefix_field {
+                            Some(prefix_field) if *name == FieldNames::BODY => prefix_field,
+                            _ => *field,
+                        };
+                        let fragments: Vec<String> = retrieved_doc
+                            .get_all(text_field)
+                            .filter_map(|value| value.as_str())
+                            .map(|text| generator.snippet(text))
+                            .filter(|snippet| !snippet.is_empty())
+                            .map(|snippet| snippet.to_html())
+                            .collect();
+                        if !fragments.is_empty() {
+                            highlights.insert(name.to_string(), fragments);
+                        }
+                    }
 
+                    (snippet_field, snippet, highlights)
+                })
+                .collect::<Vec<_>>()
+        });
+
+        // One postings lookup per query term and result
+        let matched_fields = match &query_terms {
+            Some(terms) => phase!("matched_fields", timings.highlight_ms, {
+                page.iter()
+                    .map(|(_, doc_address)| matched_fields(&searcher, &handle.schema, terms, *doc_address).map(Some))
+                    .collect::<Result<Vec<_>>>()?
+            }),
+            None => vec![None; page.len()],
+        };
+
+        // Convert results, stopping before the response outgrows its byte budget
+        let body_chars = self.result_body_chars(&query);
+        let mut results = Vec::with_capacity(docs.len());
+        let mut budget = ResponseBudget::new(self.max_response_bytes);
+        let mut truncated_by_size = false;
+        let created_at_field = handle.schema.get_field(FieldNames::CREATED_AT).ok();
+        let checksum_field = handle.schema.get_field(FieldNames::CHECKSUM).ok();
+        let external_id_field = handle.schema.get_field(FieldNames::EXTERNAL_ID).ok();
+
+        let annotations = highlighted.into_iter().zip(matched_fields);
+        for ((score, retrieved_doc), ((snippet_field, snippet, highlights), matched_fields)) in docs.into_iter().zip(annotations) {
             let id = retrieved_doc
                 .get_first(id_field)
                 .and_then(|v| v.as_str())
@@ -353,139 +1708,4067 @@ impl IndexManager {
                 .unwrap_or("")
                 .to_string();
 
-            let body = retrieved_doc
-                .get_first(body_field)
-                .and_then(|v| v.as_str())
-                .unwrap_or("")
-                .to_string();
+            let body = stored_body(&handle.schema, &retrieved_doc).unwrap_or("");
+            let (body, body_truncated) = match body_chars {
+                Some(max_chars) => truncate_at_word_boundary(body, max_chars),
+                None => (body, false),
+            };
+            let body_truncated = body_truncated || body_capped(&handle.schema, &retrieved_doc);
 
             let created_at = created_at_field
                 .and_then(|f| retrieved_doc.get_first(f))
                 .and_then(|v| v.as_str())
                 .map(|s| s.to_string());
 
-            // Extract tags from the document
-            let tags = if let Some(tags_f) = tags_field {
-                retrieved_doc
-                    .get_all(tags_f)
-                    .filter_map(|v| v.as_str().map(|s| s.to_string()))
-                    .collect()
-            } else {
-                Vec::new()
-            };
+            let checksum = checksum_field
+                .and_then(|f| retrieved_doc.get_first(f))
+                .and_then(|v| v.as_str())
+                .map(|s| s.to_string());
+
+            let external_id = external_id_field
+                .and_then(|f| retrieved_doc.get_first(f))
+                .and_then(|v| v.as_str())
+                .map(|s| s.to_string());
 
-            documents.push(DocumentDetail {
+            let result = SearchResult {
                 id,
+                external_id,
                 title,
-                body, // Full body, not truncated
+                body: body.to_string(),
+                body_truncated,
+                score,
                 created_at,
-                tags,
-            });
+                snippet,
+                snippet_field,
+                highlights,
+                checksum,
+                matched_fields,
+            };
+            if !budget.take(&result) {
+                truncated_by_size = true;
+                break;
+            }
+            results.push(result);
         }
 
         let took_ms = start.elapsed().as_millis() as u64;
-        let total = documents.len();
+        let total = results.len();
 
-        debug!(
-            user_id = %user_id,
-            documents = total,
-            took_ms = took_ms,
-            "Browse completed"
-        );
+        if logging::sample_with_latency("search", Some(took_ms)) {
+            debug!(
+                user_id = %user_id,
+                query = %query.query,
+                results = total,
+                took_ms = took_ms,
+                timed_out = timed_out,
+                truncated_by_size = truncated_by_size,
+                "Search completed"
+            );
+        }
 
-        Ok(BrowseDocumentsResponse {
-            documents,
+        Ok(SearchResponse {
+            results,
+            ids: None,
+            columns: None,
             total,
+            matched: page.len(),
+            query: query.query,
             took_ms,
+            generation,
+            timed_out,
+            limit,
+            capped: false,
+            truncated_by_size,
+            timings: options.detailed_timings.then_some(timings),
+            parsed_query: described_query,
         })
     }
-}
-
-/// Statistics about a user's index
-#[derive(Debug, Clone)]
-pub struct UserIndexStats {
-    pub user_id: Uuid,
-    pub num_documents: usize,
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::search::models::DocumentMetadata;
-    use tempfile::TempDir;
+    /// Characters of body each search result keeps, or `None` for the whole body
+    ///
+    /// `include_body: false` wins, then `body_max_chars`; `include_body: true`
+    /// alone asks for the whole body. A request setting neither gets the
+    /// `SEARCH_BODY_MODE` default, where no body is zero characters.
+    fn result_body_chars(&self, query: &SearchQuery) -> Option<usize> {
+        match (query.include_body, query.body_max_chars) {
+            (Some(false), _) => Some(0),
+            (_, Some(max_chars)) => Some(max_chars),
+            (Some(true), None) => None,
+            (None, None) => match self.search_body_mode {
+                SearchBodyMode::Full => None,
+                SearchBodyMode::Preview => Some(self.preview_default_chars),
+                SearchBodyMode::None => Some(0),
+            },
+        }
+    }
 
-    #[tokio::test]
-    async fn test_index_and_search() {
-        let temp_dir = TempDir::new().unwrap();
-        let manager = IndexManager::new(temp_dir.path().to_path_buf());
+    /// Check a user's index and repair it if a segment fails to open
+    ///
+    /// The cached handle is dropped so the index is opened from disk. Segments
+    /// that cannot be opened (e.g. files lost in an ungraceful shutdown) are
+    /// removed from `meta.json`, rolling the index back to the segments that are
+    /// still readable. If the metadata itself is unreadable the index is
+    /// reported as unrecoverable and left untouched.
+    pub async fn repair_index(&self, user_id: Uuid) -> Result<RepairReport> {
+        // Hold the cache lock for the whole repair so no request reopens the index meanwhile
+        let mut indexes = self.indexes.write().await;
 
-        let user_id = Uuid::new_v4();
+        if let Some(handle) = indexes.remove(&user_id) {
+            // Wait for in-flight writes before releasing the writer's directory lock
+            drop(self.wait_for_writer(user_id, &handle).await?);
+        }
 
-        // Index a document
+        let user_dir = self.base_dir.join(user_id.to_string());
+        recover_interrupted_swap(user_id, &user_dir)?;
+        let index_path = user_dir.join("index");
+        let mut report = RepairReport {
+            user_id: user_id.to_string(),
+            status: "healthy".to_string(),
+            action: "none".to_string(),
+            dropped_segments: Vec::new(),
+            documents_lost: 0,
+            diagnostic: None,
+        };
+
+        if !index_path.exists() {
+            report.status = "missing".to_string();
+            report.action = "no index exists for this user".to_string();
+            return Ok(report);
+        }
+
+        let opened = self
+            .handle_options
+            .open_directory(&index_path, user_id)
+            .and_then(|directory| Ok(Index::open(directory)?));
+        let index = match opened {
+            Ok(index) => index,
+            Err(e) => {
+                warn!(user_id = %user_id, error = %e, "Index metadata is unreadable");
+                report.status = "unrecoverable".to_string();
+                report.action = "index metadata could not be read; restore from backup or delete and reindex".to_string();
+                report.diagnostic = Some(format!("{:#}", e));
+                return Ok(report);
+            }
+        };
+
+        let mut metas = index.load_metas().context("Failed to load index metadata")?;
+        let mut healthy = Vec::with_capacity(metas.segments.len());
+        for segment_meta in metas.segments.drain(..) {
+            match SegmentReader::open(&index.segment(segment_meta.clone())) {
+                Ok(_) => healthy.push(segment_meta),
+                Err(e) => {
+                    warn!(
+                        user_id = %user_id,
+                        segment = %segment_meta.id().uuid_string(),
+                        error = %e,
+                        "Dropping unreadable segment"
+                    );
+                    report.documents_lost += segment_meta.num_docs();
+                    report.dropped_segments.push(segment_meta.id().uuid_string());
+                    report.diagnostic.get_or_insert_with(|| e.to_string());
+                }
+            }
+        }
+        metas.segments = healthy;
+
+        if !report.dropped_segments.is_empty() {
+            let mut buffer = serde_json::to_vec_pretty(&metas)?;
+            buffer.push(b'\n');
+            index
+                .directory()
+                .atomic_write(std::path::Path::new("meta.json"), &buffer)
+                .context("Failed to write repaired index metadata")?;
+
+            report.status = "repaired".to_string();
+            report.action = format!(
+                "rolled back to the last readable state by dropping {} segment(s)",
+                report.dropped_segments.len()
+            );
+        }
+        drop(index);
+
+        // Re-open through the normal path to confirm the index is usable again
+        let reopened = UserConfig::load(&self.base_dir.join(user_id.to_string())).and_then(
+            |user_config| IndexHandle::new(index_path, user_id, &self.handle_options, &user_config),
+        );
+        match reopened {
+            Ok(handle) => {
+                self.open_failures.lock().unwrap().remove(&user_id);
+                indexes.insert(user_id, Arc::new(handle));
+            }
+            Err(e) => {
+                report.status = "unrecoverable".to_string();
+                report.action = "index still fails to open after repair".to_string();
+                report.diagnostic = Some(format!("{:#}", e));
+            }
+        }
+
+        info!(
+            user_id = %user_id,
+            status = %report.status,
+            dropped_segments = report.dropped_segments.len(),
+            documents_lost = report.documents_lost,
+            "Index repair finished"
+        );
+
+        Ok(report)
+    }
+
+    /// Rebuild a user's index from its stored documents without interrupting searches
+    ///
+    /// The new index is built with the current schema and analyzers in a
+    /// sibling directory while the old one keeps serving reads. Writes are
+    /// rejected with `IndexError::Rebuilding` until the rebuild is done. The
+    /// swap takes the old handle out of the cache, waits for requests still
+    /// using it without holding the cache lock, moves the new directory into
+    /// place and opens it. Opens of this user's index wait for the swap; other
+    /// users are unaffected. The generation keeps increasing across the swap.
+    pub async fn reindex(&self, user_id: Uuid) -> Result<ReindexResponse> {
+        let start = Instant::now();
+
+        if !self.reindexing.lock().unwrap().insert(user_id) {
+            return Err(IndexError::Conflict("A reindex is already running for this index".to_string()).into());
+        }
+        let _reindexing = ReindexGuard {
+            users: &self.reindexing,
+            user_id,
+        };
+
+        let handle = self.get_or_create_index(user_id).await?;
+
+        let body_field = handle.schema
+            .get_field(FieldNames::BODY)
+            .context("Body field not found in schema")?;
+        if !handle.schema.get_field_entry(body_field).is_stored() {
+            return Err(IndexError::Unsupported(
+                "Indexes without whole stored bodies cannot be rebuilt; re-index the documents instead".to_string(),
+            )
+            .into());
+        }
+
+        let user_dir = self.base_dir.join(user_id.to_string());
+        let index_path = user_dir.join("index");
+        let rebuild_path = user_dir.join(REBUILD_DIR);
+        let retired_path = user_dir.join(RETIRED_DIR);
+        let user_config = UserConfig::load(&user_dir)?;
+
+        // Writes already queued finish first; new ones fail fast on the reindexing flag
+        let writer = self.wait_for_writer(user_id, &handle).await?;
+        handle.reader.reload()?;
+        let rebuild = IndexRebuild {
+            user_id,
+            searcher: handle.reader.searcher(),
+            old_schema: handle.schema.clone(),
+            path: rebuild_path.clone(),
+            schema: build_schema(&user_config.schema_options()),
+            document_options: DocumentOptions {
+                stored_body_max_bytes: user_config.stored_body_max_bytes,
+                ..self.document_options.clone()
+            },
+            handle_options: self.handle_options.clone(),
+            previous_generation: handle.generation(),
+        };
+        // Reads and rewrites the whole corpus, so it stays off the async workers
+        let documents = tokio::task::spawn_blocking(move || rebuild.run())
+            .await
+            .context("Index rebuild task failed")??;
+
+        // Take the old index out of the cache; until the new one is cached,
+        // opens wait instead of reopening the old directory
+        {
+            let mut indexes = self.indexes.write().await;
+            self.swapping.lock().unwrap().insert(user_id);
+            if indexes.get(&user_id).is_some_and(|cached| Arc::ptr_eq(cached, &handle)) {
+                indexes.remove(&user_id);
+            }
+        }
+        let _swapping = ReindexGuard {
+            users: &self.swapping,
+            user_id,
+        };
+
+        // Writes still queued on the old writer see the flag and return for a retry
+        handle.retired.store(true, Ordering::SeqCst);
+        drop(writer);
+
+        // Other users' indexes stay reachable while requests finish with the old handle
+        let deadline = Instant::now() + self.writer_lock_timeout;
+        while Arc::strong_count(&handle) > 1 {
+            if Instant::now() >= deadline {
+                handle.retired.store(false, Ordering::SeqCst);
+                self.indexes.write().await.insert(user_id, handle);
+                let _ = std::fs::remove_dir_all(&rebuild_path);
+                warn!(user_id = %user_id, "Reindex abandoned; old index still in use");
+                return Err(IndexError::Busy { retry_after_secs: self.retry_after_secs() }.into());
+            }
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+
+        // Closing the old handle releases its writer's directory lock before the move.
+        // A crash between the two renames is undone when the index is next opened.
+        drop(handle);
+        if retired_path.exists() {
+            std::fs::remove_dir_all(&retired_path)
+                .with_context(|| format!("Failed to clear {:?}", retired_path))?;
+        }
+        std::fs::rename(&index_path, &retired_path)
+            .with_context(|| format!("Failed to move {:?} aside", index_path))?;
+        if let Err(e) = std::fs::rename(&rebuild_path, &index_path) {
+            std::fs::rename(&retired_path, &index_path)?;
+            return Err(e).with_context(|| format!("Failed to move {:?} into place", rebuild_path));
+        }
+
+        let handle = match IndexHandle::new(index_path.clone(), user_id, &self.handle_options, &user_config) {
+            Ok(handle) => Arc::new(handle),
+            Err(e) => {
+                // Put the old index back; the next request reopens it
+                std::fs::rename(&index_path, &rebuild_path)?;
+                std::fs::rename(&retired_path, &index_path)?;
+                return Err(e.context("Rebuilt index failed to open"));
+            }
+        };
+        let generation = handle.generation();
+        self.commit_history.record(user_id, generation, documents);
+        self.touch(&handle);
+        self.indexes.write().await.insert(user_id, handle);
+        drop(_swapping);
+
+        if let Err(e) = std::fs::remove_dir_all(&retired_path) {
+            warn!(user_id = %user_id, error = %e, "Failed to remove the replaced index");
+        }
+
+        let took_ms = start.elapsed().as_millis() as u64;
+        info!(
+            user_id = %user_id,
+            documents = documents,
+            took_ms = took_ms,
+            "Index rebuilt"
+        );
+
+        Ok(ReindexResponse {
+            user_id: user_id.to_string(),
+            documents,
+            generation,
+            took_ms,
+        })
+    }
+
+    /// Get a document's title and the first `max_chars` characters of its body
+    ///
+    /// Returns `None` if the user has no document with this ID.
+    pub async fn preview_document(
+        &self,
+        user_id: Uuid,
+        document_id: &str,
+        max_chars: usize,
+    ) -> Result<Option<DocumentPreview>> {
+        let handle = self.get_or_create_index(user_id).await?;
+
+        handle.reader.reload()?;
+        let searcher = handle.reader.searcher();
+
+        let id_field = handle.schema.get_field(FieldNames::ID).context("ID field not found")?;
+        let title_field = handle.schema.get_field(FieldNames::TITLE).context("Title field not found")?;
+
+        let query = TermQuery::new(
+            Term::from_field_text(id_field, document_id),
+            IndexRecordOption::Basic,
+        );
+        let doc_address = match searcher.search(&query, &TopDocs::with_limit(1))?.first() {
+            Some((_, doc_address)) => *doc_address,
+            None => return Ok(None),
+        };
+        let doc: TantivyDocument = searcher.doc(doc_address)?;
+
+        let title = doc
+            .get_first(title_field)
+            .and_then(|v| v.as_str())
+            .unwrap_or("")
+            .to_string();
+        let body = stored_body(&handle.schema, &doc).unwrap_or("");
+        let (preview, truncated) = truncate_at_word_boundary(body, max_chars);
+        let truncated = truncated || body_capped(&handle.schema, &doc);
+
+        Ok(Some(DocumentPreview {
+            id: document_id.to_string(),
+            title,
+            preview: preview.to_string(),
+            truncated,
+        }))
+    }
+
+    /// Analyzer of a user's titles and bodies, for comparing texts by the terms the index holds
+    ///
+    /// Reflects the user's tokenizer, accent folding and token length. Users
+    /// without an index get the analyzer their config would create, and no
+    /// index is created.
+    pub async fn text_analyzer(&self, user_id: Uuid) -> Result<TextAnalyzer> {
+        if self.index_exists(user_id).await {
+            let handle = self.get_or_create_index(user_id).await?;
+            let body_field = handle.schema.get_field(FieldNames::BODY).context("Body field not found")?;
+            return Ok(handle.index.tokenizer_for_field(body_field)?);
+        }
+
+        let user_config = UserConfig::load(&self.base_dir.join(user_id.to_string()))?;
+        let index = Index::create_in_ram(build_schema(&user_config.schema_options()));
+        let schema = index.schema();
+        tokenizer::register_analyzers(&schema, index.tokenizers());
+        let body_field = schema.get_field(FieldNames::BODY).context("Body field not found")?;
+        Ok(index.tokenizer_for_field(body_field)?)
+    }
+
+    /// Title and body of a stored document as one text, for comparing documents
+    ///
+    /// Returns `None` when the document (or the user's index) doesn't exist.
+    /// Indexes that don't store bodies fail with `IndexError::Unsupported`,
+    /// since a title alone would misrepresent the document.
+    pub async fn document_text(&self, user_id: Uuid, document_id: &str) -> Result<Option<String>> {
+        if !self.index_exists(user_id).await {
+            return Ok(None);
+        }
+
+        let handle = self.get_or_create_index(user_id).await?;
+        let body_field = handle.schema.get_field(FieldNames::BODY).context("Body field not found")?;
+        if !handle.schema.get_field_entry(body_field).is_stored() {
+            return Err(IndexError::Unsupported(
+                "This index does not store whole document bodies; send the text inline instead".to_string(),
+            )
+            .into());
+        }
+
+        handle.reader.reload()?;
+        let searcher = handle.reader.searcher();
+
+        let id_field = handle.schema.get_field(FieldNames::ID).context("ID field not found")?;
+        let title_field = handle.schema.get_field(FieldNames::TITLE).context("Title field not found")?;
+
+        let query = TermQuery::new(
+            Term::from_field_text(id_field, document_id),
+            IndexRecordOption::Basic,
+        );
+        let doc_address = match searcher.search(&query, &TopDocs::with_limit(1))?.first() {
+            Some((_, doc_address)) => *doc_address,
+            None => return Ok(None),
+        };
+        let doc: TantivyDocument = searcher.doc(doc_address)?;
+
+        let title = doc.get_first(title_field).and_then(|v| v.as_str()).unwrap_or("");
+        let body = stored_body(&handle.schema, &doc).unwrap_or("");
+        Ok(Some(format!("{}\n{}", title, body)))
+    }
+
+    /// Documents that share the most significant body terms of a stored document
+    ///
+    /// Terms of the source body occurring at least `min_term_freq` times are
+    /// weighted by tf-idf against the index's term statistics, and the top
+    /// `max_query_terms` are searched for as optional clauses boosted by that
+    /// weight. Terms no other document contains can't find a neighbor and are
+    /// skipped. Returns `None` when the document (or the user's index) doesn't
+    /// exist; indexes that don't store bodies fail with `IndexError::Unsupported`.
+    pub async fn similar_documents(
+        &self,
+        user_id: Uuid,
+        document_id: &str,
+        limit: usize,
+        max_query_terms: usize,
+        min_term_freq: u32,
+    ) -> Result<Option<SimilarDocumentsResponse>> {
+        let start = Instant::now();
+        if !self.index_exists(user_id).await {
+            return Ok(None);
+        }
+
+        let handle = self.get_or_create_index(user_id).await?;
+        let body_field = handle.schema.get_field(FieldNames::BODY).context("Body field not found")?;
+        if !handle.schema.get_field_entry(body_field).is_stored() {
+            return Err(IndexError::Unsupported(
+                "This index does not store whole document bodies, so similar documents can't be found".to_string(),
+            )
+            .into());
+        }
+
+        handle.reader.reload()?;
+        let searcher = handle.reader.searcher();
+
+        let id_field = handle.schema.get_field(FieldNames::ID).context("ID field not found")?;
+        let title_field = handle.schema.get_field(FieldNames::TITLE).context("Title field not found")?;
+
+        let id_term = Term::from_field_text(id_field, document_id);
+        let id_query = TermQuery::new(id_term.clone(), IndexRecordOption::Basic);
+        let doc_address = match searcher.search(&id_query, &TopDocs::with_limit(1))?.first() {
+            Some((_, doc_address)) => *doc_address,
+            None => return Ok(None),
+        };
+        let source: TantivyDocument = searcher.doc(doc_address)?;
+
+        // Weight each candidate term by its frequency in the source and rarity in the index
+        let mut analyzer = handle.index.tokenizer_for_field(body_field)?;
+        let num_docs = searcher.num_docs() as f32;
+        let mut weighted = Vec::new();
+        for (text, term_freq) in
+            similarity::term_vector(&mut analyzer, stored_body(&handle.schema, &source).unwrap_or(""))
+        {
+            if term_freq < min_term_freq {
+                continue;
+            }
+            let term = Term::from_field_text(body_field, &text);
+            let doc_freq = searcher.doc_freq(&term)? as f32;
+            // The source itself accounts for one document when its body is indexed
+            if doc_freq < 2.0 {
+                continue;
+            }
+            let idf = (1.0 + (num_docs - doc_freq + 0.5) / (doc_freq + 0.5)).ln();
+            weighted.push((text, term, term_freq as f32 * idf));
+        }
+        // Ties are broken by term so the seed terms don't depend on hash order
+        weighted.sort_by(|a, b| b.2.total_cmp(&a.2).then_with(|| a.0.cmp(&b.0)));
+        weighted.truncate(max_query_terms);
+
+        let max_weight = weighted.first().map_or(1.0, |(_, _, weight)| *weight);
+        let mut clauses: Vec<(Occur, Box<dyn Query>)> = weighted
+            .iter()
+            .map(|(_, term, weight)| {
+                let query = TermQuery::new(term.clone(), IndexRecordOption::WithFreqs);
+                let boosted: Box<dyn Query> = Box::new(BoostQuery::new(Box::new(query), weight / max_weight));
+                (Occur::Should, boosted)
+            })
+            .collect();
+
+        let top_docs = if clauses.is_empty() {
+            Vec::new()
+        } else {
+            clauses.push((Occur::MustNot, Box::new(id_query)));
+            searcher.search(&BooleanQuery::new(clauses), &TopDocs::with_limit(limit))?
+        };
+
+        let created_at_field = handle.schema.get_field(FieldNames::CREATED_AT).ok();
+        let checksum_field = handle.schema.get_field(FieldNames::CHECKSUM).ok();
+        let external_id_field = handle.schema.get_field(FieldNames::EXTERNAL_ID).ok();
+        let stored_text = |doc: &TantivyDocument, field: Option<Field>| {
+            field
+                .and_then(|f| doc.get_first(f))
+                .and_then(|v| v.as_str())
+                .map(|s| s.to_string())
+        };
+
+        let mut results = Vec::with_capacity(top_docs.len());
+        let mut budget = ResponseBudget::new(self.max_response_bytes);
+        for (score, doc_address) in top_docs {
+            let doc: TantivyDocument = searcher.doc(doc_address)?;
+            let result = SearchResult {
+                id: stored_text(&doc, Some(id_field)).unwrap_or_else(|| "unknown".to_string()),
+                external_id: stored_text(&doc, external_id_field),
+                title: stored_text(&doc, Some(title_field)).unwrap_or_default(),
+                body: stored_body(&handle.schema, &doc).unwrap_or("").to_string(),
+                body_truncated: body_capped(&handle.schema, &doc),
+                score,
+                created_at: stored_text(&doc, created_at_field),
+                snippet: None,
+                snippet_field: None,
+                highlights: HashMap::new(),
+                checksum: stored_text(&doc, checksum_field),
+                matched_fields: None,
+            };
+            if !budget.take(&result) {
+                break;
+            }
+            results.push(result);
+        }
+
+        let took_ms = start.elapsed().as_millis() as u64;
+        debug!(
+            user_id = %user_id,
+            document_id = %document_id,
+            seed_terms = weighted.len(),
+            results = results.len(),
+            took_ms = took_ms,
+            "Similar documents search completed"
+        );
+
+        Ok(Some(SimilarDocumentsResponse {
+            id: document_id.to_string(),
+            terms: weighted.into_iter().map(|(text, _, _)| text).collect(),
+            total: results.len(),
+            results,
+            took_ms,
+        }))
+    }
+
+    /// Parse a query and apply its filters without searching
+    ///
+    /// Syntax errors, unknown fields and invalid filters are reported in the
+    /// response rather than as errors; failures to open the index are not.
+    pub async fn validate_query(&self, user_id: Uuid, query: &SearchQuery) -> Result<QueryValidationResponse> {
+        let handle = self.get_or_create_index(user_id).await?;
+
+        let parsed = parse_search_query(
+            &handle.index,
+            &handle.schema,
+            &query.query,
+            query.match_mode,
+            &handle.field_aliases,
+            self.allow_expensive_queries,
+        )
+            .and_then(|parsed| {
+                let mut fields = BTreeSet::new();
+                parsed.query_terms(&mut |term, _| {
+                    fields.insert(handle.schema.get_field_name(term.field()).to_string());
+                });
+                apply_filters(&handle.schema, parsed, &query.filters)?;
+                Ok(fields)
+            });
+
+        match parsed {
+            Ok(fields) => Ok(QueryValidationResponse {
+                valid: true,
+                error: None,
+                interpreted_fields: fields.into_iter().collect(),
+            }),
+            Err(e) if is_query_error(&e) => Ok(QueryValidationResponse {
+                valid: false,
+                error: Some(format!("{:#}", e)),
+                interpreted_fields: Vec::new(),
+            }),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Whether the user's config leaves `feature` enabled
+    pub async fn feature_enabled(&self, user_id: Uuid, feature: Feature) -> Result<bool> {
+        // Don't create an index just to answer this for a user without one
+        if !self.index_exists(user_id).await {
+            let user_config = UserConfig::load(&self.base_dir.join(user_id.to_string()))?;
+            return Ok(!user_config.disabled_features.contains(&feature));
+        }
+
+        let handle = self.get_or_create_index(user_id).await?;
+        Ok(!handle.disabled_features.contains(&feature))
+    }
+
+    /// Run text through an analyzer of the user's index, without indexing it
+    ///
+    /// The analyzer is the one configured for `field`, or the registered
+    /// analyzer named `analyzer`. Unknown fields and analyzers, and fields that
+    /// are not tokenized text, fail with `IndexError::InvalidQuery`.
+    pub async fn analyze(&self, user_id: Uuid, request: &AnalyzeRequest) -> Result<AnalyzeResponse> {
+        let handle = self.get_or_create_index(user_id).await?;
+
+        let name = match (&request.field, &request.analyzer) {
+            (Some(field_name), _) => {
+                let field = handle.schema.get_field(field_name).map_err(|_| {
+                    IndexError::InvalidQuery(format!("Unknown field '{}'", field_name))
+                })?;
+                match handle.schema.get_field_entry(field).field_type() {
+                    FieldType::Str(options) => options
+                        .get_indexing_options()
+                        .map(|indexing| indexing.tokenizer().to_string()),
+                    _ => None,
+                }
+                .ok_or_else(|| {
+                    IndexError::InvalidQuery(format!("Field '{}' is not an indexed text field", field_name))
+                })?
+            }
+            (None, Some(analyzer)) => analyzer.clone(),
+            (None, None) => {
+                return Err(IndexError::InvalidQuery("Either field or analyzer is required".to_string()).into())
+            }
+        };
+
+        let mut analyzer = handle
+            .index
+            .tokenizers()
+            .get(&name)
+            .ok_or_else(|| IndexError::InvalidQuery(format!("Unknown analyzer '{}'", name)))?;
+
+        let mut tokens = Vec::new();
+        let mut stream = analyzer.token_stream(&request.text);
+        while stream.advance() {
+            let token = stream.token();
+            tokens.push(AnalyzedToken {
+                text: token.text.clone(),
+                offset_from: token.offset_from,
+                offset_to: token.offset_to,
+                position: token.position,
+            });
+        }
+
+        Ok(AnalyzeResponse { analyzer: name, tokens })
+    }
+
+    /// Get statistics about a user's index
+    pub async fn get_user_stats(&self, user_id: Uuid) -> Result<UserIndexStats> {
+        if !self.index_exists(user_id).await {
+            return Ok(UserIndexStats {
+                user_id,
+                num_documents: 0,
+                num_segments: 0,
+                disk_bytes: 0,
+            });
+        }
+
+        let handle = self.get_or_create_index(user_id).await?;
+
+        // Reload the reader to see latest commits
+        handle.reader.reload()?;
+        let searcher = handle.reader.searcher();
+
+        let num_docs = searcher.num_docs() as usize;
+        let index_path = self.base_dir.join(user_id.to_string()).join("index");
+
+        Ok(UserIndexStats {
+            user_id,
+            num_documents: num_docs,
+            num_segments: searcher.segment_readers().len(),
+            disk_bytes: directory_bytes(&index_path)?,
+        })
+    }
+
+    /// Get statistics about several users' indexes, at most `concurrency` at a time
+    ///
+    /// Results are in the order of `user_ids`. A user whose index fails to
+    /// open gets that error without affecting the others. Opening indexes for
+    /// their stats counts as using them for cache eviction.
+    pub async fn get_users_stats(self: &Arc<Self>, user_ids: &[Uuid], concurrency: usize) -> Vec<Result<UserIndexStats>> {
+        let permits = Arc::new(Semaphore::new(concurrency.max(1)));
+        let mut tasks = tokio::task::JoinSet::new();
+        for (position, &user_id) in user_ids.iter().enumerate() {
+            let manager = Arc::clone(self);
+            let permits = Arc::clone(&permits);
+            tasks.spawn(async move {
+                let _permit = permits.acquire_owned().await;
+                (position, manager.get_user_stats(user_id).await)
+            });
+        }
+
+        let mut results: Vec<Option<Result<UserIndexStats>>> = user_ids.iter().map(|_| None).collect();
+        while let Some(joined) = tasks.join_next().await {
+            match joined {
+                Ok((position, stats)) => results[position] = Some(stats),
+                Err(e) => warn!(error = %e, "Stats task failed"),
+            }
+        }
+        results
+            .into_iter()
+            .map(|stats| stats.unwrap_or_else(|| Err(anyhow::anyhow!("Stats task failed"))))
+            .collect()
+    }
+
+    /// The `limit` terms of `field` found in the most documents, skipping `stop_words`
+    ///
+    /// Segment term dictionaries are sorted, so they are merged as streams and
+    /// each term's document frequencies summed as it goes by; only the current
+    /// term of each segment and the best `limit` terms so far are held in memory.
+    /// Document frequencies include deleted documents until their segment is
+    /// merged. Fields that are not indexed text, or not queryable, fail with
+    /// `IndexError::InvalidQuery`.
+    pub async fn top_terms(
+        &self,
+        user_id: Uuid,
+        field_name: &str,
+        limit: usize,
+        mut stop_words: Option<StopWords>,
+    ) -> Result<TopTermsResponse> {
+        let start = Instant::now();
+        let handle = self.get_or_create_index(user_id).await?;
+
+        let field = handle
+            .schema
+            .get_field(field_name)
+            .ok()
+            .filter(|field| {
+                QUERYABLE_FIELDS.contains(&field_name)
+                    && matches!(handle.schema.get_field_entry(*field).field_type(), FieldType::Str(options) if options.get_indexing_options().is_some())
+            })
+            .ok_or_else(|| {
+                IndexError::InvalidQuery(format!(
+                    "Field '{}' has no terms to list. Valid fields: {}",
+                    field_name,
+                    QUERYABLE_FIELDS.join(", ")
+                ))
+            })?;
+
+        handle.reader.reload()?;
+        let searcher = handle.reader.searcher();
+        let inverted_indexes = searcher
+            .segment_readers()
+            .iter()
+            .map(|segment_reader| segment_reader.inverted_index(field))
+            .collect::<tantivy::Result<Vec<_>>>()?;
+        let mut streams = inverted_indexes
+            .iter()
+            .map(|inverted_index| inverted_index.terms().stream())
+            .collect::<std::io::Result<Vec<_>>>()?;
+
+        // Smallest current term across segments first
+        let mut pending = BinaryHeap::new();
+        for (segment, stream) in streams.iter_mut().enumerate() {
+            if stream.advance() {
+                pending.push(Reverse((stream.key().to_vec(), segment)));
+            }
+        }
+
+        // Min-heap of the best terms so far: lowest frequency, then highest term, is evicted first
+        let mut top: BinaryHeap<Reverse<(u64, Reverse<String>)>> = BinaryHeap::with_capacity(limit + 1);
+        while let Some(Reverse((key, segment))) = pending.pop() {
+            let mut doc_freq = 0u64;
+            let mut next = Some(segment);
+            while let Some(segment) = next {
+                let stream = &mut streams[segment];
+                doc_freq += stream.value().doc_freq as u64;
+                if stream.advance() {
+                    pending.push(Reverse((stream.key().to_vec(), segment)));
+                }
+                next = match pending.peek() {
+                    Some(Reverse((other, _))) if *other == key => pending.pop().map(|Reverse((_, segment))| segment),
+                    _ => None,
+                };
+            }
+
+            // Terms arrive in ascending order, so a tie with the weakest kept term loses
+            if top.len() == limit && top.peek().is_some_and(|Reverse((weakest, _))| doc_freq <= *weakest) {
+                continue;
+            }
+            let Ok(term) = std::str::from_utf8(&key) else {
+                continue;
+            };
+            if stop_words.as_mut().is_some_and(|stop_words| stop_words.contains(term)) {
+                continue;
+            }
+            top.push(Reverse((doc_freq, Reverse(term.to_string()))));
+            if top.len() > limit {
+                top.pop();
+            }
+        }
+
+        // Ascending min-heap order reversed: highest frequency first, ties by term
+        let terms = top
+            .into_sorted_vec()
+            .into_iter()
+            .map(|Reverse((doc_freq, Reverse(term)))| TermFrequency { term, doc_freq })
+            .collect();
+
+        Ok(TopTermsResponse {
+            field: field_name.to_string(),
+            terms,
+            took_ms: start.elapsed().as_millis() as u64,
+        })
+    }
+
+    /// Count documents under each level of a user's hierarchical tags
+    ///
+    /// Counts the `depth` levels below `prefix` (the top level when `None`).
+    /// A collector can't count a facet together with its descendants, so each
+    /// level is one collection pass over the children found by the previous
+    /// one. A document counts once per path, however many of its tags are
+    /// at or below it.
+    pub async fn tag_facets(&self, user_id: Uuid, prefix: Option<Facet>, depth: usize) -> Result<TagFacetsResponse> {
+        let start = Instant::now();
+        let path = prefix.as_ref().map(facet_path).unwrap_or_default();
+
+        if !self.index_exists(user_id).await {
+            return Ok(TagFacetsResponse {
+                path,
+                facets: Vec::new(),
+                took_ms: start.elapsed().as_millis() as u64,
+            });
+        }
+
+        let handle = self.get_or_create_index(user_id).await?;
+        if handle.schema.get_field(FieldNames::TAG_PATHS).is_err() {
+            return Err(IndexError::Unsupported(
+                "Tag facets are not supported by this index; rebuild it with POST /v1/reindex".to_string(),
+            )
+            .into());
+        }
+
+        handle.reader.reload()?;
+        let searcher = handle.reader.searcher();
+
+        let mut counted = Vec::new();
+        let mut parents = vec![prefix.unwrap_or_else(Facet::root)];
+        for _ in 0..depth {
+            if parents.is_empty() {
+                break;
+            }
+            let mut collector = FacetCollector::for_field(FieldNames::TAG_PATHS);
+            for parent in &parents {
+                collector.add_facet(parent.clone());
+            }
+            let counts = searcher.search(&AllQuery, &collector)?;
+
+            let mut children = Vec::new();
+            for parent in &parents {
+                for (facet, count) in counts.get(parent.clone()) {
+                    children.push(facet.clone());
+                    counted.push((facet.clone(), count));
+                }
+            }
+            parents = children;
+        }
+
+        // Facets order by their encoded path, which is depth-first
+        counted.sort();
+        let facets = counted
+            .into_iter()
+            .map(|(facet, count)| TagFacetCount {
+                path: facet_path(&facet),
+                level: facet.to_path().len(),
+                count,
+            })
+            .collect();
+
+        Ok(TagFacetsResponse {
+            path,
+            facets,
+            took_ms: start.elapsed().as_millis() as u64,
+        })
+    }
+
+    /// Open a user's index and load the structures searches read, without searching
+    ///
+    /// Every segment's term dictionaries are walked and its fast field columns
+    /// opened, so their file pages are cached before the first real request.
+    pub async fn warm_index(&self, user_id: Uuid) -> Result<WarmIndexResponse> {
+        let start = Instant::now();
+        let already_open = self.indexes.read().await.contains_key(&user_id);
+        let handle = self.get_or_create_index(user_id).await?;
+
+        handle.reader.reload()?;
+        let searcher = handle.reader.searcher();
+
+        for segment_reader in searcher.segment_readers() {
+            for (field, entry) in handle.schema.fields() {
+                if entry.is_indexed() {
+                    let inverted_index = segment_reader.inverted_index(field)?;
+                    let mut terms = inverted_index.terms().stream()?;
+                    while terms.advance() {}
+                }
+                if entry.is_fast() {
+                    for column in segment_reader.fast_fields().dynamic_column_handles(entry.name())? {
+                        column.open()?;
+                    }
+                }
+            }
+        }
+
+        let took_ms = start.elapsed().as_millis() as u64;
+        info!(
+            user_id = %user_id,
+            already_open = already_open,
+            took_ms = took_ms,
+            "Warmed index"
+        );
+
+        Ok(WarmIndexResponse {
+            user_id: user_id.to_string(),
+            already_open,
+            segments: searcher.segment_readers().len(),
+            took_ms,
+        })
+    }
+
+    /// Make every write queued for a user visible to later searches
+    ///
+    /// Each write commits before it responds, so there is nothing uncommitted
+    /// to flush; this waits for writes still queued on the writer (FIFO) and
+    /// reloads the reader. Users without an index get generation 0 and no
+    /// index is created.
+    pub async fn flush(&self, user_id: Uuid) -> Result<FlushResponse> {
+        let start = Instant::now();
+
+        if !self.index_exists(user_id).await {
+            return Ok(FlushResponse {
+                generation: 0,
+                took_ms: start.elapsed().as_millis() as u64,
+            });
+        }
+
+        let handle = self.get_or_create_index(user_id).await?;
+        drop(self.wait_for_writer(user_id, &handle).await?);
+
+        let generation = handle.generation();
+        handle.reader.reload()?;
+
+        let took_ms = start.elapsed().as_millis() as u64;
+        debug!(
+            user_id = %user_id,
+            generation = generation,
+            took_ms = took_ms,
+            "Flushed index"
+        );
+
+        Ok(FlushResponse {
+            generation,
+            took_ms,
+        })
+    }
+
+    /// List the latest `limit` commits of a user's index, newest first
+    ///
+    /// The history is kept in memory from startup; an index opened since
+    /// then reports its generation but no earlier commits. Users without an
+    /// index get generation 0 and no index is created.
+    pub async fn history(&self, user_id: Uuid, limit: usize) -> Result<IndexHistoryResponse> {
+        let generation = if self.index_exists(user_id).await {
+            self.get_or_create_index(user_id).await?.generation()
+        } else {
+            0
+        };
+
+        Ok(IndexHistoryResponse {
+            generation,
+            commits: self.commit_history.recent(user_id, limit),
+        })
+    }
+
+    /// Subscribe to a user's indexed and deleted documents from now on
+    ///
+    /// Fails when `CHANGES_MAX_CONNECTIONS` subscriptions are open, or
+    /// `CHANGES_MAX_CONNECTIONS_PER_USER` for this user. No index is opened
+    /// or created.
+    pub fn subscribe_changes(&self, user_id: Uuid) -> Result<ChangeSubscription, SubscribeError> {
+        self.changes.subscribe(user_id)
+    }
+
+    /// Report how fragmented a user's index is and whether it needs optimizing
+    ///
+    /// Deleted and replaced documents stay in their segments until a merge
+    /// drops them. Users without an index get zeros and no index is created.
+    pub async fn index_health(&self, user_id: Uuid) -> Result<IndexHealthResponse> {
+        if !self.index_exists(user_id).await {
+            return Ok(IndexHealthResponse {
+                num_segments: 0,
+                num_deleted_docs: 0,
+                fragmentation_ratio: 0.0,
+                needs_optimize: false,
+            });
+        }
+
+        let handle = self.get_or_create_index(user_id).await?;
+        handle.reader.reload()?;
+        let searcher = handle.reader.searcher();
+
+        let segment_readers = searcher.segment_readers();
+        let num_deleted_docs: u64 = segment_readers.iter().map(|reader| reader.num_deleted_docs() as u64).sum();
+        let max_docs: u64 = segment_readers.iter().map(|reader| reader.max_doc() as u64).sum();
+        let fragmentation_ratio = if max_docs == 0 {
+            0.0
+        } else {
+            num_deleted_docs as f64 / max_docs as f64
+        };
+
+        Ok(IndexHealthResponse {
+            num_segments: segment_readers.len(),
+            num_deleted_docs,
+            fragmentation_ratio,
+            needs_optimize: segment_readers.len() > self.optimize_max_segments
+                || fragmentation_ratio > self.optimize_max_deleted_ratio,
+        })
+    }
+
+    /// Compute summary analytics over a user's documents
+    ///
+    /// Every stored document is read once, so the cost grows linearly with the
+    /// index size; memory is bounded by the number of distinct tags. Results
+    /// are cached per index and reused until the next commit.
+    pub async fn analytics(&self, user_id: Uuid) -> Result<IndexAnalytics> {
+        let start = Instant::now();
+        let handle = self.get_or_create_index(user_id).await?;
+
+        // Read the generation before reloading so a cached result is never newer than its key
+        let generation = handle.generation();
+        if let Some(cached) = handle.analytics_cache.lock().unwrap().as_ref() {
+            if cached.generation == generation {
+                return Ok(IndexAnalytics {
+                    cached: true,
+                    took_ms: start.elapsed().as_millis() as u64,
+                    ..cached.clone()
+                });
+            }
+        }
+
+        handle.reader.reload()?;
+        let searcher = handle.reader.searcher();
+
+        let tags_field = handle.schema.get_field(FieldNames::TAGS).context("Tags field not found")?;
+        let created_at_field = handle.schema.get_field(FieldNames::CREATED_AT).ok();
+
+        let mut num_documents = 0u64;
+        let mut total_body_bytes = 0u64;
+        let mut tags = HashSet::new();
+        let mut oldest: Option<chrono::DateTime<chrono::FixedOffset>> = None;
+        let mut newest: Option<chrono::DateTime<chrono::FixedOffset>> = None;
+
+        for segment_reader in searcher.segment_readers() {
+            let store = segment_reader.get_store_reader(1)?;
+            for doc_id in segment_reader.doc_ids_alive() {
+                let doc: TantivyDocument = store.get(doc_id)?;
+                num_documents += 1;
+
+                if let Some(body) = stored_body(&handle.schema, &doc) {
+                    total_body_bytes += body.len() as u64;
+                }
+                for tag in doc.get_all(tags_field).filter_map(|v| v.as_str()) {
+                    if !tags.contains(tag) {
+                        tags.insert(tag.to_string());
+                    }
+                }
+
+                let created_at = created_at_field
+                    .and_then(|f| doc.get_first(f))
+                    .and_then(|v| v.as_str())
+                    .and_then(|s| chrono::DateTime::parse_from_rfc3339(s).ok());
+                if let Some(created_at) = created_at {
+                    oldest = Some(oldest.map_or(created_at, |o| o.min(created_at)));
+                    newest = Some(newest.map_or(created_at, |n| n.max(created_at)));
+                }
+            }
+        }
+
+        let analytics = IndexAnalytics {
+            num_documents,
+            total_body_bytes,
+            avg_body_bytes: if num_documents == 0 {
+                0.0
+            } else {
+                total_body_bytes as f64 / num_documents as f64
+            },
+            distinct_tags: tags.len(),
+            oldest_created_at: oldest.map(|t| t.to_rfc3339()),
+            newest_created_at: newest.map(|t| t.to_rfc3339()),
+            generation,
+            cached: false,
+            took_ms: start.elapsed().as_millis() as u64,
+        };
+        *handle.analytics_cache.lock().unwrap() = Some(analytics.clone());
+
+        debug!(
+            user_id = %user_id,
+            documents = num_documents,
+            took_ms = analytics.took_ms,
+            "Analytics computed"
+        );
+
+        Ok(analytics)
+    }
+
+    /// Browse/list all documents for a user
+    ///
+    /// Returns complete documents without requiring a search query.
+    pub async fn browse_documents(
+        &self,
+        user_id: Uuid,
+        query: BrowseDocumentsQuery,
+    ) -> Result<BrowseDocumentsResponse> {
+        let start = Instant::now();
+
+        if !self.index_exists(user_id).await {
+            return Ok(BrowseDocumentsResponse {
+                documents: Vec::new(),
+                total: 0,
+                offset: query.offset,
+                limit: query.limit.min(MAX_BROWSE_LIMIT),
+                has_more: false,
+                truncated_by_size: false,
+                took_ms: start.elapsed().as_millis() as u64,
+            });
+        }
+
+        let handle = self.get_or_create_index(user_id).await?;
+
+        // Reload the reader to see latest commits
+        phase!("reload", handle.reader.reload())?;
+        let searcher = handle.reader.searcher();
+
+        // Get field handles
+        let id_field = handle.schema.get_field(FieldNames::ID).context("ID field not found")?;
+        let title_field = handle.schema.get_field(FieldNames::TITLE).context("Title field not found")?;
+        let created_at_field = handle.schema.get_field(FieldNames::CREATED_AT).ok();
+        let tags_field = handle.schema.get_field(FieldNames::TAGS).ok();
+        let checksum_field = handle.schema.get_field(FieldNames::CHECKSUM).ok();
+        let external_id_field = handle.schema.get_field(FieldNames::EXTERNAL_ID).ok();
+        let client_created_at_field = handle.schema.get_field(FieldNames::CLIENT_CREATED_AT).ok();
+
+        // Use a match-all query to get all documents
+        let all_query = AllQuery;
+
+        // Get all documents, limited by the query parameters
+        let limit = query.limit.min(MAX_BROWSE_LIMIT);
+        let offset = query.offset;
+        let (top_docs, total) = phase!(
+            "search",
+            searcher.search(&all_query, &(TopDocs::with_limit(limit + offset), Count))
+        )?;
+
+        let docs = phase!("fetch_docs", {
+            top_docs
+                .into_iter()
+                .skip(offset)
+                .take(limit)
+                .map(|(_score, doc_address)| searcher.doc::<TantivyDocument>(doc_address))
+                .collect::<tantivy::Result<Vec<_>>>()?
+        });
+
+        // Convert results, stopping before the response outgrows its byte budget
+        let mut documents = Vec::with_capacity(docs.len());
+        let mut budget = ResponseBudget::new(self.max_response_bytes);
+        let mut truncated_by_size = false;
+
+        for retrieved_doc in docs {
+            let id = retrieved_doc
+                .get_first(id_field)
+                .and_then(|v| v.as_str())
+                .unwrap_or("unknown")
+                .to_string();
+
+            let title = retrieved_doc
+                .get_first(title_field)
+                .and_then(|v| v.as_str())
+                .unwrap_or("")
+                .to_string();
+
+            let body = stored_body(&handle.schema, &retrieved_doc).unwrap_or("").to_string();
+            let body_truncated = body_capped(&handle.schema, &retrieved_doc);
+
+            let created_at = created_at_field
+                .and_then(|f| retrieved_doc.get_first(f))
+                .and_then(|v| v.as_str())
+                .map(|s| s.to_string());
+
+            // Extract tags from the document
+            let tags = if let Some(tags_f) = tags_field {
+                retrieved_doc
+                    .get_all(tags_f)
+                    .filter_map(|v| v.as_str().map(|s| s.to_string()))
+                    .collect()
+            } else {
+                Vec::new()
+            };
+
+            let checksum = checksum_field
+                .and_then(|f| retrieved_doc.get_first(f))
+                .and_then(|v| v.as_str())
+                .map(|s| s.to_string());
+
+            let external_id = external_id_field
+                .and_then(|f| retrieved_doc.get_first(f))
+                .and_then(|v| v.as_str())
+                .map(|s| s.to_string());
+
+            let client_created_at = client_created_at_field
+                .and_then(|f| retrieved_doc.get_first(f))
+                .and_then(|v| v.as_str())
+                .map(|s| s.to_string());
+
+            let document = DocumentDetail {
+                id,
+                external_id,
+                title,
+                body, // Full body unless capped at index time
+                body_truncated,
+                created_at,
+                client_created_at,
+                tags,
+                checksum,
+            };
+            if !budget.take(&document) {
+                truncated_by_size = true;
+                break;
+            }
+            documents.push(document);
+        }
+
+        let took_ms = start.elapsed().as_millis() as u64;
+        let has_more = offset + documents.len() < total;
+
+        if logging::sample_with_latency("browse", Some(took_ms)) {
+            debug!(
+                user_id = %user_id,
+                documents = documents.len(),
+                total = total,
+                took_ms = took_ms,
+                "Browse completed"
+            );
+        }
+
+        Ok(BrowseDocumentsResponse {
+            documents,
+            total,
+            offset,
+            limit,
+            has_more,
+            truncated_by_size,
+            took_ms,
+        })
+    }
+}
+
+/// A facet as a `/`-delimited tag path without the leading `/`
+fn facet_path(facet: &Facet) -> String {
+    facet.to_path().join("/")
+}
+
+/// Remaining bytes a response may spend on its results
+struct ResponseBudget {
+    remaining: usize,
+}
+
+impl ResponseBudget {
+    fn new(bytes: usize) -> Self {
+        ResponseBudget { remaining: bytes }
+    }
+
+    /// Spend the serialized size of `value`, or return false if it doesn't fit
+    fn take<T: Serialize>(&mut self, value: &T) -> bool {
+        let mut counter = ByteCounter(0);
+        if serde_json::to_writer(&mut counter, value).is_err() || counter.0 > self.remaining {
+            return false;
+        }
+        self.remaining -= counter.0;
+        true
+    }
+}
+
+/// Read the columns of a `columnar` search from its stored documents
+///
+/// Rows are added in rank order until the next one would exceed `budget`;
+/// the flag tells whether any were left out. A row is sized as the JSON
+/// array of its values, which slightly overstates what it adds to the columns.
+fn search_columns(
+    schema: &Schema,
+    docs: Vec<(Score, TantivyDocument)>,
+    mut budget: ResponseBudget,
+) -> (SearchColumns, bool) {
+    let text = |doc: &TantivyDocument, name: &str| {
+        schema
+            .get_field(name)
+            .ok()
+            .and_then(|field| doc.get_first(field))
+            .and_then(|value| value.as_str())
+            .map(|value| value.to_string())
+    };
+
+    let mut columns = SearchColumns::default();
+    for (score, doc) in docs {
+        let id = text(&doc, FieldNames::ID).unwrap_or_else(|| "unknown".to_string());
+        let title = text(&doc, FieldNames::TITLE).unwrap_or_default();
+        let created_at = text(&doc, FieldNames::CREATED_AT);
+        if !budget.take(&(&id, &title, score, &created_at)) {
+            return (columns, true);
+        }
+        columns.ids.push(id);
+        columns.titles.push(title);
+        columns.scores.push(score);
+        columns.created_ats.push(created_at);
+    }
+    (columns, false)
+}
+
+/// Writer that only counts bytes, to size a value without serializing it into memory
+struct ByteCounter(usize);
+
+impl std::io::Write for ByteCounter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0 += buf.len();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Run a search, stopping collection at the deadline if one is set
+///
+/// Returns the collected fruit and whether the deadline cut collection short.
+fn search_within<C: Collector>(
+    searcher: &Searcher,
+    query: &dyn Query,
+    collector: C,
+    deadline: Option<Instant>,
+) -> Result<(C::Fruit, bool)> {
+    match deadline {
+        Some(deadline) => {
+            let budgeted = TimeBudgetCollector::new(collector, deadline);
+            let timed_out = budgeted.timed_out_flag();
+            let fruit = searcher.search(query, &budgeted)?;
+            Ok((fruit, timed_out.load(Ordering::Relaxed)))
+        }
+        None => Ok((searcher.search(query, &collector)?, false)),
+    }
+}
+
+/// Whether an error is the client's query being rejected rather than a failure to run it
+fn is_query_error(err: &anyhow::Error) -> bool {
+    err.downcast_ref::<QueryParserError>().is_some()
+        || matches!(
+            err.downcast_ref::<IndexError>(),
+            Some(IndexError::InvalidQuery(_) | IndexError::Unsupported(_))
+        )
+}
+
+/// Queryable fields of a hit containing any of the query's terms, in `QUERYABLE_FIELDS` order
+///
+/// Terms of the stemmed and n-gram copies count for the field they were made
+/// from. Only term presence is checked, so a field holding one word of a
+/// phrase counts even where the phrase itself matched elsewhere.
+fn matched_fields(searcher: &Searcher, schema: &Schema, terms: &[Term], doc_address: DocAddress) -> Result<Vec<String>> {
+    let segment_reader = searcher.segment_reader(doc_address.segment_ord);
+    let mut matched = vec![false; QUERYABLE_FIELDS.len()];
+
+    for term in terms {
+        let name = match schema.get_field_name(term.field()) {
+            FieldNames::TITLE_NGRAM => FieldNames::TITLE,
+            FieldNames::BODY_NGRAM => FieldNames::BODY,
+            name if LANGUAGES.iter().any(|language| language.body_field() == name) => FieldNames::BODY,
+            name => name,
+        };
+        let Some(position) = QUERYABLE_FIELDS.iter().position(|field| *field == name) else {
+            continue;
+        };
+        if matched[position] {
+            continue;
+        }
+        let postings = segment_reader
+            .inverted_index(term.field())?
+            .read_postings(term, IndexRecordOption::Basic)?;
+        if let Some(mut postings) = postings {
+            matched[position] = postings.seek(doc_address.doc_id) == doc_address.doc_id;
+        }
+    }
+
+    Ok(QUERYABLE_FIELDS
+        .iter()
+        .zip(matched)
+        .filter(|(_, matched)| *matched)
+        .map(|(field, _)| field.to_string())
+        .collect())
+}
+
+/// Resolve the IDs of search hits from the `id` fast column
+///
+/// Avoids loading stored documents. Indexes created before the `id` field was
+/// made fast have no column, so they fall back to reading the stored value.
+fn scored_ids(searcher: &Searcher, id_field: Field, hits: &[(Score, DocAddress)]) -> Result<Vec<ScoredId>> {
+    // Tantivy fails rather than returning no column for a field that isn't fast
+    let id_is_fast = searcher.schema().get_field_entry(id_field).is_fast();
+    let mut columns = HashMap::new();
+    let mut ids = Vec::with_capacity(hits.len());
+
+    for &(score, doc_address) in hits {
+        if let Entry::Vacant(entry) = columns.entry(doc_address.segment_ord) {
+            let column = if id_is_fast {
+                searcher
+                    .segment_reader(doc_address.segment_ord)
+                    .fast_fields()
+                    .str(FieldNames::ID)?
+            } else {
+                None
+            };
+            entry.insert(column);
+        }
+
+        let mut id = String::new();
+        match &columns[&doc_address.segment_ord] {
+            Some(column) => {
+                if let Some(ord) = column.term_ords(doc_address.doc_id).next() {
+                    column.ord_to_str(ord, &mut id)?;
+                }
+            }
+            None => {
+                let doc: TantivyDocument = searcher.doc(doc_address)?;
+                if let Some(value) = doc.get_first(id_field).and_then(|v| v.as_str()) {
+                    id = value.to_string();
+                }
+            }
+        }
+
+        ids.push(ScoredId { id, score });
+    }
+
+    Ok(ids)
+}
+
+/// Check whether a live document with the given ID exists in the searcher's snapshot
+fn document_exists(searcher: &Searcher, id_field: Field, id: &str) -> Result<bool> {
+    let query = TermQuery::new(
+        Term::from_field_text(id_field, id),
+        IndexRecordOption::Basic,
+    );
+    Ok(searcher.search(&query, &Count)? > 0)
+}
+
+/// ID of a document with this content checksum, other than those `skip` accepts
+///
+/// Fails with `IndexError::Unsupported` on indexes that predate checksums.
+fn find_same_content(
+    searcher: &Searcher,
+    schema: &Schema,
+    checksum: &str,
+    skip: impl Fn(&str) -> bool,
+) -> Result<Option<String>> {
+    let (Ok(checksum_field), Ok(id_field)) = (schema.get_field(FieldNames::CHECKSUM), schema.get_field(FieldNames::ID))
+    else {
+        return Err(IndexError::Unsupported(
+            "This index predates content checksums; rebuild it with POST /v1/reindex to detect duplicate content"
+                .to_string(),
+        )
+        .into());
+    };
+
+    let query = TermQuery::new(Term::from_field_text(checksum_field, checksum), IndexRecordOption::Basic);
+    let mut addresses: Vec<DocAddress> = searcher.search(&query, &DocSetCollector)?.into_iter().collect();
+    addresses.sort();
+    for address in addresses {
+        let doc: TantivyDocument = searcher.doc(address)?;
+        if let Some(id) = doc.get_first(id_field).and_then(|v| v.as_str()).filter(|id| !skip(id)) {
+            return Ok(Some(id.to_string()));
+        }
+    }
+    Ok(None)
+}
+
+fn duplicate_content_message(id: &str, existing: &str) -> String {
+    format!("Document '{}' has the same content as document '{}'", id, existing)
+}
+
+/// Name of the fast field holding a length, failing on indexes that predate it
+fn length_field_name(schema: &Schema, field: LengthField) -> Result<&'static str> {
+    let name = match field {
+        LengthField::Title => FieldNames::TITLE_LENGTH,
+        LengthField::Body => FieldNames::BODY_LENGTH,
+    };
+    if schema.get_field(name).is_err() {
+        return Err(IndexError::Unsupported(
+            "This index predates length fields; rebuild it with POST /v1/reindex to sort by length".to_string(),
+        )
+        .into());
+    }
+    Ok(name)
+}
+
+/// Bytes of a document's title, body and tags as submitted, counted for `indexed_bytes`
+fn input_bytes(input: &IndexDocumentInput) -> u64 {
+    let tag_bytes: usize = input.metadata.tags.iter().map(String::len).sum();
+    (input.title.len() + input.body.len() + tag_bytes) as u64
+}
+
+/// Reject inputs needing a field this index doesn't have
+///
+/// Indexes that don't store bodies, or predate the field, have nowhere to keep
+/// an unindexed body, and indexes that predate external IDs can't hold one.
+fn check_input_storable(schema: &Schema, input: &IndexDocumentInput) -> Result<()> {
+    if !input.index_body && schema.get_field(FieldNames::BODY_STORED).is_err() {
+        return Err(IndexError::Unsupported(
+            "index_body: false requires an index that stores bodies; this index cannot store unindexed bodies"
+                .to_string(),
+        )
+        .into());
+    }
+    if input.external_id.is_some() && schema.get_field(FieldNames::EXTERNAL_ID).is_err() {
+        return Err(IndexError::Unsupported(
+            "This index predates external IDs; rebuild it with POST /v1/reindex to store them".to_string(),
+        )
+        .into());
+    }
+    Ok(())
+}
+
+/// Per-segment score tweak multiplying each hit's score by its document boost
+/// and, when `recency_boost` is positive, its recency decay
+///
+/// Documents without a stored boost count as 1.0, and segments written before
+/// the date column existed get no decay.
+fn adjust_score(segment_reader: &SegmentReader, recency_boost: f32, now_secs: i64) -> impl FnMut(DocId, Score) -> Score {
+    let fast_fields = segment_reader.fast_fields();
+    let boosts = fast_fields.f64(FieldNames::BOOST).ok();
+    let created_at = if recency_boost > 0.0 {
+        fast_fields.date(FieldNames::CREATED_AT_TS).ok()
+    } else {
+        None
+    };
+    move |doc: DocId, score: Score| {
+        let boost = boosts
+            .as_ref()
+            .and_then(|column| column.first(doc))
+            .map_or(1.0, |boost| boost as f32);
+        let age_secs = created_at
+            .as_ref()
+            .and_then(|column| column.first(doc))
+            .map(|ts| now_secs - ts.into_timestamp_secs());
+        match age_secs {
+            Some(age_secs) => score * boost * recency_multiplier(age_secs, recency_boost),
+            None => score * boost,
+        }
+    }
+}
+
+/// Exponential recency decay applied to a hit's score
+///
+/// `multiplier = exp(-boost * age_days / 365)`, so with a boost of 1.0 a
+/// year-old document keeps ~37% of its score. Future timestamps count as age 0.
+fn recency_multiplier(age_secs: i64, boost: f32) -> f32 {
+    let age_days = age_secs.max(0) as f32 / 86_400.0;
+    (-boost * age_days / 365.0).exp()
+}
+
+/// Statistics about a user's index
+#[derive(Debug, Clone)]
+pub struct UserIndexStats {
+    pub user_id: Uuid,
+    pub num_documents: usize,
+    pub num_segments: usize,
+    /// Size of the index directory's files, including segments awaiting cleanup
+    pub disk_bytes: u64,
+}
+
+/// Total size of the files directly inside `path`
+///
+/// Files removed while listing, e.g. segments garbage-collected after a
+/// merge, are skipped.
+fn directory_bytes(path: &Path) -> Result<u64> {
+    let mut bytes = 0;
+    for entry in std::fs::read_dir(path).with_context(|| format!("Failed to list {:?}", path))? {
+        let metadata = match entry.and_then(|entry| entry.metadata()) {
+            Ok(metadata) => metadata,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e).with_context(|| format!("Failed to read {:?}", path)),
+        };
+        if metadata.is_file() {
+            bytes += metadata.len();
+        }
+    }
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::search::models::{DocumentMetadata, MatchMode, SearchFilters, TagFilter};
+    use tempfile::TempDir;
+
+    /// Create a user's index with the schema from before `id` was a fast field
+    fn create_index_without_fast_id(base_dir: &Path, user_id: Uuid) {
+        let schema = build_schema(&UserConfig::default().schema_options());
+        let mut builder = Schema::builder();
+        for (_, entry) in schema.fields() {
+            if entry.name() == FieldNames::ID {
+                builder.add_text_field(FieldNames::ID, tantivy::schema::STRING | tantivy::schema::STORED);
+            } else {
+                builder.add_field(entry.clone());
+            }
+        }
+        let index_path = base_dir.join(user_id.to_string()).join("index");
+        std::fs::create_dir_all(&index_path).unwrap();
+        Index::create_in_dir(&index_path, builder.build()).unwrap();
+    }
+
+    /// Poll `future` once so it queues on whatever it waits for, then run it as a task
+    ///
+    /// Lets a test line up several requests behind a held lock in a known order.
+    async fn queue<F>(future: F) -> tokio::task::JoinHandle<F::Output>
+    where
+        F: std::future::Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        let mut future = Box::pin(future);
+        std::future::poll_fn(|cx| {
+            assert!(future.as_mut().poll(cx).is_pending());
+            std::task::Poll::Ready(())
+        })
+        .await;
+        tokio::spawn(future)
+    }
+
+    /// Input for a document with default metadata and options
+    fn test_doc(id: impl Into<String>, title: impl Into<String>, body: impl Into<String>) -> IndexDocumentInput {
+        IndexDocumentInput {
+            id: Some(id.into()),
+            title: title.into(),
+            body: body.into(),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_index_and_search() {
+        let temp_dir = TempDir::new().unwrap();
+        let manager = IndexManager::new(temp_dir.path().to_path_buf());
+
+        let user_id = Uuid::new_v4();
+
+        // Index a document
+        let input = test_doc("doc1", "Rust Programming", "Rust is a systems programming language");
+
+        let response = manager.index_document(user_id, input).await.unwrap();
+        assert_eq!(response.id, "doc1");
+
+        // Search for the document
+        let query = SearchQuery {
+            query: "Rust".to_string(),
+            limit: 10,
+            offset: 0,
+            filters: Default::default(),
+            ..Default::default()
+        };
+
+        let search_response = manager.search(user_id, query).await.unwrap();
+        assert_eq!(search_response.results.len(), 1);
+        assert_eq!(search_response.results[0].title, "Rust Programming");
+    }
+
+    #[tokio::test]
+    async fn test_multi_tenant_isolation() {
+        let temp_dir = TempDir::new().unwrap();
+        let manager = IndexManager::new(temp_dir.path().to_path_buf());
+
+        let user1_id = Uuid::new_v4();
+        let user2_id = Uuid::new_v4();
+
+        // User 1 indexes a document
+        let input1 = test_doc("doc1", "User 1 Document", "This belongs to user 1");
+        manager.index_document(user1_id, input1).await.unwrap();
+
+        // User 2 indexes a document
+        let input2 = test_doc("doc2", "User 2 Document", "This belongs to user 2");
+        manager.index_document(user2_id, input2).await.unwrap();
+
+        // User 1 searches - should only see their document
+        let query = SearchQuery {
+            query: "Document".to_string(),
+            limit: 10,
+            offset: 0,
+            filters: Default::default(),
+            ..Default::default()
+        };
+
+        let user1_results = manager.search(user1_id, query.clone()).await.unwrap();
+        assert_eq!(user1_results.results.len(), 1);
+        assert!(user1_results.results[0].title.contains("User 1"));
+
+        // User 2 searches - should only see their document
+        let user2_results = manager.search(user2_id, query).await.unwrap();
+        assert_eq!(user2_results.results.len(), 1);
+        assert!(user2_results.results[0].title.contains("User 2"));
+    }
+
+    #[tokio::test]
+    async fn test_checksum_stable_across_reindex() {
+        let temp_dir = TempDir::new().unwrap();
+        let manager = IndexManager::new(temp_dir.path().to_path_buf());
+        let user_id = Uuid::new_v4();
+
+        let input = IndexDocumentInput {
+            id: Some("doc1".to_string()),
+            title: "Quarterly Return".to_string(),
+            body: "Estimated tax payment for Q3".to_string(),
+            metadata: DocumentMetadata {
+                tags: vec!["tax".to_string(), "q3".to_string()],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let browse = BrowseDocumentsQuery { limit: 10, offset: 0 };
+
+        manager.index_document(user_id, input.clone()).await.unwrap();
+        let first = manager.browse_documents(user_id, browse.clone()).await.unwrap();
+
+        manager.index_document(user_id, input.clone()).await.unwrap();
+        let second = manager.browse_documents(user_id, browse).await.unwrap();
+
+        let expected = crate::search::schema::compute_checksum(
+            &input.title,
+            &input.body,
+            &input.metadata.tags,
+        );
+        assert_eq!(second.documents.len(), 1);
+        assert_eq!(first.documents[0].checksum.as_deref(), Some(expected.as_str()));
+        assert_eq!(first.documents[0].checksum, second.documents[0].checksum);
+    }
+
+    #[tokio::test]
+    async fn test_checksum_covers_stored_tags() {
+        let temp_dir = TempDir::new().unwrap();
+        let manager = IndexManager::new(temp_dir.path().to_path_buf());
+        let user_id = Uuid::new_v4();
+        let user_dir = temp_dir.path().join(user_id.to_string());
+        std::fs::create_dir_all(&user_dir).unwrap();
+        std::fs::write(user_dir.join("config.json"), r#"{"duplicate_content": "reject"}"#).unwrap();
+
+        let tagged = |id: &str, tags: &[&str]| IndexDocumentInput {
+            metadata: DocumentMetadata {
+                tags: tags.iter().map(|tag| tag.to_string()).collect(),
+                ..Default::default()
+            },
+            ..test_doc(id, "Quarterly Return", "Estimated tax payment")
+        };
+
+        // Repeated and empty tags are dropped before the checksum is taken
+        manager.index_document(user_id, tagged("doc1", &["tax", "Tax", " ", "q3"])).await.unwrap();
+        let browse = BrowseDocumentsQuery { limit: 10, offset: 0 };
+        let document = manager.browse_documents(user_id, browse).await.unwrap().documents.remove(0);
+        let expected = crate::search::schema::compute_checksum(&document.title, &document.body, &document.tags);
+        assert_eq!(document.tags, vec!["tax", "q3"]);
+        assert_eq!(document.checksum.as_deref(), Some(expected.as_str()));
+
+        // So a copy without them is still the same content
+        let err = manager.index_document(user_id, tagged("doc2", &["q3", "tax"])).await.unwrap_err();
+        assert!(matches!(err.downcast_ref::<IndexError>(), Some(IndexError::Conflict(_))));
+        let err = manager.index_documents_bulk(user_id, vec![tagged("doc2", &["q3", "", "tax", "q3"])]).await.unwrap_err();
+        assert!(matches!(err.downcast_ref::<IndexError>(), Some(IndexError::Conflict(_))));
+    }
+
+    #[tokio::test]
+    async fn test_recency_boost_prefers_newer_documents() {
+        let temp_dir = TempDir::new().unwrap();
+        let manager = IndexManager::new(temp_dir.path().to_path_buf());
+        let user_id = Uuid::new_v4();
+
+        let now = chrono::Utc::now();
+        for (id, age_days) in [("old", 400), ("new", 2)] {
+            let input = IndexDocumentInput {
+                id: Some(id.to_string()),
+                title: "Annual statement".to_string(),
+                body: "Income summary for the tax year".to_string(),
+                metadata: DocumentMetadata {
+                    created_at: Some(now - chrono::Duration::days(age_days)),
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+            manager.index_document(user_id, input).await.unwrap();
+        }
+
+        let query = SearchQuery {
+            query: "income".to_string(),
+            recency_boost: Some(1.0),
+            ..Default::default()
+        };
+
+        let response = manager.search(user_id, query).await.unwrap();
+        assert_eq!(response.results.len(), 2);
+        assert_eq!(response.results[0].id, "new");
+        assert!(response.results[0].score > response.results[1].score);
+    }
+
+    #[tokio::test]
+    async fn test_document_boost_outranks_text_relevance() {
+        let temp_dir = TempDir::new().unwrap();
+        let manager = IndexManager::new(temp_dir.path().to_path_buf());
+        let user_id = Uuid::new_v4();
+
+        let docs = [
+            ("relevant", "Refund refund refund", "Refund status and refund timing", None),
+            ("faq", "Frequently asked questions", "How long does a refund take", Some(10.0)),
+        ];
+        for (id, title, body, boost) in docs {
+            let input = IndexDocumentInput {
+                id: Some(id.to_string()),
+                title: title.to_string(),
+                body: body.to_string(),
+                boost,
+                ..Default::default()
+            };
+            manager.index_document(user_id, input).await.unwrap();
+        }
+
+        let search = |query: &str| SearchQuery {
+            query: query.to_string(),
+            ..Default::default()
+        };
+
+        // Without the boost the keyword-heavy document would win
+        let response = manager.search(user_id, search("refund")).await.unwrap();
+        assert_eq!(response.results.len(), 2);
+        assert_eq!(response.results[0].id, "faq");
+        assert!(response.results[0].score > response.results[1].score);
+
+        // The weight survives a refresh from stored fields
+        manager.refresh_document(user_id, "faq").await.unwrap();
+        let response = manager.search(user_id, search("refund")).await.unwrap();
+        assert_eq!(response.results[0].id, "faq");
+
+        let response = manager.search(user_id, search("timing")).await.unwrap();
+        assert_eq!(response.results[0].id, "relevant");
+    }
+
+    #[tokio::test]
+    async fn test_generation_advances_with_commits() {
+        let temp_dir = TempDir::new().unwrap();
+        let manager = IndexManager::new(temp_dir.path().to_path_buf());
+        let user_id = Uuid::new_v4();
+
+        let input = test_doc("doc1", "Payment receipt", "Paid in full");
+
+        let first = manager.index_document(user_id, input.clone()).await.unwrap();
+        let query = SearchQuery {
+            query: "receipt".to_string(),
+            ..Default::default()
+        };
+        let search = manager.search(user_id, query).await.unwrap();
+        assert!(search.generation >= first.generation);
+
+        let second = manager.index_document(user_id, input).await.unwrap();
+        assert!(second.generation > first.generation);
+
+        // A fresh manager over the same directory continues from the persisted commit
+        drop(manager);
+        let reopened = IndexManager::new(temp_dir.path().to_path_buf());
+        let handle = reopened.get_or_create_index(user_id).await.unwrap();
+        assert_eq!(handle.generation(), second.generation);
+    }
+
+    #[tokio::test]
+    async fn test_history_grows_with_commits() {
+        let temp_dir = TempDir::new().unwrap();
+        let manager = IndexManager::new(temp_dir.path().to_path_buf());
+        let user_id = Uuid::new_v4();
+
+        let empty = manager.history(user_id, 10).await.unwrap();
+        assert_eq!((empty.generation, empty.commits.len()), (0, 0));
+        assert!(!manager.index_exists(user_id).await);
+
+        let input = |id: &str| test_doc(id, "Payment receipt", "Paid in full");
+        let indexed = manager.index_document(user_id, input("doc1")).await.unwrap();
+        let bulk = manager
+            .index_documents_bulk(user_id, vec![input("doc2"), input("doc3")])
+            .await
+            .unwrap();
+        let deleted = manager.delete_document(user_id, "doc1".to_string()).await.unwrap();
+
+        let history = manager.history(user_id, 10).await.unwrap();
+        assert_eq!(history.generation, deleted.generation);
+        let commits: Vec<(u64, u64)> = history
+            .commits
+            .iter()
+            .map(|commit| (commit.generation, commit.operations))
+            .collect();
+        assert_eq!(
+            commits,
+            [(deleted.generation, 1), (bulk.generation, 2), (indexed.generation, 1)]
+        );
+        assert!(chrono::DateTime::parse_from_rfc3339(&history.commits[0].committed_at).is_ok());
+        assert_eq!(manager.history(user_id, 1).await.unwrap().commits.len(), 1);
+
+        // The history is in memory; a restart keeps the generation but not the commits
+        drop(manager);
+        let reopened = IndexManager::new(temp_dir.path().to_path_buf());
+        let history = reopened.history(user_id, 10).await.unwrap();
+        assert_eq!((history.generation, history.commits.len()), (deleted.generation, 0));
+    }
+
+    #[tokio::test]
+    async fn test_index_health_flags_deleted_documents() {
+        let temp_dir = TempDir::new().unwrap();
+        let manager = IndexManager::new(temp_dir.path().to_path_buf());
+        let user_id = Uuid::new_v4();
+
+        let health = manager.index_health(user_id).await.unwrap();
+        assert_eq!((health.num_segments, health.needs_optimize), (0, false));
+        assert!(!manager.index_exists(user_id).await);
+
+        let inputs = (0..10)
+            .map(|i| test_doc(format!("doc{}", i), "Payment receipt", "Paid in full"))
+            .collect();
+        manager.index_documents_bulk(user_id, inputs).await.unwrap();
+        let health = manager.index_health(user_id).await.unwrap();
+        assert_eq!((health.num_segments, health.num_deleted_docs), (1, 0));
+        assert!(!health.needs_optimize);
+
+        // Deletes only mark documents in their segment, so fragmentation grows past the 0.2 default
+        for i in 0..2 {
+            manager.delete_document(user_id, format!("doc{}", i)).await.unwrap();
+        }
+        let health = manager.index_health(user_id).await.unwrap();
+        assert_eq!(health.num_deleted_docs, 2);
+        assert!((health.fragmentation_ratio - 0.2).abs() < 1e-9);
+        assert!(!health.needs_optimize);
+
+        manager.delete_document(user_id, "doc2".to_string()).await.unwrap();
+        let health = manager.index_health(user_id).await.unwrap();
+        assert_eq!(health.num_deleted_docs, 3);
+        assert!(health.needs_optimize);
+    }
+
+    #[tokio::test]
+    async fn test_encrypted_index_round_trip() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = Config {
+            data_dir: temp_dir.path().to_path_buf(),
+            index_encryption_key: Some("42".repeat(32).parse().unwrap()),
+            ..Config::default()
+        };
+        let manager = IndexManager::from_config(&config);
+        let user_id = Uuid::new_v4();
+
+        let input = test_doc("doc1", "Confidential W-2", "Wages zyxwvutsrq withheld");
+        manager.index_document(user_id, input).await.unwrap();
+
+        // No index file contains the plaintext term
+        let index_dir = temp_dir.path().join(user_id.to_string()).join("index");
+        for entry in std::fs::read_dir(&index_dir).unwrap() {
+            let bytes = std::fs::read(entry.unwrap().path()).unwrap();
+            assert!(!bytes.windows(10).any(|w| w == b"zyxwvutsrq"));
+        }
+
+        // Reopening with the same key finds the document
+        drop(manager);
+        let manager = IndexManager::from_config(&config);
+        let query = SearchQuery {
+            query: "zyxwvutsrq".to_string(),
+            ..Default::default()
+        };
+        let response = manager.search(user_id, query.clone()).await.unwrap();
+        assert_eq!(response.total, 1);
+
+        // Without the key the index cannot be opened
+        drop(manager);
+        let plaintext = IndexManager::new(temp_dir.path().to_path_buf());
+        assert!(plaintext.search(user_id, query).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_snippet_field_priority() {
+        let temp_dir = TempDir::new().unwrap();
+        let manager = IndexManager::new(temp_dir.path().to_path_buf());
+        let user_id = Uuid::new_v4();
+
+        let docs = [
+            ("body-match", "Quarterly summary", "Estimated payment for the charity deduction"),
+            ("title-match", "Charity receipt", "Thanks"),
+        ];
+        for (id, title, body) in docs {
+            let input = test_doc(id, title, body);
+            manager.index_document(user_id, input).await.unwrap();
+        }
+
+        let query = SearchQuery {
+            query: "charity".to_string(),
+            ..Default::default()
+        };
+        let response = manager.search(user_id, query.clone()).await.unwrap();
+        let source = |response: &SearchResponse, id: &str| {
+            let result = response.results.iter().find(|r| r.id == id).unwrap();
+            assert!(result.snippet.as_deref().unwrap().contains("<b>"));
+            result.snippet_field.clone().unwrap()
+        };
+        // Default priority is body then title; each falls back to the field that matched
+        assert_eq!(source(&response, "body-match"), "body");
+        assert_eq!(source(&response, "title-match"), "title");
+
+        drop(manager);
+        let manager = IndexManager::from_config(&Config {
+            data_dir: temp_dir.path().to_path_buf(),
+            snippet_fields: vec!["title".to_string()],
+            ..Config::default()
+        });
+        let response = manager.search(user_id, query).await.unwrap();
+        let body_match = response.results.iter().find(|r| r.id == "body-match").unwrap();
+        assert_eq!(body_match.snippet, None);
+        assert_eq!(source(&response, "title-match"), "title");
+    }
+
+    #[tokio::test]
+    async fn test_create_document_conflicts_on_existing_id() {
+        let temp_dir = TempDir::new().unwrap();
+        let manager = Arc::new(IndexManager::new(temp_dir.path().to_path_buf()));
+        let user_id = Uuid::new_v4();
+        let input = |body: &str| test_doc("w2-2024", "W-2", body);
+
+        // Concurrent creates race on the same ID; exactly one wins
+        let attempts: Vec<_> = (0..4)
+            .map(|i| {
+                let manager = Arc::clone(&manager);
+                let input = input(&format!("Attempt {}", i));
+                tokio::spawn(async move { manager.create_document(user_id, input).await })
+            })
+            .collect();
+        let mut created = 0;
+        for attempt in attempts {
+            match attempt.await.unwrap() {
+                Ok(_) => created += 1,
+                Err(e) => assert!(matches!(
+                    e.downcast_ref::<IndexError>(),
+                    Some(IndexError::Conflict(_))
+                )),
+            }
+        }
+        assert_eq!(created, 1);
+
+        // Upsert still replaces
+        manager.index_document(user_id, input("Replaced")).await.unwrap();
+        let stats = manager.get_user_stats(user_id).await.unwrap();
+        assert_eq!(stats.num_documents, 1);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_concurrent_upserts_keep_one_document() {
+        let temp_dir = TempDir::new().unwrap();
+        let manager = Arc::new(IndexManager::new(temp_dir.path().to_path_buf()));
+        let user_id = Uuid::new_v4();
+        let input = |writer: usize| test_doc("w2-2024", "W-2", format!("Written by writer {}", writer));
+
+        // Single and bulk upserts of the same new ID race; each reports its commit's generation
+        let writes: Vec<_> = (0..12)
+            .map(|writer| {
+                let manager = Arc::clone(&manager);
+                let input = input(writer);
+                tokio::spawn(async move {
+                    let generation = if writer % 3 == 0 {
+                        manager.index_documents_bulk(user_id, vec![input]).await.unwrap().generation
+                    } else {
+                        manager.index_document(user_id, input).await.unwrap().generation
+                    };
+                    (generation, writer)
+                })
+            })
+            .collect();
+        let mut committed = Vec::new();
+        for write in writes {
+            committed.push(write.await.unwrap());
+        }
+
+        // Every write committed separately, and the last commit's content is what remains
+        committed.sort();
+        committed.dedup_by_key(|(generation, _)| *generation);
+        assert_eq!(committed.len(), 12);
+        let (_, last_writer) = committed.last().unwrap();
+
+        let stats = manager.get_user_stats(user_id).await.unwrap();
+        assert_eq!(stats.num_documents, 1);
+        let text = manager.document_text(user_id, "w2-2024").await.unwrap().unwrap();
+        assert_eq!(text, format!("W-2\nWritten by writer {}", last_writer));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_replace_never_exposes_both_versions() {
+        let temp_dir = TempDir::new().unwrap();
+        let manager = Arc::new(IndexManager::new(temp_dir.path().to_path_buf()));
+        let user_id = Uuid::new_v4();
+        let input = |version: usize| test_doc("1099-int", "Interest statement", format!("Revision {} of the interest statement", version));
+        manager.index_document(user_id, input(0)).await.unwrap();
+
+        let writer = {
+            let manager = Arc::clone(&manager);
+            tokio::spawn(async move {
+                for version in 1..=25 {
+                    manager.index_document(user_id, input(version)).await.unwrap();
+                }
+            })
+        };
+
+        // Every committed state has exactly one live version, never zero or two
+        let query = SearchQuery {
+            query: "interest".to_string(),
+            ..Default::default()
+        };
+        let mut observed = 0;
+        while !writer.is_finished() || observed == 0 {
+            let response = manager.search(user_id, query.clone()).await.unwrap();
+            assert_eq!(response.total, 1, "generation {}", response.generation);
+            assert_eq!(response.results[0].id, "1099-int");
+            assert_eq!(manager.get_user_stats(user_id).await.unwrap().num_documents, 1);
+            observed += 1;
+        }
+        writer.await.unwrap();
+
+        let response = manager.search(user_id, query).await.unwrap();
+        assert_eq!(response.total, 1);
+        assert!(response.results[0].body.starts_with("Revision 25 "));
+    }
+
+    #[tokio::test]
+    async fn test_bulk_commit_modes_with_small_heap() {
+        // About 5MB of text: more than a quarter of the smallest heap Tantivy allows
+        let documents: Vec<IndexDocumentInput> = (0..20)
+            .map(|i| test_doc(format!("doc-{}", i), format!("Ledger {}", i), format!("entry{} refund invoice receipt ", i).repeat(8_000)))
+            .collect();
+
+        for (mode, expected_partial_commits) in [(BulkCommitMode::Atomic, 0), (BulkCommitMode::Chunked, 1)] {
+            let temp_dir = TempDir::new().unwrap();
+            let manager = IndexManager::from_config(&Config {
+                data_dir: temp_dir.path().to_path_buf(),
+                writer_heap_bytes: crate::config::MIN_WRITER_HEAP_BYTES,
+                bulk_commit_mode: mode,
+                ..Config::default()
+            });
+            let user_id = Uuid::new_v4();
+
+            let response = manager.index_documents_bulk(user_id, documents.clone()).await.unwrap();
+            assert_eq!(response.indexed, 20, "{:?}", mode);
+            assert_eq!(response.partial_commits, expected_partial_commits, "{:?}", mode);
+            assert_eq!(manager.get_user_stats(user_id).await.unwrap().num_documents, 20);
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_search_available_throughout_reindex() {
+        let temp_dir = TempDir::new().unwrap();
+        let manager = Arc::new(IndexManager::new(temp_dir.path().to_path_buf()));
+        let user_id = Uuid::new_v4();
+        let documents = (0..500)
+            .map(|i| IndexDocumentInput {
+                id: Some(format!("doc-{}", i)),
+                title: format!("Receipt {}", i),
+                body: "Office supplies for the home office".to_string(),
+                metadata: DocumentMetadata {
+                    tags: vec!["receipts".to_string()],
+                    ..Default::default()
+                },
+                ..Default::default()
+            })
+            .collect();
+        manager.index_documents_bulk(user_id, documents).await.unwrap();
+        let before = manager.get_or_create_index(user_id).await.unwrap().generation();
+
+        let query = SearchQuery {
+            query: "receipt".to_string(),
+            ..Default::default()
+        };
+        let stop = Arc::new(AtomicBool::new(false));
+        let searcher = {
+            let manager = Arc::clone(&manager);
+            let stop = Arc::clone(&stop);
+            let query = query.clone();
+            tokio::spawn(async move {
+                let mut searches = 0;
+                while !stop.load(Ordering::SeqCst) {
+                    let response = manager.search(user_id, query.clone()).await.unwrap();
+                    assert_eq!(response.results.len(), 10);
+                    assert_eq!(manager.get_user_stats(user_id).await.unwrap().num_documents, 500);
+                    searches += 1;
+                }
+                searches
+            })
+        };
+
+        let rebuilt = manager.reindex(user_id).await.unwrap();
+        stop.store(true, Ordering::SeqCst);
+        assert!(searcher.await.unwrap() > 0);
+
+        assert_eq!(rebuilt.documents, 500);
+        assert!(rebuilt.generation > before);
+
+        // The rebuilt index serves the same documents and takes writes again
+        let response = manager.search(user_id, query).await.unwrap();
+        assert_eq!(response.results.len(), 10);
+        assert_eq!(manager.get_user_stats(user_id).await.unwrap().num_documents, 500);
+        let input = test_doc("doc-500", "Receipt 500", "Printer paper");
+        let written = manager.index_document(user_id, input).await.unwrap();
+        assert!(written.generation > rebuilt.generation);
+
+        let user_dir = temp_dir.path().join(user_id.to_string());
+        assert!(!user_dir.join(REBUILD_DIR).exists());
+        assert!(!user_dir.join(RETIRED_DIR).exists());
+    }
+
+    #[tokio::test]
+    async fn test_writes_rejected_during_reindex() {
+        let temp_dir = TempDir::new().unwrap();
+        let manager = IndexManager::new(temp_dir.path().to_path_buf());
+        let user_id = Uuid::new_v4();
+        let input = || test_doc("doc1", "W-2", "Wage statement");
+        manager.index_document(user_id, input()).await.unwrap();
+
+        manager.reindexing.lock().unwrap().insert(user_id);
+        let err = manager.index_document(user_id, input()).await.unwrap_err();
+        assert!(matches!(err.downcast_ref::<IndexError>(), Some(IndexError::Rebuilding { .. })));
+
+        // A second rebuild of the same index is refused while one runs
+        let err = manager.reindex(user_id).await.unwrap_err();
+        assert!(matches!(err.downcast_ref::<IndexError>(), Some(IndexError::Conflict(_))));
+        manager.reindexing.lock().unwrap().remove(&user_id);
+
+        // Writes that were queued on a handle the swap retired are bounced too
+        let handle = manager.get_or_create_index(user_id).await.unwrap();
+        handle.retired.store(true, Ordering::SeqCst);
+        let err = manager.index_document(user_id, input()).await.unwrap_err();
+        assert!(matches!(err.downcast_ref::<IndexError>(), Some(IndexError::Rebuilding { .. })));
+    }
+
+    #[tokio::test]
+    async fn test_reindex_drain_leaves_other_users_reachable() {
+        let temp_dir = TempDir::new().unwrap();
+        let manager = Arc::new(IndexManager::from_config(&Config {
+            data_dir: temp_dir.path().to_path_buf(),
+            writer_lock_timeout_ms: 60_000,
+            ..Config::default()
+        }));
+        let user_id = Uuid::new_v4();
+        let other_user = Uuid::new_v4();
+        manager.index_document(user_id, test_doc("doc1", "W-2", "Wage statement")).await.unwrap();
+
+        // A request still using the old handle keeps the swap waiting
+        let in_flight = manager.get_or_create_index(user_id).await.unwrap();
+        let reindex = {
+            let manager = Arc::clone(&manager);
+            tokio::spawn(async move { manager.reindex(user_id).await })
+        };
+        while !manager.swapping.lock().unwrap().contains(&user_id) {
+            tokio::task::yield_now().await;
+        }
+
+        // Opening another user's index doesn't wait behind the swap
+        manager.index_document(other_user, test_doc("doc1", "1099", "Interest income")).await.unwrap();
+        assert!(!reindex.is_finished());
+
+        // The rebuilding user's own requests wait for the new index
+        let stats = {
+            let manager = Arc::clone(&manager);
+            tokio::spawn(async move { manager.get_user_stats(user_id).await })
+        };
+        drop(in_flight);
+
+        let rebuilt = reindex.await.unwrap().unwrap();
+        assert_eq!(rebuilt.documents, 1);
+        assert_eq!(stats.await.unwrap().unwrap().num_documents, 1);
+        let handle = manager.get_or_create_index(user_id).await.unwrap();
+        assert_eq!(handle.generation(), rebuilt.generation);
+    }
+
+    #[tokio::test]
+    async fn test_open_recovers_interrupted_reindex() {
+        let temp_dir = TempDir::new().unwrap();
+        let user_id = Uuid::new_v4();
+        let user_dir = temp_dir.path().join(user_id.to_string());
+        {
+            let manager = IndexManager::new(temp_dir.path().to_path_buf());
+            manager.index_document(user_id, test_doc("doc1", "W-2", "Wage statement")).await.unwrap();
+        }
+
+        // Crash after moving the old index aside but before the new one moved in
+        std::fs::rename(user_dir.join("index"), user_dir.join(RETIRED_DIR)).unwrap();
+        std::fs::create_dir_all(user_dir.join(REBUILD_DIR)).unwrap();
+
+        let manager = IndexManager::new(temp_dir.path().to_path_buf());
+        assert_eq!(manager.get_user_stats(user_id).await.unwrap().num_documents, 1);
+        assert!(!user_dir.join(RETIRED_DIR).exists());
+        drop(manager);
+
+        // Crash after the new index moved in but before the old one was removed
+        std::fs::create_dir_all(user_dir.join(RETIRED_DIR)).unwrap();
+        let manager = IndexManager::new(temp_dir.path().to_path_buf());
+        assert_eq!(manager.get_user_stats(user_id).await.unwrap().num_documents, 1);
+        assert!(!user_dir.join(RETIRED_DIR).exists());
+    }
+
+    #[tokio::test]
+    async fn test_language_detection_stems_per_language() {
+        let temp_dir = TempDir::new().unwrap();
+        let manager = IndexManager::new(temp_dir.path().to_path_buf());
+        let user_id = Uuid::new_v4();
+
+        let user_dir = temp_dir.path().join(user_id.to_string());
+        std::fs::create_dir_all(&user_dir).unwrap();
+        std::fs::write(user_dir.join("config.json"), r#"{"detect_language": true}"#).unwrap();
+
+        let docs = [
+            ("en", "The refunds were processed and the forms for this year have been filed with the agency."),
+            ("de", "Die Erstattungen wurden bearbeitet und die Formulare sind bei der Behörde eingereicht."),
+            ("none", "Refunds 2024 ACME"),
+        ];
+        for (id, body) in docs {
+            let input = test_doc(id, "Tax office", body);
+            manager.index_document(user_id, input).await.unwrap();
+        }
+
+        let ids = |query: &str| {
+            let query = SearchQuery {
+                query: query.to_string(),
+                ..Default::default()
+            };
+            let manager = &manager;
+            async move {
+                let mut ids: Vec<String> = manager
+                    .search(user_id, query)
+                    .await
+                    .unwrap()
+                    .results
+                    .into_iter()
+                    .map(|result| result.id)
+                    .collect();
+                ids.sort();
+                ids
+            }
+        };
+
+        // Singular queries reach plural bodies only through the detected language's stemmer
+        assert_eq!(ids("refund").await, ["en"]);
+        assert_eq!(ids("erstattung").await, ["de"]);
+        // The English stemmer never ran over the German body, and vice versa
+        assert_eq!(ids("formular").await, ["de"]);
+        // Undetected documents still match their exact words
+        assert_eq!(ids("refunds").await, ["en", "none"]);
+        assert_eq!(ids("language:de").await, ["de"]);
+
+        // Refreshing re-adds the stemmed body from the stored one
+        manager.refresh_document(user_id, "de").await.unwrap().unwrap();
+        assert_eq!(ids("erstattung").await, ["de"]);
+    }
+
+    #[tokio::test]
+    async fn test_substring_search_with_ngrams() {
+        let temp_dir = TempDir::new().unwrap();
+        let manager = IndexManager::new(temp_dir.path().to_path_buf());
+        let user_id = Uuid::new_v4();
+
+        let user_dir = temp_dir.path().join(user_id.to_string());
+        std::fs::create_dir_all(&user_dir).unwrap();
+        std::fs::write(user_dir.join("config.json"), r#"{"ngram": {"min_gram": 3, "max_gram": 5}}"#).unwrap();
+
+        let docs = [
+            ("invoice", "Invoice ACME-2024", "Consulting services rendered in March"),
+            ("payslip", "Payslip", "Monthly salary statement"),
+        ];
+        for (id, title, body) in docs {
+            let input = test_doc(id, title, body);
+            manager.index_document(user_id, input).await.unwrap();
+        }
+
+        let search = |query: &str, match_mode| {
+            let query = SearchQuery {
+                query: query.to_string(),
+                match_mode,
+                ..Default::default()
+            };
+            let manager = &manager;
+            async move { manager.search(user_id, query).await }
+        };
+        let ids = |response: SearchResponse| -> Vec<String> {
+            response.results.into_iter().map(|result| result.id).collect()
+        };
+
+        // The default tokenizer only matches whole words
+        assert!(ids(search("voice", MatchMode::Standard).await.unwrap()).is_empty());
+        assert_eq!(ids(search("voice", MatchMode::Substring).await.unwrap()), ["invoice"]);
+
+        // Case-insensitive, across punctuation, and words longer than max_gram need all their grams
+        assert_eq!(ids(search("ME-20", MatchMode::Substring).await.unwrap()), ["invoice"]);
+        assert_eq!(ids(search("sulting", MatchMode::Substring).await.unwrap()), ["invoice"]);
+        assert_eq!(ids(search("ARY stat", MatchMode::Substring).await.unwrap()), ["payslip"]);
+        assert!(ids(search("voice salary", MatchMode::Substring).await.unwrap()).is_empty());
+
+        // Refreshing rebuilds the grams from the stored title and body
+        manager.refresh_document(user_id, "invoice").await.unwrap().unwrap();
+        assert_eq!(ids(search("voice", MatchMode::Substring).await.unwrap()), ["invoice"]);
+
+        let err = search("in", MatchMode::Substring).await.unwrap_err();
+        assert!(matches!(err.downcast_ref::<IndexError>(), Some(IndexError::InvalidQuery(_))));
+
+        // Indexes without n-grams can't serve substring searches
+        let other_user = Uuid::new_v4();
+        manager.index_document(other_user, test_doc("invoice", "Invoice", "Consulting")).await.unwrap();
+        let query = SearchQuery {
+            query: "voice".to_string(),
+            match_mode: MatchMode::Substring,
+            ..Default::default()
+        };
+        let err = manager.search(other_user, query).await.unwrap_err();
+        assert!(matches!(err.downcast_ref::<IndexError>(), Some(IndexError::Unsupported(_))));
+    }
+
+    #[tokio::test]
+    async fn test_field_aliases() {
+        let temp_dir = TempDir::new().unwrap();
+        let manager = IndexManager::new(temp_dir.path().to_path_buf());
+        let (aliased_user, plain_user) = (Uuid::new_v4(), Uuid::new_v4());
+
+        let user_dir = temp_dir.path().join(aliased_user.to_string());
+        std::fs::create_dir_all(&user_dir).unwrap();
+        std::fs::write(
+            user_dir.join("config.json"),
+            r#"{"field_aliases": {"content": "body", "name": "title"}}"#,
+        )
+        .unwrap();
+
+        let docs = [
+            ("refund", "Tax notice", "Your refund has been approved"),
+            ("notice", "Refund form", "Please fill in the form"),
+        ];
+        for user_id in [aliased_user, plain_user] {
+            for (id, title, body) in docs {
+                let input = test_doc(id, title, body);
+                manager.index_document(user_id, input).await.unwrap();
+            }
+        }
+
+        let search = |user_id: Uuid, query: &str| {
+            let query = SearchQuery {
+                query: query.to_string(),
+                ..Default::default()
+            };
+            let manager = &manager;
+            async move { manager.search(user_id, query).await }
+        };
+        let ids = |response: SearchResponse| -> Vec<String> {
+            response.results.into_iter().map(|result| result.id).collect()
+        };
+
+        // Aliases resolve to the real fields, which keep working alongside them
+        assert_eq!(ids(search(aliased_user, "content:refund").await.unwrap()), ["refund"]);
+        assert_eq!(ids(search(aliased_user, "name:refund").await.unwrap()), ["notice"]);
+        assert_eq!(ids(search(aliased_user, "body:refund").await.unwrap()), ["refund"]);
+        let query = SearchQuery {
+            query: "content:approved".to_string(),
+            ..Default::default()
+        };
+        assert!(manager.validate_query(aliased_user, &query).await.unwrap().valid);
+
+        // Aliases are per index
+        let err = search(plain_user, "content:refund").await.unwrap_err();
+        assert!(matches!(err.downcast_ref::<IndexError>(), Some(IndexError::InvalidQuery(_))));
+    }
+
+    #[tokio::test]
+    async fn test_default_source() {
+        let temp_dir = TempDir::new().unwrap();
+        let manager = IndexManager::from_config(&Config {
+            data_dir: temp_dir.path().to_path_buf(),
+            default_source: Some("erp".to_string()),
+            ..Config::default()
+        });
+        let (default_user, tenant_user) = (Uuid::new_v4(), Uuid::new_v4());
+
+        let user_dir = temp_dir.path().join(tenant_user.to_string());
+        std::fs::create_dir_all(&user_dir).unwrap();
+        std::fs::write(user_dir.join("config.json"), r#"{"default_source": "crm"}"#).unwrap();
+
+        for user_id in [default_user, tenant_user] {
+            for (id, source) in [("implicit", None), ("explicit", Some("upload"))] {
+                let input = IndexDocumentInput {
+                    id: Some(id.to_string()),
+                    title: "Invoice".to_string(),
+                    body: "Invoice export".to_string(),
+                    metadata: DocumentMetadata {
+                        source: source.map(str::to_string),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                };
+                manager.index_document(user_id, input).await.unwrap();
+            }
+        }
+
+        let search = |user_id: Uuid, source: &str| {
+            let query = SearchQuery {
+                query: "invoice".to_string(),
+                filters: SearchFilters {
+                    source: Some(source.to_string()),
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+            let manager = &manager;
+            async move { manager.search(user_id, query).await.unwrap() }
+        };
+        let ids = |response: SearchResponse| -> Vec<String> {
+            response.results.into_iter().map(|result| result.id).collect()
+        };
+
+        // The tenant's default wins over the global one; an explicit source wins over both
+        assert_eq!(ids(search(default_user, "erp").await), ["implicit"]);
+        assert_eq!(ids(search(tenant_user, "crm").await), ["implicit"]);
+        assert!(search(tenant_user, "erp").await.results.is_empty());
+        assert_eq!(ids(search(default_user, "upload").await), ["explicit"]);
+        assert_eq!(ids(search(tenant_user, "upload").await), ["explicit"]);
+    }
+
+    #[tokio::test]
+    async fn test_accent_folding() {
+        let temp_dir = TempDir::new().unwrap();
+        let manager = IndexManager::new(temp_dir.path().to_path_buf());
+        let (default_user, folding_user) = (Uuid::new_v4(), Uuid::new_v4());
+
+        let user_dir = temp_dir.path().join(folding_user.to_string());
+        std::fs::create_dir_all(&user_dir).unwrap();
+        std::fs::write(user_dir.join("config.json"), r#"{"fold_accents": true}"#).unwrap();
+
+        let docs = [
+            ("accented", "José Müller", "Déclaration for the café"),
+            ("plain", "Jose Muller", "Declaration for the cafe"),
+        ];
+        for user_id in [default_user, folding_user] {
+            for (id, title, body) in docs {
+                let input = test_doc(id, title, body);
+                manager.index_document(user_id, input).await.unwrap();
+            }
+        }
+
+        let search = |user_id: Uuid, query: &str| {
+            let query = SearchQuery {
+                query: query.to_string(),
+                ..Default::default()
+            };
+            let manager = &manager;
+            async move {
+                let mut ids: Vec<String> = manager
+                    .search(user_id, query)
+                    .await
+                    .unwrap()
+                    .results
+                    .into_iter()
+                    .map(|result| result.id)
+                    .collect();
+                ids.sort();
+                ids
+            }
+        };
+
+        // Off by default: accented and plain spellings are different terms
+        assert_eq!(search(default_user, "jose").await, ["plain"]);
+        assert_eq!(search(default_user, "José").await, ["accented"]);
+
+        // Folding applies to indexed text and queries alike, whatever their case
+        assert_eq!(search(folding_user, "jose").await, ["accented", "plain"]);
+        assert_eq!(search(folding_user, "JOSÉ").await, ["accented", "plain"]);
+        assert_eq!(search(folding_user, "title:muller body:déclaration").await, ["accented", "plain"]);
+
+        // Snippets highlight the original, accented text
+        let query = SearchQuery {
+            query: "cafe".to_string(),
+            ..Default::default()
+        };
+        let response = manager.search(folding_user, query).await.unwrap();
+        let accented = response.results.iter().find(|result| result.id == "accented").unwrap();
+        assert!(accented.snippet.as_deref().unwrap().contains("<b>café</b>"));
+    }
+
+    #[tokio::test]
+    async fn test_max_token_length() {
+        let temp_dir = TempDir::new().unwrap();
+        let manager = IndexManager::new(temp_dir.path().to_path_buf());
+        let (default_user, raised_user) = (Uuid::new_v4(), Uuid::new_v4());
+
+        let user_dir = temp_dir.path().join(raised_user.to_string());
+        std::fs::create_dir_all(&user_dir).unwrap();
+        std::fs::write(user_dir.join("config.json"), r#"{"max_token_length": 60}"#).unwrap();
+
+        let medium = "Qm".repeat(25);
+        let long = "Zx".repeat(35);
+        for user_id in [default_user, raised_user] {
+            let input = test_doc("blob", "Attachment", format!("Encoded {} and {}", medium, long));
+            manager.index_document(user_id, input).await.unwrap();
+        }
+
+        async fn total(manager: &IndexManager, user_id: Uuid, query: &str) -> usize {
+            let query = SearchQuery {
+                query: query.to_string(),
+                ..Default::default()
+            };
+            manager.search(user_id, query).await.unwrap().total
+        }
+
+        // Tantivy's limit drops both; the raised one keeps only the 50-byte token
+        assert_eq!(total(&manager, default_user, &medium).await, 0);
+        assert_eq!(total(&manager, raised_user, &medium).await, 1);
+        assert_eq!(total(&manager, raised_user, &long).await, 0);
+        assert_eq!(total(&manager, raised_user, "encoded").await, 1);
+
+        // The limit is taken from the schema when the index is opened again
+        drop(manager);
+        let reopened = IndexManager::new(temp_dir.path().to_path_buf());
+        assert_eq!(total(&reopened, raised_user, &medium).await, 1);
+    }
+
+    #[tokio::test]
+    async fn test_cjk_bigram_tokenizer() {
+        let temp_dir = TempDir::new().unwrap();
+        let manager = IndexManager::new(temp_dir.path().to_path_buf());
+        let (default_user, cjk_user) = (Uuid::new_v4(), Uuid::new_v4());
+
+        let user_dir = temp_dir.path().join(cjk_user.to_string());
+        std::fs::create_dir_all(&user_dir).unwrap();
+        std::fs::write(user_dir.join("config.json"), r#"{"tokenizer": "cjk_bigram"}"#).unwrap();
+
+        let docs = [
+            ("tokyo", "東京都の確定申告", "住民税の納付書が届きました。"),
+            ("seoul", "서울 세금 신고서", "Refund of 소득세 for 2024"),
+        ];
+        for user_id in [default_user, cjk_user] {
+            for (id, title, body) in docs {
+                let input = test_doc(id, title, body);
+                manager.index_document(user_id, input).await.unwrap();
+            }
+        }
+
+        let ids = |user_id: Uuid, query: &str| {
+            let query = SearchQuery {
+                query: query.to_string(),
+                ..Default::default()
+            };
+            let manager = &manager;
+            async move {
+                let response = manager.search(user_id, query).await.unwrap();
+                response.results.into_iter().map(|result| result.id).collect::<Vec<_>>()
+            }
+        };
+
+        // Unspaced text is one token to the default tokenizer, so a word inside it is missed
+        assert!(ids(default_user, "確定申告").await.is_empty());
+        assert!(ids(default_user, "住民税").await.is_empty());
+        assert!(ids(default_user, "신고").await.is_empty());
+
+        // Bigrams find words anywhere in the title or body, in order
+        assert_eq!(ids(cjk_user, "確定申告").await, ["tokyo"]);
+        assert_eq!(ids(cjk_user, "住民税").await, ["tokyo"]);
+        assert_eq!(ids(cjk_user, "신고").await, ["seoul"]);
+        assert!(ids(cjk_user, "申告確定").await.is_empty());
+
+        // Other scripts are still matched by whole, lowercased words
+        assert_eq!(ids(cjk_user, "refund").await, ["seoul"]);
+        assert!(ids(cjk_user, "fund").await.is_empty());
+
+        // Snippets highlight the matched characters
+        let query = SearchQuery {
+            query: "住民税".to_string(),
+            ..Default::default()
+        };
+        let response = manager.search(cjk_user, query).await.unwrap();
+        assert!(response.results[0].snippet.as_deref().unwrap_or("").contains("住民"));
+    }
+
+    #[tokio::test]
+    async fn test_tag_filter_any_and_all() {
+        let temp_dir = TempDir::new().unwrap();
+        let manager = IndexManager::new(temp_dir.path().to_path_buf());
+        let user_id = Uuid::new_v4();
+
+        let docs = [
+            ("a", "Invoice from the accountant", vec!["tax", "2024"]),
+            ("b", "Invoice for software", vec!["Tax"]),
+            ("c", "Invoice for furniture", vec!["receipts", "tax-2023"]),
+            ("d", "Bank statement", vec!["tax", "2024"]),
+        ];
+        for (id, body, tags) in docs {
+            let input = IndexDocumentInput {
+                id: Some(id.to_string()),
+                title: "Document".to_string(),
+                body: body.to_string(),
+                metadata: DocumentMetadata {
+                    tags: tags.into_iter().map(String::from).collect(),
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+            manager.index_document(user_id, input).await.unwrap();
+        }
+
+        let search = |values: &[&str], all: bool| {
+            let query = SearchQuery {
+                query: "invoice".to_string(),
+                filters: SearchFilters {
+                    tags: TagFilter {
+                        values: values.iter().map(|v| v.to_string()).collect(),
+                        all,
+                    },
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+            let manager = &manager;
+            async move {
+                let mut ids: Vec<String> = manager
+                    .search(user_id, query)
+                    .await
+                    .unwrap()
+                    .results
+                    .into_iter()
+                    .map(|r| r.id)
+                    .collect();
+                ids.sort();
+                ids
+            }
+        };
+
+        // ANY: tags match exactly and case-insensitively, ANDed with the text query
+        assert_eq!(search(&["TAX", "receipts"], false).await, vec!["a", "b", "c"]);
+        // "tax" does not match the "tax-2023" tag
+        assert_eq!(search(&["tax"], false).await, vec!["a", "b"]);
+        assert_eq!(search(&["tax-2023"], false).await, vec!["c"]);
+
+        // ALL: every tag is required
+        assert_eq!(search(&["tax", "2024"], true).await, vec!["a"]);
+        assert!(search(&["tax", "receipts"], true).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_tag_facet_rollup() {
+        let temp_dir = TempDir::new().unwrap();
+        let manager = IndexManager::new(temp_dir.path().to_path_buf());
+        let user_id = Uuid::new_v4();
+
+        let docs: [(&str, &[&str]); 4] = [
+            ("a", &["finance/tax/2024", "finance/audit"]),
+            ("b", &["finance/tax/2023"]),
+            ("c", &["Finance/Tax/2024 ", "personal"]),
+            ("d", &["misc"]),
+        ];
+        for (id, tags) in docs {
+            let input = IndexDocumentInput {
+                id: Some(id.to_string()),
+                title: "Statement".to_string(),
+                body: "Statement".to_string(),
+                metadata: DocumentMetadata {
+                    tags: tags.iter().map(|tag| tag.to_string()).collect(),
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+            manager.index_document(user_id, input).await.unwrap();
+        }
+
+        let counts = |response: TagFacetsResponse| -> Vec<(String, usize, u64)> {
+            response.facets.into_iter().map(|f| (f.path, f.level, f.count)).collect()
+        };
+        let expected = |rows: &[(&str, usize, u64)]| -> Vec<(String, usize, u64)> {
+            rows.iter().map(|(path, level, count)| (path.to_string(), *level, *count)).collect()
+        };
+
+        // "a" has two tags under finance but counts once there
+        let response = manager.tag_facets(user_id, None, 3).await.unwrap();
+        assert_eq!(
+            counts(response),
+            expected(&[
+                ("finance", 1, 3),
+                ("finance/audit", 2, 1),
+                ("finance/tax", 2, 3),
+                ("finance/tax/2023", 3, 1),
+                ("finance/tax/2024", 3, 2),
+                ("misc", 1, 1),
+                ("personal", 1, 1),
+            ])
+        );
+
+        let response = manager.tag_facets(user_id, None, 1).await.unwrap();
+        assert_eq!(counts(response), expected(&[("finance", 1, 3), ("misc", 1, 1), ("personal", 1, 1)]));
+
+        let response = manager
+            .tag_facets(user_id, crate::search::schema::tag_facet("finance/tax"), 1)
+            .await
+            .unwrap();
+        assert_eq!(response.path, "finance/tax");
+        assert_eq!(counts(response), expected(&[("finance/tax/2023", 3, 1), ("finance/tax/2024", 3, 2)]));
+    }
+
+    #[tokio::test]
+    async fn test_source_filter_exact_and_prefix() {
+        let temp_dir = TempDir::new().unwrap();
+        let manager = IndexManager::new(temp_dir.path().to_path_buf());
+        let user_id = Uuid::new_v4();
+
+        let docs = [
+            ("a", "erp.invoices.2024"),
+            ("b", "erp.invoices.2023"),
+            ("c", "erp.invoices-archive"),
+            ("d", "erp.payroll.2024"),
+        ];
+        for (id, source) in docs {
+            let input = IndexDocumentInput {
+                id: Some(id.to_string()),
+                title: "Invoice".to_string(),
+                body: "Invoice export".to_string(),
+                metadata: DocumentMetadata {
+                    source: Some(source.to_string()),
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+            manager.index_document(user_id, input).await.unwrap();
+        }
+
+        let search = |source: &str| {
+            let query = SearchQuery {
+                query: "invoice".to_string(),
+                filters: SearchFilters {
+                    source: Some(source.to_string()),
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+            manager.search(user_id, query)
+        };
+        let ids = |response: SearchResponse| -> Vec<String> {
+            let mut ids: Vec<String> = response.results.into_iter().map(|r| r.id).collect();
+            ids.sort();
+            ids
+        };
+
+        assert_eq!(ids(search("erp.invoices.2024").await.unwrap()), vec!["a"]);
+        assert_eq!(ids(search("erp.invoices.*").await.unwrap()), vec!["a", "b"]);
+        assert_eq!(ids(search("erp.invoices*").await.unwrap()), vec!["a", "b", "c"]);
+        assert!(search("erp.invoices").await.unwrap().results.is_empty());
+
+        let err = search("*.2024").await.unwrap_err();
+        assert!(matches!(err.downcast_ref::<IndexError>(), Some(IndexError::InvalidQuery(_))));
+    }
+
+    #[tokio::test]
+    async fn test_title_exact_filter() {
+        let temp_dir = TempDir::new().unwrap();
+        let manager = IndexManager::new(temp_dir.path().to_path_buf());
+        let user_id = Uuid::new_v4();
+
+        let docs = [
+            ("a", "Invoice 2024"),
+            ("b", "INVOICE 2024"),
+            ("c", "Invoice 2024 (copy)"),
+            ("d", "2024 Invoice"),
+        ];
+        for (id, title) in docs {
+            let input = test_doc(id, title, "Invoice export");
+            manager.index_document(user_id, input).await.unwrap();
+        }
+
+        let search = |title_exact: Option<&str>| {
+            let query = SearchQuery {
+                query: "invoice 2024".to_string(),
+                filters: SearchFilters {
+                    title_exact: title_exact.map(str::to_string),
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+            manager.search(user_id, query)
+        };
+        let ids = |response: SearchResponse| -> Vec<String> {
+            let mut ids: Vec<String> = response.results.into_iter().map(|r| r.id).collect();
+            ids.sort();
+            ids
+        };
+
+        // Text matching finds every title containing the words
+        assert_eq!(ids(search(None).await.unwrap()), vec!["a", "b", "c", "d"]);
+        assert_eq!(ids(search(Some("Invoice 2024")).await.unwrap()), vec!["a", "b"]);
+        assert_eq!(ids(search(Some(" invoice 2024 ")).await.unwrap()), vec!["a", "b"]);
+        assert!(search(Some("Invoice")).await.unwrap().results.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_response_byte_budget() {
+        let temp_dir = TempDir::new().unwrap();
+        let manager = IndexManager::from_config(&Config {
+            data_dir: temp_dir.path().to_path_buf(),
+            max_response_bytes: 25_000,
+            ..Config::default()
+        });
+        let user_id = Uuid::new_v4();
+
+        // Each result is a little over 10KB, so only two fit
+        for i in 0..5 {
+            let input = test_doc(format!("doc{}", i), "Ledger", "refund ".repeat(1_500));
+            manager.index_document(user_id, input).await.unwrap();
+        }
+
+        let query = SearchQuery {
+            query: "refund".to_string(),
+            limit: 10,
+            ..Default::default()
+        };
+        let response = manager.search(user_id, query).await.unwrap();
+        assert_eq!(response.results.len(), 2);
+        assert!(response.truncated_by_size);
+
+        let query = BrowseDocumentsQuery {
+            limit: 10,
+            offset: 0,
+        };
+        let response = manager.browse_documents(user_id, query).await.unwrap();
+        assert_eq!(response.documents.len(), 2);
+        assert_eq!(response.total, 5);
+        assert!(response.truncated_by_size);
+        assert!(response.has_more);
+
+        // Pages that fit are unaffected
+        let query = SearchQuery {
+            query: "refund".to_string(),
+            limit: 2,
+            ..Default::default()
+        };
+        assert!(!manager.search(user_id, query).await.unwrap().truncated_by_size);
+    }
+
+    #[tokio::test]
+    async fn test_per_field_highlights() {
+        let temp_dir = TempDir::new().unwrap();
+        let manager = IndexManager::new(temp_dir.path().to_path_buf());
+        let user_id = Uuid::new_v4();
+
+        let input = IndexDocumentInput {
+            id: Some("doc1".to_string()),
+            title: "Charity receipt".to_string(),
+            body: "Receipt for the charity donation made in March".to_string(),
+            metadata: DocumentMetadata {
+                tags: vec!["charity".to_string(), "2024".to_string()],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        manager.index_document(user_id, input).await.unwrap();
+
+        let mut query = SearchQuery {
+            query: "charity".to_string(),
+            ..Default::default()
+        };
+        let response = manager.search(user_id, query.clone()).await.unwrap();
+        assert!(response.results[0].highlights.is_empty());
+
+        query.highlight = true;
+        let response = manager.search(user_id, query.clone()).await.unwrap();
+        let result = &response.results[0];
+        assert_eq!(result.highlights["title"], vec!["<b>Charity</b> receipt".to_string()]);
+        assert_eq!(result.highlights["body"].len(), 1);
+        assert!(result.highlights["body"][0].contains("<b>charity</b>"));
+        // Tags aren't searched by default, so they have no matching terms
+        assert!(!result.highlights.contains_key("tags"));
+
+        // The legacy snippet still comes from the body
+        assert_eq!(result.snippet_field.as_deref(), Some("body"));
+        assert_eq!(result.snippet.as_deref(), Some(result.highlights["body"][0].as_str()));
+
+        // Only the matching value of a multi-valued field is returned
+        query.query = "tags:charity".to_string();
+        let response = manager.search(user_id, query).await.unwrap();
+        assert_eq!(response.results[0].highlights["tags"], vec!["<b>charity</b>".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn test_refresh_document_preserves_content() {
+        let temp_dir = TempDir::new().unwrap();
+        let manager = IndexManager::new(temp_dir.path().to_path_buf());
+        let user_id = Uuid::new_v4();
+
+        let created_at = chrono::Utc::now() - chrono::Duration::days(400);
         let input = IndexDocumentInput {
             id: Some("doc1".to_string()),
-            title: "Rust Programming".to_string(),
-            body: "Rust is a systems programming language".to_string(),
-            metadata: DocumentMetadata::default(),
+            title: "Donation receipt".to_string(),
+            body: "Charitable contribution".to_string(),
+            metadata: DocumentMetadata {
+                tags: vec!["charity".to_string()],
+                source: Some("upload".to_string()),
+                created_at: Some(created_at),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let indexed = manager.index_document(user_id, input).await.unwrap();
+        let query = SearchQuery {
+            query: "donation".to_string(),
+            recency_boost: Some(1.0),
+            ..Default::default()
+        };
+        let before = manager.search(user_id, query.clone()).await.unwrap();
+
+        let refreshed = manager.refresh_document(user_id, "doc1").await.unwrap().unwrap();
+        assert!(refreshed.generation > indexed.generation);
+
+        let after = manager.search(user_id, query).await.unwrap();
+        assert_eq!(after.total, 1);
+        let (old, new) = (&before.results[0], &after.results[0]);
+        assert_eq!(new.body, old.body);
+        assert_eq!(new.created_at, old.created_at);
+        assert_eq!(new.checksum, old.checksum);
+        // The recency column is rebuilt, so the decayed score is unchanged
+        assert!((new.score - old.score).abs() < 1e-4);
+
+        assert!(manager.refresh_document(user_id, "missing").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_analytics() {
+        let temp_dir = TempDir::new().unwrap();
+        let manager = IndexManager::new(temp_dir.path().to_path_buf());
+        let user_id = Uuid::new_v4();
+
+        let empty = manager.analytics(user_id).await.unwrap();
+        assert_eq!(empty.num_documents, 0);
+        assert_eq!(empty.avg_body_bytes, 0.0);
+        assert_eq!(empty.oldest_created_at, None);
+
+        let docs = [
+            ("a", "2023-01-15T00:00:00Z", vec!["tax", "w2"], "1234"),
+            ("b", "2024-06-01T00:00:00Z", vec!["tax"], "12345678"),
+        ];
+        for (id, created_at, tags, body) in docs {
+            let input = IndexDocumentInput {
+                id: Some(id.to_string()),
+                title: "Document".to_string(),
+                body: body.to_string(),
+                metadata: DocumentMetadata {
+                    tags: tags.into_iter().map(String::from).collect(),
+                    created_at: Some(created_at.parse().unwrap()),
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+            manager.index_document(user_id, input).await.unwrap();
+        }
+
+        let analytics = manager.analytics(user_id).await.unwrap();
+        assert!(!analytics.cached);
+        assert_eq!(analytics.num_documents, 2);
+        assert_eq!(analytics.total_body_bytes, 12);
+        assert_eq!(analytics.avg_body_bytes, 6.0);
+        assert_eq!(analytics.distinct_tags, 2);
+        assert_eq!(analytics.oldest_created_at.as_deref(), Some("2023-01-15T00:00:00+00:00"));
+        assert_eq!(analytics.newest_created_at.as_deref(), Some("2024-06-01T00:00:00+00:00"));
+
+        // Unchanged index is served from the cache; a commit invalidates it
+        assert!(manager.analytics(user_id).await.unwrap().cached);
+        manager.delete_document(user_id, "b".to_string()).await.unwrap();
+        let analytics = manager.analytics(user_id).await.unwrap();
+        assert!(!analytics.cached);
+        assert_eq!(analytics.num_documents, 1);
+    }
+
+    #[tokio::test]
+    async fn test_body_not_stored() {
+        let temp_dir = TempDir::new().unwrap();
+        let manager = IndexManager::new(temp_dir.path().to_path_buf());
+        let user_id = Uuid::new_v4();
+
+        let user_dir = temp_dir.path().join(user_id.to_string());
+        std::fs::create_dir_all(&user_dir).unwrap();
+        std::fs::write(user_dir.join("config.json"), r#"{"store_body": false}"#).unwrap();
+
+        let input = test_doc("doc1", "Brokerage statement", "Dividends and capital gains");
+        manager.index_document(user_id, input).await.unwrap();
+
+        // The body is still searchable but not returned
+        let query = SearchQuery {
+            query: "dividends".to_string(),
+            ..Default::default()
+        };
+        let response = manager.search(user_id, query).await.unwrap();
+        assert_eq!(response.total, 1);
+        assert_eq!(response.results[0].title, "Brokerage statement");
+        assert_eq!(response.results[0].body, "");
+
+        let browse = manager
+            .browse_documents(user_id, BrowseDocumentsQuery { limit: 10, offset: 0 })
+            .await
+            .unwrap();
+        assert_eq!(browse.documents[0].body, "");
+
+        let err = manager.refresh_document(user_id, "doc1").await.unwrap_err();
+        assert!(matches!(err.downcast_ref::<IndexError>(), Some(IndexError::Unsupported(_))));
+
+        // Without stored bodies there is nowhere to keep an unindexed one
+        let input = IndexDocumentInput {
+            id: Some("doc2".to_string()),
+            title: "Appendix".to_string(),
+            body: "Not indexed".to_string(),
+            index_body: false,
+            ..Default::default()
+        };
+        let err = manager.index_document(user_id, input).await.unwrap_err();
+        assert!(matches!(err.downcast_ref::<IndexError>(), Some(IndexError::Unsupported(_))));
+    }
+
+    #[tokio::test]
+    async fn test_stored_body_cap() {
+        let temp_dir = TempDir::new().unwrap();
+        let manager = IndexManager::new(temp_dir.path().to_path_buf());
+        let user_id = Uuid::new_v4();
+
+        let user_dir = temp_dir.path().join(user_id.to_string());
+        std::fs::create_dir_all(&user_dir).unwrap();
+        std::fs::write(user_dir.join("config.json"), r#"{"stored_body_max_bytes": 16}"#).unwrap();
+
+        let documents = [
+            ("contract", format!("{}chargeback clause", "filler ".repeat(1000)), true),
+            ("memo", "Short note".to_string(), true),
+            ("appendix", "ü".repeat(20), false),
+        ];
+        for (id, body, index_body) in documents {
+            let input = IndexDocumentInput {
+                id: Some(id.to_string()),
+                title: id.to_string(),
+                body,
+                index_body,
+                ..Default::default()
+            };
+            manager.index_document(user_id, input).await.unwrap();
+        }
+
+        // Text far past the cap is still indexed; only the stored body is cut
+        let query = SearchQuery {
+            query: "chargeback".to_string(),
+            ..Default::default()
+        };
+        let response = manager.search(user_id, query).await.unwrap();
+        assert_eq!(response.total, 1);
+        assert_eq!(response.results[0].body, "filler filler fi");
+        assert!(response.results[0].body_truncated);
+
+        // Snippets and highlights of the body come from its stored start
+        let query = SearchQuery {
+            query: "filler".to_string(),
+            highlight: true,
+            ..Default::default()
+        };
+        let response = manager.search(user_id, query).await.unwrap();
+        assert_eq!(response.results[0].snippet_field.as_deref(), Some("body"));
+        assert!(response.results[0].highlights["body"][0].contains("<b>filler</b>"));
+
+        let browse = manager
+            .browse_documents(user_id, BrowseDocumentsQuery { limit: 10, offset: 0 })
+            .await
+            .unwrap();
+        let detail = |id: &str| browse.documents.iter().find(|doc| doc.id == id).unwrap();
+        assert!(detail("contract").body_truncated);
+        assert_eq!((detail("memo").body.as_str(), detail("memo").body_truncated), ("Short note", false));
+        // Unindexed bodies are capped too, at a character boundary
+        assert_eq!(detail("appendix").body, "ü".repeat(8));
+        assert!(detail("appendix").body_truncated);
+
+        let err = manager.refresh_document(user_id, "memo").await.unwrap_err();
+        assert!(matches!(err.downcast_ref::<IndexError>(), Some(IndexError::Unsupported(_))));
+    }
+
+    #[tokio::test]
+    async fn test_unindexed_body_is_retrievable_but_not_searchable() {
+        let temp_dir = TempDir::new().unwrap();
+        let manager = IndexManager::new(temp_dir.path().to_path_buf());
+        let user_id = Uuid::new_v4();
+
+        let input = IndexDocumentInput {
+            id: Some("return-2023".to_string()),
+            title: "Tax return".to_string(),
+            body: "Appendix with depreciation schedules".to_string(),
+            index_body: false,
+            ..Default::default()
+        };
+        manager.index_document(user_id, input).await.unwrap();
+
+        let search = |query: &str| SearchQuery {
+            query: query.to_string(),
+            ..Default::default()
+        };
+        assert_eq!(manager.search(user_id, search("depreciation")).await.unwrap().total, 0);
+
+        // The title is still indexed, and the body comes back with the document
+        let response = manager.search(user_id, search("return")).await.unwrap();
+        assert_eq!(response.total, 1);
+        assert_eq!(response.results[0].body, "Appendix with depreciation schedules");
+        let preview = manager.preview_document(user_id, "return-2023", 200).await.unwrap().unwrap();
+        assert_eq!(preview.preview, "Appendix with depreciation schedules");
+
+        // Refreshing keeps the body out of the index
+        manager.refresh_document(user_id, "return-2023").await.unwrap();
+        assert_eq!(manager.search(user_id, search("depreciation")).await.unwrap().total, 0);
+        let browse = manager
+            .browse_documents(user_id, BrowseDocumentsQuery { limit: 10, offset: 0 })
+            .await
+            .unwrap();
+        assert_eq!(browse.documents[0].body, "Appendix with depreciation schedules");
+    }
+
+    #[tokio::test]
+    async fn test_open_failure_cooldown() {
+        let temp_dir = TempDir::new().unwrap();
+        let manager = IndexManager::from_config(&Config {
+            data_dir: temp_dir.path().to_path_buf(),
+            index_open_retry_ms: 200,
+            ..Config::default()
+        });
+        let user_id = Uuid::new_v4();
+
+        // A file where the index directory should be makes every open fail
+        let user_dir = temp_dir.path().join(user_id.to_string());
+        std::fs::create_dir_all(&user_dir).unwrap();
+        std::fs::write(user_dir.join("index"), b"not a directory").unwrap();
+
+        let first = manager.get_user_stats(user_id).await.unwrap_err();
+        assert!(first.downcast_ref::<IndexError>().is_none());
+
+        // Even once the directory is fixed, requests fail fast until the cooldown expires
+        std::fs::remove_file(user_dir.join("index")).unwrap();
+        for _ in 0..3 {
+            let err = manager.get_user_stats(user_id).await.unwrap_err();
+            assert!(matches!(
+                err.downcast_ref::<IndexError>(),
+                Some(IndexError::Unavailable { retry_after_secs: 1, .. })
+            ));
+        }
+
+        tokio::time::sleep(Duration::from_millis(250)).await;
+        assert_eq!(manager.get_user_stats(user_id).await.unwrap().num_documents, 0);
+    }
+
+    #[tokio::test]
+    async fn test_id_exact_match_shapes() {
+        let temp_dir = TempDir::new().unwrap();
+        let manager = IndexManager::new(temp_dir.path().to_path_buf());
+        let user_id = Uuid::new_v4();
+
+        // Prefixes and shared tokens must not collide: matching is on the whole ID
+        let ids = [
+            "550e8400-e29b-41d4-a716-446655440000",
+            "550e8400",
+            "w2-2024-acme",
+            "w2 2024 acme",
+            "2024/receipts/march.pdf",
+            "2024/receipts",
+            "reçu été 数据",
+            "RECU ETE",
+        ];
+        for id in ids {
+            let input = test_doc(id, format!("Title {}", id), "Exact id test");
+            manager.index_document(user_id, input).await.unwrap();
+        }
+
+        for id in ids {
+            let preview = manager.preview_document(user_id, id, 50).await.unwrap().unwrap();
+            assert_eq!(preview.id, id);
+            assert_eq!(preview.title, format!("Title {}", id));
+        }
+        assert!(manager.preview_document(user_id, "w2", 50).await.unwrap().is_none());
+        assert!(manager.preview_document(user_id, "reçu", 50).await.unwrap().is_none());
+
+        // Deleting one ID leaves IDs that share a prefix or tokens untouched
+        manager.delete_document(user_id, "2024/receipts".to_string()).await.unwrap();
+        manager.delete_document(user_id, "w2 2024 acme".to_string()).await.unwrap();
+        let stats = manager.get_user_stats(user_id).await.unwrap();
+        assert_eq!(stats.num_documents, ids.len() - 2);
+        assert!(manager.preview_document(user_id, "2024/receipts/march.pdf", 50).await.unwrap().is_some());
+        assert!(manager.preview_document(user_id, "w2-2024-acme", 50).await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_id_prefix_filter() {
+        let temp_dir = TempDir::new().unwrap();
+        let manager = IndexManager::new(temp_dir.path().to_path_buf());
+        let user_id = Uuid::new_v4();
+
+        for id in ["2024/invoices/1", "2024/invoices/2", "2024/invoices-old/3", "2024/receipts/4", "2023/invoices/5"] {
+            let input = test_doc(id, "Payment", "Payment record");
+            manager.index_document(user_id, input).await.unwrap();
+        }
+
+        let search = |prefix: &str| SearchQuery {
+            query: "payment".to_string(),
+            filters: SearchFilters {
+                id_prefix: Some(prefix.to_string()),
+                ..Default::default()
+            },
+            ..Default::default()
         };
 
-        let response = manager.index_document(user_id, input).await.unwrap();
-        assert_eq!(response.id, "doc1");
+        let response = manager.search(user_id, search("2024/invoices/")).await.unwrap();
+        let mut ids: Vec<_> = response.results.iter().map(|r| r.id.as_str()).collect();
+        ids.sort();
+        assert_eq!(ids, ["2024/invoices/1", "2024/invoices/2"]);
+        // The filter does not change relevance scores
+        assert!(response.results.iter().all(|r| r.score == response.results[0].score));
 
-        // Search for the document
+        assert_eq!(manager.search(user_id, search("2024/")).await.unwrap().total, 4);
+        assert_eq!(manager.search(user_id, search("2025/")).await.unwrap().total, 0);
+    }
+
+    #[tokio::test]
+    async fn test_repair_drops_unreadable_segment() {
+        let temp_dir = TempDir::new().unwrap();
+        let manager = IndexManager::new(temp_dir.path().to_path_buf());
+        let user_id = Uuid::new_v4();
+
+        // Two commits produce two segments
+        for id in ["first", "second"] {
+            let input = test_doc(id, format!("{} document", id), "Repair test");
+            manager.index_document(user_id, input).await.unwrap();
+        }
+
+        let report = manager.repair_index(user_id).await.unwrap();
+        assert_eq!(report.status, "healthy");
+
+        // Simulate a lost segment file after an ungraceful shutdown
+        let handle = manager.get_or_create_index(user_id).await.unwrap();
+        let segment_id = handle.index.searchable_segment_metas().unwrap()[0]
+            .id()
+            .uuid_string();
+        let index_path = temp_dir.path().join(user_id.to_string()).join("index");
+        std::fs::remove_file(index_path.join(format!("{}.store", segment_id))).unwrap();
+        drop(handle);
+
+        let report = manager.repair_index(user_id).await.unwrap();
+        assert_eq!(report.status, "repaired");
+        assert_eq!(report.dropped_segments, vec![segment_id]);
+        assert_eq!(report.documents_lost, 1);
+
+        let stats = manager.get_user_stats(user_id).await.unwrap();
+        assert_eq!(stats.num_documents, 1);
+    }
+
+    #[tokio::test]
+    async fn test_consistent_search_sees_committed_deletes() {
+        let temp_dir = TempDir::new().unwrap();
+        let manager = Arc::new(IndexManager::new(temp_dir.path().to_path_buf()));
+        let user_id = Uuid::new_v4();
+        for id in ["a", "b"] {
+            let input = test_doc(id, "Receipt", "Office supplies");
+            manager.index_document(user_id, input).await.unwrap();
+        }
         let query = SearchQuery {
-            query: "Rust".to_string(),
-            limit: 10,
-            offset: 0,
-            filters: Default::default(),
+            query: "receipt".to_string(),
+            consistent: true,
+            ..Default::default()
         };
 
-        let search_response = manager.search(user_id, query).await.unwrap();
-        assert_eq!(search_response.results.len(), 1);
-        assert_eq!(search_response.results[0].title, "Rust Programming");
+        // Deleted, then searched right away
+        let deleted = manager.delete_document(user_id, "a".to_string()).await.unwrap();
+        let response = manager.search(user_id, query.clone()).await.unwrap();
+        let ids: Vec<&str> = response.results.iter().map(|r| r.id.as_str()).collect();
+        assert_eq!(ids, ["b"]);
+        assert_eq!(response.generation, deleted.generation);
+
+        // A write holding the writer doesn't block the search, which sees the last commit
+        let handle = manager.get_or_create_index(user_id).await.unwrap();
+        let held = handle.writer.lock().await;
+        let response = manager.search(user_id, query.clone()).await.unwrap();
+        assert_eq!(response.results.len(), 1);
+        assert_eq!(response.generation, deleted.generation);
+        drop(held);
+
+        manager.delete_document(user_id, "b".to_string()).await.unwrap();
+        assert!(manager.search(user_id, query).await.unwrap().results.is_empty());
     }
 
     #[tokio::test]
-    async fn test_multi_tenant_isolation() {
+    async fn test_flush_waits_for_queued_writes() {
+        let temp_dir = TempDir::new().unwrap();
+        let manager = Arc::new(IndexManager::new(temp_dir.path().to_path_buf()));
+        let user_id = Uuid::new_v4();
+
+        // No index yet: nothing to wait for and nothing is created
+        assert_eq!(manager.flush(user_id).await.unwrap().generation, 0);
+        assert!(!temp_dir.path().join(user_id.to_string()).exists());
+
+        // Writes sent without waiting for their responses queue behind a busy writer
+        let handle = manager.get_or_create_index(user_id).await.unwrap();
+        let held = handle.writer.lock().await;
+        let mut writes = Vec::new();
+        for i in 0..3 {
+            let manager = Arc::clone(&manager);
+            let input = test_doc(format!("doc{}", i), "Receipt", "Office supplies");
+            writes.push(queue(async move { manager.index_document(user_id, input).await }).await);
+        }
+        let flush = queue({
+            let manager = Arc::clone(&manager);
+            async move { manager.flush(user_id).await }
+        })
+        .await;
+        drop(held);
+
+        let flushed = flush.await.unwrap().unwrap();
+        assert_eq!(flushed.generation, handle.generation());
+        let query = SearchQuery {
+            query: "receipt".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(manager.search(user_id, query).await.unwrap().total, 3);
+        for write in writes {
+            assert!(write.await.unwrap().unwrap().generation <= flushed.generation);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_score_modes() {
         let temp_dir = TempDir::new().unwrap();
         let manager = IndexManager::new(temp_dir.path().to_path_buf());
+        let user_id = Uuid::new_v4();
 
-        let user1_id = Uuid::new_v4();
-        let user2_id = Uuid::new_v4();
+        // More mentions of "refund" score higher
+        let documents = (1..=20)
+            .map(|i| test_doc(format!("doc-{:02}", i), "Letter", format!("{} office supplies and furniture", "refund ".repeat(i))))
+            .collect();
+        manager.index_documents_bulk(user_id, documents).await.unwrap();
 
-        // User 1 indexes a document
-        let input1 = IndexDocumentInput {
-            id: Some("doc1".to_string()),
-            title: "User 1 Document".to_string(),
-            body: "This belongs to user 1".to_string(),
-            metadata: DocumentMetadata::default(),
+        let search = |score_mode, offset| {
+            manager.search(
+                user_id,
+                SearchQuery {
+                    query: "refund".to_string(),
+                    limit: 5,
+                    offset,
+                    score_mode,
+                    ..Default::default()
+                },
+            )
         };
-        manager.index_document(user1_id, input1).await.unwrap();
+        let scores = |response: &SearchResponse| response.results.iter().map(|r| r.score).collect::<Vec<_>>();
 
-        // User 2 indexes a document
-        let input2 = IndexDocumentInput {
-            id: Some("doc2".to_string()),
-            title: "User 2 Document".to_string(),
-            body: "This belongs to user 2".to_string(),
-            metadata: DocumentMetadata::default(),
+        let raw = search(ScoreMode::Raw, 0).await.unwrap();
+        assert_ne!(raw.results[0].score, 1.0);
+
+        let normalized = search(ScoreMode::Normalized, 0).await.unwrap();
+        assert_eq!(normalized.results[0].id, raw.results[0].id);
+        assert_eq!(normalized.results[0].score, 1.0);
+        assert!(scores(&normalized).windows(2).all(|pair| pair[0] >= pair[1] && pair[1] > 0.0));
+
+        // The top result outranks every match; the next page ranks lower against the same set
+        let percentile = search(ScoreMode::Percentile, 0).await.unwrap();
+        assert_eq!(percentile.results[0].id, raw.results[0].id);
+        assert_eq!(percentile.results[0].score, 100.0);
+        assert!(scores(&percentile).windows(2).all(|pair| pair[0] >= pair[1]));
+        let next_page = search(ScoreMode::Percentile, 5).await.unwrap();
+        assert!(next_page.results[0].score < percentile.results[4].score);
+        assert!(next_page.results[0].score > 0.0);
+    }
+
+    #[tokio::test]
+    async fn test_sort_by_length() {
+        let temp_dir = TempDir::new().unwrap();
+        let manager = IndexManager::new(temp_dir.path().to_path_buf());
+        let user_id = Uuid::new_v4();
+
+        let documents = [
+            ("medium", "Refund", "refund for the office chair"),
+            ("long", "Refund request", "refund for the office chair and the desk delivered late"),
+            ("short", "Refund request for a chair", "refund"),
+            ("unrelated", "Memo", "chair"),
+        ]
+        .into_iter()
+        .map(|(id, title, body)| test_doc(id, title, body))
+        .collect();
+        manager.index_documents_bulk(user_id, documents).await.unwrap();
+
+        let search = |field, order, offset| {
+            manager.search(
+                user_id,
+                SearchQuery {
+                    query: "refund".to_string(),
+                    offset,
+                    sort: SortBy::ByLength { field, order },
+                    ..Default::default()
+                },
+            )
         };
-        manager.index_document(user2_id, input2).await.unwrap();
+        let ids = |response: &SearchResponse| response.results.iter().map(|r| r.id.clone()).collect::<Vec<_>>();
+
+        // Shortest first among matching documents only, unscored
+        let shortest = search(LengthField::Body, SortOrder::Asc, 0).await.unwrap();
+        assert_eq!(ids(&shortest), ["short", "medium", "long"]);
+        assert!(shortest.results.iter().all(|r| r.score == 0.0));
+
+        let by_title = search(LengthField::Title, SortOrder::Desc, 0).await.unwrap();
+        assert_eq!(ids(&by_title), ["short", "long", "medium"]);
+
+        let next_page = search(LengthField::Body, SortOrder::Asc, 1).await.unwrap();
+        assert_eq!(ids(&next_page), ["medium", "long"]);
+    }
+
+    #[tokio::test]
+    async fn test_reads_by_new_user_create_no_files() {
+        let temp_dir = TempDir::new().unwrap();
+        let manager = IndexManager::new(temp_dir.path().to_path_buf());
+        let user_id = Uuid::new_v4();
+        let query = |text: &str| SearchQuery {
+            query: text.to_string(),
+            ..Default::default()
+        };
+
+        let response = manager.search(user_id, query("receipt")).await.unwrap();
+        assert!(response.results.is_empty());
+        assert_eq!(response.generation, 0);
+        let browsed = manager.browse_documents(user_id, BrowseDocumentsQuery { limit: 10, offset: 0 }).await.unwrap();
+        assert!(browsed.documents.is_empty());
+        assert_eq!(manager.get_user_stats(user_id).await.unwrap().num_documents, 0);
+        assert!(manager.feature_enabled(user_id, Feature::Browse).await.unwrap());
+
+        // Invalid queries are still rejected
+        let err = manager.search(user_id, query("nosuchfield:x")).await.unwrap_err();
+        assert!(matches!(err.downcast_ref::<IndexError>(), Some(IndexError::InvalidQuery(_))));
+
+        assert_eq!(std::fs::read_dir(temp_dir.path()).unwrap().count(), 0);
+
+        // The first write creates the index
+        let input = test_doc("a", "Receipt", "Office supplies");
+        manager.index_document(user_id, input).await.unwrap();
+        assert!(temp_dir.path().join(user_id.to_string()).join("index").is_dir());
+        assert_eq!(manager.search(user_id, query("receipt")).await.unwrap().results.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_ids_only_search() {
+        let temp_dir = TempDir::new().unwrap();
+        let manager = IndexManager::new(temp_dir.path().to_path_buf());
+        let user_id = Uuid::new_v4();
+
+        for id in ["w2-2023", "w2-2024"] {
+            let input = test_doc(id, format!("W-2 {}", id), "Wage and tax statement");
+            manager.index_document(user_id, input).await.unwrap();
+        }
 
-        // User 1 searches - should only see their document
         let query = SearchQuery {
-            query: "Document".to_string(),
-            limit: 10,
-            offset: 0,
-            filters: Default::default(),
+            query: "wage".to_string(),
+            ids_only: true,
+            ..Default::default()
         };
+        let response = manager.search(user_id, query).await.unwrap();
 
-        let user1_results = manager.search(user1_id, query.clone()).await.unwrap();
-        assert_eq!(user1_results.results.len(), 1);
-        assert!(user1_results.results[0].title.contains("User 1"));
+        assert!(response.results.is_empty());
+        assert_eq!(response.total, 2);
+        let mut ids: Vec<String> = response.ids.unwrap().into_iter().map(|hit| hit.id).collect();
+        ids.sort();
+        assert_eq!(ids, vec!["w2-2023", "w2-2024"]);
+    }
 
-        // User 2 searches - should only see their document
-        let user2_results = manager.search(user2_id, query).await.unwrap();
-        assert_eq!(user2_results.results.len(), 1);
-        assert!(user2_results.results[0].title.contains("User 2"));
+    #[tokio::test]
+    async fn test_merge_policy_limits_segment_count() {
+        async fn segments_after_commits(min_segments: usize, wait_for_merge: bool) -> usize {
+            let temp_dir = TempDir::new().unwrap();
+            let manager = IndexManager::from_config(&Config {
+                data_dir: temp_dir.path().to_path_buf(),
+                merge_min_segments: min_segments,
+                ..Config::default()
+            });
+            let user_id = Uuid::new_v4();
+
+            // One commit per document produces one segment per document
+            for i in 0..8 {
+                let input = test_doc(format!("doc{}", i), format!("Document {}", i), "Merge policy test");
+                manager.index_document(user_id, input).await.unwrap();
+            }
+
+            // Merges run in the background; wait for the segment count to drop
+            let handle = manager.get_or_create_index(user_id).await.unwrap();
+            let mut count = handle.index.searchable_segment_metas().unwrap().len();
+            for _ in 0..50 {
+                if !wait_for_merge || count < 8 {
+                    break;
+                }
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                count = handle.index.searchable_segment_metas().unwrap().len();
+            }
+            count
+        }
+
+        // Merging disabled in practice: every commit stays its own segment
+        assert_eq!(segments_after_commits(100, false).await, 8);
+
+        // Aggressive merging collapses segments
+        assert!(segments_after_commits(2, true).await < 8);
+    }
+
+    #[tokio::test]
+    async fn test_writer_lock_timeout_returns_busy() {
+        let temp_dir = TempDir::new().unwrap();
+        let manager = IndexManager::from_config(&Config {
+            data_dir: temp_dir.path().to_path_buf(),
+            writer_lock_timeout_ms: 50,
+            ..Config::default()
+        });
+        let user_id = Uuid::new_v4();
+
+        // Hold the writer as a long-running import would
+        let handle = manager.get_or_create_index(user_id).await.unwrap();
+        let _guard = handle.writer.lock().await;
+
+        let err = manager
+            .delete_document(user_id, "doc1".to_string())
+            .await
+            .unwrap_err();
+
+        assert_eq!(
+            err.downcast_ref::<IndexError>(),
+            Some(&IndexError::Busy { retry_after_secs: 1 })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_bulk_retries_busy_writer_only() {
+        let temp_dir = TempDir::new().unwrap();
+        let manager = Arc::new(IndexManager::from_config(&Config {
+            data_dir: temp_dir.path().to_path_buf(),
+            writer_lock_timeout_ms: 50,
+            bulk_retry_attempts: 3,
+            bulk_retry_backoff_ms: 100,
+            ..Config::default()
+        }));
+        let user_id = Uuid::new_v4();
+
+        let user_dir = temp_dir.path().join(user_id.to_string());
+        std::fs::create_dir_all(&user_dir).unwrap();
+        std::fs::write(user_dir.join("config.json"), r#"{"store_body": false}"#).unwrap();
+
+        let document = |id: &str, index_body: bool| IndexDocumentInput {
+            id: Some(id.to_string()),
+            title: "Receipt".to_string(),
+            body: "Office supplies".to_string(),
+            index_body,
+            ..Default::default()
+        };
+
+        // A writer released after the first attempt times out is picked up by a retry
+        let handle = manager.get_or_create_index(user_id).await.unwrap();
+        let guard = Arc::clone(&handle.writer).lock_owned().await;
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(120)).await;
+            drop(guard);
+        });
+
+        let response = manager
+            .index_documents_bulk(user_id, vec![document("doc1", true), document("doc2", true)])
+            .await
+            .unwrap();
+        assert_eq!(response.indexed, 2);
+        assert!(response.retries >= 1);
+        let ids: Vec<&str> = response.results.iter().map(|result| result.id.as_str()).collect();
+        assert_eq!(ids, ["doc1", "doc2"]);
+
+        // A document the index can't hold fails as such, not as a busy writer after retries
+        let _guard = handle.writer.lock().await;
+        let err = manager
+            .index_documents_bulk(user_id, vec![document("doc3", false)])
+            .await
+            .unwrap_err();
+        assert!(matches!(err.downcast_ref::<IndexError>(), Some(IndexError::Unsupported(_))));
+
+        // Without retries a busy writer is reported straight away
+        let manager = IndexManager::from_config(&Config {
+            data_dir: temp_dir.path().to_path_buf(),
+            writer_lock_timeout_ms: 50,
+            ..Config::default()
+        });
+        let user_id = Uuid::new_v4();
+        let handle = manager.get_or_create_index(user_id).await.unwrap();
+        let _guard = handle.writer.lock().await;
+        let err = manager
+            .index_documents_bulk(user_id, vec![document("doc4", true)])
+            .await
+            .unwrap_err();
+        assert!(matches!(err.downcast_ref::<IndexError>(), Some(IndexError::Busy { .. })));
+    }
+
+    #[tokio::test]
+    async fn test_index_rate_limit() {
+        let temp_dir = TempDir::new().unwrap();
+        let manager = IndexManager::from_config(&Config {
+            data_dir: temp_dir.path().to_path_buf(),
+            max_index_rate_per_minute: 3,
+            writer_lock_timeout_ms: 50,
+            ..Config::default()
+        });
+        let user_id = Uuid::new_v4();
+
+        let document = |id: &str| test_doc(id, "Receipt", "Office supplies");
+        let rate_limited = |err: anyhow::Error| match err.downcast_ref::<IndexError>() {
+            Some(IndexError::RateLimited { retry_after_secs }) => *retry_after_secs,
+            other => panic!("expected RateLimited, got {:?}", other),
+        };
+
+        // Bulk documents count individually against the same allowance
+        manager.index_document(user_id, document("doc1")).await.unwrap();
+        manager.index_documents_bulk(user_id, vec![document("doc2"), document("doc3")]).await.unwrap();
+        let err = manager.index_document(user_id, document("doc4")).await.unwrap_err();
+        assert_eq!(rate_limited(err), 20);
+        let err = manager.create_document(user_id, document("doc4")).await.unwrap_err();
+        rate_limited(err);
+
+        // A batch larger than a minute's allowance can never be admitted
+        let err = manager
+            .index_documents_bulk(Uuid::new_v4(), (0..4).map(|i| document(&format!("doc{}", i))).collect())
+            .await
+            .unwrap_err();
+        assert!(matches!(err.downcast_ref::<IndexError>(), Some(IndexError::InvalidQuery(_))));
+
+        // Writes rejected as conflicting, duplicate or busy take nothing from the allowance
+        let user_id = Uuid::new_v4();
+        let user_dir = temp_dir.path().join(user_id.to_string());
+        std::fs::create_dir_all(&user_dir).unwrap();
+        std::fs::write(user_dir.join("config.json"), r#"{"duplicate_content": "reject"}"#).unwrap();
+        manager.create_document(user_id, document("doc1")).await.unwrap();
+        for _ in 0..3 {
+            let err = manager.create_document(user_id, document("doc1")).await.unwrap_err();
+            assert!(matches!(err.downcast_ref::<IndexError>(), Some(IndexError::Conflict(_))));
+            let err = manager.index_document(user_id, document("copy")).await.unwrap_err();
+            assert!(matches!(err.downcast_ref::<IndexError>(), Some(IndexError::Conflict(_))));
+        }
+        let handle = manager.get_or_create_index(user_id).await.unwrap();
+        {
+            let _held = handle.writer.lock().await;
+            let err = manager.index_document(user_id, test_doc("doc2", "Invoice", "Consulting")).await.unwrap_err();
+            assert!(matches!(err.downcast_ref::<IndexError>(), Some(IndexError::Busy { .. })));
+        }
+        let documents = vec![test_doc("doc2", "Invoice", "Consulting"), test_doc("doc3", "W-2", "Wages")];
+        manager.index_documents_bulk(user_id, documents).await.unwrap();
+        let err = manager.index_document(user_id, test_doc("doc4", "1099", "Interest")).await.unwrap_err();
+        rate_limited(err);
+
+        // Other users have their own allowance, and a user's config can override the rate
+        let other_user = Uuid::new_v4();
+        let user_dir = temp_dir.path().join(other_user.to_string());
+        std::fs::create_dir_all(&user_dir).unwrap();
+        std::fs::write(user_dir.join("config.json"), r#"{"max_index_rate_per_minute": 0}"#).unwrap();
+        let documents: Vec<_> = (0..10).map(|i| document(&format!("doc{}", i))).collect();
+        let response = manager.index_documents_bulk(other_user, documents).await.unwrap();
+        assert_eq!(response.indexed, 10);
+    }
+
+    #[tokio::test]
+    async fn test_pinned_index_survives_eviction() {
+        let temp_dir = TempDir::new().unwrap();
+        let pinned_user = Uuid::new_v4();
+        let manager = IndexManager::from_config(&Config {
+            data_dir: temp_dir.path().to_path_buf(),
+            index_cache_capacity: 1,
+            pinned_users: vec![pinned_user],
+            ..Config::default()
+        });
+        let runtime_pinned = Uuid::new_v4();
+        assert!(manager.pin_index(runtime_pinned));
+        assert!(!manager.pin_index(runtime_pinned));
+
+        let others: Vec<Uuid> = (0..3).map(|_| Uuid::new_v4()).collect();
+        for user_id in [pinned_user, runtime_pinned].iter().chain(&others) {
+            let input = test_doc("doc1", "Eviction", "Eviction pressure");
+            manager.index_document(*user_id, input).await.unwrap();
+        }
+
+        // Only the most recently used unpinned index is kept alongside the pinned ones
+        {
+            let indexes = manager.indexes.read().await;
+            assert!(indexes.contains_key(&pinned_user));
+            assert!(indexes.contains_key(&runtime_pinned));
+            assert!(indexes.contains_key(&others[2]));
+            assert!(!indexes.contains_key(&others[0]));
+            assert!(!indexes.contains_key(&others[1]));
+        }
+
+        // Unpinning makes the index evictable again
+        assert!(manager.unpin_index(runtime_pinned).await);
+        assert!(!manager.unpin_index(runtime_pinned).await);
+        assert_eq!(manager.pinned_indexes(), vec![pinned_user]);
+        assert_eq!(manager.indexes.read().await.len(), 2);
+
+        // Evicted indexes reopen transparently
+        let stats = manager.get_user_stats(others[0]).await.unwrap();
+        assert_eq!(stats.num_documents, 1);
+    }
+
+    #[tokio::test]
+    async fn test_warm_index_makes_index_resident() {
+        let temp_dir = TempDir::new().unwrap();
+        let user_id = Uuid::new_v4();
+
+        {
+            let manager = IndexManager::new(temp_dir.path().to_path_buf());
+            let input = test_doc("doc1", "Warm start", "Index opened ahead of the first search");
+            manager.index_document(user_id, input).await.unwrap();
+        }
+
+        // A fresh manager starts cold, as after eviction or a restart
+        let manager = IndexManager::new(temp_dir.path().to_path_buf());
+        assert!(!manager.indexes.read().await.contains_key(&user_id));
+
+        let warmed = manager.warm_index(user_id).await.unwrap();
+        assert!(!warmed.already_open);
+        assert_eq!(warmed.segments, 1);
+        assert!(manager.indexes.read().await.contains_key(&user_id));
+
+        let again = manager.warm_index(user_id).await.unwrap();
+        assert!(again.already_open);
+    }
+
+    #[tokio::test]
+    async fn test_ids_only_without_fast_id() {
+        let temp_dir = TempDir::new().unwrap();
+        let user_id = Uuid::new_v4();
+        create_index_without_fast_id(temp_dir.path(), user_id);
+        let manager = IndexManager::from_config(&Config {
+            data_dir: temp_dir.path().to_path_buf(),
+            tie_break_by_id: false,
+            ..Config::default()
+        });
+
+        for id in ["receipt-b", "receipt-a"] {
+            manager.index_document(user_id, test_doc(id, "Receipt", "Office supplies")).await.unwrap();
+        }
+
+        let query = SearchQuery {
+            query: "receipt".to_string(),
+            ids_only: true,
+            ..Default::default()
+        };
+        let response = manager.search(user_id, query).await.unwrap();
+        let mut ids: Vec<String> = response.ids.unwrap().into_iter().map(|hit| hit.id).collect();
+        ids.sort();
+        assert_eq!(ids, ["receipt-a", "receipt-b"]);
+    }
+
+    #[tokio::test]
+    async fn test_tie_break_without_fast_id() {
+        let temp_dir = TempDir::new().unwrap();
+        let user_id = Uuid::new_v4();
+        create_index_without_fast_id(temp_dir.path(), user_id);
+        let manager = IndexManager::new(temp_dir.path().to_path_buf());
+        assert!(manager.tie_break_by_id);
+
+        for id in ["receipt-b", "receipt-a"] {
+            manager.index_document(user_id, test_doc(id, "Receipt", "Office supplies")).await.unwrap();
+        }
+
+        // Ties keep the internal order instead of failing the search
+        let query = SearchQuery {
+            query: "receipt".to_string(),
+            ..Default::default()
+        };
+        let response = manager.search(user_id, query).await.unwrap();
+        assert_eq!(response.total, 2);
+    }
+
+    #[tokio::test]
+    async fn test_warmup_queries_run_on_open() {
+        let temp_dir = TempDir::new().unwrap();
+        let user_id = Uuid::new_v4();
+        let user_dir = temp_dir.path().join(user_id.to_string());
+        std::fs::create_dir_all(&user_dir).unwrap();
+        // Of a valid query, a syntax error and a query rejected as expensive, only the first runs
+        std::fs::write(user_dir.join("config.json"), r#"{"warmup_queries": ["refund", "title:(", "*"]}"#).unwrap();
+
+        {
+            let manager = IndexManager::new(temp_dir.path().to_path_buf());
+            let input = test_doc("doc1", "Refund", "Refund claim for 2023");
+            manager.index_document(user_id, input).await.unwrap();
+        }
+
+        {
+            let manager = IndexManager::new(temp_dir.path().to_path_buf());
+            let handle = manager.get_or_create_index(user_id).await.unwrap();
+            assert_eq!(handle.warmup_queries_run.load(Ordering::Relaxed), 1);
+
+            // Only opening the index warms it, not later lookups of the open handle
+            let handle = manager.get_or_create_index(user_id).await.unwrap();
+            assert_eq!(handle.warmup_queries_run.load(Ordering::Relaxed), 1);
+        }
+
+        let manager = IndexManager::from_config(&Config {
+            data_dir: temp_dir.path().to_path_buf(),
+            warmup_queries_enabled: false,
+            ..Config::default()
+        });
+        let handle = manager.get_or_create_index(user_id).await.unwrap();
+        assert_eq!(handle.warmup_queries_run.load(Ordering::Relaxed), 0);
+    }
+
+    #[tokio::test]
+    async fn test_global_writer_limit_queues_writes() {
+        let temp_dir = TempDir::new().unwrap();
+        let manager = Arc::new(IndexManager::from_config(&Config {
+            data_dir: temp_dir.path().to_path_buf(),
+            max_concurrent_writers: 1,
+            writer_lock_timeout_ms: 50,
+            ..Config::default()
+        }));
+        let busy_user = Uuid::new_v4();
+        let queued_user = Uuid::new_v4();
+
+        // Saturate the only permit with a write in progress for another tenant
+        let handle = manager.get_or_create_index(busy_user).await.unwrap();
+        let guard = manager.lock_writer(busy_user, &handle).await.unwrap();
+
+        let write = {
+            let manager = Arc::clone(&manager);
+            tokio::spawn(async move {
+                let input = test_doc("doc1", "Queued", "Queued write");
+                manager.index_document(queued_user, input).await
+            })
+        };
+
+        // Well past the writer lock timeout, the write is still waiting rather than failed
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        assert!(!write.is_finished());
+
+        // Reads don't need a permit
+        let stats = manager.get_user_stats(busy_user).await.unwrap();
+        assert_eq!(stats.num_documents, 0);
+
+        drop(guard);
+        write.await.unwrap().unwrap();
+        let stats = manager.get_user_stats(queued_user).await.unwrap();
+        assert_eq!(stats.num_documents, 1);
+    }
+
+    #[tokio::test]
+    async fn test_search_phases_are_traced() {
+        use std::sync::Mutex;
+        use tracing_subscriber::layer::{Context, SubscriberExt};
+
+        /// Records the names of spans as they close
+        struct ClosedSpans(Arc<Mutex<Vec<&'static str>>>);
+
+        impl<S> tracing_subscriber::Layer<S> for ClosedSpans
+        where
+            S: tracing::Subscriber + for<'a> tracing_subscriber::registry::LookupSpan<'a>,
+        {
+            fn on_close(&self, id: tracing::span::Id, ctx: Context<'_, S>) {
+                if let Some(span) = ctx.span(&id) {
+                    self.0.lock().unwrap().push(span.name());
+                }
+            }
+        }
+
+        let temp_dir = TempDir::new().unwrap();
+        let manager = IndexManager::new(temp_dir.path().to_path_buf());
+        let user_id = Uuid::new_v4();
+        let input = test_doc("doc1", "Traced", "Phase timing");
+        manager.index_document(user_id, input).await.unwrap();
+
+        let closed = Arc::new(Mutex::new(Vec::new()));
+        let subscriber = tracing_subscriber::registry().with(ClosedSpans(Arc::clone(&closed)));
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let query = SearchQuery {
+            query: "phase".to_string(),
+            ..Default::default()
+        };
+        manager.search(user_id, query).await.unwrap();
+        assert_eq!(
+            *closed.lock().unwrap(),
+            vec!["reload", "parse", "search", "fetch_docs", "highlight"]
+        );
+
+        closed.lock().unwrap().clear();
+        manager
+            .browse_documents(user_id, BrowseDocumentsQuery { limit: 10, offset: 0 })
+            .await
+            .unwrap();
+        assert_eq!(*closed.lock().unwrap(), vec!["reload", "search", "fetch_docs"]);
+    }
+
+    #[tokio::test]
+    async fn test_equal_scores_are_ordered_by_id() {
+        let temp_dir = TempDir::new().unwrap();
+        let manager = IndexManager::new(temp_dir.path().to_path_buf());
+        let user_id = Uuid::new_v4();
+
+        let input = |id: &str| test_doc(id, "Monthly statement", "Identical statement body");
+        let ids: Vec<String> = (0..10).map(|i| format!("stmt-{}", i)).collect();
+        for id in ids.iter().rev() {
+            manager.index_document(user_id, input(id)).await.unwrap();
+        }
+
+        let paged = || async {
+            let mut seen = Vec::new();
+            for offset in (0..10).step_by(3) {
+                let query = SearchQuery {
+                    query: "statement".to_string(),
+                    limit: 3,
+                    offset,
+                    ..Default::default()
+                };
+                let response = manager.search(user_id, query).await.unwrap();
+                seen.extend(response.results.into_iter().map(|r| r.id));
+            }
+            seen
+        };
+
+        // Pages line up without gaps or repeats, in ID order
+        assert_eq!(paged().await, ids);
+        assert_eq!(paged().await, ids);
+
+        // Rewriting a document moves it to a new segment without reordering
+        manager.index_document(user_id, input("stmt-0")).await.unwrap();
+        assert_eq!(paged().await, ids);
     }
 }
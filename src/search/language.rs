@@ -0,0 +1,229 @@
+//! Content-language detection for per-language stemming
+//!
+//! Detection is deliberately lightweight: it counts common function words
+//! (articles, pronouns, conjunctions) of each supported language and picks the
+//! language that is clearly ahead. That is reliable for ordinary prose of a
+//! sentence or more, but short texts, lists of names or numbers and documents
+//! mixing languages are usually left undetected, and closely related
+//! languages can be confused. Undetected documents are still searchable
+//! through the language-neutral `body` field.
+
+use tantivy::tokenizer::{
+    Language as StemmerLanguage, LowerCaser, RawTokenizer, RemoveLongFilter, SimpleTokenizer, Stemmer, StopWordFilter,
+    TextAnalyzer, TokenStream, TokenizerManager,
+};
+
+/// A language with its own stemmed body field
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Language {
+    English,
+    German,
+    French,
+    Spanish,
+    Italian,
+    Dutch,
+    Portuguese,
+}
+
+/// Every language detection can report
+pub const LANGUAGES: &[Language] = &[
+    Language::English,
+    Language::German,
+    Language::French,
+    Language::Spanish,
+    Language::Italian,
+    Language::Dutch,
+    Language::Portuguese,
+];
+
+/// Fewest function words a text needs before a language is reported
+const MIN_HITS: usize = 3;
+
+impl Language {
+    /// ISO 639-1 code stored in the `language` field
+    pub fn code(&self) -> &'static str {
+        match self {
+            Language::English => "en",
+            Language::German => "de",
+            Language::French => "fr",
+            Language::Spanish => "es",
+            Language::Italian => "it",
+            Language::Dutch => "nl",
+            Language::Portuguese => "pt",
+        }
+    }
+
+    /// Language with the given ISO 639-1 code
+    pub fn from_code(code: &str) -> Option<Language> {
+        LANGUAGES.iter().copied().find(|language| language.code() == code)
+    }
+
+    /// Name of the stemming analyzer registered for this language
+    pub fn analyzer_name(&self) -> &'static str {
+        match self {
+            Language::English => "en_stem",
+            Language::German => "de_stem",
+            Language::French => "fr_stem",
+            Language::Spanish => "es_stem",
+            Language::Italian => "it_stem",
+            Language::Dutch => "nl_stem",
+            Language::Portuguese => "pt_stem",
+        }
+    }
+
+    /// Schema field holding the stemmed body of documents in this language
+    pub fn body_field(&self) -> &'static str {
+        match self {
+            Language::English => "body_en",
+            Language::German => "body_de",
+            Language::French => "body_fr",
+            Language::Spanish => "body_es",
+            Language::Italian => "body_it",
+            Language::Dutch => "body_nl",
+            Language::Portuguese => "body_pt",
+        }
+    }
+
+    fn stemmer(&self) -> StemmerLanguage {
+        match self {
+            Language::English => StemmerLanguage::English,
+            Language::German => StemmerLanguage::German,
+            Language::French => StemmerLanguage::French,
+            Language::Spanish => StemmerLanguage::Spanish,
+            Language::Italian => StemmerLanguage::Italian,
+            Language::Dutch => StemmerLanguage::Dutch,
+            Language::Portuguese => StemmerLanguage::Portuguese,
+        }
+    }
+
+    /// Frequent function words, chosen to overlap little with the other languages
+    fn function_words(&self) -> &'static [&'static str] {
+        match self {
+            Language::English => &[
+                "the", "and", "of", "to", "is", "that", "for", "with", "was", "are", "this", "from", "have",
+                "which", "you", "not", "be", "by", "it", "were", "will", "your", "been", "has",
+            ],
+            Language::German => &[
+                "der", "die", "das", "und", "ist", "nicht", "mit", "den", "dem", "ein", "eine", "einer", "auf",
+                "sich", "auch", "für", "von", "zu", "wird", "werden", "sind", "bei", "nach", "wurden", "ich",
+            ],
+            Language::French => &[
+                "le", "les", "et", "des", "est", "une", "du", "pour", "dans", "qui", "pas", "sur", "avec", "ce",
+                "au", "sont", "nous", "vous", "elle", "mais", "aux", "été", "ont",
+            ],
+            Language::Spanish => &[
+                "el", "los", "las", "y", "es", "del", "para", "como", "más", "pero", "está", "también", "muy",
+                "hay", "sus", "esta", "fue", "han", "ser", "entre", "cuando", "sin",
+            ],
+            Language::Italian => &[
+                "il", "lo", "gli", "della", "delle", "che", "non", "sono", "per", "è", "anche", "nel", "alla",
+                "questo", "più", "dei", "degli", "nella", "stato", "essere", "ha",
+            ],
+            Language::Dutch => &[
+                "het", "een", "van", "dat", "niet", "zijn", "op", "voor", "met", "ook", "maar", "wordt", "deze",
+                "bij", "naar", "dit", "werd", "worden", "heeft", "wij",
+            ],
+            Language::Portuguese => &[
+                "os", "um", "não", "da", "dos", "das", "em", "com", "são", "mais", "isso", "foi", "ao", "pela",
+                "pelo", "uma", "também", "seu", "sua", "foram",
+            ],
+        }
+    }
+}
+
+/// Detect the language of `text`, or `None` when no language is clearly ahead
+pub fn detect(text: &str) -> Option<Language> {
+    let mut hits = [0usize; LANGUAGES.len()];
+    for word in text.split(|c: char| !c.is_alphabetic()).filter(|word| !word.is_empty()) {
+        let word = word.to_lowercase();
+        for (count, language) in hits.iter_mut().zip(LANGUAGES) {
+            if language.function_words().contains(&word.as_str()) {
+                *count += 1;
+            }
+        }
+    }
+
+    let (best, &best_hits) = hits.iter().enumerate().max_by_key(|(_, count)| **count)?;
+    let runner_up = hits
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| *i != best)
+        .map(|(_, count)| *count)
+        .max()
+        .unwrap_or(0);
+
+    // Require a clear lead so related languages sharing a few words aren't guessed
+    (best_hits >= MIN_HITS && best_hits * 2 >= runner_up * 3).then(|| LANGUAGES[best])
+}
+
+/// A language's stop-word list (from Snowball, or Lucene's for English), checked one word at a time
+pub struct StopWords(TextAnalyzer);
+
+impl StopWords {
+    pub fn new(language: Language) -> Self {
+        let filter = StopWordFilter::new(language.stemmer())
+            .expect("tantivy has a stop-word list for every supported language");
+        StopWords(TextAnalyzer::builder(RawTokenizer::default()).filter(filter).build())
+    }
+
+    /// Whether `word`, already lowercased, is a stop word
+    pub fn contains(&mut self, word: &str) -> bool {
+        !self.0.token_stream(word).advance()
+    }
+}
+
+/// Register the stemming analyzer of every supported language
+///
+/// Must run before a writer or query parser is created for an index whose
+/// schema has per-language fields, since they look analyzers up by name.
+pub fn register_analyzers(tokenizers: &TokenizerManager) {
+    for language in LANGUAGES {
+        let analyzer = TextAnalyzer::builder(SimpleTokenizer::default())
+            .filter(RemoveLongFilter::limit(40))
+            .filter(LowerCaser)
+            .filter(Stemmer::new(language.stemmer()))
+            .build();
+        tokenizers.register(language.analyzer_name(), analyzer);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_language() {
+        let english = "The refunds were processed and the forms for this year have been filed with the agency.";
+        assert_eq!(detect(english), Some(Language::English));
+
+        let german = "Die Erstattungen wurden bearbeitet und die Formulare für dieses Jahr sind bei der Behörde eingereicht.";
+        assert_eq!(detect(german), Some(Language::German));
+
+        // Too little prose to tell
+        assert_eq!(detect("Invoice 2024-118 ACME GmbH"), None);
+        assert_eq!(detect(""), None);
+    }
+
+    #[test]
+    fn test_language_codes_round_trip() {
+        for language in LANGUAGES {
+            assert_eq!(Language::from_code(language.code()), Some(*language));
+        }
+        assert_eq!(Language::from_code("xx"), None);
+    }
+
+    #[test]
+    fn test_stop_words() {
+        for language in LANGUAGES {
+            StopWords::new(*language);
+        }
+
+        let mut english = StopWords::new(Language::English);
+        assert!(english.contains("the"));
+        assert!(!english.contains("refund"));
+
+        let mut german = StopWords::new(Language::German);
+        assert!(german.contains("und"));
+        assert!(!german.contains("the"));
+    }
+}
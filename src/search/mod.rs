@@ -1,6 +1,21 @@
+pub mod changes;
+pub mod collector;
+pub mod commit_history;
+pub mod encryption;
+pub mod error;
 pub mod index_manager;
+pub mod language;
 pub mod models;
+pub mod ngram;
+pub mod query;
+pub mod recent_deletes;
 pub mod schema;
+pub mod similarity;
+pub mod text;
+pub mod tokenizer;
+pub mod user_config;
+pub mod write_rate;
 
-pub use index_manager::IndexManager;
+pub use error::IndexError;
+pub use index_manager::{IndexManager, SearchOptions};
 pub use models::*;
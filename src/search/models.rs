@@ -1,6 +1,8 @@
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::str::FromStr;
+use uuid::Uuid;
 
 /// Input for indexing a document
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -8,6 +10,13 @@ pub struct IndexDocumentInput {
     /// Optional client-provided document ID. If None, a UUID will be generated.
     pub id: Option<String>,
 
+    /// Optional human-readable ID (e.g. an invoice number), searchable as text
+    ///
+    /// Unlike `id`, it is tokenized, so `2024-0042` matches `INV-2024-0042`.
+    /// It is not unique and cannot be used to delete or fetch the document.
+    #[serde(default)]
+    pub external_id: Option<String>,
+
     /// Document title
     pub title: String,
 
@@ -17,6 +26,32 @@ pub struct IndexDocumentInput {
     /// Optional metadata
     #[serde(default)]
     pub metadata: DocumentMetadata,
+
+    /// Optional ranking weight multiplied into the document's search score (default 1.0)
+    #[serde(default)]
+    pub boost: Option<f32>,
+
+    /// Index the body for search; when false it is only stored for retrieval
+    #[serde(default = "default_index_body")]
+    pub index_body: bool,
+}
+
+fn default_index_body() -> bool {
+    true
+}
+
+impl Default for IndexDocumentInput {
+    fn default() -> Self {
+        IndexDocumentInput {
+            id: None,
+            external_id: None,
+            title: String::new(),
+            body: String::new(),
+            metadata: DocumentMetadata::default(),
+            boost: None,
+            index_body: default_index_body(),
+        }
+    }
 }
 
 /// Document metadata
@@ -48,6 +83,180 @@ pub struct IndexDocumentResponse {
 
     /// Message
     pub message: String,
+
+    /// Generation token of the commit containing this write
+    pub generation: u64,
+
+    /// Existing document with the same content, when the write was skipped as an alias
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub alias_of: Option<String>,
+}
+
+/// Input for indexing many documents in one request
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BulkIndexInput {
+    /// Documents to index, in order
+    pub documents: Vec<IndexDocumentInput>,
+}
+
+/// How repeated document IDs within one bulk index request are handled
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DuplicateIdMode {
+    /// Reject the whole request, listing the repeated IDs (default)
+    #[default]
+    Reject,
+    /// Index only the last occurrence of each ID and report the others as overwritten
+    LastWins,
+}
+
+impl FromStr for DuplicateIdMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "reject" => Ok(DuplicateIdMode::Reject),
+            "last_wins" => Ok(DuplicateIdMode::LastWins),
+            other => Err(format!("expected \"reject\" or \"last_wins\", got \"{}\"", other)),
+        }
+    }
+}
+
+/// How a bulk index larger than the writer heap is committed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BulkCommitMode {
+    /// One commit at the end, so searches see none or all of the batch (default)
+    #[default]
+    Atomic,
+    /// Commit whenever the text added since the last commit nears the writer heap
+    Chunked,
+}
+
+impl FromStr for BulkCommitMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "atomic" => Ok(BulkCommitMode::Atomic),
+            "chunked" => Ok(BulkCommitMode::Chunked),
+            other => Err(format!("expected \"atomic\" or \"chunked\", got \"{}\"", other)),
+        }
+    }
+}
+
+/// How a write is handled when another document already has the same content
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DuplicateContentMode {
+    /// Index it like any other document (default)
+    #[default]
+    Allow,
+    /// Fail with a conflict naming the existing document
+    Reject,
+    /// Skip the write and return the existing document's ID for the client to use instead
+    Alias,
+}
+
+impl FromStr for DuplicateContentMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "allow" => Ok(DuplicateContentMode::Allow),
+            "reject" => Ok(DuplicateContentMode::Reject),
+            "alias" => Ok(DuplicateContentMode::Alias),
+            other => Err(format!("expected \"allow\", \"reject\" or \"alias\", got \"{}\"", other)),
+        }
+    }
+}
+
+/// How a search `limit` above the server cap is handled
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LimitOverflowMode {
+    /// Reject the request with a validation error (default)
+    #[default]
+    Reject,
+    /// Search with the cap instead and flag the response as `capped`
+    Clamp,
+}
+
+impl FromStr for LimitOverflowMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "reject" => Ok(LimitOverflowMode::Reject),
+            "clamp" => Ok(LimitOverflowMode::Clamp),
+            other => Err(format!("expected \"reject\" or \"clamp\", got \"{}\"", other)),
+        }
+    }
+}
+
+/// How much of each body search results return when the request doesn't say
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SearchBodyMode {
+    /// The complete body (default)
+    #[default]
+    Full,
+    /// The body shortened to `PREVIEW_DEFAULT_CHARS` at a word boundary
+    Preview,
+    /// An empty body
+    None,
+}
+
+impl FromStr for SearchBodyMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "full" => Ok(SearchBodyMode::Full),
+            "preview" => Ok(SearchBodyMode::Preview),
+            "none" => Ok(SearchBodyMode::None),
+            other => Err(format!("expected \"full\", \"preview\" or \"none\", got \"{}\"", other)),
+        }
+    }
+}
+
+/// Per-document outcome of a bulk index
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BulkIndexResult {
+    /// Document ID (generated when the input had none)
+    pub id: String,
+
+    /// Outcome: "indexed", "overwritten" when a later document in the request has the same ID,
+    /// or "alias" when another document already has the same content
+    pub status: String,
+
+    /// Existing document with the same content, when `status` is "alias"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub alias_of: Option<String>,
+}
+
+/// Response after a bulk index
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BulkIndexResponse {
+    /// Outcome for each submitted document, in request order
+    pub results: Vec<BulkIndexResult>,
+
+    /// Number of documents indexed
+    pub indexed: usize,
+
+    /// UTF-8 bytes of the titles, bodies and tags of the indexed documents as submitted
+    pub indexed_bytes: u64,
+
+    /// Number of documents skipped in favor of a later one with the same ID
+    pub overwritten: usize,
+
+    /// Commits made before the final one; searches could see the batch partially applied when non-zero
+    pub partial_commits: u32,
+
+    /// Extra attempts made because the index writer was busy
+    pub retries: u32,
+
+    /// Generation token of the commit containing these writes
+    pub generation: u64,
+
+    /// Time taken in milliseconds
+    pub took_ms: u64,
 }
 
 /// Input for deleting a document
@@ -68,8 +277,50 @@ pub struct DeleteDocumentResponse {
 
     /// Message
     pub message: String,
+
+    /// Generation token of the commit containing this delete
+    pub generation: u64,
+}
+
+/// Input for deleting many documents in one request
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BulkDeleteInput {
+    /// Document IDs to delete
+    pub ids: Vec<String>,
+}
+
+/// Per-document outcome of a bulk delete
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BulkDeleteResult {
+    /// Document ID
+    pub id: String,
+
+    /// Outcome: "deleted" or "not_found"
+    pub status: String,
 }
 
+/// Response after a bulk delete
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BulkDeleteResponse {
+    /// Outcome for each distinct requested ID, in request order
+    pub results: Vec<BulkDeleteResult>,
+
+    /// Number of documents deleted
+    pub deleted: usize,
+
+    /// Number of IDs that did not match a document
+    pub not_found: usize,
+
+    /// Time taken in milliseconds
+    pub took_ms: u64,
+}
+
+/// Largest `limit` a single search returns
+pub const MAX_SEARCH_LIMIT: usize = 100;
+
+/// Largest `limit` a single browse returns
+pub const MAX_BROWSE_LIMIT: usize = 1000;
+
 /// Search query input
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SearchQuery {
@@ -87,21 +338,224 @@ pub struct SearchQuery {
     /// Optional filters
     #[serde(default)]
     pub filters: SearchFilters,
+
+    /// IDs of documents to leave out of the results, e.g. ones already on screen
+    #[serde(default)]
+    pub exclude_ids: Vec<String>,
+
+    /// Optional recency decay rate; when set, each hit's score is multiplied by
+    /// `exp(-recency_boost * age_days / 365)` where the age comes from `created_at`
+    #[serde(default)]
+    pub recency_boost: Option<f32>,
+
+    /// Return only `{id, score}` pairs in `ids`, skipping stored-document retrieval
+    #[serde(default)]
+    pub ids_only: bool,
+
+    /// Populate `highlights` with highlighted fragments for every eligible field
+    #[serde(default)]
+    pub highlight: bool,
+
+    /// Make sure the search reflects every commit made before it runs, even one that
+    /// finished while the reader was reloading
+    #[serde(default)]
+    pub consistent: bool,
+
+    /// How result scores are reported
+    #[serde(default)]
+    pub score_mode: ScoreMode,
+
+    /// How the query string is matched against documents
+    #[serde(default)]
+    pub match_mode: MatchMode,
+
+    /// Order of the results; defaults to relevance
+    #[serde(default)]
+    pub sort: SortBy,
+
+    /// Return result bodies (`false` returns them empty); defaults to `SEARCH_BODY_MODE`
+    #[serde(default)]
+    pub include_body: Option<bool>,
+
+    /// Shorten result bodies to at most this many characters at a word boundary
+    #[serde(default)]
+    pub body_max_chars: Option<usize>,
+
+    /// Return how the query string was parsed in `parsed_query`, for debugging
+    #[serde(default)]
+    pub return_parsed_query: bool,
+
+    /// List the searchable fields containing a query term in each result's `matched_fields`
+    #[serde(default)]
+    pub return_matched_fields: bool,
+
+    /// Shape of the results: an array of objects (default) or parallel arrays in `columns`
+    #[serde(default)]
+    pub format: SearchFormat,
+}
+
+impl Default for SearchQuery {
+    fn default() -> Self {
+        SearchQuery {
+            query: String::new(),
+            limit: default_limit(),
+            offset: 0,
+            filters: SearchFilters::default(),
+            exclude_ids: Vec::new(),
+            recency_boost: None,
+            ids_only: false,
+            highlight: false,
+            consistent: false,
+            score_mode: ScoreMode::Raw,
+            match_mode: MatchMode::Standard,
+            sort: SortBy::Relevance,
+            include_body: None,
+            body_max_chars: None,
+            return_parsed_query: false,
+            return_matched_fields: false,
+            format: SearchFormat::Objects,
+        }
+    }
 }
 
 fn default_limit() -> usize {
     10
 }
 
+/// How result scores are reported
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScoreMode {
+    /// Raw relevance score, including boosts and recency decay (default)
+    #[default]
+    Raw,
+    /// Score divided by the best match's score, from 0 to 1
+    Normalized,
+    /// Percentage of all matches scoring at or below the result, from 0 to 100
+    Percentile,
+}
+
+/// How search results are ordered
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SortBy {
+    /// Highest score first (default)
+    #[default]
+    Relevance,
+    /// By the character count of the title or body; needs an index with
+    /// length fields, and every result scores 0
+    ByLength {
+        field: LengthField,
+        #[serde(default)]
+        order: SortOrder,
+    },
+}
+
+/// Field whose length results are sorted by
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LengthField {
+    Title,
+    Body,
+}
+
+/// Direction of a sort
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SortOrder {
+    /// Smallest first (default)
+    #[default]
+    Asc,
+    /// Largest first
+    Desc,
+}
+
+/// How a search's query string is matched
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MatchMode {
+    /// Query syntax over whole words of the title and body (default)
+    #[default]
+    Standard,
+    /// Each word is found anywhere inside the title or body, even within a
+    /// longer word; needs an index with n-grams
+    Substring,
+}
+
+/// Shape of a search's results
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SearchFormat {
+    /// One object per result in `results` (default)
+    #[default]
+    Objects,
+    /// Parallel arrays of IDs, titles, scores and creation timestamps in
+    /// `columns`, so keys aren't repeated for every result
+    Columnar,
+}
+
 /// Search filters
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct SearchFilters {
-    /// Filter by tags (any match)
+    /// Filter by exact tags
     #[serde(default)]
-    pub tags: Vec<String>,
+    pub tags: TagFilter,
 
     /// Filter by source
     pub source: Option<String>,
+
+    /// Only match documents whose ID starts with this prefix (byte-wise)
+    #[serde(default)]
+    pub id_prefix: Option<String>,
+
+    /// Only match documents whose external ID contains these words, in order
+    #[serde(default)]
+    pub external_id: Option<String>,
+
+    /// Only match documents whose whole title is this, ignoring case and surrounding whitespace
+    #[serde(default)]
+    pub title_exact: Option<String>,
+}
+
+/// Exact-tag filter
+///
+/// Accepts either a plain list of tags (any may match) or
+/// `{"values": [...], "all": true}` to require every tag.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(from = "TagFilterInput")]
+pub struct TagFilter {
+    /// Tags to match, compared case-insensitively
+    pub values: Vec<String>,
+
+    /// Require every tag (ALL) instead of at least one (ANY)
+    pub all: bool,
+}
+
+impl TagFilter {
+    /// Whether the filter restricts anything
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum TagFilterInput {
+    Any(Vec<String>),
+    Full {
+        values: Vec<String>,
+        #[serde(default)]
+        all: bool,
+    },
+}
+
+impl From<TagFilterInput> for TagFilter {
+    fn from(input: TagFilterInput) -> Self {
+        match input {
+            TagFilterInput::Any(values) => TagFilter { values, all: false },
+            TagFilterInput::Full { values, all } => TagFilter { values, all },
+        }
+    }
 }
 
 /// Search result
@@ -110,12 +564,19 @@ pub struct SearchResult {
     /// Document ID
     pub id: String,
 
+    /// Human-readable external ID, if the document has one
+    pub external_id: Option<String>,
+
     /// Document title
     pub title: String,
 
-    /// Document body (may be truncated)
+    /// Document body, complete unless shortened or left out (see `body_truncated`)
     pub body: String,
 
+    /// True when `body` is shorter than the stored body or the stored body was capped at index time
+    #[serde(default)]
+    pub body_truncated: bool,
+
     /// Search score
     pub score: f32,
 
@@ -124,22 +585,124 @@ pub struct SearchResult {
 
     /// Snippet/highlight (optional)
     pub snippet: Option<String>,
+
+    /// Field the snippet was taken from
+    pub snippet_field: Option<String>,
+
+    /// Highlighted fragments keyed by field, for fields with a match (requires `highlight`)
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub highlights: HashMap<String, Vec<String>>,
+
+    /// Content checksum (see `compute_checksum` for the normalization)
+    pub checksum: Option<String>,
+
+    /// Searchable fields containing a query term (requires `return_matched_fields`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub matched_fields: Option<Vec<String>>,
+}
+
+/// Document ID and score returned by an `ids_only` search
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScoredId {
+    /// Document ID
+    pub id: String,
+
+    /// Search score
+    pub score: f32,
+}
+
+/// Results of a `columnar` search as parallel arrays
+///
+/// Entry `i` of every array belongs to the same result, in rank order.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SearchColumns {
+    /// Document IDs
+    pub ids: Vec<String>,
+
+    /// Document titles
+    pub titles: Vec<String>,
+
+    /// Search scores
+    pub scores: Vec<f32>,
+
+    /// Creation timestamps
+    pub created_ats: Vec<Option<String>>,
 }
 
 /// Search response
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SearchResponse {
-    /// Search results
+    /// Search results (empty for `ids_only` and `columnar` searches)
     pub results: Vec<SearchResult>,
 
+    /// Matching document IDs, present only for `ids_only` searches
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ids: Option<Vec<ScoredId>>,
+
+    /// Results as parallel arrays, present only for `columnar` searches
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub columns: Option<SearchColumns>,
+
     /// Total number of results found
     pub total: usize,
 
+    /// Hits on the page before `MAX_RESPONSE_BYTES` trimmed it; only used server-side
+    #[serde(skip)]
+    pub matched: usize,
+
     /// Query that was executed
     pub query: String,
 
     /// Time taken in milliseconds
     pub took_ms: u64,
+
+    /// Generation token; the results reflect every write with a generation <= this value
+    pub generation: u64,
+
+    /// True when the `X-Timeout-Ms` budget expired and the results are partial
+    #[serde(default)]
+    pub timed_out: bool,
+
+    /// Limit the search actually used
+    pub limit: usize,
+
+    /// True when the requested limit exceeded the server cap and was clamped to `limit`
+    #[serde(default)]
+    pub capped: bool,
+
+    /// True when results were left out to keep the response within `MAX_RESPONSE_BYTES`
+    #[serde(default)]
+    pub truncated_by_size: bool,
+
+    /// Time spent in each search phase, present only when requested with `X-Timing: detailed`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timings: Option<SearchTimings>,
+
+    /// Parsed form of the query string, present only when requested with `return_parsed_query`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parsed_query: Option<String>,
+}
+
+/// Wall time of each phase of a search, in fractional milliseconds
+///
+/// Phases that did not run are 0. The phases add up to slightly less than
+/// `took_ms`, which also covers opening the index.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct SearchTimings {
+    /// Reloading the index reader to see the latest commits
+    pub reload_ms: f64,
+
+    /// Parsing the query and applying filters
+    pub parse_ms: f64,
+
+    /// Collecting the top hits, plus the second pass of `percentile` scoring
+    pub search_ms: f64,
+
+    /// Reading stored documents, or IDs for `ids_only`
+    pub fetch_ms: f64,
+
+    /// Building snippets and highlights
+    pub highlight_ms: f64,
 }
 
 /// Health check response
@@ -175,18 +738,31 @@ pub struct DocumentDetail {
     /// Document ID
     pub id: String,
 
+    /// Human-readable external ID, if the document has one
+    pub external_id: Option<String>,
+
     /// Document title
     pub title: String,
 
-    /// Complete document body
+    /// Complete document body, or its start when the index caps stored bodies
     pub body: String,
 
+    /// True when `body` is only the start of a body capped at index time
+    #[serde(default)]
+    pub body_truncated: bool,
+
     /// Creation timestamp
     pub created_at: Option<String>,
 
+    /// Client-supplied timestamp, recorded when the server stamped `created_at`
+    pub client_created_at: Option<String>,
+
     /// Tags
     #[serde(default)]
     pub tags: Vec<String>,
+
+    /// Content checksum (see `compute_checksum` for the normalization)
+    pub checksum: Option<String>,
 }
 
 /// Browse response
@@ -195,9 +771,473 @@ pub struct BrowseDocumentsResponse {
     /// Documents
     pub documents: Vec<DocumentDetail>,
 
-    /// Total number of documents returned
+    /// Total number of documents in the index
+    pub total: usize,
+
+    /// Offset of the first returned document
+    pub offset: usize,
+
+    /// Page size applied (the requested limit, capped at 1000)
+    pub limit: usize,
+
+    /// True when more documents follow this page
+    pub has_more: bool,
+
+    /// True when documents were left out to keep the response within `MAX_RESPONSE_BYTES`
+    #[serde(default)]
+    pub truncated_by_size: bool,
+
+    /// Time taken in milliseconds
+    pub took_ms: u64,
+}
+
+/// Outcome of an index repair attempt
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RepairReport {
+    /// User whose index was checked
+    pub user_id: String,
+
+    /// Result: "healthy", "repaired", "missing" or "unrecoverable"
+    pub status: String,
+
+    /// Human-readable description of what was done
+    pub action: String,
+
+    /// Segments removed from the index metadata because they failed to open
+    #[serde(default)]
+    pub dropped_segments: Vec<String>,
+
+    /// Number of documents contained in the dropped segments
+    pub documents_lost: u32,
+
+    /// Error encountered while diagnosing or repairing, if any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub diagnostic: Option<String>,
+}
+
+/// Outcome of validating a query without executing it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueryValidationResponse {
+    /// Whether the query and its filters would be accepted by `/v1/search`
+    pub valid: bool,
+
+    /// Why the query was rejected
+    pub error: Option<String>,
+
+    /// Fields the parsed query searches, sorted
+    pub interpreted_fields: Vec<String>,
+}
+
+/// Text to run through an analyzer
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AnalyzeRequest {
+    /// Text to tokenize
+    pub text: String,
+
+    /// Use the analyzer of this schema field
+    #[serde(default)]
+    pub field: Option<String>,
+
+    /// Use this registered analyzer by name
+    #[serde(default)]
+    pub analyzer: Option<String>,
+}
+
+/// One token produced by an analyzer
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AnalyzedToken {
+    /// Token text as indexed
+    pub text: String,
+
+    /// Byte offset where the token starts in the input
+    pub offset_from: usize,
+
+    /// Byte offset where the token ends in the input
+    pub offset_to: usize,
+
+    /// Token position
+    pub position: usize,
+}
+
+/// Token stream produced by an analyzer
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AnalyzeResponse {
+    /// Analyzer that produced the tokens
+    pub analyzer: String,
+
+    /// Tokens in stream order
+    pub tokens: Vec<AnalyzedToken>,
+}
+
+/// Outcome of rebuilding a user's index
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReindexResponse {
+    /// User whose index was rebuilt
+    pub user_id: String,
+
+    /// Documents copied into the new index
+    pub documents: u64,
+
+    /// Generation token of the rebuilt index; always greater than before the rebuild
+    pub generation: u64,
+
+    /// Time taken in milliseconds
+    pub took_ms: u64,
+}
+
+/// Outcome of warming a user's index
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WarmIndexResponse {
+    /// User whose index was warmed
+    pub user_id: String,
+
+    /// Whether the index was already open before the request
+    pub already_open: bool,
+
+    /// Number of segments whose structures were loaded
+    pub segments: usize,
+
+    /// Time taken to open and warm the index in milliseconds
+    pub took_ms: u64,
+}
+
+/// Outcome of flushing a user's pending writes
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FlushResponse {
+    /// Generation token covering every write queued before the flush
+    pub generation: u64,
+
+    /// Time taken, mostly waiting for queued writes, in milliseconds
+    pub took_ms: u64,
+}
+
+/// One commit of a user's index
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CommitRecord {
+    /// Generation token the commit produced, as returned by the write
+    pub generation: u64,
+
+    /// When the commit finished (RFC 3339); its writes are searchable from then on
+    pub committed_at: String,
+
+    /// Documents indexed, refreshed or deleted by the commit
+    pub operations: u64,
+}
+
+/// What happened to a document in a change event
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeKind {
+    /// The document was indexed or replaced
+    Indexed,
+    /// The document was deleted
+    Deleted,
+}
+
+impl ChangeKind {
+    /// Name used as the event type of `/v1/changes`
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ChangeKind::Indexed => "indexed",
+            ChangeKind::Deleted => "deleted",
+        }
+    }
+}
+
+/// One document change, as streamed by `/v1/changes`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChangeEvent {
+    /// Whether the document was indexed or deleted
+    pub kind: ChangeKind,
+
+    /// Document ID
+    pub id: String,
+
+    /// Generation token of the commit that made the change searchable
+    pub generation: u64,
+}
+
+/// Recent commits of a user's index
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IndexHistoryResponse {
+    /// Current generation token of the index
+    pub generation: u64,
+
+    /// Commits since the service started, newest first
+    pub commits: Vec<CommitRecord>,
+}
+
+/// Query parameters for a user's commit history
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct IndexHistoryQuery {
+    /// Number of commits to return (defaults to all that are kept)
+    pub limit: Option<usize>,
+}
+
+/// Fragmentation of a user's index and whether it is worth optimizing
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IndexHealthResponse {
+    /// Number of searchable segments
+    pub num_segments: usize,
+
+    /// Deleted or replaced documents still held by segments
+    pub num_deleted_docs: u64,
+
+    /// Share of documents in segments that are deleted, from 0 to 1
+    pub fragmentation_ratio: f64,
+
+    /// Whether the segment count or fragmentation ratio exceeds its threshold
+    pub needs_optimize: bool,
+}
+
+/// Users whose indexes are pinned in the cache
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PinnedIndexesResponse {
+    /// Pinned user IDs, sorted
+    pub pinned: Vec<String>,
+}
+
+/// Users whose index statistics an operator requests at once
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AdminStatsRequest {
+    /// Users to report on, at most `ADMIN_STATS_MAX_USERS`
+    pub user_ids: Vec<Uuid>,
+}
+
+/// Index statistics of one user, or why they couldn't be read
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AdminUserStats {
+    pub user_id: String,
+
+    /// Live documents; 0 for users without an index
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub num_documents: Option<usize>,
+
+    /// Size of the index files on disk in bytes
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub disk_bytes: Option<u64>,
+
+    /// Segments searched, before pending merges
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub num_segments: Option<usize>,
+
+    /// Why the index couldn't be read; the other fields are then absent
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Index statistics of several users, in request order
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AdminStatsResponse {
+    pub users: Vec<AdminUserStats>,
+}
+
+/// Outcome of pinning or unpinning a user's index
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PinIndexResponse {
+    /// User whose index was pinned or unpinned
+    pub user_id: String,
+
+    /// Whether the index is pinned after the request
+    pub pinned: bool,
+
+    /// Whether the request changed the pin state
+    pub changed: bool,
+}
+
+/// Query parameters for a document preview
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DocumentPreviewQuery {
+    /// Maximum number of body characters (defaults to `PREVIEW_DEFAULT_CHARS`)
+    pub chars: Option<usize>,
+}
+
+/// Query parameters for the most frequent terms of a field
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TopTermsQuery {
+    /// Field to read (defaults to `body`)
+    pub field: Option<String>,
+
+    /// Number of terms to return (defaults to 20)
+    pub limit: Option<usize>,
+
+    /// Language code whose stop words are skipped, or `none` (defaults to `en`)
+    pub stopwords: Option<String>,
+}
+
+/// A term and the number of documents containing it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TermFrequency {
+    /// Term as indexed (lowercased by the field's analyzer)
+    pub term: String,
+
+    /// Documents containing the term
+    pub doc_freq: u64,
+}
+
+/// Most frequent terms of a field
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TopTermsResponse {
+    /// Field the terms come from
+    pub field: String,
+
+    /// Terms by descending document frequency, ties in term order
+    pub terms: Vec<TermFrequency>,
+
+    /// Time taken in milliseconds
+    pub took_ms: u64,
+}
+
+/// Query parameters of `GET /v1/tags/facets`
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct TagFacetsQuery {
+    /// Tag path to count below, levels separated by `/` (defaults to the top level)
+    pub path: Option<String>,
+
+    /// Number of levels below `path` to count (defaults to 1)
+    pub depth: Option<usize>,
+}
+
+/// Number of documents with a tag at or below a path
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TagFacetCount {
+    /// Tag path, levels separated by `/` and lowercased
+    pub path: String,
+
+    /// Number of levels in `path`
+    pub level: usize,
+
+    /// Documents with at least one tag at or below `path`
+    pub count: u64,
+}
+
+/// Hierarchical tag counts below a path
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TagFacetsResponse {
+    /// Path the counts are below (empty for the top level)
+    pub path: String,
+
+    /// Counts at each level, depth-first with siblings in path order
+    pub facets: Vec<TagFacetCount>,
+
+    /// Time taken in milliseconds
+    pub took_ms: u64,
+}
+
+/// One side of a similarity comparison: a stored document or inline text
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SimilarityTarget {
+    /// ID of a stored document, whose title and body are compared
+    pub id: Option<String>,
+
+    /// Text to compare directly
+    pub text: Option<String>,
+}
+
+/// Request body of `POST /v1/similarity`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SimilarityRequest {
+    pub left: SimilarityTarget,
+    pub right: SimilarityTarget,
+}
+
+/// Similarity scores of two texts
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SimilarityResponse {
+    /// Cosine similarity of the term count vectors, 0 to 1
+    pub cosine: f64,
+
+    /// Shared distinct terms over all distinct terms, 0 to 1
+    pub jaccard: f64,
+
+    /// Distinct terms on the left side
+    pub left_terms: usize,
+
+    /// Distinct terms on the right side
+    pub right_terms: usize,
+
+    /// Time taken in milliseconds
+    pub took_ms: u64,
+}
+
+/// Request body of `POST /v1/documents/:id/similar`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SimilarDocumentsRequest {
+    /// Maximum number of similar documents to return (defaults to 10)
+    pub limit: Option<usize>,
+
+    /// Number of the source body's most significant terms to search for (defaults to 25)
+    pub max_query_terms: Option<usize>,
+
+    /// Times a term must occur in the source body to be considered (defaults to 1)
+    pub min_term_freq: Option<u32>,
+}
+
+/// Documents sharing the most significant terms of a source document
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SimilarDocumentsResponse {
+    /// ID of the source document
+    pub id: String,
+
+    /// Seed terms searched for, most significant first
+    pub terms: Vec<String>,
+
+    /// Similar documents, best match first; never includes the source
+    pub results: Vec<SearchResult>,
+
+    /// Number of results returned
     pub total: usize,
 
     /// Time taken in milliseconds
     pub took_ms: u64,
 }
+
+/// Title and shortened body of a document for list views
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DocumentPreview {
+    /// Document ID
+    pub id: String,
+
+    /// Document title
+    pub title: String,
+
+    /// Body trimmed to the requested length at a word boundary
+    pub preview: String,
+
+    /// True when the body was longer than the preview
+    pub truncated: bool,
+}
+
+/// Summary analytics over a user's documents
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IndexAnalytics {
+    /// Number of live documents
+    pub num_documents: u64,
+
+    /// Sum of body sizes in UTF-8 bytes
+    pub total_body_bytes: u64,
+
+    /// Mean body size in UTF-8 bytes (0 for an empty index)
+    pub avg_body_bytes: f64,
+
+    /// Number of distinct tags across all documents
+    pub distinct_tags: usize,
+
+    /// Earliest `created_at` (RFC 3339)
+    pub oldest_created_at: Option<String>,
+
+    /// Latest `created_at` (RFC 3339)
+    pub newest_created_at: Option<String>,
+
+    /// Generation the analytics were computed at
+    pub generation: u64,
+
+    /// True when served from the cache instead of recomputed
+    pub cached: bool,
+
+    /// Computation or cache lookup time in milliseconds
+    pub took_ms: u64,
+}
@@ -0,0 +1,104 @@
+//! N-gram indexing for substring matching
+//!
+//! Indexes opted in through their user config get `title_ngram` and
+//! `body_ngram` fields holding every lowercased run of `min_gram` to
+//! `max_gram` characters of the title and body, so "voice" can match
+//! "invoice". The gram sizes are recorded in the fields' analyzer name
+//! (`ngram_3_5`), which keeps them with the index even if the config changes.
+
+use serde::{Deserialize, Serialize};
+use tantivy::schema::{FieldType, Schema};
+use tantivy::tokenizer::{LowerCaser, NgramTokenizer, TextAnalyzer, TokenizerManager};
+
+/// Smallest accepted `min_gram`; single characters would match nearly every document
+pub const MIN_GRAM: usize = 2;
+
+/// Largest accepted `max_gram`
+pub const MAX_GRAM: usize = 10;
+
+/// Prefix of the analyzer names of n-gram fields
+const ANALYZER_PREFIX: &str = "ngram_";
+
+/// Gram sizes of an index's n-gram fields, in characters
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct NgramSize {
+    pub min_gram: usize,
+    pub max_gram: usize,
+}
+
+impl NgramSize {
+    /// Check the sizes are within `MIN_GRAM..=MAX_GRAM` and in order
+    pub fn validate(&self) -> Result<(), String> {
+        if self.min_gram < MIN_GRAM || self.max_gram > MAX_GRAM || self.min_gram > self.max_gram {
+            return Err(format!(
+                "ngram sizes must satisfy {} <= min_gram <= max_gram <= {}, got {} and {}",
+                MIN_GRAM, MAX_GRAM, self.min_gram, self.max_gram
+            ));
+        }
+        Ok(())
+    }
+
+    /// Name of the analyzer producing grams of these sizes
+    pub fn analyzer_name(&self) -> String {
+        format!("{}{}_{}", ANALYZER_PREFIX, self.min_gram, self.max_gram)
+    }
+
+    /// Sizes encoded in an analyzer name, if it is an n-gram analyzer's
+    fn from_analyzer_name(name: &str) -> Option<NgramSize> {
+        let (min_gram, max_gram) = name.strip_prefix(ANALYZER_PREFIX)?.split_once('_')?;
+        Some(NgramSize {
+            min_gram: min_gram.parse().ok()?,
+            max_gram: max_gram.parse().ok()?,
+        })
+    }
+
+    /// Gram sizes of the named field, or `None` when it is missing or not n-gram indexed
+    pub fn of_field(schema: &Schema, name: &str) -> Option<NgramSize> {
+        let field = schema.get_field(name).ok()?;
+        match schema.get_field_entry(field).field_type() {
+            FieldType::Str(options) => {
+                NgramSize::from_analyzer_name(options.get_indexing_options()?.tokenizer())
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Register the analyzer of every n-gram field in the schema
+///
+/// Like the stemmers, these must be registered before a writer or query is
+/// built for the index.
+pub fn register_analyzers(schema: &Schema, tokenizers: &TokenizerManager) {
+    for (_, entry) in schema.fields() {
+        let FieldType::Str(options) = entry.field_type() else {
+            continue;
+        };
+        let Some(name) = options.get_indexing_options().map(|indexing| indexing.tokenizer()) else {
+            continue;
+        };
+        let Some(size) = NgramSize::from_analyzer_name(name) else {
+            continue;
+        };
+        if let Ok(tokenizer) = NgramTokenizer::new(size.min_gram, size.max_gram, false) {
+            tokenizers.register(name, TextAnalyzer::builder(tokenizer).filter(LowerCaser).build());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ngram_size() {
+        let size = NgramSize { min_gram: 3, max_gram: 5 };
+        assert!(size.validate().is_ok());
+        assert_eq!(NgramSize::from_analyzer_name(&size.analyzer_name()), Some(size));
+        assert_eq!(NgramSize::from_analyzer_name("en_stem"), None);
+
+        assert!(NgramSize { min_gram: 1, max_gram: 3 }.validate().is_err());
+        assert!(NgramSize { min_gram: 4, max_gram: 3 }.validate().is_err());
+        assert!(NgramSize { min_gram: 3, max_gram: 11 }.validate().is_err());
+    }
+}
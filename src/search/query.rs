@@ -0,0 +1,585 @@
+use std::collections::BTreeMap;
+use std::ops::Bound;
+use tantivy::query::{
+    BooleanQuery, ConstScoreQuery, Occur, PhraseQuery, Query, QueryParser, QueryParserError, RangeQuery, TermQuery,
+    TermSetQuery,
+};
+use tantivy::schema::{Field, IndexRecordOption, Schema, Type};
+use tantivy::query_grammar::{self, UserInputAst, UserInputBound, UserInputLeaf};
+use tantivy::tokenizer::{TokenStream, TokenizerManager};
+use tantivy::{Index, Term};
+
+use super::error::IndexError;
+use super::language::LANGUAGES;
+use super::models::{MatchMode, SearchFilters, TagFilter};
+use super::ngram::NgramSize;
+use super::schema::{tag_key, title_key, FieldNames};
+
+/// Fields searched when a query term has no `field:` prefix
+pub const DEFAULT_SEARCH_FIELDS: &[&str] = &[FieldNames::TITLE, FieldNames::BODY];
+
+/// Fields a query may reference explicitly with `field:value` syntax
+pub const QUERYABLE_FIELDS: &[&str] = &[
+    FieldNames::TITLE,
+    FieldNames::BODY,
+    FieldNames::TAGS,
+    FieldNames::SOURCE,
+    FieldNames::ID,
+    FieldNames::LANGUAGE,
+    FieldNames::EXTERNAL_ID,
+];
+
+/// Parse a search's query string according to its match mode
+///
+/// `field_aliases` maps alternative field names to queryable fields; they
+/// only apply to the `field:value` syntax of standard queries. Unless
+/// `allow_expensive` is set, standard queries that could scan the whole index
+/// or term dictionary are rejected (see `reject_expensive_query`).
+pub fn parse_search_query(
+    index: &Index,
+    schema: &Schema,
+    query: &str,
+    match_mode: MatchMode,
+    field_aliases: &BTreeMap<String, String>,
+    allow_expensive: bool,
+) -> anyhow::Result<Box<dyn Query>> {
+    match match_mode {
+        MatchMode::Standard => {
+            let query = resolve_field_aliases(query, field_aliases);
+            if !allow_expensive {
+                reject_expensive_query(&query)?;
+            }
+            parse_user_query(index, schema, &query)
+        }
+        MatchMode::Substring => Ok(parse_substring_query(schema, query)?),
+    }
+}
+
+/// Shortest prefix, in characters, a `*` prefix query may expand without `ALLOW_EXPENSIVE_QUERIES`
+pub const MIN_PREFIX_CHARS: usize = 3;
+
+/// Reject query syntax whose cost grows with the index rather than the query
+///
+/// Inspects the query's syntax tree for a bare `*` wildcard, which matches
+/// every document; a `field:*` existence check, which visits every document
+/// with a value in the field; a range with an open end, which walks the term dictionary
+/// from one bound to its end; and a prefix shorter than `MIN_PREFIX_CHARS`,
+/// which expands to a large share of the dictionary. Strings that don't parse
+/// are left for the query parser to report.
+fn reject_expensive_query(query: &str) -> Result<(), IndexError> {
+    match query_grammar::parse_query(query) {
+        Ok(ast) => check_expensive_ast(&ast),
+        Err(_) => Ok(()),
+    }
+}
+
+fn check_expensive_ast(ast: &UserInputAst) -> Result<(), IndexError> {
+    let restricted = |message: String| {
+        Err(IndexError::InvalidQuery(format!(
+            "{}; expensive queries are disabled on this server",
+            message
+        )))
+    };
+    match ast {
+        UserInputAst::Clause(clauses) => clauses.iter().try_for_each(|(_, clause)| check_expensive_ast(clause)),
+        UserInputAst::Boost(inner, _) => check_expensive_ast(inner),
+        UserInputAst::Leaf(leaf) => match leaf.as_ref() {
+            UserInputLeaf::All => restricted("The wildcard '*' matches every document".to_string()),
+            UserInputLeaf::Exists { field } => restricted(format!(
+                "'{}:*' visits every document with a value in the field",
+                field
+            )),
+            UserInputLeaf::Range { lower, upper, .. }
+                if matches!(lower, UserInputBound::Unbounded) || matches!(upper, UserInputBound::Unbounded) =>
+            {
+                restricted("Ranges need both a lower and an upper bound".to_string())
+            }
+            UserInputLeaf::Literal(literal) if literal.prefix => {
+                let prefix = literal.phrase.split_whitespace().last().unwrap_or("");
+                if prefix.chars().count() < MIN_PREFIX_CHARS {
+                    restricted(format!(
+                        "Prefix '{}*' is too short; prefixes need at least {} characters",
+                        prefix, MIN_PREFIX_CHARS
+                    ))
+                } else {
+                    Ok(())
+                }
+            }
+            _ => Ok(()),
+        },
+    }
+}
+
+/// Rewrite aliased `field:` prefixes of a query string to the fields they stand for
+///
+/// Only a name starting a clause (after whitespace, `(`, `+` or `-`) and
+/// followed by `:` is a field prefix, so `content:` inside a quoted phrase or
+/// a value like `title:content:draft` is left alone.
+pub fn resolve_field_aliases(query: &str, aliases: &BTreeMap<String, String>) -> String {
+    if aliases.is_empty() {
+        return query.to_string();
+    }
+
+    let mut resolved = String::with_capacity(query.len());
+    let mut in_phrase = false;
+    let mut clause_start = true;
+    let mut rest = query;
+    while let Some(c) = rest.chars().next() {
+        if clause_start && !in_phrase {
+            let name_len = rest
+                .find(|c: char| !is_field_name_char(c))
+                .unwrap_or(rest.len());
+            if name_len > 0 && rest[name_len..].starts_with(':') {
+                let name = &rest[..name_len];
+                resolved.push_str(aliases.get(name).map_or(name, String::as_str));
+                rest = &rest[name_len..];
+                clause_start = false;
+                continue;
+            }
+        }
+
+        let mut len = c.len_utf8();
+        if c == '\\' {
+            // An escaped character is never a quote or separator
+            len += rest[len..].chars().next().map_or(0, char::len_utf8);
+        } else if c == '"' {
+            in_phrase = !in_phrase;
+        }
+        clause_start = c.is_whitespace() || c == '(' || (clause_start && matches!(c, '+' | '-'));
+        resolved.push_str(&rest[..len]);
+        rest = &rest[len..];
+    }
+    resolved
+}
+
+/// Whether a character may appear in a field alias
+pub fn is_field_name_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+/// Build a query matching every whitespace-separated word inside the title or body
+///
+/// Matching is case-insensitive and ignores query syntax. A word of at most
+/// `max_gram` characters is a single gram, so it matches exactly where it
+/// occurs. A longer word needs all of its `max_gram`-character grams, which
+/// can also match a document where they occur apart. Words shorter than
+/// `min_gram` fail with `IndexError::InvalidQuery`, and indexes without
+/// n-gram fields with `IndexError::Unsupported`.
+pub fn parse_substring_query(schema: &Schema, query: &str) -> Result<Box<dyn Query>, IndexError> {
+    let fields: Vec<(Field, NgramSize)> = [FieldNames::TITLE_NGRAM, FieldNames::BODY_NGRAM]
+        .iter()
+        .filter_map(|name| Some((schema.get_field(name).ok()?, NgramSize::of_field(schema, name)?)))
+        .collect();
+    if fields.is_empty() {
+        return Err(IndexError::Unsupported(
+            "Substring search is not enabled for this index; set `ngram` in its config.json and rebuild it with POST /v1/reindex"
+                .to_string(),
+        ));
+    }
+
+    let words: Vec<String> = query.split_whitespace().map(str::to_lowercase).collect();
+    if words.is_empty() {
+        return Err(IndexError::InvalidQuery("Substring search needs at least one word".to_string()));
+    }
+
+    let mut clauses: Vec<(Occur, Box<dyn Query>)> = Vec::with_capacity(words.len());
+    for word in &words {
+        let chars: Vec<char> = word.chars().collect();
+        let mut alternatives: Vec<(Occur, Box<dyn Query>)> = Vec::with_capacity(fields.len());
+        for (field, size) in &fields {
+            if chars.len() < size.min_gram {
+                return Err(IndexError::InvalidQuery(format!(
+                    "Substring search words need at least {} characters, got \"{}\"",
+                    size.min_gram, word
+                )));
+            }
+            let grams: Vec<String> = if chars.len() <= size.max_gram {
+                vec![word.clone()]
+            } else {
+                chars.windows(size.max_gram).map(|gram| gram.iter().collect()).collect()
+            };
+            let gram_clauses = grams
+                .iter()
+                .map(|gram| {
+                    let term = Term::from_field_text(*field, gram);
+                    let query: Box<dyn Query> = Box::new(TermQuery::new(term, IndexRecordOption::WithFreqs));
+                    (Occur::Must, query)
+                })
+                .collect();
+            alternatives.push((Occur::Should, Box::new(BooleanQuery::new(gram_clauses))));
+        }
+        clauses.push((Occur::Must, Box::new(BooleanQuery::new(alternatives))));
+    }
+    Ok(Box::new(BooleanQuery::new(clauses)))
+}
+
+/// Parse a user-supplied query string against a user's index
+///
+/// Unknown or non-searchable `field:` references fail with
+/// `IndexError::InvalidQuery` naming the field and listing the valid ones;
+/// other syntax errors are returned as-is. Unprefixed terms also search the
+/// per-language stemmed body fields of indexes with language detection.
+pub fn parse_user_query(index: &Index, schema: &Schema, query: &str) -> anyhow::Result<Box<dyn Query>> {
+    let default_fields = DEFAULT_SEARCH_FIELDS
+        .iter()
+        .copied()
+        .chain(LANGUAGES.iter().map(|language| language.body_field()))
+        .filter_map(|name| schema.get_field(name).ok())
+        .collect();
+    let query_parser = QueryParser::for_index(index, default_fields);
+
+    let parsed = query_parser.parse_query(query).map_err(|e| match e {
+        QueryParserError::FieldDoesNotExist(field) | QueryParserError::FieldNotIndexed(field) => {
+            anyhow::Error::from(unknown_field_error(&field))
+        }
+        other => anyhow::Error::from(other).context("Failed to parse search query"),
+    })?;
+
+    // The field exists in the schema but is internal (e.g. checksum, created_at_ts)
+    let mut non_queryable = None;
+    parsed.query_terms(&mut |term, _| {
+        let name = schema.get_field_name(term.field());
+        let stemmed_body = LANGUAGES.iter().any(|language| language.body_field() == name);
+        if non_queryable.is_none() && !QUERYABLE_FIELDS.contains(&name) && !stemmed_body {
+            non_queryable = Some(name.to_string());
+        }
+    });
+    if let Some(field) = non_queryable {
+        return Err(unknown_field_error(&field).into());
+    }
+
+    Ok(parsed)
+}
+
+/// Render a parsed query for debugging, with field numbers replaced by names
+///
+/// This is the query's `Debug` output, e.g. `BooleanQuery { subqueries: [(Should,
+/// TermQuery(Term(field=title, type=Str, "refund"))), ...] }`, in which both
+/// `field=2` and `Field(2)` name the field. The format is Tantivy's and may
+/// change between versions.
+pub fn describe_query(schema: &Schema, query: &dyn Query) -> String {
+    const FIELD_MARKERS: [&str; 2] = ["field=", "Field("];
+
+    let names: Vec<&str> = schema.fields().map(|(_, entry)| entry.name()).collect();
+    let debug = format!("{:?}", query);
+
+    let mut described = String::with_capacity(debug.len());
+    let mut rest = debug.as_str();
+    while let Some((start, marker)) = FIELD_MARKERS
+        .iter()
+        .filter_map(|marker| rest.find(marker).map(|start| (start, marker)))
+        .min()
+    {
+        let (before, after) = rest.split_at(start + marker.len());
+        described.push_str(before);
+        let digits = after.len() - after.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+        let name = after[..digits].parse::<usize>().ok().and_then(|id| names.get(id));
+        described.push_str(name.copied().unwrap_or(&after[..digits]));
+        rest = &after[digits..];
+    }
+    described.push_str(rest);
+    described
+}
+
+/// Leave the documents with the given IDs out of a query's matches
+///
+/// The IDs are one term-set lookup in a MUST_NOT clause, so excluded
+/// documents never reach the collector and don't take up result slots.
+pub fn exclude_ids(schema: &Schema, query: Box<dyn Query>, ids: &[String]) -> anyhow::Result<Box<dyn Query>> {
+    if ids.is_empty() {
+        return Ok(query);
+    }
+    let id_field = schema.get_field(FieldNames::ID)?;
+    let terms = ids.iter().map(|id| Term::from_field_text(id_field, id));
+    Ok(Box::new(BooleanQuery::new(vec![
+        (Occur::Must, query),
+        (Occur::MustNot, Box::new(TermSetQuery::new(terms))),
+    ])))
+}
+
+/// Restrict a parsed user query with the request's filters
+///
+/// Filters are ANDed with the query and contribute nothing to the score.
+pub fn apply_filters(schema: &Schema, query: Box<dyn Query>, filters: &SearchFilters) -> anyhow::Result<Box<dyn Query>> {
+    let mut clauses: Vec<(Occur, Box<dyn Query>)> = vec![(Occur::Must, query)];
+
+    if let Some(prefix) = &filters.id_prefix {
+        let id_field = schema.get_field(FieldNames::ID)?;
+        clauses.push((Occur::Must, Box::new(ConstScoreQuery::new(prefix_query(id_field, FieldNames::ID, prefix), 0.0))));
+    }
+
+    if let Some(pattern) = &filters.source {
+        let source_field = schema.get_field(FieldNames::SOURCE)?;
+        let source_query: Box<dyn Query> = match parse_source_pattern(pattern)? {
+            SourcePattern::Exact(source) => Box::new(TermQuery::new(
+                Term::from_field_text(source_field, source),
+                IndexRecordOption::Basic,
+            )),
+            SourcePattern::Prefix(prefix) => prefix_query(source_field, FieldNames::SOURCE, prefix),
+        };
+        clauses.push((Occur::Must, Box::new(ConstScoreQuery::new(source_query, 0.0))));
+    }
+
+    if let Some(external_id) = &filters.external_id {
+        let external_id_field = schema.get_field(FieldNames::EXTERNAL_ID).map_err(|_| {
+            IndexError::Unsupported(
+                "external_id filters are not supported by this index; rebuild it with POST /v1/reindex".to_string(),
+            )
+        })?;
+        clauses.push((Occur::Must, Box::new(ConstScoreQuery::new(external_id_query(external_id_field, external_id)?, 0.0))));
+    }
+
+    if let Some(title) = &filters.title_exact {
+        let title_key_field = schema.get_field(FieldNames::TITLE_KEY).map_err(|_| {
+            IndexError::Unsupported(
+                "title_exact filters are not supported by this index; rebuild it with POST /v1/reindex".to_string(),
+            )
+        })?;
+        let title_query = TermQuery::new(
+            Term::from_field_text(title_key_field, &title_key(title)),
+            IndexRecordOption::Basic,
+        );
+        clauses.push((Occur::Must, Box::new(ConstScoreQuery::new(Box::new(title_query), 0.0))));
+    }
+
+    if !filters.tags.is_empty() {
+        // Indexes created before tag keys existed cannot match tags exactly
+        let tag_keys_field = schema.get_field(FieldNames::TAG_KEYS).map_err(|_| {
+            IndexError::Unsupported(
+                "Tag filters are not supported by this index; reindex the documents to enable them".to_string(),
+            )
+        })?;
+        clauses.push((Occur::Must, Box::new(ConstScoreQuery::new(tag_filter_query(tag_keys_field, &filters.tags), 0.0))));
+    }
+
+    if clauses.len() == 1 {
+        return Ok(clauses.remove(0).1);
+    }
+    Ok(Box::new(BooleanQuery::new(clauses)))
+}
+
+/// Shortest literal prefix accepted before a trailing `*` in a source filter
+pub const MIN_SOURCE_PREFIX_CHARS: usize = 3;
+
+/// A parsed `filters.source` value
+#[derive(Debug, PartialEq)]
+enum SourcePattern<'a> {
+    Exact(&'a str),
+    Prefix(&'a str),
+}
+
+/// Parse a source filter: an exact source, or a prefix followed by a single trailing `*`
+///
+/// Wildcards anywhere else can't use the term dictionary's ordering and would
+/// scan every distinct source, so they are rejected along with prefixes too
+/// short to narrow the range meaningfully.
+fn parse_source_pattern(pattern: &str) -> Result<SourcePattern<'_>, IndexError> {
+    let (literal, is_prefix) = match pattern.strip_suffix('*') {
+        Some(prefix) => (prefix, true),
+        None => (pattern, false),
+    };
+
+    if literal.contains('*') {
+        return Err(IndexError::InvalidQuery(
+            "Source filter wildcards are only supported as a single trailing '*', e.g. 'erp.invoices.*'".to_string(),
+        ));
+    }
+    if !is_prefix {
+        return Ok(SourcePattern::Exact(literal));
+    }
+    if literal.chars().count() < MIN_SOURCE_PREFIX_CHARS {
+        return Err(IndexError::InvalidQuery(format!(
+            "Source prefix must have at least {} characters before '*'",
+            MIN_SOURCE_PREFIX_CHARS
+        )));
+    }
+    Ok(SourcePattern::Prefix(literal))
+}
+
+/// Match values of an exact-term field starting with `prefix`, compared byte-wise
+///
+/// Such fields hold single exact terms, so a prefix is the term range
+/// `[prefix, successor(prefix))` in the term dictionary.
+fn prefix_query(field: Field, field_name: &str, prefix: &str) -> Box<dyn Query> {
+    let lower = Bound::Included(Term::from_field_text(field, prefix));
+    let upper = match prefix_successor(prefix.as_bytes()) {
+        Some(bytes) => Bound::Excluded(Term::from_field_bytes(field, &bytes)),
+        None => Bound::Unbounded,
+    };
+    Box::new(RangeQuery::new_term_bounds(
+        field_name.to_string(),
+        Type::Str,
+        &lower,
+        &upper,
+    ))
+}
+
+/// Match documents carrying any (or, with `all`, every) of the filter's tags
+///
+/// Any-of uses a single `TermSetQuery` so many selected tags cost one
+/// term-dictionary pass instead of one query per tag.
+fn tag_filter_query(tag_keys_field: Field, filter: &TagFilter) -> Box<dyn Query> {
+    let terms = filter
+        .values
+        .iter()
+        .map(|tag| Term::from_field_text(tag_keys_field, &tag_key(tag)));
+
+    if filter.all {
+        Box::new(BooleanQuery::new(
+            terms
+                .map(|term| -> (Occur, Box<dyn Query>) {
+                    (Occur::Must, Box::new(TermQuery::new(term, IndexRecordOption::Basic)))
+                })
+                .collect(),
+        ))
+    } else {
+        Box::new(TermSetQuery::new(terms))
+    }
+}
+
+/// Smallest byte string greater than every string starting with `prefix`
+fn prefix_successor(prefix: &[u8]) -> Option<Vec<u8>> {
+    let mut bytes = prefix.to_vec();
+    while let Some(last) = bytes.pop() {
+        if last < u8::MAX {
+            bytes.push(last + 1);
+            return Some(bytes);
+        }
+    }
+    None
+}
+
+/// Match the words of `value` as a phrase in the external ID field
+///
+/// The value is split and lowercased like the field itself, so `2024-0042`
+/// matches the external ID `INV-2024-0042` but not `INV-0042-2024`.
+fn external_id_query(field: Field, value: &str) -> Result<Box<dyn Query>, IndexError> {
+    let mut analyzer = TokenizerManager::default()
+        .get("default")
+        .expect("tantivy registers the default tokenizer");
+    let mut terms = Vec::new();
+    analyzer
+        .token_stream(value)
+        .process(&mut |token| terms.push(Term::from_field_text(field, &token.text)));
+
+    match terms.len() {
+        0 => Err(IndexError::InvalidQuery(
+            "external_id filter must contain at least one letter or digit".to_string(),
+        )),
+        1 => Ok(Box::new(TermQuery::new(terms.remove(0), IndexRecordOption::Basic))),
+        _ => Ok(Box::new(PhraseQuery::new(terms))),
+    }
+}
+
+fn unknown_field_error(field: &str) -> IndexError {
+    IndexError::InvalidQuery(format!(
+        "Unknown or non-searchable field '{}' in query. Valid fields: {}",
+        field,
+        QUERYABLE_FIELDS.join(", ")
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::search::schema::{build_schema, SchemaOptions};
+
+    #[test]
+    fn test_unknown_field_names_valid_fields() {
+        let index = Index::create_in_ram(build_schema(&SchemaOptions::default()));
+        let schema = index.schema();
+
+        let err = parse_user_query(&index, &schema, "nonexistent:foo").unwrap_err();
+        let message = err.downcast_ref::<IndexError>().unwrap().to_string();
+        assert!(message.contains("'nonexistent'"));
+        assert!(message.contains("title, body, tags, source, id"));
+
+        let err = parse_user_query(&index, &schema, "checksum:abc").unwrap_err();
+        assert!(err.downcast_ref::<IndexError>().is_some());
+
+        assert!(parse_user_query(&index, &schema, "tags:urgent title:invoice").is_ok());
+    }
+
+    #[test]
+    fn test_resolve_field_aliases() {
+        let aliases: BTreeMap<String, String> = [("content", "body"), ("name", "title")]
+            .into_iter()
+            .map(|(alias, field)| (alias.to_string(), field.to_string()))
+            .collect();
+        let resolve = |query: &str| resolve_field_aliases(query, &aliases);
+
+        assert_eq!(resolve("content:refund"), "body:refund");
+        assert_eq!(resolve("(name:invoice OR -content:draft) +name:\"tax return\""), "(title:invoice OR -body:draft) +title:\"tax return\"");
+        // Real fields, values, phrases and escapes are left alone
+        assert_eq!(resolve("title:content:x tags:name"), "title:content:x tags:name");
+        assert_eq!(resolve("\"content:refund\" contents:x"), "\"content:refund\" contents:x");
+        assert_eq!(resolve("a\\ content:x"), "a\\ content:x");
+        assert_eq!(resolve("see-content:x"), "see-content:x");
+        assert_eq!(resolve_field_aliases("content:x", &BTreeMap::new()), "content:x");
+
+        let index = Index::create_in_ram(build_schema(&SchemaOptions::default()));
+        let schema = index.schema();
+        assert!(parse_search_query(&index, &schema, "content:refund", MatchMode::Standard, &aliases, false).is_ok());
+        assert!(parse_search_query(&index, &schema, "content:refund", MatchMode::Standard, &BTreeMap::new(), false).is_err());
+    }
+
+    #[test]
+    fn test_reject_expensive_query() {
+        for query in ["*", "tax AND *", "(* -draft)^2", "title:*", "tax -tags:*", "title:>a", "body:[* TO c]", "\"tax r\"*"] {
+            let err = reject_expensive_query(query).expect_err(query);
+            assert!(err.to_string().contains("expensive queries are disabled"), "{}", query);
+        }
+        let err = reject_expensive_query("tags:*").unwrap_err();
+        assert!(err.to_string().contains("'tags:*' visits every document"), "{}", err);
+        for query in ["tax return", "title:[a TO c]", "\"tax ret\"*", "in*", "tags:IN [a b]", "*voice"] {
+            assert!(reject_expensive_query(query).is_ok(), "{}", query);
+        }
+
+        let index = Index::create_in_ram(build_schema(&SchemaOptions::default()));
+        let schema = index.schema();
+        assert!(parse_search_query(&index, &schema, "*", MatchMode::Standard, &BTreeMap::new(), true).is_ok());
+        // Substring queries have no syntax to expand
+        assert!(parse_search_query(&index, &schema, "*", MatchMode::Substring, &BTreeMap::new(), false).is_err_and(
+            |err| !err.to_string().contains("expensive")
+        ));
+    }
+
+    #[test]
+    fn test_describe_query() {
+        let index = Index::create_in_ram(build_schema(&SchemaOptions::default()));
+        let schema = index.schema();
+
+        let parsed = parse_user_query(&index, &schema, "tags:urgent \"tax return\"").unwrap();
+        let described = describe_query(&schema, &*parsed);
+        assert!(described.contains("field=tags"), "{}", described);
+        assert!(described.contains("field=body"), "{}", described);
+        assert!(described.contains("PhraseQuery { field: Field(title)"), "{}", described);
+        for marker in ["field=", "Field("] {
+            let numbered = described.match_indices(marker).any(|(start, _)| {
+                described[start + marker.len()..].starts_with(|c: char| c.is_ascii_digit())
+            });
+            assert!(!numbered, "{}", described);
+        }
+    }
+
+    #[test]
+    fn test_parse_source_pattern() {
+        assert_eq!(parse_source_pattern("erp.invoices"), Ok(SourcePattern::Exact("erp.invoices")));
+        assert_eq!(parse_source_pattern("erp.invoices.*"), Ok(SourcePattern::Prefix("erp.invoices.")));
+        assert_eq!(parse_source_pattern("erp*"), Ok(SourcePattern::Prefix("erp")));
+
+        assert!(parse_source_pattern("*").is_err());
+        assert!(parse_source_pattern("er*").is_err());
+        assert!(parse_source_pattern("*.2024").is_err());
+        assert!(parse_source_pattern("erp.*.2024").is_err());
+        assert!(parse_source_pattern("erp.invoices.**").is_err());
+    }
+
+    #[test]
+    fn test_prefix_successor() {
+        assert_eq!(prefix_successor(b"2024/"), Some(b"20240".to_vec()));
+        assert_eq!(prefix_successor(b"a\xff\xff"), Some(b"b".to_vec()));
+        assert_eq!(prefix_successor(b"\xff"), None);
+        assert_eq!(prefix_successor(b""), None);
+    }
+}
@@ -0,0 +1,93 @@
+//! Short-lived memory of the documents each user's bulk deletes removed
+//!
+//! A client that retries a bulk delete, for example after a timeout that hid
+//! the first response, would otherwise see `not_found` for documents the
+//! first attempt deleted. IDs a bulk delete found are kept for a fixed window
+//! so the retry reports them `deleted` again. Nothing is persisted: the
+//! window starts over empty after a restart.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use uuid::Uuid;
+
+/// Users kept before those with only expired IDs are dropped
+const PRUNE_THRESHOLD: usize = 1024;
+
+/// Recently deleted IDs per user, with when they were deleted
+pub struct RecentDeletes {
+    window: Duration,
+    users: Mutex<HashMap<Uuid, HashMap<String, Instant>>>,
+}
+
+impl RecentDeletes {
+    /// Remember deletes for `window`; a zero window remembers nothing
+    pub fn new(window: Duration) -> Self {
+        RecentDeletes {
+            window,
+            users: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Record IDs deleted at `now`
+    pub fn record(&self, user_id: Uuid, ids: &[String], now: Instant) {
+        if self.window.is_zero() || ids.is_empty() {
+            return;
+        }
+
+        let mut users = self.users.lock().unwrap();
+        if users.len() >= PRUNE_THRESHOLD {
+            users.retain(|_, deleted| {
+                deleted.retain(|_, at| !self.expired(*at, now));
+                !deleted.is_empty()
+            });
+        }
+
+        let deleted = users.entry(user_id).or_default();
+        deleted.retain(|_, at| !self.expired(*at, now));
+        for id in ids {
+            deleted.insert(id.clone(), now);
+        }
+    }
+
+    /// Whether the user deleted the ID within the window before `now`
+    pub fn contains(&self, user_id: Uuid, id: &str, now: Instant) -> bool {
+        self.users
+            .lock()
+            .unwrap()
+            .get(&user_id)
+            .and_then(|deleted| deleted.get(id))
+            .is_some_and(|at| !self.expired(*at, now))
+    }
+
+    fn expired(&self, at: Instant, now: Instant) -> bool {
+        now.saturating_duration_since(at) >= self.window
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_recent_deletes_window() {
+        let recent = RecentDeletes::new(Duration::from_secs(60));
+        let (user_id, other_user) = (Uuid::new_v4(), Uuid::new_v4());
+        let now = Instant::now();
+
+        recent.record(user_id, &["doc1".to_string()], now);
+        assert!(recent.contains(user_id, "doc1", now + Duration::from_secs(59)));
+        assert!(!recent.contains(user_id, "doc2", now));
+        assert!(!recent.contains(other_user, "doc1", now));
+        assert!(!recent.contains(user_id, "doc1", now + Duration::from_secs(60)));
+
+        // Expired IDs are dropped when the user records more
+        recent.record(user_id, &["doc2".to_string()], now + Duration::from_secs(61));
+        assert_eq!(recent.users.lock().unwrap()[&user_id].len(), 1);
+
+        let disabled = RecentDeletes::new(Duration::ZERO);
+        disabled.record(user_id, &["doc1".to_string()], now);
+        assert!(!disabled.contains(user_id, "doc1", now));
+    }
+}
@@ -1,10 +1,19 @@
-use tantivy::schema::{Schema, TextOptions, TextFieldIndexing, IndexRecordOption, Value, STORED, STRING, TEXT};
+use tantivy::schema::{
+    DateOptions, DateTimePrecision, Facet, FacetOptions, Field, FieldType, Schema, TextOptions, TextFieldIndexing,
+    IndexRecordOption, Value, FAST, STORED, STRING, TEXT,
+};
 use tantivy::{TantivyError};
 use tantivy::TantivyDocument;
-use chrono::Utc;
+use chrono::{DateTime, Duration, Utc};
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::str::FromStr;
 use uuid::Uuid;
 
+use super::language::{self, Language, LANGUAGES};
 use super::models::IndexDocumentInput;
+use super::ngram::NgramSize;
+use super::tokenizer::{TextTokenizer, DEFAULT_MAX_TOKEN_LENGTH};
 
 /// Field names used in the Tantivy schema
 pub struct FieldNames;
@@ -14,52 +23,395 @@ impl FieldNames {
     pub const TITLE: &'static str = "title";
     pub const BODY: &'static str = "body";
     pub const CREATED_AT: &'static str = "created_at";
+    pub const CREATED_AT_TS: &'static str = "created_at_ts";
     pub const TAGS: &'static str = "tags";
     pub const SOURCE: &'static str = "source";
+    pub const CHECKSUM: &'static str = "checksum";
+    pub const TAG_KEYS: &'static str = "tag_keys";
+    pub const TAG_PATHS: &'static str = "tag_paths";
+    pub const BOOST: &'static str = "boost";
+    pub const BODY_STORED: &'static str = "body_stored";
+    pub const LANGUAGE: &'static str = "language";
+    pub const EXTERNAL_ID: &'static str = "external_id";
+    pub const TITLE_KEY: &'static str = "title_key";
+    pub const CLIENT_CREATED_AT: &'static str = "client_created_at";
+    pub const TITLE_NGRAM: &'static str = "title_ngram";
+    pub const BODY_NGRAM: &'static str = "body_ngram";
+    pub const TITLE_LENGTH: &'static str = "title_length";
+    pub const BODY_LENGTH: &'static str = "body_length";
+    pub const BODY_PREFIX: &'static str = "body_prefix";
+}
+
+/// Stored text fields that can supply a search result snippet
+pub const SNIPPET_SOURCE_FIELDS: &[&str] = &[FieldNames::TITLE, FieldNames::BODY, FieldNames::TAGS];
+
+/// Fields eligible for per-field highlights: text that is both stored and indexed with positions
+///
+/// Derived from the index's own schema, so a body that is not stored is
+/// excluded; a capped body counts, highlighted in its stored prefix.
+pub fn highlight_fields(schema: &Schema) -> Vec<(Field, &str)> {
+    let body_capped = schema.get_field(FieldNames::BODY_PREFIX).is_ok();
+    schema
+        .fields()
+        .filter(|(_, entry)| match entry.field_type() {
+            FieldType::Str(options) => {
+                (options.is_stored() || (body_capped && entry.name() == FieldNames::BODY))
+                    && options
+                        .get_indexing_options()
+                        .is_some_and(|indexing| indexing.index_option().has_positions())
+            }
+            _ => false,
+        })
+        .map(|(field, entry)| (field, entry.name()))
+        .collect()
+}
+
+/// Options fixed when a user's index schema is created
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SchemaOptions {
+    /// Store the body; when false it is indexed for search but not returned
+    pub store_body: bool,
+
+    /// Detect each body's language and also index it with that language's stemmer
+    pub detect_language: bool,
+
+    /// Also index the title and body as n-grams of these sizes for substring search
+    pub ngram: Option<NgramSize>,
+
+    /// Tokenizer of the title and body fields
+    pub tokenizer: TextTokenizer,
+
+    /// Fold accented letters of the title and body to ASCII, e.g. "José" to "jose"
+    pub fold_accents: bool,
+
+    /// Longest title and body token kept, in bytes; longer ones are dropped
+    pub max_token_length: usize,
+
+    /// Store bodies in a separate field that can be capped; the body field is then indexed only
+    pub cap_stored_body: bool,
+}
+
+impl Default for SchemaOptions {
+    fn default() -> Self {
+        SchemaOptions {
+            store_body: true,
+            detect_language: false,
+            ngram: None,
+            tokenizer: TextTokenizer::Default,
+            fold_accents: false,
+            max_token_length: DEFAULT_MAX_TOKEN_LENGTH,
+            cap_stored_body: false,
+        }
+    }
 }
 
 /// Build the Tantivy schema for document indexing
 ///
 /// Fields:
-/// - id: String field (stored, indexed) - unique document identifier
-/// - title: Text field (stored, indexed) - document title
-/// - body: Text field (stored, indexed) - document content
+/// - id: String field (stored, indexed, fast) - unique document identifier
+/// - external_id: Text field (stored, indexed) - optional human-readable ID, matched by its words
+/// - title: Text field (stored, indexed with the configured tokenizer) - document title
+/// - title_key: String field (indexed) - whole title as one lowercased term for exact-title filters
+/// - body: Text field (indexed with the configured tokenizer, stored unless disabled or
+///   capped) - document content
 /// - created_at: Text field (stored) - ISO 8601 timestamp
+/// - created_at_ts: Date field (indexed, fast) - creation time for ranking and range queries
+/// - client_created_at: Text field (stored) - client-supplied timestamp when the server stamps `created_at`
 /// - tags: Text field (indexed) - searchable tags
+/// - tag_paths: Facet field - each tag as a `/`-delimited path for hierarchical tag counts
 /// - source: Text field (stored, indexed) - optional source identifier
-pub fn build_schema() -> Schema {
+/// - checksum: String field (stored, indexed) - SHA-256 of the normalized content
+/// - boost: F64 field (stored, fast) - ranking weight, omitted when not supplied
+/// - title_length, body_length: U64 fields (stored, fast) - character counts of the
+///   title and body for sorting by length
+/// - body_stored: Text field (stored only, absent when bodies aren't stored) - body of
+///   documents indexed with `index_body: false`
+/// - body_prefix: Text field (stored only; only with a stored-body cap) - start of the
+///   indexed body, kept in place of the body itself
+/// - language: String field (stored, indexed; only with language detection) - detected
+///   ISO 639-1 code, omitted when undetected
+/// - body_<code>: Text field (indexed; only with language detection) - body stemmed for
+///   its detected language, one field per supported language
+/// - title_ngram, body_ngram: Text fields (indexed; only with n-grams) - lowercased
+///   character n-grams of the title and indexed body for substring search
+pub fn build_schema(options: &SchemaOptions) -> Schema {
     let mut schema_builder = Schema::builder();
 
-    // ID field - stored and indexed as a string
-    schema_builder.add_text_field(FieldNames::ID, STRING | STORED);
+    // ID field - stored and indexed as a string; fast so ID-only results skip stored reads
+    schema_builder.add_text_field(FieldNames::ID, STRING | STORED | FAST);
+
+    // External ID - tokenized so part of a human-readable ID matches; `id` stays the exact key
+    schema_builder.add_text_field(FieldNames::EXTERNAL_ID, TEXT | STORED);
 
     // Title - full-text searchable and stored
-    let text_options = TextOptions::default()
-        .set_indexing_options(
-            TextFieldIndexing::default()
-                .set_tokenizer("default")
-                .set_index_option(IndexRecordOption::WithFreqsAndPositions),
-        )
-        .set_stored();
-    schema_builder.add_text_field(FieldNames::TITLE, text_options.clone());
+    let indexed_text = TextOptions::default().set_indexing_options(
+        TextFieldIndexing::default()
+            .set_tokenizer(&options.tokenizer.analyzer_name(options.fold_accents, options.max_token_length))
+            .set_index_option(IndexRecordOption::WithFreqsAndPositions),
+    );
+    schema_builder.add_text_field(FieldNames::TITLE, indexed_text.clone().set_stored());
+
+    // Title key - the tokenized title can't tell "Invoice 2024" from "Invoice 2024 (copy)"
+    schema_builder.add_text_field(FieldNames::TITLE_KEY, STRING);
 
-    // Body - full-text searchable and stored
-    schema_builder.add_text_field(FieldNames::BODY, text_options);
+    // Body - full-text searchable; storing it is optional for search-to-ID tenants
+    let body_options = if options.store_body && !options.cap_stored_body {
+        indexed_text.set_stored()
+    } else {
+        indexed_text
+    };
+    schema_builder.add_text_field(FieldNames::BODY, body_options);
 
     // Created timestamp - stored as text (ISO 8601)
     schema_builder.add_text_field(FieldNames::CREATED_AT, STRING | STORED);
 
+    // Created timestamp - fast date column used for recency scoring
+    let date_options = DateOptions::default()
+        .set_indexed()
+        .set_fast()
+        .set_precision(DateTimePrecision::Seconds);
+    schema_builder.add_date_field(FieldNames::CREATED_AT_TS, date_options);
+
+    // Client timestamp - kept for audit when `created_at` is the server's ingestion time
+    schema_builder.add_text_field(FieldNames::CLIENT_CREATED_AT, STORED);
+
     // Tags - indexed for filtering
     schema_builder.add_text_field(FieldNames::TAGS, TEXT | STORED);
 
+    // Tag keys - each tag as one exact, lowercased term for tag filters
+    schema_builder.add_text_field(FieldNames::TAG_KEYS, STRING);
+
+    // Tag paths - each tag as a facet, so `finance/tax/2024` also counts under `finance`
+    schema_builder.add_facet_field(FieldNames::TAG_PATHS, FacetOptions::default());
+
     // Source - stored and indexed as string
     schema_builder.add_text_field(FieldNames::SOURCE, STRING | STORED);
 
+    // Checksum - stored and indexed as string for drift detection
+    schema_builder.add_text_field(FieldNames::CHECKSUM, STRING | STORED);
+
+    // Boost - fast for scoring; stored so refreshed documents keep it
+    schema_builder.add_f64_field(FieldNames::BOOST, STORED | FAST);
+
+    // Lengths - fast for sorting by length; stored so refreshed documents keep them
+    schema_builder.add_u64_field(FieldNames::TITLE_LENGTH, STORED | FAST);
+    schema_builder.add_u64_field(FieldNames::BODY_LENGTH, STORED | FAST);
+
+    // Unindexed body - a field's indexing can't vary per document, so opted-out bodies live here
+    if options.store_body {
+        schema_builder.add_text_field(FieldNames::BODY_STORED, STORED);
+    }
+
+    // Body prefix - the body field can't store part of its text, so a capped body is stored here
+    if options.store_body && options.cap_stored_body {
+        schema_builder.add_text_field(FieldNames::BODY_PREFIX, STORED);
+    }
+
+    // Language - detected language and a stemmed body field per language, searched together
+    if options.detect_language {
+        schema_builder.add_text_field(FieldNames::LANGUAGE, STRING | STORED);
+        for language in LANGUAGES {
+            let stemmed = TextOptions::default().set_indexing_options(
+                TextFieldIndexing::default()
+                    .set_tokenizer(language.analyzer_name())
+                    .set_index_option(IndexRecordOption::WithFreqsAndPositions),
+            );
+            schema_builder.add_text_field(language.body_field(), stemmed);
+        }
+    }
+
+    // N-grams - every short run of characters, so a query can match inside a word
+    if let Some(ngram) = options.ngram {
+        let grams = TextOptions::default().set_indexing_options(
+            TextFieldIndexing::default()
+                .set_tokenizer(&ngram.analyzer_name())
+                .set_index_option(IndexRecordOption::WithFreqs),
+        );
+        schema_builder.add_text_field(FieldNames::TITLE_NGRAM, grams.clone());
+        schema_builder.add_text_field(FieldNames::BODY_NGRAM, grams);
+    }
+
     schema_builder.build()
 }
 
+/// How repeated tags within a single document are collapsed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TagDedupMode {
+    /// `Tax` and `tax` are the same tag; the first-seen casing is stored (default)
+    #[default]
+    CaseInsensitive,
+    /// Only byte-identical tags are collapsed
+    CaseSensitive,
+}
+
+impl FromStr for TagDedupMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "case_insensitive" => Ok(TagDedupMode::CaseInsensitive),
+            "case_sensitive" => Ok(TagDedupMode::CaseSensitive),
+            other => Err(format!(
+                "expected \"case_insensitive\" or \"case_sensitive\", got \"{}\"",
+                other
+            )),
+        }
+    }
+}
+
+/// What happens to empty or whitespace-only tags in a document
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EmptyTagsMode {
+    /// Leave them out and index the remaining tags (default)
+    #[default]
+    Drop,
+    /// Reject the document with a validation error
+    Reject,
+}
+
+impl FromStr for EmptyTagsMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "drop" => Ok(EmptyTagsMode::Drop),
+            "reject" => Ok(EmptyTagsMode::Reject),
+            other => Err(format!("expected \"drop\" or \"reject\", got \"{}\"", other)),
+        }
+    }
+}
+
+/// What happens to a client-supplied `created_at` outside the accepted window
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CreatedAtMode {
+    /// Move the timestamp to the nearest edge of the window (default)
+    #[default]
+    Clamp,
+    /// Reject the document with a validation error
+    Reject,
+    /// Index the timestamp as supplied
+    Allow,
+}
+
+impl FromStr for CreatedAtMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "clamp" => Ok(CreatedAtMode::Clamp),
+            "reject" => Ok(CreatedAtMode::Reject),
+            "allow" => Ok(CreatedAtMode::Allow),
+            other => Err(format!(
+                "expected \"clamp\", \"reject\" or \"allow\", got \"{}\"",
+                other
+            )),
+        }
+    }
+}
+
+/// Whether a document's `created_at` comes from the client or the server
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CreatedAtSource {
+    /// A client-supplied `created_at` is used; the ingestion time fills in when absent (default)
+    #[default]
+    Client,
+    /// Always the ingestion time; a client-supplied value is stored as `client_created_at`
+    Server,
+}
+
+impl FromStr for CreatedAtSource {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "client" => Ok(CreatedAtSource::Client),
+            "server" => Ok(CreatedAtSource::Server),
+            other => Err(format!("expected \"client\" or \"server\", got \"{}\"", other)),
+        }
+    }
+}
+
+/// Accepted range for client-supplied `created_at` values, relative to now
+#[derive(Debug, Clone, Copy)]
+pub struct CreatedAtPolicy {
+    pub mode: CreatedAtMode,
+    /// How far into the future a timestamp may be
+    pub max_future: Duration,
+    /// How far into the past a timestamp may be
+    pub max_past: Duration,
+}
+
+impl CreatedAtPolicy {
+    /// Check a timestamp against the window and return the value to index
+    ///
+    /// Out-of-window values skew recency ranking and date filters, so they
+    /// are clamped or rejected depending on the mode.
+    pub fn apply(&self, value: DateTime<Utc>, now: DateTime<Utc>) -> Result<DateTime<Utc>, String> {
+        let latest = now + self.max_future;
+        let earliest = now - self.max_past;
+        if (earliest..=latest).contains(&value) {
+            return Ok(value);
+        }
+
+        match self.mode {
+            CreatedAtMode::Allow => Ok(value),
+            CreatedAtMode::Clamp => Ok(value.clamp(earliest, latest)),
+            CreatedAtMode::Reject => Err(format!(
+                "created_at {} is outside the accepted range {} to {}",
+                value.to_rfc3339(),
+                earliest.to_rfc3339(),
+                latest.to_rfc3339()
+            )),
+        }
+    }
+}
+
+/// Options controlling how an input is turned into a Tantivy document
+#[derive(Debug, Clone, Default)]
+pub struct DocumentOptions {
+    /// Tag deduplication behavior
+    pub tag_dedup: TagDedupMode,
+
+    /// Whose timestamp becomes `created_at`
+    pub created_at_source: CreatedAtSource,
+
+    /// Source of inputs that don't set one
+    pub default_source: Option<String>,
+
+    /// Bytes of each body stored in an index with a stored-body cap; `None` stores it whole
+    pub stored_body_max_bytes: Option<usize>,
+}
+
+/// Trim surrounding whitespace from tags and leave out the ones that are then empty
+pub fn clean_tags(tags: &[String]) -> Vec<String> {
+    tags.iter()
+        .map(|tag| tag.trim())
+        .filter(|tag| !tag.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Remove repeated tags, keeping the first occurrence (and its casing)
+pub fn dedup_tags(tags: &[String], mode: TagDedupMode) -> Vec<String> {
+    let mut seen = HashSet::new();
+    tags.iter()
+        .filter(|tag| {
+            let key = match mode {
+                TagDedupMode::CaseInsensitive => tag.to_lowercase(),
+                TagDedupMode::CaseSensitive => tag.to_string(),
+            };
+            seen.insert(key)
+        })
+        .cloned()
+        .collect()
+}
+
 /// Convert an IndexDocumentInput into a Tantivy Document
-pub fn doc_from_input(schema: &Schema, input: &IndexDocumentInput) -> Result<TantivyDocument, TantivyError> {
+pub fn doc_from_input(
+    schema: &Schema,
+    input: &IndexDocumentInput,
+    options: &DocumentOptions,
+) -> Result<TantivyDocument, TantivyError> {
     let mut doc = TantivyDocument::default();
 
     // Get field handles - these should always exist in our schema
@@ -80,29 +432,365 @@ pub fn doc_from_input(schema: &Schema, input: &IndexDocumentInput) -> Result<Tan
     let doc_id = input.id.clone().unwrap_or_else(|| Uuid::new_v4().to_string());
     doc.add_text(id_field, &doc_id);
 
-    // Title and body
+    // External ID - callers check the index has the field first (see `check_input_storable`)
+    if let (Ok(external_id_field), Some(external_id)) = (schema.get_field(FieldNames::EXTERNAL_ID), &input.external_id) {
+        doc.add_text(external_id_field, external_id);
+    }
+
+    // Title and body; title-only documents have no body value at all
     doc.add_text(title_field, &input.title);
-    doc.add_text(body_field, &input.body);
+    add_title_key(schema, &mut doc, &input.title);
+    add_ngrams(schema, &mut doc, FieldNames::TITLE_NGRAM, &input.title);
+    if !input.body.is_empty() {
+        if input.index_body {
+            doc.add_text(body_field, &input.body);
+            add_body_prefix(schema, &mut doc, &input.body, options.stored_body_max_bytes);
+            add_language(schema, &mut doc, &input.body, None);
+            add_ngrams(schema, &mut doc, FieldNames::BODY_NGRAM, &input.body);
+        } else {
+            let body_stored_field = schema.get_field(FieldNames::BODY_STORED).map_err(|_| {
+                TantivyError::SchemaError("This index cannot store unindexed bodies".to_string())
+            })?;
+            doc.add_text(body_stored_field, capped_body(schema, &input.body, options.stored_body_max_bytes));
+        }
+    }
 
-    // Created timestamp
-    let created_at = input.metadata.created_at
-        .unwrap_or_else(Utc::now)
-        .to_rfc3339();
-    doc.add_text(created_at_field, &created_at);
+    // Created timestamp; with server stamping the client's value is only recorded
+    let created_at = match options.created_at_source {
+        CreatedAtSource::Client => input.metadata.created_at.unwrap_or_else(Utc::now),
+        CreatedAtSource::Server => {
+            // Indexes created before this field existed don't record the client value
+            if let (Ok(client_created_at_field), Some(client_created_at)) =
+                (schema.get_field(FieldNames::CLIENT_CREATED_AT), input.metadata.created_at)
+            {
+                doc.add_text(client_created_at_field, client_created_at.to_rfc3339());
+            }
+            Utc::now()
+        }
+    };
+    doc.add_text(created_at_field, created_at.to_rfc3339());
+    if let Ok(created_at_ts_field) = schema.get_field(FieldNames::CREATED_AT_TS) {
+        doc.add_date(
+            created_at_ts_field,
+            tantivy::DateTime::from_timestamp_secs(created_at.timestamp()),
+        );
+    }
 
-    // Tags
-    for tag in &input.metadata.tags {
-        doc.add_text(tags_field, tag);
+    // Tags - empty ones would pollute facets; duplicates would inflate term frequency
+    let tags = clean_tags(&input.metadata.tags);
+    for tag in dedup_tags(&tags, options.tag_dedup) {
+        doc.add_text(tags_field, &tag);
     }
+    add_tag_keys(schema, &mut doc, &tags);
+    add_tag_paths(schema, &mut doc, &tags);
 
-    // Source
-    if let Some(ref source) = input.metadata.source {
+    // Source - an explicit one always wins over the default
+    if let Some(source) = input.metadata.source.as_ref().or(options.default_source.as_ref()) {
         doc.add_text(source_field, source);
     }
 
+    // Checksum - indexes created before this field existed simply omit it
+    if let Ok(checksum_field) = schema.get_field(FieldNames::CHECKSUM) {
+        doc.add_text(checksum_field, input_checksum(input, options.tag_dedup));
+    }
+
+    // Boost - indexes created before this field existed rank every document at 1.0
+    if let (Ok(boost_field), Some(boost)) = (schema.get_field(FieldNames::BOOST), input.boost) {
+        doc.add_f64(boost_field, boost as f64);
+    }
+
+    // Lengths - counted whether or not the body is indexed
+    add_length(schema, &mut doc, FieldNames::TITLE_LENGTH, &input.title);
+    add_length(schema, &mut doc, FieldNames::BODY_LENGTH, &input.body);
+
     Ok(doc)
 }
 
+/// Checksum of a document as it will be stored
+///
+/// Covers the tags the document keeps (see `clean_tags` and `dedup_tags`), so
+/// the value can be recomputed from the returned document and copies that
+/// differ only in dropped tags share it.
+pub fn input_checksum(input: &IndexDocumentInput, tag_dedup: TagDedupMode) -> String {
+    let tags = dedup_tags(&clean_tags(&input.metadata.tags), tag_dedup);
+    compute_checksum(&input.title, &input.body, &tags)
+}
+
+/// Compute the content checksum returned to clients for drift detection
+///
+/// `tags` are the stored tags: trimmed, empty ones left out and repeats
+/// removed. Normalization, so clients can reproduce the value from a
+/// returned document:
+/// 1. Trim leading/trailing whitespace from the title, the body and each tag
+/// 2. Sort the tags by byte order
+/// 3. Join title, body and the sorted tags with the ASCII unit separator (`0x1F`)
+/// 4. Hash the UTF-8 bytes with SHA-256 and encode as lowercase hex
+pub fn compute_checksum(title: &str, body: &str, tags: &[String]) -> String {
+    let mut sorted_tags: Vec<&str> = tags.iter().map(|t| t.trim()).collect();
+    sorted_tags.sort_unstable();
+
+    let mut hasher = Sha256::new();
+    hasher.update(title.trim().as_bytes());
+    hasher.update([0x1f]);
+    hasher.update(body.trim().as_bytes());
+    for tag in sorted_tags {
+        hasher.update([0x1f]);
+        hasher.update(tag.as_bytes());
+    }
+
+    format!("{:x}", hasher.finalize())
+}
+
+/// Rebuild an indexable document from a stored one
+///
+/// Stored values are reused verbatim; fields that are indexed but not stored
+/// (the `created_at_ts` date) are derived again from their stored source so
+/// re-adding the document tokenizes it with the current analyzers without
+/// changing its content. Documents copied from an index without a
+/// stored-body cap into one with it are capped with `options`.
+pub fn refresh_doc(schema: &Schema, stored: TantivyDocument, options: &DocumentOptions) -> TantivyDocument {
+    let mut doc = cap_body_stored(schema, stored, options.stored_body_max_bytes);
+
+    let created_at = schema
+        .get_field(FieldNames::CREATED_AT)
+        .ok()
+        .and_then(|field| doc.get_first(field))
+        .and_then(|v| v.as_str())
+        .and_then(|s| chrono::DateTime::parse_from_rfc3339(s).ok());
+
+    if let (Ok(created_at_ts_field), Some(created_at)) =
+        (schema.get_field(FieldNames::CREATED_AT_TS), created_at)
+    {
+        doc.add_date(
+            created_at_ts_field,
+            tantivy::DateTime::from_timestamp_secs(created_at.timestamp()),
+        );
+    }
+
+    let tags: Vec<String> = schema
+        .get_field(FieldNames::TAGS)
+        .map(|field| {
+            doc.get_all(field)
+                .filter_map(|v| v.as_str())
+                .map(str::to_string)
+                .collect()
+        })
+        .unwrap_or_default();
+    add_tag_keys(schema, &mut doc, &tags);
+    add_tag_paths(schema, &mut doc, &tags);
+
+    let title = schema
+        .get_field(FieldNames::TITLE)
+        .ok()
+        .and_then(|field| doc.get_first(field))
+        .and_then(|v| v.as_str())
+        .map(str::to_string);
+    if let Some(title) = &title {
+        add_title_key(schema, &mut doc, title);
+        add_ngrams(schema, &mut doc, FieldNames::TITLE_NGRAM, title);
+    }
+
+    // The stemmed body isn't stored; rebuild it from the stored body and language
+    let body = schema
+        .get_field(FieldNames::BODY)
+        .ok()
+        .and_then(|field| doc.get_first(field))
+        .and_then(|v| v.as_str())
+        .map(str::to_string);
+    let stored_language = schema
+        .get_field(FieldNames::LANGUAGE)
+        .ok()
+        .and_then(|field| doc.get_first(field))
+        .and_then(|v| v.as_str())
+        .and_then(Language::from_code);
+    if let Some(body) = body {
+        add_language(schema, &mut doc, &body, stored_language);
+        add_ngrams(schema, &mut doc, FieldNames::BODY_NGRAM, &body);
+        let needs_prefix = schema
+            .get_field(FieldNames::BODY_PREFIX)
+            .is_ok_and(|field| doc.get_first(field).is_none());
+        if needs_prefix {
+            add_body_prefix(schema, &mut doc, &body, options.stored_body_max_bytes);
+        }
+    }
+
+    // Documents from before the length fields existed get them from their stored text
+    let needs_length = |doc: &TantivyDocument, name: &str| {
+        schema.get_field(name).is_ok_and(|field| doc.get_first(field).is_none())
+    };
+    if needs_length(&doc, FieldNames::TITLE_LENGTH) {
+        add_length(schema, &mut doc, FieldNames::TITLE_LENGTH, title.as_deref().unwrap_or(""));
+    }
+    if needs_length(&doc, FieldNames::BODY_LENGTH) {
+        let body = stored_body(schema, &doc).unwrap_or("").to_string();
+        add_length(schema, &mut doc, FieldNames::BODY_LENGTH, &body);
+    }
+
+    doc
+}
+
+/// Start of a body kept by a stored-body cap, cut at a character boundary
+///
+/// Indexes without the cap keep the whole body.
+fn capped_body<'a>(schema: &Schema, body: &'a str, max_bytes: Option<usize>) -> &'a str {
+    let Some(max_bytes) = max_bytes.filter(|_| schema.get_field(FieldNames::BODY_PREFIX).is_ok()) else {
+        return body;
+    };
+    if body.len() <= max_bytes {
+        return body;
+    }
+    let end = (0..=max_bytes).rev().find(|&i| body.is_char_boundary(i)).unwrap_or(0);
+    &body[..end]
+}
+
+/// Store the start of an indexed body; indexes without a stored-body cap store the body itself
+fn add_body_prefix(schema: &Schema, doc: &mut TantivyDocument, body: &str, max_bytes: Option<usize>) {
+    if let Ok(prefix_field) = schema.get_field(FieldNames::BODY_PREFIX) {
+        doc.add_text(prefix_field, capped_body(schema, body, max_bytes));
+    }
+}
+
+/// Cap the unindexed bodies of a stored document when the index caps stored bodies
+fn cap_body_stored(schema: &Schema, doc: TantivyDocument, max_bytes: Option<usize>) -> TantivyDocument {
+    let (Ok(body_stored_field), Some(max_bytes)) = (schema.get_field(FieldNames::BODY_STORED), max_bytes) else {
+        return doc;
+    };
+    let too_long = |doc: &TantivyDocument| {
+        doc.get_all(body_stored_field)
+            .filter_map(|v| v.as_str())
+            .any(|body| body.len() > max_bytes)
+    };
+    if schema.get_field(FieldNames::BODY_PREFIX).is_err() || !too_long(&doc) {
+        return doc;
+    }
+
+    let mut capped = TantivyDocument::new();
+    for field_value in doc.field_values() {
+        match field_value.value().as_str() {
+            Some(body) if field_value.field() == body_stored_field => {
+                capped.add_text(body_stored_field, capped_body(schema, body, Some(max_bytes)));
+            }
+            _ => capped.add_field_value(field_value.field(), field_value.value().clone()),
+        }
+    }
+    capped
+}
+
+/// Whether a document's stored body is only the start of its body, cut by a stored-body cap
+pub fn body_capped(schema: &Schema, doc: &TantivyDocument) -> bool {
+    if schema.get_field(FieldNames::BODY_PREFIX).is_err() {
+        return false;
+    }
+    let length = schema
+        .get_field(FieldNames::BODY_LENGTH)
+        .ok()
+        .and_then(|field| doc.get_first(field))
+        .and_then(|v| v.as_u64());
+    let stored = stored_body(schema, doc).unwrap_or("");
+    length.is_some_and(|length| (stored.chars().count() as u64) < length)
+}
+
+/// Add the character count of a text to a length field; indexes predating the field skip this
+fn add_length(schema: &Schema, doc: &mut TantivyDocument, field_name: &str, text: &str) {
+    if let Ok(length_field) = schema.get_field(field_name) {
+        doc.add_u64(length_field, text.chars().count() as u64);
+    }
+}
+
+/// Add the body's language and its stemmed copy; indexes without language detection skip this
+///
+/// `known` is a language already stored on the document, which is kept
+/// rather than detected again.
+fn add_language(schema: &Schema, doc: &mut TantivyDocument, body: &str, known: Option<Language>) {
+    let Ok(language_field) = schema.get_field(FieldNames::LANGUAGE) else {
+        return;
+    };
+    let Some(language) = known.or_else(|| language::detect(body)) else {
+        return;
+    };
+
+    if known.is_none() {
+        doc.add_text(language_field, language.code());
+    }
+    if let Ok(stemmed_field) = schema.get_field(language.body_field()) {
+        doc.add_text(stemmed_field, body);
+    }
+}
+
+/// Add text to an n-gram field; indexes without n-grams skip this
+fn add_ngrams(schema: &Schema, doc: &mut TantivyDocument, field_name: &str, text: &str) {
+    if let Ok(ngram_field) = schema.get_field(field_name) {
+        doc.add_text(ngram_field, text);
+    }
+}
+
+/// Normalized form of a tag used for exact tag filtering
+pub fn tag_key(tag: &str) -> String {
+    tag.trim().to_lowercase()
+}
+
+/// Separator between the levels of a hierarchical tag
+pub const TAG_PATH_DELIMITER: char = '/';
+
+/// Facet of a tag split at `/`, with each level normalized like a tag key
+///
+/// Empty levels are dropped, so `finance//tax/` is `/finance/tax`; a tag
+/// with no non-empty level has no facet.
+pub fn tag_facet(tag: &str) -> Option<Facet> {
+    let levels: Vec<String> = tag
+        .split(TAG_PATH_DELIMITER)
+        .map(tag_key)
+        .filter(|level| !level.is_empty())
+        .collect();
+    (!levels.is_empty()).then(|| Facet::from_path(levels))
+}
+
+/// Add each distinct tag path; indexes created before tag paths existed skip this
+fn add_tag_paths(schema: &Schema, doc: &mut TantivyDocument, tags: &[String]) {
+    let Ok(tag_paths_field) = schema.get_field(FieldNames::TAG_PATHS) else {
+        return;
+    };
+    let mut seen = HashSet::new();
+    for facet in tags.iter().filter_map(|tag| tag_facet(tag)) {
+        if seen.insert(facet.clone()) {
+            doc.add_facet(tag_paths_field, facet);
+        }
+    }
+}
+
+/// Normalized form of a title used for exact-title filtering
+pub fn title_key(title: &str) -> String {
+    title.trim().to_lowercase()
+}
+
+/// Add the title key; indexes created before title keys existed skip this
+fn add_title_key(schema: &Schema, doc: &mut TantivyDocument, title: &str) {
+    if let Ok(title_key_field) = schema.get_field(FieldNames::TITLE_KEY) {
+        doc.add_text(title_key_field, title_key(title));
+    }
+}
+
+/// Add each distinct tag key; indexes created before tag keys existed skip this
+fn add_tag_keys(schema: &Schema, doc: &mut TantivyDocument, tags: &[String]) {
+    let Ok(tag_keys_field) = schema.get_field(FieldNames::TAG_KEYS) else {
+        return;
+    };
+    let mut seen = HashSet::new();
+    for key in tags.iter().map(|tag| tag_key(tag)) {
+        if !key.is_empty() && seen.insert(key.clone()) {
+            doc.add_text(tag_keys_field, &key);
+        }
+    }
+}
+
+/// Stored body of a document, whether or not it was indexed
+pub fn stored_body<'a>(schema: &Schema, doc: &'a TantivyDocument) -> Option<&'a str> {
+    [FieldNames::BODY, FieldNames::BODY_PREFIX, FieldNames::BODY_STORED]
+        .iter()
+        .filter_map(|name| schema.get_field(name).ok())
+        .find_map(|field| doc.get_first(field).and_then(|v| v.as_str()))
+}
+
 /// Extract document ID from a Tantivy document
 #[allow(dead_code)]
 pub fn extract_doc_id(schema: &Schema, doc: &TantivyDocument) -> Option<String> {
@@ -117,19 +805,45 @@ mod tests {
     use super::*;
     use crate::search::models::DocumentMetadata;
 
+    #[test]
+    fn test_created_at_window() {
+        let now = Utc::now();
+        let policy = |mode| CreatedAtPolicy {
+            mode,
+            max_future: Duration::days(1),
+            max_past: Duration::days(365 * 50),
+        };
+        let future = now + Duration::days(30);
+        let epoch = DateTime::<Utc>::from_timestamp(0, 0).unwrap();
+        let recent = now - Duration::days(10);
+
+        let clamp = policy(CreatedAtMode::Clamp);
+        assert_eq!(clamp.apply(future, now).unwrap(), now + Duration::days(1));
+        assert_eq!(clamp.apply(epoch, now).unwrap(), now - Duration::days(365 * 50));
+        assert_eq!(clamp.apply(recent, now).unwrap(), recent);
+
+        let reject = policy(CreatedAtMode::Reject);
+        assert!(reject.apply(future, now).unwrap_err().contains("outside the accepted range"));
+        assert!(reject.apply(epoch, now).is_err());
+        assert_eq!(reject.apply(recent, now).unwrap(), recent);
+
+        assert_eq!(policy(CreatedAtMode::Allow).apply(epoch, now).unwrap(), epoch);
+    }
+
     #[test]
     fn test_schema_creation() {
-        let schema = build_schema();
+        let schema = build_schema(&SchemaOptions::default());
 
         assert!(schema.get_field(FieldNames::ID).is_ok());
         assert!(schema.get_field(FieldNames::TITLE).is_ok());
         assert!(schema.get_field(FieldNames::BODY).is_ok());
         assert!(schema.get_field(FieldNames::CREATED_AT).is_ok());
+        assert!(schema.get_field(FieldNames::CREATED_AT_TS).is_ok());
     }
 
     #[test]
     fn test_doc_from_input() {
-        let schema = build_schema();
+        let schema = build_schema(&SchemaOptions::default());
         let input = IndexDocumentInput {
             id: Some("test-123".to_string()),
             title: "Test Document".to_string(),
@@ -140,11 +854,120 @@ mod tests {
                 created_at: None,
                 custom: Default::default(),
             },
+            ..Default::default()
         };
 
-        let doc = doc_from_input(&schema, &input).unwrap();
+        let doc = doc_from_input(&schema, &input, &DocumentOptions::default()).unwrap();
         let extracted_id = extract_doc_id(&schema, &doc);
 
         assert_eq!(extracted_id, Some("test-123".to_string()));
     }
+
+    #[test]
+    fn test_empty_tags_are_dropped() {
+        let schema = build_schema(&SchemaOptions::default());
+        let tags_field = schema.get_field(FieldNames::TAGS).unwrap();
+        let input = IndexDocumentInput {
+            id: Some("tagged".to_string()),
+            title: "Tagged".to_string(),
+            body: "Body".to_string(),
+            metadata: DocumentMetadata {
+                tags: vec!["".to_string(), "  ".to_string(), " valid ".to_string(), "\t".to_string(), "tax".to_string()],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let doc = doc_from_input(&schema, &input, &DocumentOptions::default()).unwrap();
+        let stored: Vec<&str> = doc.get_all(tags_field).filter_map(|v| v.as_str()).collect();
+        assert_eq!(stored, vec!["valid", "tax"]);
+    }
+
+    #[test]
+    fn test_duplicate_tags_are_collapsed() {
+        let schema = build_schema(&SchemaOptions::default());
+        let tags_field = schema.get_field(FieldNames::TAGS).unwrap();
+        let input = IndexDocumentInput {
+            id: Some("tagged".to_string()),
+            title: "Tagged".to_string(),
+            body: "Body".to_string(),
+            metadata: DocumentMetadata {
+                tags: vec!["tax".to_string(), "tax".to_string(), "Tax".to_string()],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let doc = doc_from_input(&schema, &input, &DocumentOptions::default()).unwrap();
+        let stored: Vec<&str> = doc.get_all(tags_field).filter_map(|v| v.as_str()).collect();
+        assert_eq!(stored, vec!["tax"]);
+
+        let options = DocumentOptions {
+            tag_dedup: TagDedupMode::CaseSensitive,
+            ..Default::default()
+        };
+        let doc = doc_from_input(&schema, &input, &options).unwrap();
+        let stored: Vec<&str> = doc.get_all(tags_field).filter_map(|v| v.as_str()).collect();
+        assert_eq!(stored, vec!["tax", "Tax"]);
+    }
+
+    #[test]
+    fn test_created_at_source() {
+        let schema = build_schema(&SchemaOptions::default());
+        let created_at_field = schema.get_field(FieldNames::CREATED_AT).unwrap();
+        let client_created_at_field = schema.get_field(FieldNames::CLIENT_CREATED_AT).unwrap();
+        let supplied = DateTime::parse_from_rfc3339("2021-04-15T09:30:00Z").unwrap().with_timezone(&Utc);
+        let input = IndexDocumentInput {
+            id: Some("stamped".to_string()),
+            title: "Receipt".to_string(),
+            body: "Body".to_string(),
+            metadata: DocumentMetadata {
+                created_at: Some(supplied),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let stored = |doc: &TantivyDocument, field| doc.get_first(field).and_then(|v| v.as_str()).map(str::to_string);
+
+        let doc = doc_from_input(&schema, &input, &DocumentOptions::default()).unwrap();
+        assert_eq!(stored(&doc, created_at_field), Some(supplied.to_rfc3339()));
+        assert_eq!(stored(&doc, client_created_at_field), None);
+
+        let options = DocumentOptions {
+            created_at_source: CreatedAtSource::Server,
+            ..Default::default()
+        };
+        let before = Utc::now() - Duration::seconds(1);
+        let doc = doc_from_input(&schema, &input, &options).unwrap();
+        let created_at = DateTime::parse_from_rfc3339(&stored(&doc, created_at_field).unwrap()).unwrap();
+        assert!(created_at >= before && created_at <= Utc::now());
+        assert_eq!(stored(&doc, client_created_at_field), Some(supplied.to_rfc3339()));
+
+        // Without a client value there is nothing to record
+        let input = IndexDocumentInput {
+            metadata: DocumentMetadata::default(),
+            ..input
+        };
+        let doc = doc_from_input(&schema, &input, &options).unwrap();
+        assert_eq!(stored(&doc, client_created_at_field), None);
+    }
+
+    #[test]
+    fn test_checksum_normalization() {
+        let a = compute_checksum(
+            "Title",
+            "Body text",
+            &["b".to_string(), "a".to_string()],
+        );
+        let b = compute_checksum(
+            "  Title ",
+            "Body text\n",
+            &["a".to_string(), " b".to_string()],
+        );
+        let c = compute_checksum("Title", "Different body", &[]);
+
+        assert_eq!(a, b);
+        assert_ne!(a, c);
+        assert_eq!(a.len(), 64);
+    }
 }
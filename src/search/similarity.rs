@@ -0,0 +1,89 @@
+//! Similarity of two texts from their term frequency vectors
+//!
+//! Texts are tokenized with the analyzer the tenant's `title` and `body` are
+//! indexed with, so two documents compare by the same terms a search would
+//! match. Scores use raw term counts; terms common to every document (e.g.
+//! "the") count like any other.
+
+use std::collections::HashMap;
+
+use tantivy::tokenizer::{TextAnalyzer, TokenStream};
+
+/// Term counts of a text
+pub type TermVector = HashMap<String, u32>;
+
+/// Count the terms of `text` as `analyzer` produces them
+pub fn term_vector(analyzer: &mut TextAnalyzer, text: &str) -> TermVector {
+    let mut terms = TermVector::new();
+    analyzer
+        .token_stream(text)
+        .process(&mut |token| *terms.entry(token.text.clone()).or_default() += 1);
+    terms
+}
+
+/// Cosine of the angle between two term count vectors, from 0 (no shared terms) to 1
+///
+/// 1 means the texts use the same terms in the same proportions, regardless
+/// of length or word order. A text without terms scores 0 against anything.
+pub fn cosine(a: &TermVector, b: &TermVector) -> f64 {
+    let norm = |v: &TermVector| v.values().map(|&n| f64::from(n).powi(2)).sum::<f64>().sqrt();
+    let (norm_a, norm_b) = (norm(a), norm(b));
+    if norm_a == 0.0 || norm_b == 0.0 {
+        return 0.0;
+    }
+
+    let dot: f64 = a
+        .iter()
+        .filter_map(|(term, &n)| b.get(term).map(|&m| f64::from(n) * f64::from(m)))
+        .sum();
+    (dot / (norm_a * norm_b)).min(1.0)
+}
+
+/// Shared distinct terms over all distinct terms, from 0 to 1, ignoring counts
+///
+/// A text without terms scores 0 against anything.
+pub fn jaccard(a: &TermVector, b: &TermVector) -> f64 {
+    let shared = a.keys().filter(|term| b.contains_key(*term)).count();
+    let union = a.len() + b.len() - shared;
+    if union == 0 {
+        return 0.0;
+    }
+    shared as f64 / union as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tantivy::tokenizer::TokenizerManager;
+
+    /// Terms as tantivy's `default` analyzer produces them
+    fn default_terms(text: &str) -> TermVector {
+        let mut analyzer = TokenizerManager::default().get("default").unwrap();
+        term_vector(&mut analyzer, text)
+    }
+
+    #[test]
+    fn test_term_vector_counts_analyzed_terms() {
+        let terms = default_terms("Refund, refund REFUND invoice");
+        assert_eq!(terms.get("refund"), Some(&3));
+        assert_eq!(terms.get("invoice"), Some(&1));
+        assert_eq!(terms.len(), 2);
+    }
+
+    #[test]
+    fn test_cosine_and_jaccard() {
+        let a = default_terms("refund invoice receipt");
+        let b = default_terms("receipt invoice refund refund invoice receipt");
+        // Same terms in the same proportions
+        assert!((cosine(&a, &b) - 1.0).abs() < 1e-9);
+        assert_eq!(jaccard(&a, &b), 1.0);
+
+        let c = default_terms("refund invoice payroll");
+        assert!((cosine(&a, &c) - 2.0 / 3.0).abs() < 1e-9);
+        assert_eq!(jaccard(&a, &c), 0.5);
+
+        let empty = default_terms("");
+        assert_eq!(cosine(&a, &empty), 0.0);
+        assert_eq!(jaccard(&empty, &empty), 0.0);
+    }
+}
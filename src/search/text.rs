@@ -0,0 +1,176 @@
+use std::str::FromStr;
+use unicode_normalization::UnicodeNormalization;
+
+/// How document text containing invalid characters is handled at index time
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum InvalidTextMode {
+    /// Reject the document with a validation error (default)
+    #[default]
+    Reject,
+    /// Replace each invalid character with U+FFFD and index the result
+    Replace,
+}
+
+impl FromStr for InvalidTextMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "reject" => Ok(InvalidTextMode::Reject),
+            "replace" => Ok(InvalidTextMode::Replace),
+            other => Err(format!("expected \"reject\" or \"replace\", got \"{}\"", other)),
+        }
+    }
+}
+
+/// Returns true for characters that indicate garbled or non-textual input:
+/// the replacement character left behind by lossy decoders, Unicode
+/// noncharacters, and C0/C1 control characters other than tab and newlines.
+fn is_invalid_char(c: char) -> bool {
+    let cp = c as u32;
+    c == char::REPLACEMENT_CHARACTER
+        || (c.is_control() && !matches!(c, '\t' | '\n' | '\r'))
+        || (0xFDD0..=0xFDEF).contains(&cp)
+        || (cp & 0xFFFE) == 0xFFFE
+}
+
+/// NFC-normalize a text field and apply the configured invalid-character policy
+///
+/// Returns the normalized text, or an error message naming the field and the
+/// first offending character when the mode is `Reject`.
+pub fn normalize_text(field: &str, value: &str, mode: InvalidTextMode) -> Result<String, String> {
+    if let Some((pos, c)) = value.chars().enumerate().find(|(_, c)| is_invalid_char(*c)) {
+        if mode == InvalidTextMode::Reject {
+            return Err(format!(
+                "{} contains an invalid character U+{:04X} at position {}",
+                field, c as u32, pos
+            ));
+        }
+    }
+
+    Ok(value
+        .nfc()
+        .map(|c| if is_invalid_char(c) { char::REPLACEMENT_CHARACTER } else { c })
+        .collect())
+}
+
+/// Longest accepted document ID in bytes
+pub const MAX_DOCUMENT_ID_BYTES: usize = 512;
+
+/// Check that a document ID can be matched exactly
+///
+/// IDs are indexed as a single untokenized term and compared byte-for-byte,
+/// so any printable text (spaces, slashes, hyphens, non-ASCII) is an exact
+/// match. Rejected are empty IDs, leading or trailing whitespace (easily lost
+/// by clients and proxies), control and other invalid characters, and IDs
+/// longer than [`MAX_DOCUMENT_ID_BYTES`].
+pub fn validate_document_id(id: &str) -> Result<(), String> {
+    if id.trim().is_empty() {
+        return Err("Document ID cannot be empty".to_string());
+    }
+    if id.trim() != id {
+        return Err("Document ID cannot start or end with whitespace".to_string());
+    }
+    if id.len() > MAX_DOCUMENT_ID_BYTES {
+        return Err(format!(
+            "Document ID is {} bytes; the maximum is {}",
+            id.len(),
+            MAX_DOCUMENT_ID_BYTES
+        ));
+    }
+    if let Some((pos, c)) = id
+        .chars()
+        .enumerate()
+        .find(|(_, c)| is_invalid_char(*c) || c.is_control())
+    {
+        return Err(format!(
+            "Document ID contains unsupported character U+{:04X} at position {}",
+            c as u32, pos
+        ));
+    }
+    Ok(())
+}
+
+/// Shorten text to at most `max_chars` characters, cutting at a word boundary
+///
+/// The cut falls on the last whitespace within the limit so no word is split;
+/// a single word longer than the limit is cut mid-word instead. Trailing
+/// whitespace is trimmed. Returns the preview and whether anything was removed.
+pub fn truncate_at_word_boundary(text: &str, max_chars: usize) -> (&str, bool) {
+    let cut = match text.char_indices().nth(max_chars) {
+        Some((byte_idx, _)) => byte_idx,
+        None => return (text, false),
+    };
+
+    let head = &text[..cut];
+    let at_boundary = text[cut..].starts_with(char::is_whitespace);
+    let end = if at_boundary {
+        cut
+    } else {
+        head.rfind(char::is_whitespace).filter(|&i| i > 0).unwrap_or(cut)
+    };
+
+    (text[..end].trim_end(), true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_document_id() {
+        for id in [
+            "550e8400-e29b-41d4-a716-446655440000",
+            "w2-2024-acme",
+            "2024/receipts/march invoice.pdf",
+            "reçu été №7 数据",
+        ] {
+            assert_eq!(validate_document_id(id), Ok(()), "{}", id);
+        }
+
+        assert!(validate_document_id("  ").unwrap_err().contains("empty"));
+        assert!(validate_document_id(" doc").unwrap_err().contains("whitespace"));
+        assert!(validate_document_id(&"x".repeat(513)).unwrap_err().contains("513 bytes"));
+        assert_eq!(
+            validate_document_id("doc\tone").unwrap_err(),
+            "Document ID contains unsupported character U+0009 at position 3"
+        );
+        assert!(validate_document_id("doc\u{FFFE}").is_err());
+    }
+
+    #[test]
+    fn test_truncate_at_word_boundary() {
+        assert_eq!(truncate_at_word_boundary("short text", 50), ("short text", false));
+        assert_eq!(truncate_at_word_boundary("Annual tax return", 10), ("Annual tax", true));
+        assert_eq!(truncate_at_word_boundary("Annual tax return", 9), ("Annual", true));
+        assert_eq!(truncate_at_word_boundary("Supercalifragilistic", 5), ("Super", true));
+        // Multi-byte characters are counted as characters, never split
+        assert_eq!(truncate_at_word_boundary("Größe über alles", 8), ("Größe", true));
+    }
+
+    #[test]
+    fn test_combining_characters_are_composed() {
+        let decomposed = "Cafe\u{0301} receipts";
+        let normalized = normalize_text("title", decomposed, InvalidTextMode::Reject).unwrap();
+
+        assert_eq!(normalized, "Caf\u{00E9} receipts");
+    }
+
+    #[test]
+    fn test_invalid_characters_by_mode() {
+        let malformed = "Invoice\u{0000} total \u{FFFF}";
+
+        let err = normalize_text("body", malformed, InvalidTextMode::Reject).unwrap_err();
+        assert!(err.contains("body"));
+        assert!(err.contains("U+0000"));
+
+        let replaced = normalize_text("body", malformed, InvalidTextMode::Replace).unwrap();
+        assert_eq!(replaced, "Invoice\u{FFFD} total \u{FFFD}");
+    }
+
+    #[test]
+    fn test_whitespace_controls_are_allowed() {
+        let text = "line one\n\tline two\r\n";
+        assert_eq!(normalize_text("body", text, InvalidTextMode::Reject).unwrap(), text);
+    }
+}
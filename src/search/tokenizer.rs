@@ -0,0 +1,323 @@
+//! Selectable tokenizers for the title and body fields
+//!
+//! The default tokenizer splits at whitespace and punctuation, which leaves a
+//! run of Chinese or Japanese text, written without spaces, as one long token
+//! that only an identical query can match. The CJK bigram tokenizer instead
+//! splits such runs into overlapping pairs of characters, the approach of
+//! Lucene's CJK analyzer: 東京都 becomes 東京 and 京都. Multi-character
+//! queries are split the same way and matched as a phrase of pairs, so a
+//! word is found wherever it occurs. No dictionary is needed, at the cost of
+//! a larger index and the occasional match across a word boundary.
+//!
+//! Either tokenizer can also fold accents, so "José" and "Jose" index the
+//! same term. Folded analyzers are registered under their own names, which
+//! keeps the choice with the index even if the config changes.
+//!
+//! Both drop tokens longer than a limit, such as base64 blobs or long URLs,
+//! which would only bloat the term dictionary. Analyzers with a limit other
+//! than Tantivy's carry it in their name, like `default_max100`, and are
+//! registered from the index's schema.
+
+use serde::{Deserialize, Serialize};
+use tantivy::schema::{FieldType, Schema};
+use tantivy::tokenizer::{
+    AsciiFoldingFilter, LowerCaser, RemoveLongFilter, SimpleTokenizer, TextAnalyzer, Token, TokenStream, Tokenizer,
+    TokenizerManager,
+};
+
+/// Name the CJK bigram analyzer is registered under
+pub const CJK_BIGRAM_ANALYZER: &str = "cjk_bigram";
+
+/// Name of the default analyzer with accent folding
+pub const DEFAULT_FOLDED_ANALYZER: &str = "default_folded";
+
+/// Name of the CJK bigram analyzer with accent folding
+pub const CJK_BIGRAM_FOLDED_ANALYZER: &str = "cjk_bigram_folded";
+
+/// Longest token, in bytes, kept by default; Tantivy's `default` analyzer drops tokens of 40 bytes or more
+pub const DEFAULT_MAX_TOKEN_LENGTH: usize = 39;
+
+/// Largest token length limit an index can be configured with, as Tantivy can't index longer terms
+pub const MAX_TOKEN_LENGTH_LIMIT: usize = tantivy::tokenizer::MAX_TOKEN_LEN;
+
+/// Separates an analyzer's base name from a non-default token length limit
+const MAX_TOKEN_LENGTH_MARKER: &str = "_max";
+
+/// Tokenizer of an index's title and body fields
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TextTokenizer {
+    /// Words split at whitespace and punctuation, lowercased (default)
+    #[default]
+    Default,
+    /// Like `Default`, but runs of CJK characters become overlapping character pairs
+    CjkBigram,
+}
+
+impl TextTokenizer {
+    /// Name of the analyzer the fields are indexed with, folding accents if
+    /// asked and keeping tokens up to `max_token_length` bytes
+    pub fn analyzer_name(&self, fold_accents: bool, max_token_length: usize) -> String {
+        let base = self.base_analyzer_name(fold_accents);
+        if max_token_length == DEFAULT_MAX_TOKEN_LENGTH {
+            base.to_string()
+        } else {
+            format!("{}{}{}", base, MAX_TOKEN_LENGTH_MARKER, max_token_length)
+        }
+    }
+
+    fn base_analyzer_name(&self, fold_accents: bool) -> &'static str {
+        match (self, fold_accents) {
+            (TextTokenizer::Default, false) => "default",
+            (TextTokenizer::Default, true) => DEFAULT_FOLDED_ANALYZER,
+            (TextTokenizer::CjkBigram, false) => CJK_BIGRAM_ANALYZER,
+            (TextTokenizer::CjkBigram, true) => CJK_BIGRAM_FOLDED_ANALYZER,
+        }
+    }
+
+    /// Tokenizer, folding and token length limit encoded in an analyzer name, if it is one of these
+    fn from_analyzer_name(name: &str) -> Option<(TextTokenizer, bool, usize)> {
+        let (base, max_token_length) = match name.rsplit_once(MAX_TOKEN_LENGTH_MARKER) {
+            Some((base, length)) => (base, length.parse().ok()?),
+            None => (name, DEFAULT_MAX_TOKEN_LENGTH),
+        };
+        [TextTokenizer::Default, TextTokenizer::CjkBigram]
+            .into_iter()
+            .flat_map(|tokenizer| [(tokenizer, false), (tokenizer, true)])
+            .find(|(tokenizer, fold_accents)| tokenizer.base_analyzer_name(*fold_accents) == base)
+            .map(|(tokenizer, fold_accents)| (tokenizer, fold_accents, max_token_length))
+    }
+
+    /// Build the analyzer; folding runs after lowercasing, as in Lucene
+    fn analyzer(&self, fold_accents: bool, max_token_length: usize) -> TextAnalyzer {
+        // The filter keeps tokens shorter than its limit
+        let remove_long = RemoveLongFilter::limit(max_token_length + 1);
+        match (self, fold_accents) {
+            (TextTokenizer::Default, false) => TextAnalyzer::builder(SimpleTokenizer::default())
+                .filter(remove_long)
+                .filter(LowerCaser)
+                .build(),
+            (TextTokenizer::Default, true) => TextAnalyzer::builder(SimpleTokenizer::default())
+                .filter(remove_long)
+                .filter(LowerCaser)
+                .filter(AsciiFoldingFilter)
+                .build(),
+            (TextTokenizer::CjkBigram, false) => TextAnalyzer::builder(CjkBigramTokenizer)
+                .filter(remove_long)
+                .filter(LowerCaser)
+                .build(),
+            (TextTokenizer::CjkBigram, true) => TextAnalyzer::builder(CjkBigramTokenizer)
+                .filter(remove_long)
+                .filter(LowerCaser)
+                .filter(AsciiFoldingFilter)
+                .build(),
+        }
+    }
+}
+
+/// Whether a character belongs to a script written without spaces between words
+///
+/// Covers Han ideographs, kana and Hangul. Korean separates words with
+/// spaces, but particles attach to them, so pairs help it too.
+fn is_cjk(c: char) -> bool {
+    matches!(c,
+        '\u{1100}'..='\u{11FF}'     // Hangul Jamo
+        | '\u{3040}'..='\u{30FF}'   // Hiragana, Katakana
+        | '\u{3130}'..='\u{318F}'   // Hangul Compatibility Jamo
+        | '\u{31F0}'..='\u{31FF}'   // Katakana Phonetic Extensions
+        | '\u{3400}'..='\u{4DBF}'   // CJK Unified Ideographs Extension A
+        | '\u{4E00}'..='\u{9FFF}'   // CJK Unified Ideographs
+        | '\u{AC00}'..='\u{D7AF}'   // Hangul Syllables
+        | '\u{F900}'..='\u{FAFF}'   // CJK Compatibility Ideographs
+        | '\u{FF66}'..='\u{FF9F}'   // Halfwidth Katakana
+        | '\u{20000}'..='\u{2FA1F}' // Supplementary ideographs
+    )
+}
+
+/// Splits alphanumeric runs into words and CJK runs into overlapping character pairs
+///
+/// A CJK character standing alone is kept as a single-character token.
+#[derive(Debug, Clone, Default)]
+pub struct CjkBigramTokenizer;
+
+/// Tokens of one text, produced up front
+pub struct CjkBigramTokenStream {
+    tokens: Vec<Token>,
+    current: usize,
+}
+
+impl Tokenizer for CjkBigramTokenizer {
+    type TokenStream<'a> = CjkBigramTokenStream;
+
+    fn token_stream<'a>(&'a mut self, text: &'a str) -> CjkBigramTokenStream {
+        let mut tokens = Vec::new();
+        let mut push = |from: usize, to: usize| {
+            tokens.push(Token {
+                offset_from: from,
+                offset_to: to,
+                position: tokens.len(),
+                text: text[from..to].to_string(),
+                position_length: 1,
+            });
+        };
+
+        let chars: Vec<(usize, char)> = text.char_indices().collect();
+        let end_of = |i: usize| chars.get(i + 1).map_or(text.len(), |(offset, _)| *offset);
+        let mut i = 0;
+        while i < chars.len() {
+            let (start, c) = chars[i];
+            if is_cjk(c) {
+                let mut run_end = i;
+                while run_end + 1 < chars.len() && is_cjk(chars[run_end + 1].1) {
+                    run_end += 1;
+                }
+                if run_end == i {
+                    push(start, end_of(i));
+                } else {
+                    for (pair, &(offset, _)) in chars.iter().enumerate().take(run_end).skip(i) {
+                        push(offset, end_of(pair + 1));
+                    }
+                }
+                i = run_end + 1;
+            } else if c.is_alphanumeric() {
+                let mut word_end = i;
+                while word_end + 1 < chars.len() && chars[word_end + 1].1.is_alphanumeric() && !is_cjk(chars[word_end + 1].1) {
+                    word_end += 1;
+                }
+                push(start, end_of(word_end));
+                i = word_end + 1;
+            } else {
+                i += 1;
+            }
+        }
+
+        CjkBigramTokenStream { tokens, current: 0 }
+    }
+}
+
+impl TokenStream for CjkBigramTokenStream {
+    fn advance(&mut self) -> bool {
+        if self.current < self.tokens.len() {
+            self.current += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn token(&self) -> &Token {
+        &self.tokens[self.current - 1]
+    }
+
+    fn token_mut(&mut self) -> &mut Token {
+        &mut self.tokens[self.current - 1]
+    }
+}
+
+/// Register the analyzers of the selectable tokenizers
+///
+/// Must run before a writer or query parser is created for an index whose
+/// title and body use one. Analyzers with the default token length limit are
+/// always registered; others only as the schema's fields name them.
+pub fn register_analyzers(schema: &Schema, tokenizers: &TokenizerManager) {
+    // `default` itself is Tantivy's own, with the same pipeline
+    tokenizers.register(CJK_BIGRAM_ANALYZER, TextTokenizer::CjkBigram.analyzer(false, DEFAULT_MAX_TOKEN_LENGTH));
+    tokenizers.register(CJK_BIGRAM_FOLDED_ANALYZER, TextTokenizer::CjkBigram.analyzer(true, DEFAULT_MAX_TOKEN_LENGTH));
+    tokenizers.register(DEFAULT_FOLDED_ANALYZER, TextTokenizer::Default.analyzer(true, DEFAULT_MAX_TOKEN_LENGTH));
+
+    for (_, entry) in schema.fields() {
+        let FieldType::Str(options) = entry.field_type() else {
+            continue;
+        };
+        let Some(name) = options.get_indexing_options().map(|indexing| indexing.tokenizer()) else {
+            continue;
+        };
+        if let Some((tokenizer, fold_accents, max_token_length)) = TextTokenizer::from_analyzer_name(name) {
+            if max_token_length != DEFAULT_MAX_TOKEN_LENGTH {
+                tokenizers.register(name, tokenizer.analyzer(fold_accents, max_token_length));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tantivy::schema::{TextFieldIndexing, TextOptions};
+
+    fn tokens(text: &str) -> Vec<String> {
+        let mut stream = CjkBigramTokenizer.token_stream(text);
+        let mut tokens = Vec::new();
+        while stream.advance() {
+            tokens.push(stream.token().text.clone());
+        }
+        tokens
+    }
+
+    #[test]
+    fn test_cjk_bigrams() {
+        assert_eq!(tokens("東京都"), ["東京", "京都"]);
+        assert_eq!(tokens("税金の申告"), ["税金", "金の", "の申", "申告"]);
+        // Other scripts are split into words; a lone CJK character is kept
+        assert_eq!(tokens("Invoice 2024: 東 ACME-Corp"), ["Invoice", "2024", "東", "ACME", "Corp"]);
+        assert_eq!(tokens("W2表格"), ["W2", "表格"]);
+        assert_eq!(tokens("세금 신고서"), ["세금", "신고", "고서"]);
+        assert!(tokens("").is_empty());
+    }
+
+    #[test]
+    fn test_folded_analyzers() {
+        let tokenizers = TokenizerManager::default();
+        register_analyzers(&Schema::builder().build(), &tokenizers);
+        let analyze = |name: &str, text: &str| {
+            let mut analyzer = tokenizers.get(name).unwrap();
+            let mut stream = analyzer.token_stream(text);
+            let mut tokens = Vec::new();
+            while stream.advance() {
+                tokens.push(stream.token().text.clone());
+            }
+            tokens
+        };
+
+        assert_eq!(analyze("default", "José Müller"), ["josé", "müller"]);
+        assert_eq!(analyze(DEFAULT_FOLDED_ANALYZER, "José Müller"), ["jose", "muller"]);
+        assert_eq!(analyze(CJK_BIGRAM_FOLDED_ANALYZER, "Café 東京都"), ["cafe", "東京", "京都"]);
+    }
+
+    #[test]
+    fn test_max_token_length_analyzers() {
+        assert_eq!(TextTokenizer::Default.analyzer_name(false, DEFAULT_MAX_TOKEN_LENGTH), "default");
+        let name = TextTokenizer::CjkBigram.analyzer_name(true, 100);
+        assert_eq!(name, "cjk_bigram_folded_max100");
+        assert_eq!(TextTokenizer::from_analyzer_name(&name), Some((TextTokenizer::CjkBigram, true, 100)));
+        assert_eq!(TextTokenizer::from_analyzer_name("en_stem"), None);
+
+        let mut builder = Schema::builder();
+        builder.add_text_field(
+            "body",
+            TextOptions::default().set_indexing_options(TextFieldIndexing::default().set_tokenizer("default_max5")),
+        );
+        let tokenizers = TokenizerManager::default();
+        register_analyzers(&builder.build(), &tokenizers);
+
+        // Tokens up to the limit are kept, whatever their case
+        let mut analyzer = tokenizers.get("default_max5").unwrap();
+        let mut stream = analyzer.token_stream("Tax RETURN forms");
+        let mut tokens = Vec::new();
+        while stream.advance() {
+            tokens.push(stream.token().text.clone());
+        }
+        assert_eq!(tokens, ["tax", "forms"]);
+    }
+
+    #[test]
+    fn test_cjk_offsets_and_positions() {
+        let mut stream = CjkBigramTokenizer.token_stream("a 東京都");
+        let mut seen = Vec::new();
+        while stream.advance() {
+            let token = stream.token();
+            seen.push((token.position, token.offset_from, token.offset_to));
+        }
+        assert_eq!(seen, [(0, 0, 1), (1, 2, 8), (2, 5, 11)]);
+    }
+}
@@ -0,0 +1,347 @@
+//! Per-user index configuration
+//!
+//! Operators can place a `config.json` next to a user's index
+//! (`{DATA_DIR}/{user_id}/config.json`) to tailor that tenant's index. A
+//! missing file means all defaults.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+
+use super::models::DuplicateContentMode;
+use super::ngram::NgramSize;
+use super::query::{is_field_name_char, QUERYABLE_FIELDS};
+use super::schema::SchemaOptions;
+use super::tokenizer::{TextTokenizer, DEFAULT_MAX_TOKEN_LENGTH, MAX_TOKEN_LENGTH_LIMIT};
+
+/// File name of the per-user configuration inside the user's directory
+pub const USER_CONFIG_FILE: &str = "config.json";
+
+/// Most warmup queries one user's config may list; each one delays opening the index
+pub const MAX_WARMUP_QUERIES: usize = 10;
+
+/// Most field aliases one user's config may define; each one is checked on every query
+pub const MAX_FIELD_ALIASES: usize = 50;
+
+/// Longest field alias name, in bytes
+pub const MAX_FIELD_ALIAS_LENGTH: usize = 64;
+
+/// Optional capability that can be switched off per tenant, e.g. for plan tiers
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Feature {
+    /// `POST /v1/documents/bulk` and `POST /v1/documents/bulk-delete`
+    Bulk,
+    /// `POST /v1/browse`
+    Browse,
+    /// `GET /v1/analytics`
+    Analytics,
+    /// `GET /v1/tags/facets`
+    Facets,
+}
+
+impl Feature {
+    /// Name used in `config.json` and error messages
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Feature::Bulk => "bulk",
+            Feature::Browse => "browse",
+            Feature::Analytics => "analytics",
+            Feature::Facets => "facets",
+        }
+    }
+}
+
+/// Settings for a single user's index
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct UserConfig {
+    /// Store document bodies so results can return them; when false the body
+    /// is indexed for search only. Applied when the index is created.
+    pub store_body: bool,
+
+    /// Detect each document's language and stem its body accordingly.
+    /// Applied when the index is created.
+    pub detect_language: bool,
+
+    /// Also index titles and bodies as n-grams of these sizes so searches with
+    /// `match_mode: substring` can match inside words. Applied when the index is created.
+    pub ngram: Option<NgramSize>,
+
+    /// Tokenizer of titles and bodies; `cjk_bigram` splits Chinese, Japanese
+    /// and Korean text into searchable units. Applied when the index is created.
+    pub tokenizer: TextTokenizer,
+
+    /// Fold accents in titles, bodies and queries so "José" matches "Jose".
+    /// Applied when the index is created.
+    pub fold_accents: bool,
+
+    /// Longest title and body token kept, in bytes; longer ones such as
+    /// base64 blobs are dropped. Applied when the index is created.
+    pub max_token_length: usize,
+
+    /// Store only the first this many bytes of each body, while still
+    /// indexing all of it. Whether bodies are capped is applied when the index
+    /// is created; the size is read when the index is opened.
+    pub stored_body_max_bytes: Option<usize>,
+
+    /// Features this tenant may not use; everything is enabled by default.
+    /// Read when the index is opened.
+    pub disabled_features: BTreeSet<Feature>,
+
+    /// Alternative field names for `field:value` queries, e.g. `content` for
+    /// `body`, mapped to queryable fields. Read when the index is opened.
+    pub field_aliases: BTreeMap<String, String>,
+
+    /// Documents this tenant may index per minute, overriding
+    /// `MAX_INDEX_RATE_PER_MINUTE` (0 = unlimited). Read when the index is opened.
+    pub max_index_rate_per_minute: Option<u32>,
+
+    /// Source given to documents indexed without one, overriding
+    /// `DEFAULT_SOURCE`. Read when the index is opened.
+    pub default_source: Option<String>,
+
+    /// How writes duplicating another document's content are handled,
+    /// overriding `DUPLICATE_CONTENT`. Read when the index is opened.
+    pub duplicate_content: Option<DuplicateContentMode>,
+
+    /// Searches run when the index is opened, e.g. a dashboard's default
+    /// query, so the postings they read are cached before the first request.
+    /// Read when the index is opened.
+    pub warmup_queries: Vec<String>,
+}
+
+impl Default for UserConfig {
+    fn default() -> Self {
+        UserConfig {
+            store_body: true,
+            detect_language: false,
+            ngram: None,
+            tokenizer: TextTokenizer::Default,
+            fold_accents: false,
+            max_token_length: DEFAULT_MAX_TOKEN_LENGTH,
+            stored_body_max_bytes: None,
+            disabled_features: BTreeSet::new(),
+            field_aliases: BTreeMap::new(),
+            max_index_rate_per_minute: None,
+            default_source: None,
+            duplicate_content: None,
+            warmup_queries: Vec::new(),
+        }
+    }
+}
+
+impl UserConfig {
+    /// Load the configuration from a user's directory, falling back to defaults
+    pub fn load(user_dir: &Path) -> Result<Self> {
+        let path = user_dir.join(USER_CONFIG_FILE);
+        match std::fs::read(&path) {
+            Ok(bytes) => {
+                let config: UserConfig = serde_json::from_slice(&bytes)
+                    .with_context(|| format!("Invalid user config {:?}", path))?;
+                if let Some(ngram) = config.ngram {
+                    ngram
+                        .validate()
+                        .map_err(|e| anyhow::anyhow!("Invalid user config {:?}: {}", path, e))?;
+                }
+                if config.max_token_length == 0 || config.max_token_length > MAX_TOKEN_LENGTH_LIMIT {
+                    anyhow::bail!(
+                        "Invalid user config {:?}: max_token_length must be between 1 and {}",
+                        path,
+                        MAX_TOKEN_LENGTH_LIMIT
+                    );
+                }
+                if config.stored_body_max_bytes == Some(0) {
+                    anyhow::bail!("Invalid user config {:?}: stored_body_max_bytes must be at least 1", path);
+                }
+                if config.stored_body_max_bytes.is_some() && !config.store_body {
+                    anyhow::bail!(
+                        "Invalid user config {:?}: stored_body_max_bytes requires store_body",
+                        path
+                    );
+                }
+                if config.field_aliases.len() > MAX_FIELD_ALIASES {
+                    anyhow::bail!(
+                        "Invalid user config {:?}: field_aliases cannot define more than {} aliases",
+                        path,
+                        MAX_FIELD_ALIASES
+                    );
+                }
+                for (alias, field) in &config.field_aliases {
+                    validate_field_alias(alias, field)
+                        .map_err(|e| anyhow::anyhow!("Invalid user config {:?}: {}", path, e))?;
+                }
+                if config.default_source.as_deref().is_some_and(|source| source.trim().is_empty()) {
+                    anyhow::bail!("Invalid user config {:?}: default_source must not be empty", path);
+                }
+                if config.warmup_queries.len() > MAX_WARMUP_QUERIES {
+                    anyhow::bail!(
+                        "Invalid user config {:?}: warmup_queries cannot list more than {} queries",
+                        path,
+                        MAX_WARMUP_QUERIES
+                    );
+                }
+                if config.warmup_queries.iter().any(|query| query.trim().is_empty()) {
+                    anyhow::bail!("Invalid user config {:?}: warmup_queries must not be empty", path);
+                }
+                Ok(config)
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(UserConfig::default()),
+            Err(e) => Err(e).with_context(|| format!("Failed to read user config {:?}", path)),
+        }
+    }
+
+    /// Schema options used when creating this user's index
+    pub fn schema_options(&self) -> SchemaOptions {
+        SchemaOptions {
+            store_body: self.store_body,
+            detect_language: self.detect_language,
+            ngram: self.ngram,
+            tokenizer: self.tokenizer,
+            fold_accents: self.fold_accents,
+            max_token_length: self.max_token_length,
+            cap_stored_body: self.stored_body_max_bytes.is_some(),
+        }
+    }
+}
+
+/// Check an alias is a plain name that doesn't shadow a field and points to a queryable one
+fn validate_field_alias(alias: &str, field: &str) -> Result<(), String> {
+    if alias.is_empty() || !alias.chars().all(is_field_name_char) {
+        return Err(format!(
+            "field alias '{}' must consist of ASCII letters, digits and underscores",
+            alias
+        ));
+    }
+    if alias.len() > MAX_FIELD_ALIAS_LENGTH {
+        return Err(format!(
+            "field alias '{}' is longer than {} bytes",
+            alias, MAX_FIELD_ALIAS_LENGTH
+        ));
+    }
+    if QUERYABLE_FIELDS.contains(&alias) {
+        return Err(format!("field alias '{}' would shadow the field of that name", alias));
+    }
+    if !QUERYABLE_FIELDS.contains(&field) {
+        return Err(format!(
+            "field alias '{}' points to '{}', which is not one of: {}",
+            alias,
+            field,
+            QUERYABLE_FIELDS.join(", ")
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_user_config() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        assert_eq!(UserConfig::load(temp_dir.path()).unwrap(), UserConfig::default());
+
+        std::fs::write(temp_dir.path().join(USER_CONFIG_FILE), r#"{"store_body": false}"#).unwrap();
+        assert!(!UserConfig::load(temp_dir.path()).unwrap().store_body);
+
+        std::fs::write(temp_dir.path().join(USER_CONFIG_FILE), r#"{"disabled_features": ["bulk", "facets"]}"#).unwrap();
+        let config = UserConfig::load(temp_dir.path()).unwrap();
+        assert!(config.disabled_features.contains(&Feature::Bulk));
+        assert!(config.disabled_features.contains(&Feature::Facets));
+        assert!(!config.disabled_features.contains(&Feature::Browse));
+
+        std::fs::write(temp_dir.path().join(USER_CONFIG_FILE), r#"{"max_index_rate_per_minute": 600}"#).unwrap();
+        assert_eq!(UserConfig::load(temp_dir.path()).unwrap().max_index_rate_per_minute, Some(600));
+
+        std::fs::write(temp_dir.path().join(USER_CONFIG_FILE), r#"{"default_source": "erp"}"#).unwrap();
+        assert_eq!(UserConfig::load(temp_dir.path()).unwrap().default_source.as_deref(), Some("erp"));
+
+        std::fs::write(temp_dir.path().join(USER_CONFIG_FILE), r#"{"duplicate_content": "alias"}"#).unwrap();
+        assert_eq!(UserConfig::load(temp_dir.path()).unwrap().duplicate_content, Some(DuplicateContentMode::Alias));
+
+        std::fs::write(temp_dir.path().join(USER_CONFIG_FILE), r#"{"default_source": " "}"#).unwrap();
+        assert!(UserConfig::load(temp_dir.path()).is_err());
+
+        std::fs::write(temp_dir.path().join(USER_CONFIG_FILE), r#"{"warmup_queries": ["refund 2023"]}"#).unwrap();
+        assert_eq!(UserConfig::load(temp_dir.path()).unwrap().warmup_queries, ["refund 2023"]);
+
+        let too_many = format!(r#"{{"warmup_queries": {:?}}}"#, vec!["refund"; MAX_WARMUP_QUERIES + 1]);
+        for config in [r#"{"warmup_queries": [" "]}"#, too_many.as_str()] {
+            std::fs::write(temp_dir.path().join(USER_CONFIG_FILE), config).unwrap();
+            assert!(UserConfig::load(temp_dir.path()).is_err(), "{}", config);
+        }
+
+        std::fs::write(temp_dir.path().join(USER_CONFIG_FILE), r#"{"ngram": {"min_gram": 3, "max_gram": 5}}"#).unwrap();
+        let ngram = UserConfig::load(temp_dir.path()).unwrap().ngram.unwrap();
+        assert_eq!((ngram.min_gram, ngram.max_gram), (3, 5));
+
+        std::fs::write(temp_dir.path().join(USER_CONFIG_FILE), r#"{"ngram": {"min_gram": 1, "max_gram": 5}}"#).unwrap();
+        assert!(UserConfig::load(temp_dir.path()).is_err());
+
+        std::fs::write(temp_dir.path().join(USER_CONFIG_FILE), r#"{"max_token_length": 100}"#).unwrap();
+        assert_eq!(UserConfig::load(temp_dir.path()).unwrap().schema_options().max_token_length, 100);
+
+        std::fs::write(temp_dir.path().join(USER_CONFIG_FILE), r#"{"max_token_length": 0}"#).unwrap();
+        assert!(UserConfig::load(temp_dir.path()).is_err());
+
+        std::fs::write(temp_dir.path().join(USER_CONFIG_FILE), r#"{"stored_body_max_bytes": 4096}"#).unwrap();
+        assert!(UserConfig::load(temp_dir.path()).unwrap().schema_options().cap_stored_body);
+
+        for config in [r#"{"stored_body_max_bytes": 0}"#, r#"{"store_body": false, "stored_body_max_bytes": 4096}"#] {
+            std::fs::write(temp_dir.path().join(USER_CONFIG_FILE), config).unwrap();
+            assert!(UserConfig::load(temp_dir.path()).is_err(), "{}", config);
+        }
+
+        std::fs::write(temp_dir.path().join(USER_CONFIG_FILE), r#"{"field_aliases": {"content": "body"}}"#).unwrap();
+        assert_eq!(UserConfig::load(temp_dir.path()).unwrap().field_aliases["content"], "body");
+
+        for aliases in [r#"{"content": "checksum"}"#, r#"{"title": "body"}"#, r#"{"my-field": "body"}"#] {
+            std::fs::write(temp_dir.path().join(USER_CONFIG_FILE), format!(r#"{{"field_aliases": {}}}"#, aliases)).unwrap();
+            assert!(UserConfig::load(temp_dir.path()).is_err(), "{}", aliases);
+        }
+
+        std::fs::write(temp_dir.path().join(USER_CONFIG_FILE), r#"{"disabled_features": ["export"]}"#).unwrap();
+        assert!(UserConfig::load(temp_dir.path()).is_err());
+
+        std::fs::write(temp_dir.path().join(USER_CONFIG_FILE), r#"{"store_bodies": false}"#).unwrap();
+        assert!(UserConfig::load(temp_dir.path()).is_err());
+    }
+
+    #[test]
+    fn test_field_definitions_rejected() {
+        // The schema is fixed; a config trying to add fields fails instead of growing it
+        let temp_dir = tempfile::tempdir().unwrap();
+        let fields: Vec<String> = (0..500).map(|i| format!(r#"{{"name": "field_{}", "type": "text"}}"#, i)).collect();
+        let config = format!(r#"{{"store_body": true, "fields": [{}]}}"#, fields.join(","));
+        std::fs::write(temp_dir.path().join(USER_CONFIG_FILE), config).unwrap();
+
+        let err = UserConfig::load(temp_dir.path()).unwrap_err();
+        assert!(format!("{:#}", err).contains("unknown field `fields`"), "{:#}", err);
+    }
+
+    #[test]
+    fn test_field_alias_limits() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let write_aliases = |names: Vec<String>| {
+            let aliases: Vec<String> = names.iter().map(|name| format!(r#""{}": "body""#, name)).collect();
+            let config = format!(r#"{{"field_aliases": {{{}}}}}"#, aliases.join(","));
+            std::fs::write(temp_dir.path().join(USER_CONFIG_FILE), config).unwrap();
+        };
+
+        write_aliases((0..MAX_FIELD_ALIASES).map(|i| format!("alias_{}", i)).collect());
+        assert_eq!(UserConfig::load(temp_dir.path()).unwrap().field_aliases.len(), MAX_FIELD_ALIASES);
+
+        write_aliases((0..=MAX_FIELD_ALIASES).map(|i| format!("alias_{}", i)).collect());
+        let err = UserConfig::load(temp_dir.path()).unwrap_err();
+        assert!(err.to_string().contains("more than 50 aliases"), "{}", err);
+
+        write_aliases(vec!["a".repeat(MAX_FIELD_ALIAS_LENGTH)]);
+        assert!(UserConfig::load(temp_dir.path()).is_ok());
+
+        write_aliases(vec!["a".repeat(MAX_FIELD_ALIAS_LENGTH + 1)]);
+        let err = UserConfig::load(temp_dir.path()).unwrap_err();
+        assert!(err.to_string().contains("longer than 64 bytes"), "{}", err);
+    }
+}
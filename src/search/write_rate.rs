@@ -0,0 +1,99 @@
+//! Per-user limit on the sustained document-indexing rate
+//!
+//! Each user has a token bucket holding up to one minute's allowance of
+//! documents, refilled continuously. A write takes one token per document
+//! and is admitted only when all of them are available, so a bulk request is
+//! never applied in part. A user who has been idle can burst up to a full
+//! minute's allowance at once.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Instant;
+
+use uuid::Uuid;
+
+/// Buckets kept before refilled ones are dropped
+const PRUNE_THRESHOLD: usize = 1024;
+
+/// Token buckets of the users who indexed recently
+#[derive(Default)]
+pub struct WriteRateLimiter {
+    buckets: Mutex<HashMap<Uuid, Bucket>>,
+}
+
+struct Bucket {
+    tokens: f64,
+    per_minute: u32,
+    updated: Instant,
+}
+
+impl Bucket {
+    fn refill(&mut self, per_minute: u32, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * f64::from(per_minute) / 60.0).min(f64::from(per_minute));
+        self.per_minute = per_minute;
+        self.updated = now;
+    }
+}
+
+impl WriteRateLimiter {
+    /// Take `documents` tokens from the user's bucket at `per_minute` documents a minute
+    ///
+    /// Returns the seconds until enough tokens have accumulated when they
+    /// aren't available now; nothing is taken in that case. `documents` must
+    /// not exceed `per_minute`, or it could never be admitted.
+    pub fn acquire(&self, user_id: Uuid, documents: u32, per_minute: u32, now: Instant) -> Result<(), u64> {
+        let mut buckets = self.buckets.lock().unwrap();
+        if buckets.len() >= PRUNE_THRESHOLD {
+            buckets.retain(|_, bucket| {
+                bucket.refill(bucket.per_minute, now);
+                bucket.tokens < f64::from(bucket.per_minute)
+            });
+        }
+
+        let bucket = buckets.entry(user_id).or_insert(Bucket {
+            tokens: f64::from(per_minute),
+            per_minute,
+            updated: now,
+        });
+        bucket.refill(per_minute, now);
+
+        let missing = f64::from(documents) - bucket.tokens;
+        if missing > 0.0 {
+            let secs = missing * 60.0 / f64::from(per_minute);
+            return Err(secs.ceil().max(1.0) as u64);
+        }
+        bucket.tokens -= f64::from(documents);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_write_rate_bucket() {
+        let limiter = WriteRateLimiter::default();
+        let user_id = Uuid::new_v4();
+        let now = Instant::now();
+
+        // A full minute's allowance is available at once
+        assert_eq!(limiter.acquire(user_id, 50, 60, now), Ok(()));
+        assert_eq!(limiter.acquire(user_id, 10, 60, now), Ok(()));
+        assert_eq!(limiter.acquire(user_id, 1, 60, now), Err(1));
+
+        // A batch waits for all of its tokens and takes none meanwhile
+        assert_eq!(limiter.acquire(user_id, 30, 60, now + Duration::from_secs(10)), Err(20));
+        assert_eq!(limiter.acquire(user_id, 30, 60, now + Duration::from_secs(30)), Ok(()));
+
+        // Other users have their own bucket
+        assert_eq!(limiter.acquire(Uuid::new_v4(), 60, 60, now), Ok(()));
+
+        // Idle time refills no further than the allowance
+        let later = now + Duration::from_secs(3600);
+        assert_eq!(limiter.acquire(user_id, 60, 60, later), Ok(()));
+        assert!(limiter.acquire(user_id, 1, 60, later).is_err());
+    }
+}
@@ -0,0 +1,85 @@
+//! Startup self-test
+//!
+//! Exercises the full indexing and search path against a throwaway index so
+//! schema or tokenizer misconfigurations surface at deploy time rather than
+//! on the first user request.
+
+use anyhow::{bail, Context, Result};
+use std::path::PathBuf;
+use uuid::Uuid;
+
+use crate::config::Config;
+use crate::search::{DocumentMetadata, IndexDocumentInput, IndexManager, SearchQuery};
+
+/// Index a document into a temporary index and verify it can be found again
+///
+/// The temporary directory lives under the system temp dir and is removed
+/// whether or not the round trip succeeds.
+pub async fn run(config: &Config) -> Result<()> {
+    let temp_dir = std::env::temp_dir().join(format!("tax2go-selftest-{}", Uuid::new_v4()));
+    let result = round_trip(config, temp_dir.clone()).await;
+    let _ = std::fs::remove_dir_all(&temp_dir);
+    result
+}
+
+async fn round_trip(config: &Config, data_dir: PathBuf) -> Result<()> {
+    std::fs::create_dir_all(&data_dir)
+        .with_context(|| format!("Failed to create self-test directory: {:?}", data_dir))?;
+
+    let manager = IndexManager::from_config(&Config {
+        data_dir,
+        ..config.clone()
+    });
+    let user_id = Uuid::new_v4();
+    let doc_id = Uuid::new_v4().to_string();
+
+    manager
+        .index_document(
+            user_id,
+            IndexDocumentInput {
+                id: Some(doc_id.clone()),
+                external_id: None,
+                title: "Self-test receipt".to_string(),
+                body: "Selftest deduction for quarterly estimated payments".to_string(),
+                metadata: DocumentMetadata {
+                    tags: vec!["selftest".to_string()],
+                    ..Default::default()
+                },
+                boost: None,
+                index_body: true,
+            },
+        )
+        .await
+        .context("Self-test failed to index a document")?;
+
+    let response = manager
+        .search(
+            user_id,
+            SearchQuery {
+                query: "quarterly deduction".to_string(),
+                ..Default::default()
+            },
+        )
+        .await
+        .context("Self-test failed to search the index")?;
+
+    match response.results.first() {
+        Some(hit) if hit.id == doc_id => Ok(()),
+        Some(hit) => bail!(
+            "Self-test search returned unexpected document '{}' (expected '{}')",
+            hit.id,
+            doc_id
+        ),
+        None => bail!("Self-test search returned no results for the indexed document"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_selftest_round_trip() {
+        run(&Config::default()).await.unwrap();
+    }
+}
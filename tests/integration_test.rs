@@ -3,20 +3,42 @@ use axum::{
     http::{Request, StatusCode},
 };
 use serde_json::{json, Value};
+use std::collections::HashMap;
 use std::sync::Arc;
 use tempfile::TempDir;
 use tower::ServiceExt;
 use uuid::Uuid;
 
 // Import from the main crate
+use tax2go_search::config::{Config, UnknownFieldsMode};
+use tax2go_search::http::server::{self, ServerOptions};
 use tax2go_search::http::{build_router, routes::AppState};
-use tax2go_search::search::IndexManager;
+use tax2go_search::query_log::{QueryLog, QueryTextMode};
+use tax2go_search::search::schema::{CreatedAtMode, EmptyTagsMode};
+use tax2go_search::search::{
+    DuplicateContentMode, DuplicateIdMode, IndexManager, LimitOverflowMode, SearchBodyMode,
+};
 
 /// Helper to create a test app with a temporary data directory
 fn create_test_app() -> (axum::Router, TempDir) {
+    create_test_app_with_config(Config::default())
+}
+
+/// Helper to create a test app with custom configuration
+///
+/// The data directory is always replaced with a fresh temporary directory.
+fn create_test_app_with_config(config: Config) -> (axum::Router, TempDir) {
     let temp_dir = TempDir::new().unwrap();
-    let index_manager = Arc::new(IndexManager::new(temp_dir.path().to_path_buf()));
-    let state = AppState { index_manager };
+    let config = Config {
+        data_dir: temp_dir.path().to_path_buf(),
+        ..config
+    };
+    let index_manager = Arc::new(IndexManager::from_config(&config));
+    let state = AppState {
+        index_manager,
+        config: Arc::new(config),
+        query_log: None,
+    };
     let app = build_router(state, false);
     (app, temp_dir)
 }
@@ -60,6 +82,18 @@ async fn request_json(
     (status, json)
 }
 
+/// Next Server-Sent Event of a streaming response body
+async fn next_event(events: &mut axum::body::BodyDataStream) -> String {
+    use futures_util::StreamExt;
+
+    let frame = tokio::time::timeout(std::time::Duration::from_secs(5), events.next())
+        .await
+        .expect("no event within 5s")
+        .unwrap()
+        .unwrap();
+    String::from_utf8(frame.to_vec()).unwrap()
+}
+
 #[tokio::test]
 async fn test_health_check() {
     let (app, _temp_dir) = create_test_app();
@@ -71,6 +105,46 @@ async fn test_health_check() {
     assert!(body["version"].is_string());
 }
 
+#[tokio::test]
+async fn test_health_check_auth() {
+    let (app, _temp_dir) = create_test_app_with_config(Config {
+        health_auth_token: Some("h3alth".to_string()),
+        ..Config::default()
+    });
+
+    let (status, body) = request_json(app.clone(), "GET", "/health", None, None).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    assert_eq!(body["error"], "missing_auth");
+    assert!(body.get("version").is_none());
+
+    let health = |authorization: &str| {
+        let request = Request::builder()
+            .uri("/health")
+            .header("Authorization", authorization)
+            .body(Body::empty())
+            .unwrap();
+        let app = app.clone();
+        async move {
+            let response = app.oneshot(request).await.unwrap();
+            let status = response.status();
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+            (status, serde_json::from_slice::<Value>(&body).unwrap())
+        }
+    };
+
+    let (status, body) = health("Bearer wrong").await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    assert_eq!(body["error"], "invalid_auth");
+    assert!(body.get("version").is_none());
+
+    for authorization in ["Bearer h3alth", "h3alth"] {
+        let (status, body) = health(authorization).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["status"], "ok");
+        assert!(body["version"].is_string());
+    }
+}
+
 #[tokio::test]
 async fn test_index_and_search_document() {
     let (app, _temp_dir) = create_test_app();
@@ -323,6 +397,25 @@ async fn test_validation_errors() {
     assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
     assert_eq!(response["error"], "validation_error");
 
+    // Non-positive boost
+    let invalid_doc = json!({
+        "title": "Title",
+        "body": "Some content",
+        "boost": 0
+    });
+
+    let (status, response) = request_json(
+        app.clone(),
+        "PUT",
+        "/v1/documents",
+        Some(user_id),
+        Some(invalid_doc),
+    )
+    .await;
+
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(response["error"], "validation_error");
+
     // Empty query
     let invalid_search = json!({
         "query": "",
@@ -436,3 +529,1912 @@ async fn test_document_update() {
     assert_eq!(response["total"], 1);
     assert_eq!(response["results"][0]["title"], "Version 2");
 }
+
+#[tokio::test]
+async fn test_invalid_text_handling() {
+    // Lone surrogates are rejected by JSON decoding regardless of mode
+    let (app, _temp_dir) = create_test_app();
+    let user_id = Uuid::new_v4();
+
+    let request = Request::builder()
+        .method("PUT")
+        .uri("/v1/documents")
+        .header("X-User-Id", user_id.to_string())
+        .header("Content-Type", "application/json")
+        .body(Body::from(r#"{"title":"Bad \ud800 title","body":"text"}"#))
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert!(response.status().is_client_error());
+
+    // Control characters are rejected by default
+    let doc = json!({ "title": "Receipt", "body": "Total\u{0000}due" });
+    let (status, response) =
+        request_json(app, "PUT", "/v1/documents", Some(user_id), Some(doc.clone())).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert!(response["message"].as_str().unwrap().contains("U+0000"));
+
+    // ...and replaced when lossy handling is configured
+    let (app, _temp_dir) = create_test_app_with_config(Config {
+        invalid_text_mode: tax2go_search::search::text::InvalidTextMode::Replace,
+        ..Config::default()
+    });
+    let (status, _) =
+        request_json(app, "PUT", "/v1/documents", Some(user_id), Some(doc)).await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_bulk_delete_documents() {
+    let (app, _temp_dir) = create_test_app();
+    let user_id = Uuid::new_v4();
+
+    for id in ["keep", "remove-1", "remove-2"] {
+        let doc = json!({ "id": id, "title": format!("Doc {}", id), "body": "Receipt" });
+        let (status, _) =
+            request_json(app.clone(), "PUT", "/v1/documents", Some(user_id), Some(doc)).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    let (status, response) = request_json(
+        app.clone(),
+        "POST",
+        "/v1/documents/bulk-delete",
+        Some(user_id),
+        Some(json!({ "ids": ["remove-1", "missing", "remove-2"] })),
+    )
+    .await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(response["deleted"], 2);
+    assert_eq!(response["not_found"], 1);
+    assert_eq!(response["results"][0]["status"], "deleted");
+    assert_eq!(response["results"][1]["id"], "missing");
+    assert_eq!(response["results"][1]["status"], "not_found");
+    assert_eq!(response["results"][2]["status"], "deleted");
+
+    let (_, stats) = request_json(app, "GET", "/v1/stats", Some(user_id), None).await;
+    assert_eq!(stats["num_documents"], 1);
+}
+
+#[tokio::test]
+async fn test_bulk_delete_repeated_ids() {
+    let (app, _temp_dir) = create_test_app();
+    let user_id = Uuid::new_v4();
+
+    let doc = json!({ "id": "a", "title": "Receipt", "body": "Paid in full" });
+    let (status, _) = request_json(app.clone(), "PUT", "/v1/documents", Some(user_id), Some(doc)).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, response) = request_json(
+        app.clone(),
+        "POST",
+        "/v1/documents/bulk-delete",
+        Some(user_id),
+        Some(json!({ "ids": ["a", "missing", "a", "missing"] })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(response["deleted"], 1);
+    assert_eq!(response["not_found"], 1);
+    let ids: Vec<&str> = response["results"].as_array().unwrap().iter().map(|r| r["id"].as_str().unwrap()).collect();
+    assert_eq!(ids, ["a", "missing"]);
+
+    // The commit counts the document once
+    let (_, json) = request_json(app, "GET", "/v1/index/history?limit=1", Some(user_id), None).await;
+    assert_eq!(json["commits"][0]["operations"], 1);
+}
+
+#[tokio::test]
+async fn test_open_failure_hides_paths() {
+    let (app, temp_dir) = create_test_app();
+    let user_id = Uuid::new_v4();
+
+    // A file where the index directory should be makes every open fail
+    let user_dir = temp_dir.path().join(user_id.to_string());
+    std::fs::create_dir_all(&user_dir).unwrap();
+    std::fs::write(user_dir.join("index"), b"not a directory").unwrap();
+
+    let (status, _) = request_json(app.clone(), "GET", "/v1/stats", Some(user_id), None).await;
+    assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+
+    let (status, json) = request_json(app, "GET", "/v1/stats", Some(user_id), None).await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(json["error"], "index_unavailable");
+    let message = json["message"].as_str().unwrap();
+    assert!(!message.contains(&*temp_dir.path().to_string_lossy()), "{}", message);
+}
+
+#[tokio::test]
+async fn test_bulk_delete_respects_cap() {
+    let (app, _temp_dir) = create_test_app_with_config(Config {
+        bulk_delete_max_ids: 2,
+        ..Config::default()
+    });
+    let user_id = Uuid::new_v4();
+
+    let (status, response) = request_json(
+        app,
+        "POST",
+        "/v1/documents/bulk-delete",
+        Some(user_id),
+        Some(json!({ "ids": ["a", "b", "c"] })),
+    )
+    .await;
+
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(response["error"], "validation_error");
+}
+
+#[tokio::test]
+async fn test_bulk_delete_retry_within_window() {
+    let (app, _temp_dir) = create_test_app();
+    let user_id = Uuid::new_v4();
+
+    let doc = json!({ "id": "receipt", "title": "Receipt", "body": "Coffee" });
+    let (status, _) = request_json(app.clone(), "PUT", "/v1/documents", Some(user_id), Some(doc)).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let ids = json!({ "ids": ["receipt", "missing"] });
+    let (status, first) =
+        request_json(app.clone(), "POST", "/v1/documents/bulk-delete", Some(user_id), Some(ids.clone())).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(first["deleted"], 1);
+
+    // The retry gets the original outcome, not a not_found for the document it deleted
+    let (status, retry) =
+        request_json(app.clone(), "POST", "/v1/documents/bulk-delete", Some(user_id), Some(ids)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(retry["results"], first["results"]);
+    assert_eq!(retry["deleted"], 1);
+    assert_eq!(retry["not_found"], 1);
+
+    // Other users' deletes are remembered separately
+    let (_, other) = request_json(
+        app,
+        "POST",
+        "/v1/documents/bulk-delete",
+        Some(Uuid::new_v4()),
+        Some(json!({ "ids": ["receipt"] })),
+    )
+    .await;
+    assert_eq!(other["results"][0]["status"], "not_found");
+}
+
+#[tokio::test]
+async fn test_unknown_query_field() {
+    let (app, _temp_dir) = create_test_app();
+    let user_id = Uuid::new_v4();
+
+    let (status, response) = request_json(
+        app,
+        "POST",
+        "/v1/search",
+        Some(user_id),
+        Some(json!({ "query": "nonexistent:foo" })),
+    )
+    .await;
+
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(response["error"], "validation_error");
+    let message = response["message"].as_str().unwrap();
+    assert!(message.contains("nonexistent"));
+    assert!(message.contains("title, body, tags, source, id"));
+}
+
+#[tokio::test]
+async fn test_admin_repair_requires_token() {
+    let user_id = Uuid::new_v4();
+    let uri = format!("/v1/admin/repair/{}", user_id);
+
+    // Admin API disabled by default
+    let (app, _temp_dir) = create_test_app();
+    let (status, _) = request_json(app, "POST", &uri, None, None).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    let (app, _temp_dir) = create_test_app_with_config(Config {
+        admin_token: Some("s3cret".to_string()),
+        ..Config::default()
+    });
+
+    let (status, _) = request_json(app.clone(), "POST", &uri, None, None).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    let request = Request::builder()
+        .method("POST")
+        .uri(&uri)
+        .header("Authorization", "Bearer s3cret")
+        .body(Body::empty())
+        .unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let report: Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(report["status"], "missing");
+}
+
+#[tokio::test]
+async fn test_admin_pin_and_unpin_index() {
+    let (app, _temp_dir) = create_test_app_with_config(Config {
+        admin_token: Some("s3cret".to_string()),
+        ..Config::default()
+    });
+    let user_id = Uuid::new_v4();
+    let uri = format!("/v1/admin/pins/{}", user_id);
+
+    let admin = |method: &str, uri: &str| {
+        Request::builder()
+            .method(method)
+            .uri(uri)
+            .header("Authorization", "Bearer s3cret")
+            .body(Body::empty())
+            .unwrap()
+    };
+    let send = |request: Request<Body>| {
+        let app = app.clone();
+        async move {
+            let response = app.oneshot(request).await.unwrap();
+            let status = response.status();
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+            (status, serde_json::from_slice::<Value>(&body).unwrap())
+        }
+    };
+
+    let (status, json) = send(admin("PUT", &uri)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(json["pinned"], true);
+    assert_eq!(json["changed"], true);
+
+    let (_, json) = send(admin("GET", "/v1/admin/pins")).await;
+    assert_eq!(json["pinned"], json!([user_id.to_string()]));
+
+    let (status, json) = send(admin("DELETE", &uri)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(json["pinned"], false);
+    assert_eq!(json["changed"], true);
+
+    let (_, json) = send(admin("GET", "/v1/admin/pins")).await;
+    assert_eq!(json["pinned"], json!([]));
+
+    // Pins are admin-only
+    let (status, _) = request_json(app.clone(), "PUT", &uri, Some(user_id), None).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn test_admin_stats_for_many_users() {
+    let (app, temp_dir) = create_test_app_with_config(Config {
+        admin_token: Some("s3cret".to_string()),
+        admin_stats_max_users: 3,
+        ..Config::default()
+    });
+    let (existing, missing, broken) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+
+    for id in ["a", "b"] {
+        let doc = json!({ "id": id, "title": "Receipt", "body": "Paid in full" });
+        let (status, _) = request_json(app.clone(), "PUT", "/v1/documents", Some(existing), Some(doc)).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    // A file where the index directory should be makes the index fail to open
+    let broken_dir = temp_dir.path().join(broken.to_string());
+    std::fs::create_dir_all(&broken_dir).unwrap();
+    std::fs::write(broken_dir.join("index"), b"not a directory").unwrap();
+
+    let stats = |user_ids: Vec<Uuid>| {
+        let request = Request::builder()
+            .method("POST")
+            .uri("/v1/admin/stats")
+            .header("Authorization", "Bearer s3cret")
+            .header("Content-Type", "application/json")
+            .body(Body::from(json!({ "user_ids": user_ids }).to_string()))
+            .unwrap();
+        let app = app.clone();
+        async move {
+            let response = app.oneshot(request).await.unwrap();
+            let status = response.status();
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+            (status, serde_json::from_slice::<Value>(&body).unwrap())
+        }
+    };
+
+    let (status, json) = stats(vec![existing, missing, broken]).await;
+    assert_eq!(status, StatusCode::OK);
+    let users = json["users"].as_array().unwrap();
+    assert_eq!(users[0]["user_id"], existing.to_string());
+    assert_eq!(users[0]["num_documents"], 2);
+    assert!(users[0]["disk_bytes"].as_u64().unwrap() > 0);
+    assert!(users[0]["num_segments"].as_u64().unwrap() >= 1);
+    assert!(users[0].get("error").is_none());
+
+    // Users without an index report zeros, and no index is created for them
+    assert_eq!(users[1]["num_documents"], 0);
+    assert_eq!(users[1]["disk_bytes"], 0);
+    assert!(!temp_dir.path().join(missing.to_string()).exists());
+
+    assert!(users[2]["error"].is_string());
+    assert!(users[2].get("num_documents").is_none());
+
+    let (status, _) = stats(vec![]).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    let (status, _) = stats(vec![existing, missing, broken, Uuid::new_v4()]).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+
+    // Admin-only
+    let body = json!({ "user_ids": [existing] });
+    let (status, _) = request_json(app.clone(), "POST", "/v1/admin/stats", Some(existing), Some(body)).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn test_search_timeout_header() {
+    let (app, _temp_dir) = create_test_app();
+    let user_id = Uuid::new_v4();
+
+    let doc = json!({ "title": "Mileage log", "body": "Business mileage for 2024" });
+    let (status, _) = request_json(app.clone(), "PUT", "/v1/documents", Some(user_id), Some(doc)).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let search = |timeout: &str| {
+        Request::builder()
+            .method("POST")
+            .uri("/v1/search")
+            .header("X-User-Id", user_id.to_string())
+            .header("X-Timeout-Ms", timeout)
+            .header("Content-Type", "application/json")
+            .body(Body::from(r#"{"query":"mileage"}"#))
+            .unwrap()
+    };
+
+    // An exhausted budget returns empty results flagged as timed out, not a 504
+    let response = app.clone().oneshot(search("0")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let json: Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(json["timed_out"], true);
+    assert_eq!(json["total"], 0);
+
+    let response = app.clone().oneshot(search("5000")).await.unwrap();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let json: Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(json["timed_out"], false);
+    assert_eq!(json["total"], 1);
+
+    let response = app.oneshot(search("soon")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+}
+
+#[tokio::test]
+async fn test_search_limit_overflow() {
+    let docs = json!({ "documents": [
+        { "id": "a", "title": "Receipt one", "body": "Office supplies" },
+        { "id": "b", "title": "Receipt two", "body": "Office chair" },
+    ] });
+    let over_cap = json!({ "query": "receipt", "limit": 500 });
+
+    // Default: an over-cap limit is rejected
+    let (app, _temp_dir) = create_test_app();
+    let user_id = Uuid::new_v4();
+    let (status, _) = request_json(app, "POST", "/v1/search", Some(user_id), Some(over_cap.clone())).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+
+    let config = Config {
+        search_limit_overflow: LimitOverflowMode::Clamp,
+        ..Config::default()
+    };
+    let (app, _temp_dir) = create_test_app_with_config(config);
+    let (status, _) = request_json(app.clone(), "POST", "/v1/documents/bulk", Some(user_id), Some(docs)).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, json) = request_json(app.clone(), "POST", "/v1/search", Some(user_id), Some(over_cap)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(json["capped"], true);
+    assert_eq!(json["limit"], 100);
+    assert_eq!(json["total"], 2);
+
+    let within_cap = json!({ "query": "receipt", "limit": 1 });
+    let (_, json) = request_json(app, "POST", "/v1/search", Some(user_id), Some(within_cap)).await;
+    assert_eq!(json["capped"], false);
+    assert_eq!(json["limit"], 1);
+    assert_eq!(json["results"].as_array().unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn test_offset_upper_bound() {
+    let (app, _temp_dir) = create_test_app();
+    let user_id = Uuid::new_v4();
+
+    let search = json!({ "query": "receipt", "offset": 1_000_000_000u64 });
+    let (status, json) = request_json(app.clone(), "POST", "/v1/search", Some(user_id), Some(search)).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert!(json["message"].as_str().unwrap().contains("add filters"));
+
+    let browse = json!({ "offset": 10_001 });
+    let (status, _) = request_json(app.clone(), "POST", "/v1/browse", Some(user_id), Some(browse)).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+
+    let browse = json!({ "offset": 1_000_000_000u64 });
+    let (status, json) = request_json(app.clone(), "POST", "/v1/browse", Some(user_id), Some(browse)).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert!(json["message"].as_str().unwrap().contains("/v1/search with filters"));
+
+    let browse = json!({ "offset": 10_000 });
+    let (status, _) = request_json(app, "POST", "/v1/browse", Some(user_id), Some(browse)).await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_document_preview() {
+    let (app, _temp_dir) = create_test_app();
+    let user_id = Uuid::new_v4();
+
+    let doc = json!({
+        "id": "long-doc",
+        "title": "Property tax notice",
+        "body": "Your assessed property value increased this year"
+    });
+    let (status, _) = request_json(app.clone(), "PUT", "/v1/documents", Some(user_id), Some(doc)).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, json) = request_json(app.clone(), "GET", "/v1/documents/long-doc/preview?chars=20", Some(user_id), None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(json["title"], "Property tax notice");
+    assert_eq!(json["preview"], "Your assessed");
+    assert_eq!(json["truncated"], true);
+
+    let (status, json) = request_json(app.clone(), "GET", "/v1/documents/long-doc/preview", Some(user_id), None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(json["preview"], "Your assessed property value increased this year");
+    assert_eq!(json["truncated"], false);
+
+    // Other tenants cannot preview the document
+    let (status, _) = request_json(app.clone(), "GET", "/v1/documents/long-doc/preview", Some(Uuid::new_v4()), None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let (status, _) = request_json(app, "GET", "/v1/documents/missing/preview", Some(user_id), None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_refresh_document() {
+    let (app, _temp_dir) = create_test_app();
+    let user_id = Uuid::new_v4();
+
+    let doc = json!({ "id": "doc-1", "title": "1099-INT", "body": "Interest income" });
+    let (status, _) = request_json(app.clone(), "PUT", "/v1/documents", Some(user_id), Some(doc)).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, json) = request_json(app.clone(), "POST", "/v1/documents/doc-1/refresh", Some(user_id), None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(json["id"], "doc-1");
+
+    let search = json!({ "query": "interest" });
+    let (_, json) = request_json(app.clone(), "POST", "/v1/search", Some(user_id), Some(search)).await;
+    assert_eq!(json["total"], 1);
+
+    let (status, _) = request_json(app, "POST", "/v1/documents/missing/refresh", Some(user_id), None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_created_at_window() {
+    let (app, _temp_dir) = create_test_app();
+    let user_id = Uuid::new_v4();
+
+    // Future dates are clamped to the window by default
+    let future = (chrono::Utc::now() + chrono::Duration::days(365)).to_rfc3339();
+    let doc = json!({
+        "id": "future",
+        "title": "Estimated payment",
+        "body": "Next year estimate",
+        "metadata": { "created_at": future }
+    });
+    let (status, _) = request_json(app.clone(), "PUT", "/v1/documents", Some(user_id), Some(doc)).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let search = json!({ "query": "estimate" });
+    let (_, json) = request_json(app, "POST", "/v1/search", Some(user_id), Some(search)).await;
+    let stored = json["results"][0]["created_at"].as_str().unwrap();
+    let stored = chrono::DateTime::parse_from_rfc3339(stored).unwrap();
+    assert!(stored < chrono::Utc::now() + chrono::Duration::days(2));
+
+    // Reject mode refuses far-past dates
+    let (app, _temp_dir) = create_test_app_with_config(Config {
+        created_at_mode: CreatedAtMode::Reject,
+        ..Config::default()
+    });
+    let doc = json!({
+        "title": "Old scan",
+        "body": "Epoch timestamp",
+        "metadata": { "created_at": "1970-01-01T00:00:00Z" }
+    });
+    let (status, json) = request_json(app, "PUT", "/v1/documents", Some(user_id), Some(doc)).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert!(json["message"].as_str().unwrap().contains("created_at"));
+}
+
+#[tokio::test]
+async fn test_document_id_validation() {
+    let (app, _temp_dir) = create_test_app();
+    let user_id = Uuid::new_v4();
+
+    let doc = json!({ "id": "line\nbreak", "title": "Receipt", "body": "Body" });
+    let (status, json) = request_json(app.clone(), "PUT", "/v1/documents", Some(user_id), Some(doc)).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(json["message"], "Document ID contains unsupported character U+000A at position 4");
+
+    let bulk = json!({ "ids": ["ok", " padded"] });
+    let (status, json) = request_json(app.clone(), "POST", "/v1/documents/bulk-delete", Some(user_id), Some(bulk)).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert!(json["message"].as_str().unwrap().starts_with("ids[1]:"));
+
+    // Path-like IDs round-trip through percent-encoded URLs
+    let doc = json!({ "id": "2024/w2 acme.pdf", "title": "W-2", "body": "Wages" });
+    let (status, _) = request_json(app.clone(), "PUT", "/v1/documents", Some(user_id), Some(doc)).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, json) = request_json(app, "GET", "/v1/documents/2024%2Fw2%20acme.pdf/preview", Some(user_id), None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(json["id"], "2024/w2 acme.pdf");
+}
+
+#[tokio::test]
+async fn test_echo_request_on_error() {
+    let (app, _temp_dir) = create_test_app_with_config(Config {
+        echo_request_on_error: true,
+        ..Config::default()
+    });
+    let user_id = Uuid::new_v4();
+
+    let doc = json!({ "id": "doc-1", "title": "  ", "body": "Private body text" });
+    let (status, json) = request_json(app.clone(), "PUT", "/v1/documents", Some(user_id), Some(doc)).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    let request = &json["details"]["request"];
+    assert_eq!(request["method"], "PUT");
+    assert_eq!(request["path"], "/v1/documents");
+    assert_eq!(request["body"]["id"], "doc-1");
+    assert_eq!(request["body"]["body"]["length"], 17);
+    assert!(!json.to_string().contains("Private body text"));
+
+    let (status, json) = request_json(app.clone(), "GET", "/v1/documents/doc-1/preview?chars=0", Some(user_id), None).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(json["details"]["request"]["query_string"], "chars=0");
+
+    // Successful responses are untouched
+    let doc = json!({ "id": "doc-1", "title": "Receipt", "body": "Private body text" });
+    let (status, json) = request_json(app, "PUT", "/v1/documents", Some(user_id), Some(doc)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(json.get("details").is_none());
+
+    // Disabled by default
+    let (app, _temp_dir) = create_test_app();
+    let search = json!({ "query": "" });
+    let (_, json) = request_json(app, "POST", "/v1/search", Some(user_id), Some(search)).await;
+    assert!(json.get("details").is_none());
+}
+
+#[tokio::test]
+async fn test_title_only_documents() {
+    let user_id = Uuid::new_v4();
+    let doc = json!({ "id": "folder-2024", "title": "Receipts folder", "body": "  " });
+
+    // Rejected by default
+    let (app, _temp_dir) = create_test_app();
+    let (status, _) = request_json(app, "PUT", "/v1/documents", Some(user_id), Some(doc.clone())).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+
+    let (app, _temp_dir) = create_test_app_with_config(Config {
+        allow_empty_body: true,
+        ..Config::default()
+    });
+    let (status, _) = request_json(app.clone(), "PUT", "/v1/documents", Some(user_id), Some(doc)).await;
+    assert_eq!(status, StatusCode::OK);
+
+    // Found by a title match, with highlighting exercising the missing body
+    let search = json!({ "query": "receipts", "highlight": true });
+    let (status, json) = request_json(app.clone(), "POST", "/v1/search", Some(user_id), Some(search)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(json["total"], 1);
+    let result = &json["results"][0];
+    assert_eq!(result["id"], "folder-2024");
+    assert_eq!(result["body"], "");
+    assert_eq!(result["snippet_field"], "title");
+    assert!(result["highlights"].get("body").is_none());
+
+    let browse = json!({ "limit": 10 });
+    let (status, json) = request_json(app.clone(), "POST", "/v1/browse", Some(user_id), Some(browse)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(json["documents"][0]["body"], "");
+
+    let (status, json) = request_json(app, "GET", "/v1/documents/folder-2024/preview", Some(user_id), None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(json["preview"], "");
+    assert_eq!(json["truncated"], false);
+}
+
+#[tokio::test]
+async fn test_create_only_documents() {
+    let (app, _temp_dir) = create_test_app();
+    let user_id = Uuid::new_v4();
+    let doc = json!({ "id": "1099-int", "title": "1099-INT", "body": "Interest income" });
+
+    let (status, json) = request_json(app.clone(), "POST", "/v1/documents", Some(user_id), Some(doc.clone())).await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(json["id"], "1099-int");
+
+    let (status, json) = request_json(app.clone(), "POST", "/v1/documents", Some(user_id), Some(doc.clone())).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    assert_eq!(json["error"], "conflict");
+    assert!(json["message"].as_str().unwrap().contains("1099-int"));
+
+    // PUT keeps upsert semantics
+    let (status, _) = request_json(app.clone(), "PUT", "/v1/documents", Some(user_id), Some(doc)).await;
+    assert_eq!(status, StatusCode::OK);
+
+    // Without an ID, the generated one is stored and can be fetched
+    let unnamed = json!({ "title": "1099-DIV", "body": "Dividend income" });
+    let (status, json) = request_json(app.clone(), "POST", "/v1/documents", Some(user_id), Some(unnamed)).await;
+    assert_eq!(status, StatusCode::CREATED);
+    let preview = format!("/v1/documents/{}/preview", json["id"].as_str().unwrap());
+    let (status, json) = request_json(app.clone(), "GET", &preview, Some(user_id), None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(json["title"], "1099-DIV");
+
+    // Create-only inserts go through the same validation
+    let invalid = json!({ "id": "x", "title": "", "body": "Body" });
+    let (status, _) = request_json(app, "POST", "/v1/documents", Some(user_id), Some(invalid)).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+}
+
+#[tokio::test]
+async fn test_tag_filter_request_shapes() {
+    let (app, _temp_dir) = create_test_app();
+    let user_id = Uuid::new_v4();
+
+    let docs = [
+        json!({ "id": "a", "title": "Invoice", "body": "Office invoice", "metadata": { "tags": ["tax", "2024"] } }),
+        json!({ "id": "b", "title": "Invoice", "body": "Travel invoice", "metadata": { "tags": ["tax"] } }),
+    ];
+    for doc in docs {
+        let (status, _) = request_json(app.clone(), "PUT", "/v1/documents", Some(user_id), Some(doc)).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    // A plain list keeps any-of semantics
+    let search = json!({ "query": "invoice", "filters": { "tags": ["2024", "missing"] } });
+    let (status, json) = request_json(app.clone(), "POST", "/v1/search", Some(user_id), Some(search)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(json["total"], 1);
+    assert_eq!(json["results"][0]["id"], "a");
+
+    let search = json!({ "query": "invoice", "filters": { "tags": { "values": ["tax", "2024"], "all": true } } });
+    let (status, json) = request_json(app.clone(), "POST", "/v1/search", Some(user_id), Some(search)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(json["total"], 1);
+
+    let search = json!({ "query": "invoice", "filters": { "tags": { "values": ["tax"] } } });
+    let (_, json) = request_json(app.clone(), "POST", "/v1/search", Some(user_id), Some(search)).await;
+    assert_eq!(json["total"], 2);
+
+    let search = json!({ "query": "invoice", "filters": { "tags": [" "] } });
+    let (status, _) = request_json(app, "POST", "/v1/search", Some(user_id), Some(search)).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+}
+
+#[tokio::test]
+async fn test_empty_tags() {
+    let (app, _temp_dir) = create_test_app();
+    let user_id = Uuid::new_v4();
+
+    let tags = json!(["", "  ", "valid", " tax "]);
+    let doc = json!({ "id": "single", "title": "Invoice", "body": "Office invoice", "metadata": { "tags": tags } });
+    let (status, _) = request_json(app.clone(), "PUT", "/v1/documents", Some(user_id), Some(doc)).await;
+    assert_eq!(status, StatusCode::OK);
+    let bulk = json!({ "documents": [{ "id": "bulk", "title": "Invoice", "body": "Travel invoice", "metadata": { "tags": tags } }] });
+    let (status, _) = request_json(app.clone(), "POST", "/v1/documents/bulk", Some(user_id), Some(bulk)).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (_, json) = request_json(app.clone(), "POST", "/v1/browse", Some(user_id), Some(json!({}))).await;
+    let documents = json["documents"].as_array().unwrap();
+    assert_eq!(documents.len(), 2);
+    for document in documents {
+        assert_eq!(document["tags"], json!(["valid", "tax"]));
+    }
+
+    let (app, _temp_dir) = create_test_app_with_config(Config {
+        empty_tags: EmptyTagsMode::Reject,
+        ..Config::default()
+    });
+    let doc = json!({ "id": "single", "title": "Invoice", "body": "Office invoice", "metadata": { "tags": tags } });
+    let (status, json) = request_json(app.clone(), "PUT", "/v1/documents", Some(user_id), Some(doc)).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert!(json["message"].as_str().unwrap().contains("Tags cannot be empty"));
+    let bulk = json!({ "documents": [{ "id": "bulk", "title": "Invoice", "body": "Travel invoice", "metadata": { "tags": ["valid", " "] } }] });
+    let (status, json) = request_json(app, "POST", "/v1/documents/bulk", Some(user_id), Some(bulk)).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert!(json["message"].as_str().unwrap().starts_with("documents[0]"));
+}
+
+#[tokio::test]
+async fn test_change_stream() {
+    let (app, _temp_dir) = create_test_app_with_config(Config {
+        changes_max_connections: 1,
+        ..Config::default()
+    });
+    let user_id = Uuid::new_v4();
+    let open_stream = |user_id: Uuid| {
+        Request::builder()
+            .uri("/v1/changes")
+            .header("X-User-Id", user_id.to_string())
+            .body(Body::empty())
+            .unwrap()
+    };
+
+    // Changes before the stream opens are not replayed
+    let doc = json!({ "id": "before", "title": "Invoice", "body": "Office invoice" });
+    request_json(app.clone(), "PUT", "/v1/documents", Some(user_id), Some(doc)).await;
+
+    let response = app.clone().oneshot(open_stream(user_id)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()["content-type"], "text/event-stream");
+    let mut events = response.into_body().into_data_stream();
+
+    // The only stream slot is taken
+    let response = app.clone().oneshot(open_stream(Uuid::new_v4())).await.unwrap();
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+
+    let doc = json!({ "id": "after", "title": "Invoice", "body": "Travel invoice" });
+    let (status, indexed) = request_json(app.clone(), "PUT", "/v1/documents", Some(user_id), Some(doc)).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) =
+        request_json(app.clone(), "DELETE", "/v1/documents", Some(user_id), Some(json!({ "id": "after" }))).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let event = next_event(&mut events).await;
+    assert!(event.starts_with("event: indexed\n"), "{}", event);
+    let data: Value = serde_json::from_str(event.lines().nth(1).unwrap().strip_prefix("data: ").unwrap()).unwrap();
+    assert_eq!(data, json!({ "kind": "indexed", "id": "after", "generation": indexed["generation"] }));
+    let event = next_event(&mut events).await;
+    assert!(event.starts_with("event: deleted\n") && event.contains(r#""id":"after""#), "{}", event);
+
+    // A generated ID is the same in the response, the event and the stored document
+    let doc = json!({ "title": "Invoice", "body": "Unnamed invoice" });
+    let (status, indexed) = request_json(app.clone(), "PUT", "/v1/documents", Some(user_id), Some(doc)).await;
+    assert_eq!(status, StatusCode::OK);
+    let event = next_event(&mut events).await;
+    let data: Value = serde_json::from_str(event.lines().nth(1).unwrap().strip_prefix("data: ").unwrap()).unwrap();
+    assert_eq!(data["id"], indexed["id"]);
+    let preview = format!("/v1/documents/{}/preview", indexed["id"].as_str().unwrap());
+    let (status, _) = request_json(app.clone(), "GET", &preview, Some(user_id), None).await;
+    assert_eq!(status, StatusCode::OK);
+
+    // Closing the stream frees its slot
+    drop(events);
+    let response = app.oneshot(open_stream(Uuid::new_v4())).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_change_streams_per_user_cap() {
+    let (app, _temp_dir) = create_test_app_with_config(Config {
+        changes_max_connections_per_user: 2,
+        ..Config::default()
+    });
+    let (user_id, other_user) = (Uuid::new_v4(), Uuid::new_v4());
+    let open_stream = |user_id: Uuid| {
+        let request = Request::builder()
+            .uri("/v1/changes")
+            .header("X-User-Id", user_id.to_string())
+            .body(Body::empty())
+            .unwrap();
+        app.clone().oneshot(request)
+    };
+
+    let mut streams = Vec::new();
+    for _ in 0..2 {
+        let response = open_stream(user_id).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        streams.push(response.into_body());
+    }
+
+    let response = open_stream(user_id).await.unwrap();
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+    assert!(response.headers().contains_key("retry-after"));
+    assert_eq!(open_stream(other_user).await.unwrap().status(), StatusCode::OK);
+
+    // Dropping a body without reading it, as on an abrupt disconnect, frees its slot
+    streams.pop();
+    assert_eq!(open_stream(user_id).await.unwrap().status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_validate_query() {
+    let (app, _temp_dir) = create_test_app();
+    let user_id = Uuid::new_v4();
+
+    let body = json!({ "query": "title:invoice tags:urgent" });
+    let (status, json) = request_json(app.clone(), "POST", "/v1/query/validate", Some(user_id), Some(body)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(json["valid"], true);
+    assert!(json["error"].is_null());
+    assert_eq!(json["interpreted_fields"], json!(["tags", "title"]));
+
+    let invalid = [
+        json!({ "query": "\"unbalanced" }),
+        json!({ "query": "nosuchfield:invoice" }),
+        json!({ "query": "   " }),
+        json!({ "query": "invoice", "filters": { "source": "*.x" } }),
+        json!({ "query": "invoice", "filters": { "tags": [""] } }),
+    ];
+    for body in invalid {
+        let (status, json) =
+            request_json(app.clone(), "POST", "/v1/query/validate", Some(user_id), Some(body.clone())).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        assert_eq!(json["valid"], false, "{}", body);
+        assert!(json["error"].is_string(), "{}", body);
+        assert_eq!(json["interpreted_fields"], json!([]));
+    }
+
+    // Validation never executes the query, so the index stays empty
+    let (_, stats) = request_json(app, "GET", "/v1/stats", Some(user_id), None).await;
+    assert_eq!(stats["num_documents"], 0);
+}
+
+#[tokio::test]
+async fn test_disabled_feature_is_forbidden() {
+    let (app, temp_dir) = create_test_app();
+    let user_id = Uuid::new_v4();
+    let other_user = Uuid::new_v4();
+
+    let user_dir = temp_dir.path().join(user_id.to_string());
+    std::fs::create_dir_all(&user_dir).unwrap();
+    std::fs::write(user_dir.join("config.json"), r#"{"disabled_features": ["bulk", "analytics", "facets"]}"#).unwrap();
+
+    let bulk = json!({ "documents": [{ "id": "a", "title": "Invoice", "body": "Office supplies" }] });
+    let (status, json) =
+        request_json(app.clone(), "POST", "/v1/documents/bulk", Some(user_id), Some(bulk.clone())).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert_eq!(json["error"], "feature_disabled");
+    assert_eq!(json["message"], "Feature 'bulk' is not enabled for this account");
+
+    let (status, _) = request_json(app.clone(), "GET", "/v1/analytics", Some(user_id), None).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    let (status, json) = request_json(app.clone(), "GET", "/v1/tags/facets", Some(user_id), None).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert_eq!(json["message"], "Feature 'facets' is not enabled for this account");
+
+    // Features not listed stay enabled
+    let (status, _) = request_json(app.clone(), "POST", "/v1/browse", Some(user_id), Some(json!({}))).await;
+    assert_eq!(status, StatusCode::OK);
+
+    // Other tenants are unaffected
+    let (status, _) = request_json(app, "POST", "/v1/documents/bulk", Some(other_user), Some(bulk)).await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_reindex() {
+    let (app, temp_dir) = create_test_app();
+    let user_id = Uuid::new_v4();
+
+    let doc = json!({ "id": "w2", "title": "W-2", "body": "Wage and tax statement", "metadata": { "tags": ["Tax"] } });
+    let (_, indexed) = request_json(app.clone(), "PUT", "/v1/documents", Some(user_id), Some(doc)).await;
+
+    let (status, json) = request_json(app.clone(), "POST", "/v1/reindex", Some(user_id), None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(json["documents"], 1);
+    assert!(json["generation"].as_u64().unwrap() > indexed["generation"].as_u64().unwrap());
+
+    let search = json!({ "query": "wage", "filters": { "tags": ["tax"] } });
+    let (status, json) = request_json(app, "POST", "/v1/search", Some(user_id), Some(search)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(json["results"][0]["id"], "w2");
+    assert_eq!(json["results"][0]["body"], "Wage and tax statement");
+
+    assert!(temp_dir.path().join(user_id.to_string()).join("index").exists());
+}
+
+#[tokio::test]
+async fn test_analyze_text() {
+    let (app, _temp_dir) = create_test_app();
+    let user_id = Uuid::new_v4();
+
+    // The field's analyzer: the default tokenizer splits and lowercases
+    let body = json!({ "text": "Quarterly TAX-Return", "field": "title" });
+    let (status, json) = request_json(app.clone(), "POST", "/v1/analyze", Some(user_id), Some(body)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(json["analyzer"], "default");
+    let tokens = json["tokens"].as_array().unwrap();
+    let texts: Vec<&str> = tokens.iter().map(|t| t["text"].as_str().unwrap()).collect();
+    assert_eq!(texts, ["quarterly", "tax", "return"]);
+    assert_eq!(tokens[1]["offset_from"], 10);
+    assert_eq!(tokens[1]["offset_to"], 13);
+    assert_eq!(tokens[2]["position"], 2);
+
+    // A named analyzer: the English stemmer reduces words to their stems
+    let body = json!({ "text": "Running deductions", "analyzer": "en_stem" });
+    let (status, json) = request_json(app.clone(), "POST", "/v1/analyze", Some(user_id), Some(body)).await;
+    assert_eq!(status, StatusCode::OK);
+    let texts: Vec<&str> = json["tokens"].as_array().unwrap().iter().map(|t| t["text"].as_str().unwrap()).collect();
+    assert_eq!(texts, ["run", "deduct"]);
+
+    let invalid = [
+        json!({ "text": "x", "field": "nosuchfield" }),
+        json!({ "text": "x", "field": "created_at_ts" }),
+        json!({ "text": "x", "analyzer": "nosuchanalyzer" }),
+        json!({ "text": "x" }),
+        json!({ "text": "x", "field": "title", "analyzer": "default" }),
+    ];
+    for body in invalid {
+        let (status, json) = request_json(app.clone(), "POST", "/v1/analyze", Some(user_id), Some(body.clone())).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY, "{}", body);
+        assert!(json["error"].is_string(), "{}", body);
+    }
+}
+
+#[tokio::test]
+async fn test_external_id() {
+    let (app, _temp_dir) = create_test_app();
+    let user_id = Uuid::new_v4();
+
+    let docs = [
+        json!({ "id": "a", "external_id": "INV-2024-0042", "title": "Invoice", "body": "Office supplies" }),
+        json!({ "id": "b", "external_id": "INV-2023-0042", "title": "Invoice", "body": "Office chairs" }),
+        json!({ "id": "c", "title": "Invoice", "body": "Office desks" }),
+    ];
+    for doc in docs {
+        let (status, _) = request_json(app.clone(), "PUT", "/v1/documents", Some(user_id), Some(doc)).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    // A partial external ID matches as a phrase of its words
+    let search = json!({ "query": "invoice", "filters": { "external_id": "2024-0042" } });
+    let (status, json) = request_json(app.clone(), "POST", "/v1/search", Some(user_id), Some(search)).await;
+    assert_eq!(status, StatusCode::OK);
+    let results = json["results"].as_array().unwrap();
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0]["id"], "a");
+    assert_eq!(results[0]["external_id"], "INV-2024-0042");
+
+    // Queries can reference the field directly; documents without one report null
+    let search = json!({ "query": "external_id:0042 OR id:c" });
+    let (status, json) = request_json(app.clone(), "POST", "/v1/search", Some(user_id), Some(search)).await;
+    assert_eq!(status, StatusCode::OK);
+    let mut external_ids: Vec<Value> = json["results"].as_array().unwrap().iter().map(|r| r["external_id"].clone()).collect();
+    external_ids.sort_by_key(|v| v.to_string());
+    assert_eq!(external_ids, [json!("INV-2023-0042"), json!("INV-2024-0042"), Value::Null]);
+
+    // The canonical ID stays the only delete key
+    request_json(app.clone(), "DELETE", "/v1/documents", Some(user_id), Some(json!({ "id": "INV-2024-0042" }))).await;
+    let search = json!({ "query": "id:a" });
+    let (_, json) = request_json(app.clone(), "POST", "/v1/search", Some(user_id), Some(search)).await;
+    assert_eq!(json["results"].as_array().unwrap().len(), 1);
+
+    let invalid_doc = json!({ "id": "d", "external_id": "  ", "title": "Invoice", "body": "Office" });
+    let (status, _) = request_json(app.clone(), "PUT", "/v1/documents", Some(user_id), Some(invalid_doc)).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+
+    for external_id in ["", "--"] {
+        let search = json!({ "query": "invoice", "filters": { "external_id": external_id } });
+        let (status, _) = request_json(app.clone(), "POST", "/v1/search", Some(user_id), Some(search)).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY, "{:?}", external_id);
+    }
+}
+
+#[tokio::test]
+async fn test_top_terms() {
+    let (app, _temp_dir) = create_test_app();
+    let user_id = Uuid::new_v4();
+
+    // Indexed one at a time, so counts are summed across segments
+    let bodies = [
+        "the refund and the invoice",
+        "the refund for the receipt",
+        "the refund of the invoice",
+        "the deduction",
+    ];
+    for (i, body) in bodies.iter().enumerate() {
+        let doc = json!({ "id": format!("doc-{}", i), "title": "Doc", "body": body });
+        let (status, _) = request_json(app.clone(), "PUT", "/v1/documents", Some(user_id), Some(doc)).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    // Stop words are skipped; equal counts are ordered by term
+    let (status, json) = request_json(app.clone(), "GET", "/v1/terms/top?limit=3", Some(user_id), None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(json["field"], "body");
+    assert_eq!(
+        json["terms"],
+        json!([
+            { "term": "refund", "doc_freq": 3 },
+            { "term": "invoice", "doc_freq": 2 },
+            { "term": "deduction", "doc_freq": 1 },
+        ])
+    );
+
+    let (status, json) =
+        request_json(app.clone(), "GET", "/v1/terms/top?limit=2&stopwords=none", Some(user_id), None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        json["terms"],
+        json!([{ "term": "the", "doc_freq": 4 }, { "term": "refund", "doc_freq": 3 }])
+    );
+
+    for path in [
+        "/v1/terms/top?limit=0",
+        "/v1/terms/top?limit=1001",
+        "/v1/terms/top?stopwords=xx",
+        "/v1/terms/top?field=created_at_ts",
+        "/v1/terms/top?field=nosuchfield",
+    ] {
+        let (status, _) = request_json(app.clone(), "GET", path, Some(user_id), None).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY, "{}", path);
+    }
+}
+
+#[tokio::test]
+async fn test_empty_result_status() {
+    let doc = json!({ "id": "a", "title": "Invoice", "body": "Office supplies" });
+    let search = json!({ "query": "receipt" });
+
+    // Default: an empty result is a 200 with an empty list
+    let (app, _temp_dir) = create_test_app();
+    let user_id = Uuid::new_v4();
+    let (status, json) = request_json(app.clone(), "POST", "/v1/browse", Some(user_id), Some(json!({}))).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(json["documents"], json!([]));
+    request_json(app.clone(), "PUT", "/v1/documents", Some(user_id), Some(doc.clone())).await;
+    let (status, json) = request_json(app, "POST", "/v1/search", Some(user_id), Some(search.clone())).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(json["total"], 0);
+
+    let (app, _temp_dir) = create_test_app_with_config(Config {
+        empty_result_status: 404,
+        ..Config::default()
+    });
+    let user_id = Uuid::new_v4();
+    let (status, json) = request_json(app.clone(), "POST", "/v1/browse", Some(user_id), Some(json!({}))).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(json["error"], "not_found");
+
+    request_json(app.clone(), "PUT", "/v1/documents", Some(user_id), Some(doc)).await;
+    let (status, json) = request_json(app.clone(), "POST", "/v1/search", Some(user_id), Some(search)).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(json["error"], "not_found");
+
+    // Non-empty results are unaffected
+    let (status, json) =
+        request_json(app.clone(), "POST", "/v1/search", Some(user_id), Some(json!({ "query": "invoice" }))).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(json["total"], 1);
+    let (status, _) = request_json(app.clone(), "POST", "/v1/browse", Some(user_id), Some(json!({}))).await;
+    assert_eq!(status, StatusCode::OK);
+
+    // Pages past the last result are empty pages, not 404s
+    let past_end = json!({ "query": "invoice", "offset": 5 });
+    let (status, json) = request_json(app.clone(), "POST", "/v1/search", Some(user_id), Some(past_end)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(json["results"], json!([]));
+    let (status, json) = request_json(app, "POST", "/v1/browse", Some(user_id), Some(json!({ "offset": 5 }))).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(json["documents"], json!([]));
+    assert_eq!(json["total"], 1);
+
+    // A match trimmed by the byte budget is a truncated page, not a missing result
+    let (app, _temp_dir) = create_test_app_with_config(Config {
+        empty_result_status: 404,
+        max_response_bytes: 1_000,
+        ..Config::default()
+    });
+    let user_id = Uuid::new_v4();
+    let large = json!({ "id": "large", "title": "Invoice", "body": "supplies ".repeat(500) });
+    request_json(app.clone(), "PUT", "/v1/documents", Some(user_id), Some(large)).await;
+    let search = json!({ "query": "invoice", "include_body": true });
+    let (status, json) = request_json(app, "POST", "/v1/search", Some(user_id), Some(search)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(json["results"], json!([]));
+    assert_eq!(json["truncated_by_size"], true);
+}
+
+#[tokio::test]
+async fn test_browse_pagination() {
+    let (app, _temp_dir) = create_test_app();
+    let user_id = Uuid::new_v4();
+
+    for i in 0..5 {
+        let doc = json!({ "id": format!("doc-{}", i), "title": format!("Receipt {}", i), "body": "Office supplies" });
+        let (status, _) = request_json(app.clone(), "PUT", "/v1/documents", Some(user_id), Some(doc)).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    let browse = |offset: usize| json!({ "limit": 2, "offset": offset });
+    let ids = |json: &Value| -> Vec<String> {
+        json["documents"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|doc| doc["id"].as_str().unwrap().to_string())
+            .collect()
+    };
+
+    let (status, first) = request_json(app.clone(), "POST", "/v1/browse", Some(user_id), Some(browse(0))).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(first["total"], 5);
+    assert_eq!(first["has_more"], true);
+
+    let (status, second) = request_json(app.clone(), "POST", "/v1/browse", Some(user_id), Some(browse(2))).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(second["total"], 5);
+    assert_eq!(second["offset"], 2);
+    assert_eq!(second["limit"], 2);
+    assert_eq!(second["has_more"], true);
+    assert_eq!(ids(&second).len(), 2);
+
+    let (_, last) = request_json(app, "POST", "/v1/browse", Some(user_id), Some(browse(4))).await;
+    assert_eq!(last["total"], 5);
+    assert_eq!(last["has_more"], false);
+    assert_eq!(ids(&last).len(), 1);
+
+    // The pages are disjoint and together cover every document
+    let mut seen: Vec<String> = [ids(&first), ids(&second), ids(&last)].concat();
+    seen.sort();
+    assert_eq!(seen, ["doc-0", "doc-1", "doc-2", "doc-3", "doc-4"]);
+}
+
+#[tokio::test]
+async fn test_bulk_index_duplicate_ids() {
+    let documents = json!({
+        "documents": [
+            { "id": "w2", "title": "W-2", "body": "First upload" },
+            { "id": "1099", "title": "1099-INT", "body": "Interest income" },
+            { "id": "w2", "title": "W-2", "body": "Corrected upload" },
+            { "title": "Receipt", "body": "Generated ID" }
+        ]
+    });
+
+    // Default: the whole batch is rejected and nothing is written
+    let (app, _temp_dir) = create_test_app();
+    let user_id = Uuid::new_v4();
+    let (status, json) =
+        request_json(app.clone(), "POST", "/v1/documents/bulk", Some(user_id), Some(documents.clone())).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(json["error"], "validation_error");
+    assert!(json["message"].as_str().unwrap().ends_with("Duplicate document IDs in request: w2"));
+    let (_, stats) = request_json(app, "GET", "/v1/stats", Some(user_id), None).await;
+    assert_eq!(stats["num_documents"], 0);
+
+    // last_wins: the later occurrence is indexed and the earlier one reported
+    let (app, _temp_dir) = create_test_app_with_config(Config {
+        bulk_duplicate_ids: DuplicateIdMode::LastWins,
+        ..Config::default()
+    });
+    let (status, json) =
+        request_json(app.clone(), "POST", "/v1/documents/bulk", Some(user_id), Some(documents)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(json["indexed"], 3);
+    assert_eq!(json["overwritten"], 1);
+    let statuses: Vec<&str> = json["results"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|result| result["status"].as_str().unwrap())
+        .collect();
+    assert_eq!(statuses, ["overwritten", "indexed", "indexed", "indexed"]);
+    assert_eq!(json["results"][0]["id"], "w2");
+    assert!(!json["results"][3]["id"].as_str().unwrap().is_empty());
+
+    let search = json!({ "query": "upload" });
+    let (_, json) = request_json(app.clone(), "POST", "/v1/search", Some(user_id), Some(search)).await;
+    assert_eq!(json["total"], 1);
+    assert_eq!(json["results"][0]["body"], "Corrected upload");
+
+    // Per-document validation errors name the offending position
+    let invalid = json!({ "documents": [{ "id": "ok", "title": "Fine", "body": "Body" }, { "title": "", "body": "Body" }] });
+    let (status, json) = request_json(app, "POST", "/v1/documents/bulk", Some(user_id), Some(invalid)).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert!(json["message"].as_str().unwrap().starts_with("documents[1]:"));
+}
+
+#[tokio::test]
+async fn test_bulk_index_reports_indexed_bytes() {
+    let (app, _temp_dir) = create_test_app_with_config(Config {
+        bulk_duplicate_ids: DuplicateIdMode::LastWins,
+        ..Config::default()
+    });
+    let user_id = Uuid::new_v4();
+
+    let documents = [
+        ("w2", "W-2", "Draft", vec![]),
+        ("w2", "W-2", "Löhne 2024", vec!["payroll", "2024"]),
+        ("1099", "1099-INT", "Interest income", vec!["bank"]),
+    ];
+    let body = json!({
+        "documents": documents
+            .iter()
+            .map(|(id, title, body, tags)| json!({ "id": id, "title": title, "body": body, "metadata": { "tags": tags } }))
+            .collect::<Vec<_>>()
+    });
+    let (status, json) = request_json(app, "POST", "/v1/documents/bulk", Some(user_id), Some(body)).await;
+    assert_eq!(status, StatusCode::OK);
+
+    // Input bytes of the indexed documents only; the overwritten draft isn't counted
+    let expected: usize = documents[1..]
+        .iter()
+        .map(|(_, title, body, tags)| title.len() + body.len() + tags.iter().map(|tag| tag.len()).sum::<usize>())
+        .sum();
+    assert_eq!(expected, 3 + 11 + 7 + 4 + 8 + 15 + 4);
+    assert_eq!(json["indexed"], 2);
+    assert_eq!(json["indexed_bytes"], expected);
+}
+
+#[tokio::test]
+async fn test_duplicate_content() {
+    let original = json!({ "id": "w2-a", "title": "W-2 2024", "body": "Wages", "metadata": { "tags": ["payroll"] } });
+    // Checksums trim surrounding whitespace, so this is the same content
+    let copy = json!({ "id": "w2-b", "title": "W-2 2024 ", "body": "Wages", "metadata": { "tags": ["payroll"] } });
+    let user_id = Uuid::new_v4();
+
+    let (app, _temp_dir) = create_test_app_with_config(Config {
+        duplicate_content: DuplicateContentMode::Reject,
+        ..Config::default()
+    });
+    let (status, _) = request_json(app.clone(), "PUT", "/v1/documents", Some(user_id), Some(original.clone())).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, json) = request_json(app.clone(), "PUT", "/v1/documents", Some(user_id), Some(copy.clone())).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    assert!(json["message"].as_str().unwrap().contains("same content as document 'w2-a'"), "{}", json);
+
+    // Rewriting a document with its own content is no duplicate
+    let (status, _) = request_json(app.clone(), "PUT", "/v1/documents", Some(user_id), Some(original.clone())).await;
+    assert_eq!(status, StatusCode::OK);
+
+    // Within a bulk request the whole batch is rejected, naming the position
+    let batch = json!({ "documents": [
+        { "id": "1099", "title": "1099-INT", "body": "Interest" },
+        { "id": "1099-copy", "title": "1099-INT", "body": "Interest" }
+    ] });
+    let (status, json) = request_json(app.clone(), "POST", "/v1/documents/bulk", Some(user_id), Some(batch)).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    assert!(json["message"].as_str().unwrap().contains("documents[1]: Document '1099-copy'"), "{}", json);
+    let (_, stats) = request_json(app, "GET", "/v1/stats", Some(user_id), None).await;
+    assert_eq!(stats["num_documents"], 1);
+
+    // Alias: nothing is written and the existing document is named
+    let (app, _temp_dir) = create_test_app_with_config(Config {
+        duplicate_content: DuplicateContentMode::Alias,
+        ..Config::default()
+    });
+    request_json(app.clone(), "PUT", "/v1/documents", Some(user_id), Some(original)).await;
+    let (status, json) = request_json(app.clone(), "POST", "/v1/documents", Some(user_id), Some(copy.clone())).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!((json["status"].as_str(), json["alias_of"].as_str()), (Some("alias"), Some("w2-a")));
+
+    let batch = json!({ "documents": [copy, { "id": "memo", "title": "Memo", "body": "Notes" }] });
+    let (status, json) = request_json(app.clone(), "POST", "/v1/documents/bulk", Some(user_id), Some(batch)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(json["indexed"], 1);
+    assert_eq!((json["results"][0]["status"].as_str(), json["results"][0]["alias_of"].as_str()), (Some("alias"), Some("w2-a")));
+    assert!(json["results"][1].get("alias_of").is_none());
+    let (_, stats) = request_json(app, "GET", "/v1/stats", Some(user_id), None).await;
+    assert_eq!(stats["num_documents"], 2);
+}
+
+#[tokio::test]
+async fn test_search_query_log() {
+    let temp_dir = TempDir::new().unwrap();
+    let config = Config {
+        data_dir: temp_dir.path().to_path_buf(),
+        ..Config::default()
+    };
+    let (query_log, mut entries) = QueryLog::channel(QueryTextMode::Redacted, "salt");
+    let state = AppState {
+        index_manager: Arc::new(IndexManager::from_config(&config)),
+        config: Arc::new(config),
+        query_log: Some(Arc::new(query_log)),
+    };
+    let app = build_router(state, false);
+    let user_id = Uuid::new_v4();
+
+    let doc = json!({ "id": "w2", "title": "W-2 2024", "body": "Wages" });
+    request_json(app.clone(), "PUT", "/v1/documents", Some(user_id), Some(doc)).await;
+    for query in ["wages", "form 1099"] {
+        let (status, _) =
+            request_json(app.clone(), "POST", "/v1/search", Some(user_id), Some(json!({ "query": query }))).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    let first = entries.try_recv().unwrap();
+    assert_eq!(first.query.as_deref(), Some("wages"));
+    assert_eq!(first.result_count, 1);
+    assert!(!first.user.contains(&user_id.to_string()));
+
+    // Zero-hit queries are logged too
+    let second = entries.try_recv().unwrap();
+    assert_eq!(second.query.as_deref(), Some("form ####"));
+    assert_eq!(second.result_count, 0);
+    assert_eq!(second.user, first.user);
+
+    // Rejected searches are not
+    request_json(app, "POST", "/v1/search", Some(user_id), Some(json!({ "query": "" }))).await;
+    assert!(entries.try_recv().is_err());
+}
+
+#[tokio::test]
+async fn test_document_similarity() {
+    let (app, _temp_dir) = create_test_app();
+    let user_id = Uuid::new_v4();
+
+    let docs = [
+        ("w2-a", "W-2 2024", "Wages and tax statement for employee Jane Doe"),
+        ("w2-b", "W-2 2024", "Wages and tax statement for employee Jane Doe"),
+        ("w2-c", "W-2 2023", "Wages and tax statement for employee John Roe"),
+        ("lunch", "Lunch", "Sandwich coffee cookie"),
+    ];
+    for (id, title, body) in docs {
+        let doc = json!({ "id": id, "title": title, "body": body });
+        let (status, _) = request_json(app.clone(), "PUT", "/v1/documents", Some(user_id), Some(doc)).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    let compare = |left: Value, right: Value| {
+        let app = app.clone();
+        async move {
+            request_json(app, "POST", "/v1/similarity", Some(user_id), Some(json!({ "left": left, "right": right }))).await
+        }
+    };
+
+    // Identical documents
+    let (status, json) = compare(json!({ "id": "w2-a" }), json!({ "id": "w2-b" })).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!((json["cosine"].as_f64().unwrap() - 1.0).abs() < 1e-9);
+    assert_eq!(json["jaccard"], 1.0);
+
+    // Similar documents: most terms shared
+    let (_, json) = compare(json!({ "id": "w2-a" }), json!({ "id": "w2-c" })).await;
+    let cosine = json["cosine"].as_f64().unwrap();
+    let jaccard = json["jaccard"].as_f64().unwrap();
+    assert!(cosine > 0.7 && cosine < 1.0, "{}", cosine);
+    assert!(jaccard > 0.5 && jaccard < 1.0, "{}", jaccard);
+
+    // Unrelated documents
+    let (_, json) = compare(json!({ "id": "w2-a" }), json!({ "id": "lunch" })).await;
+    assert_eq!(json["cosine"], 0.0);
+    assert_eq!(json["jaccard"], 0.0);
+
+    // Inline text on either side
+    let (status, json) = compare(json!({ "text": "coffee and a sandwich" }), json!({ "id": "lunch" })).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(json["cosine"].as_f64().unwrap() > 0.0);
+
+    let (status, _) = compare(json!({ "id": "missing" }), json!({ "id": "w2-a" })).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    for (left, right) in [
+        (json!({}), json!({ "id": "w2-a" })),
+        (json!({ "id": "w2-a", "text": "both" }), json!({ "id": "w2-b" })),
+        (json!({ "text": "  " }), json!({ "id": "w2-b" })),
+    ] {
+        let (status, _) = compare(left, right).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    }
+}
+
+#[tokio::test]
+async fn test_similarity_uses_tenant_analyzer() {
+    let (app, temp_dir) = create_test_app();
+    let user_id = Uuid::new_v4();
+    let user_dir = temp_dir.path().join(user_id.to_string());
+    std::fs::create_dir_all(&user_dir).unwrap();
+    std::fs::write(user_dir.join("config.json"), r#"{"fold_accents": true}"#).unwrap();
+
+    // Accents fold before the tenant's first document creates the index, and after
+    let compare = json!({ "left": { "text": "José Müller" }, "right": { "text": "jose muller" } });
+    let (status, json) = request_json(app.clone(), "POST", "/v1/similarity", Some(user_id), Some(compare.clone())).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(json["jaccard"], 1.0);
+
+    let doc = json!({ "id": "a", "title": "Invoice", "body": "Office supplies" });
+    let (status, _) = request_json(app.clone(), "PUT", "/v1/documents", Some(user_id), Some(doc)).await;
+    assert_eq!(status, StatusCode::OK);
+    let (_, json) = request_json(app.clone(), "POST", "/v1/similarity", Some(user_id), Some(compare.clone())).await;
+    assert_eq!(json["jaccard"], 1.0);
+
+    // Other tenants keep the accents
+    let (_, json) = request_json(app, "POST", "/v1/similarity", Some(Uuid::new_v4()), Some(compare)).await;
+    assert_eq!(json["jaccard"], 0.0);
+}
+
+#[tokio::test]
+async fn test_similarity_with_cjk_bigrams() {
+    let (app, temp_dir) = create_test_app();
+    let user_id = Uuid::new_v4();
+    let user_dir = temp_dir.path().join(user_id.to_string());
+    std::fs::create_dir_all(&user_dir).unwrap();
+    std::fs::write(user_dir.join("config.json"), r#"{"tokenizer": "cjk_bigram"}"#).unwrap();
+
+    // 確定申告書 and 確定申告 share the pairs 確定, 定申 and 申告
+    let compare = json!({ "left": { "text": "確定申告書" }, "right": { "text": "確定申告" } });
+    let (status, json) = request_json(app.clone(), "POST", "/v1/similarity", Some(user_id), Some(compare.clone())).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(json["left_terms"], 4);
+    assert_eq!(json["right_terms"], 3);
+    assert_eq!(json["jaccard"], 0.75);
+
+    // The default tokenizer sees each run as one word, so the texts share nothing
+    let (_, json) = request_json(app, "POST", "/v1/similarity", Some(Uuid::new_v4()), Some(compare)).await;
+    assert_eq!(json["jaccard"], 0.0);
+}
+
+#[tokio::test]
+async fn test_similar_documents() {
+    let (app, _temp_dir) = create_test_app();
+    let user_id = Uuid::new_v4();
+
+    let docs = [
+        ("mortgage", "Mortgage interest", "Mortgage interest statement from a lender for the home loan"),
+        ("refinance", "Refinance", "Home loan refinance with the lender lowered the mortgage interest rate"),
+        ("escrow", "Escrow", "Escrow account for the home loan pays property tax"),
+        ("charity", "Donation", "Receipt for the donation to the food bank charity"),
+        ("payroll", "Payroll", "Payroll summary for the employee wages and the bonus"),
+    ];
+    for (id, title, body) in docs {
+        let doc = json!({ "id": id, "title": title, "body": body });
+        let (status, _) = request_json(app.clone(), "PUT", "/v1/documents", Some(user_id), Some(doc)).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    let (status, json) =
+        request_json(app.clone(), "POST", "/v1/documents/mortgage/similar", Some(user_id), Some(json!({}))).await;
+    assert_eq!(status, StatusCode::OK);
+    let ids: Vec<&str> = json["results"].as_array().unwrap().iter().map(|r| r["id"].as_str().unwrap()).collect();
+    // Topical neighbors rank first and the source is never returned
+    assert_eq!(&ids[..2], ["refinance", "escrow"]);
+    assert!(!ids.contains(&"mortgage"));
+    let terms: Vec<&str> = json["terms"].as_array().unwrap().iter().map(|t| t.as_str().unwrap()).collect();
+    assert!(terms.contains(&"mortgage") && terms.contains(&"lender"));
+    // "statement" appears in no other document, so it can't seed a match
+    assert!(!terms.contains(&"statement"));
+
+    // Fewer seed terms narrow the search to the most significant ones
+    let body = json!({ "max_query_terms": 1, "limit": 1 });
+    let (_, json) = request_json(app.clone(), "POST", "/v1/documents/mortgage/similar", Some(user_id), Some(body)).await;
+    assert_eq!(json["terms"].as_array().unwrap().len(), 1);
+    assert_eq!(json["total"], 1);
+
+    // No term occurs twice in the source
+    let body = json!({ "min_term_freq": 2 });
+    let (status, json) =
+        request_json(app.clone(), "POST", "/v1/documents/mortgage/similar", Some(user_id), Some(body)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(json["total"], 0);
+
+    let (status, _) =
+        request_json(app.clone(), "POST", "/v1/documents/missing/similar", Some(user_id), Some(json!({}))).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    for body in [json!({ "max_query_terms": 0 }), json!({ "min_term_freq": 0 }), json!({ "limit": 101 })] {
+        let (status, _) =
+            request_json(app.clone(), "POST", "/v1/documents/mortgage/similar", Some(user_id), Some(body)).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    }
+}
+
+#[tokio::test]
+async fn test_index_rate_limit() {
+    let (app, _temp_dir) = create_test_app_with_config(Config {
+        max_index_rate_per_minute: 2,
+        ..Config::default()
+    });
+    let user_id = Uuid::new_v4();
+
+    let doc = json!({ "id": "doc1", "title": "Receipt", "body": "Office supplies" });
+    let (status, _) = request_json(app.clone(), "PUT", "/v1/documents", Some(user_id), Some(doc.clone())).await;
+    assert_eq!(status, StatusCode::OK);
+
+    // Creating an existing document conflicts without using the remaining allowance
+    for _ in 0..3 {
+        let (status, _) = request_json(app.clone(), "POST", "/v1/documents", Some(user_id), Some(doc.clone())).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+    }
+
+    // The second document of the bulk request is over the rate
+    let docs = json!({ "documents": [doc.clone(), { "id": "doc2", "title": "Invoice", "body": "Consulting" }] });
+    let (status, json) = request_json(app.clone(), "POST", "/v1/documents/bulk", Some(user_id), Some(docs)).await;
+    assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(json["error"], "rate_limited");
+
+    // A rejected request takes nothing from the allowance
+    let (status, _) = request_json(app.clone(), "PUT", "/v1/documents", Some(user_id), Some(doc.clone())).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let request = Request::builder()
+        .method("PUT")
+        .uri("/v1/documents")
+        .header("X-User-Id", user_id.to_string())
+        .header("Content-Type", "application/json")
+        .body(Body::from(doc.to_string()))
+        .unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(response.headers()["retry-after"], "30");
+}
+
+#[tokio::test]
+async fn test_search_body_modes() {
+    let body = "Consulting services for the first quarter of the fiscal year";
+    let doc = json!({ "id": "doc1", "title": "Invoice", "body": body });
+
+    // (default mode, request overrides, expected body, expected body_truncated)
+    let cases = [
+        (SearchBodyMode::Full, json!({}), body, false),
+        (SearchBodyMode::Preview, json!({}), "Consulting services", true),
+        (SearchBodyMode::None, json!({}), "", true),
+        // Request options override the default
+        (SearchBodyMode::None, json!({ "include_body": true }), body, false),
+        (SearchBodyMode::None, json!({ "body_max_chars": 12 }), "Consulting", true),
+        (SearchBodyMode::Full, json!({ "body_max_chars": 12 }), "Consulting", true),
+        (SearchBodyMode::Full, json!({ "body_max_chars": 1000 }), body, false),
+        (SearchBodyMode::Preview, json!({ "include_body": false, "body_max_chars": 12 }), "", true),
+    ];
+    for (mode, overrides, expected_body, expected_truncated) in cases {
+        let (app, _temp_dir) = create_test_app_with_config(Config {
+            search_body_mode: mode,
+            preview_default_chars: 20,
+            ..Config::default()
+        });
+        let user_id = Uuid::new_v4();
+        let (status, _) = request_json(app.clone(), "PUT", "/v1/documents", Some(user_id), Some(doc.clone())).await;
+        assert_eq!(status, StatusCode::OK);
+
+        let mut search = json!({ "query": "invoice" });
+        search.as_object_mut().unwrap().extend(overrides.as_object().unwrap().clone());
+        let (status, json) = request_json(app, "POST", "/v1/search", Some(user_id), Some(search)).await;
+        assert_eq!(status, StatusCode::OK);
+        let result = &json["results"][0];
+        assert_eq!(result["body"], expected_body, "{:?} {}", mode, overrides);
+        assert_eq!(result["body_truncated"], expected_truncated, "{:?} {}", mode, overrides);
+    }
+
+    let (app, _temp_dir) = create_test_app();
+    let search = json!({ "query": "invoice", "body_max_chars": 0 });
+    let (status, _) = request_json(app, "POST", "/v1/search", Some(Uuid::new_v4()), Some(search)).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+}
+
+#[tokio::test]
+async fn test_search_detailed_timings() {
+    let (app, _temp_dir) = create_test_app();
+    let user_id = Uuid::new_v4();
+
+    let documents: Vec<Value> = (0..200)
+        .map(|i| json!({ "id": format!("doc{}", i), "title": "Mileage log", "body": format!("Trip {} to the client office", i) }))
+        .collect();
+    let (status, _) =
+        request_json(app.clone(), "POST", "/v1/documents/bulk", Some(user_id), Some(json!({ "documents": documents }))).await;
+    assert_eq!(status, StatusCode::OK);
+
+    // Omitted unless asked for
+    let search = json!({ "query": "mileage client", "limit": 100, "highlight": true });
+    let (_, json) = request_json(app.clone(), "POST", "/v1/search", Some(user_id), Some(search.clone())).await;
+    assert!(json.get("timings").is_none());
+
+    let detailed = |timing: &str| {
+        Request::builder()
+            .method("POST")
+            .uri("/v1/search")
+            .header("X-User-Id", user_id.to_string())
+            .header("X-Timing", timing)
+            .header("Content-Type", "application/json")
+            .body(Body::from(search.to_string()))
+            .unwrap()
+    };
+    let response = app.clone().oneshot(detailed("detailed")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let json: Value = serde_json::from_slice(&body).unwrap();
+
+    let timings = &json["timings"];
+    let phases = ["reload_ms", "parse_ms", "search_ms", "fetch_ms", "highlight_ms"];
+    let sum: f64 = phases.iter().map(|phase| timings[phase].as_f64().unwrap()).sum();
+    assert!(phases.iter().all(|phase| timings[phase].as_f64().unwrap() >= 0.0));
+    // The phases run within `took_ms`, which is truncated to whole milliseconds
+    let took_ms = json["took_ms"].as_f64().unwrap();
+    assert!(sum.floor() <= took_ms, "phases {} exceed took_ms {}", sum, took_ms);
+
+    let response = app.oneshot(detailed("verbose")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+}
+
+#[tokio::test]
+async fn test_search_parsed_query() {
+    let (app, _temp_dir) = create_test_app();
+    let user_id = Uuid::new_v4();
+
+    let document = json!({ "id": "doc1", "title": "Tax return", "body": "Filed early", "metadata": { "tags": ["urgent"] } });
+    let (status, _) = request_json(app.clone(), "PUT", "/v1/documents", Some(user_id), Some(document)).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let search = json!({ "query": "tags:urgent \"tax return\"" });
+    let (_, json) = request_json(app.clone(), "POST", "/v1/search", Some(user_id), Some(search)).await;
+    assert!(json.get("parsed_query").is_none());
+
+    let search = json!({ "query": "tags:urgent \"tax return\"", "return_parsed_query": true });
+    let (status, json) = request_json(app.clone(), "POST", "/v1/search", Some(user_id), Some(search.clone())).await;
+    assert_eq!(status, StatusCode::OK);
+    let parsed_query = json["parsed_query"].as_str().unwrap();
+    assert!(parsed_query.contains("field=tags"), "{}", parsed_query);
+    assert!(parsed_query.contains("PhraseQuery"), "{}", parsed_query);
+
+    // Users without an index get it too
+    let (_, json) = request_json(app, "POST", "/v1/search", Some(Uuid::new_v4()), Some(search)).await;
+    assert!(json["parsed_query"].as_str().unwrap().contains("field=tags"));
+}
+
+#[tokio::test]
+async fn test_search_matched_fields() {
+    let (app, _temp_dir) = create_test_app();
+    let user_id = Uuid::new_v4();
+
+    let docs = [
+        json!({ "id": "body-only", "title": "Letter", "body": "Your refund was approved" }),
+        json!({ "id": "both", "title": "Refund notice", "body": "The refund is pending", "metadata": { "tags": ["refund"] } }),
+    ];
+    for doc in docs {
+        let (status, _) = request_json(app.clone(), "PUT", "/v1/documents", Some(user_id), Some(doc)).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    let (_, json) = request_json(app.clone(), "POST", "/v1/search", Some(user_id), Some(json!({ "query": "refund" }))).await;
+    assert!(json["results"][0].get("matched_fields").is_none());
+
+    let search = json!({ "query": "refund tags:refund", "return_matched_fields": true });
+    let (status, json) = request_json(app, "POST", "/v1/search", Some(user_id), Some(search)).await;
+    assert_eq!(status, StatusCode::OK);
+    let matched: HashMap<&str, &Value> = json["results"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|result| (result["id"].as_str().unwrap(), &result["matched_fields"]))
+        .collect();
+    assert_eq!(*matched["body-only"], json!(["body"]));
+    assert_eq!(*matched["both"], json!(["title", "body", "tags"]));
+}
+
+#[tokio::test]
+async fn test_expensive_queries() {
+    for allow_expensive_queries in [false, true] {
+        let (app, _temp_dir) = create_test_app_with_config(Config {
+            allow_expensive_queries,
+            ..Config::default()
+        });
+        let user_id = Uuid::new_v4();
+
+        for (id, title) in [("keep", "Receipt"), ("draft", "Draft receipt")] {
+            let doc = json!({ "id": id, "title": title, "body": "Coffee" });
+            let (status, _) = request_json(app.clone(), "PUT", "/v1/documents", Some(user_id), Some(doc)).await;
+            assert_eq!(status, StatusCode::OK);
+        }
+
+        let search = json!({ "query": "* -draft" });
+        let (status, json) = request_json(app, "POST", "/v1/search", Some(user_id), Some(search)).await;
+        if allow_expensive_queries {
+            assert_eq!(status, StatusCode::OK);
+            assert_eq!(json["total"], 1);
+            assert_eq!(json["results"][0]["id"], "keep");
+        } else {
+            assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+            assert_eq!(json["error"], "validation_error");
+            assert!(json["message"].as_str().unwrap().contains("'*' matches every document"), "{}", json);
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_search_exclude_ids() {
+    let (app, _temp_dir) = create_test_app_with_config(Config {
+        search_exclude_ids_max: 2,
+        ..Config::default()
+    });
+    let user_id = Uuid::new_v4();
+
+    let docs = [
+        ("top", "Refund refund", "Refund of the refund overpayment"),
+        ("second", "Refund", "Refund claim for 2023"),
+        ("third", "Notice", "A refund may follow"),
+    ];
+    for (id, title, body) in docs {
+        let doc = json!({ "id": id, "title": title, "body": body });
+        let (status, _) = request_json(app.clone(), "PUT", "/v1/documents", Some(user_id), Some(doc)).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    let search = |body: Value| request_json(app.clone(), "POST", "/v1/search", Some(user_id), Some(body));
+    let (_, json) = search(json!({ "query": "refund", "limit": 1 })).await;
+    assert_eq!(json["results"][0]["id"], "top");
+
+    // The excluded top hit doesn't use up the single result slot
+    let (status, json) = search(json!({ "query": "refund", "limit": 1, "exclude_ids": ["top"] })).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(json["total"], 1);
+    assert_eq!(json["results"][0]["id"], "second");
+
+    // Unknown IDs are ignored
+    let (_, json) = search(json!({ "query": "refund", "exclude_ids": ["top", "missing"] })).await;
+    let ids: Vec<&str> = json["results"].as_array().unwrap().iter().map(|r| r["id"].as_str().unwrap()).collect();
+    assert_eq!(ids, ["second", "third"]);
+
+    let (status, _) = search(json!({ "query": "refund", "exclude_ids": ["top", "second", "third"] })).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    let (status, _) = search(json!({ "query": "refund", "exclude_ids": [""] })).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+}
+
+#[tokio::test]
+async fn test_index_history() {
+    let (app, _temp_dir) = create_test_app_with_config(Config {
+        index_history_max: 2,
+        ..Config::default()
+    });
+    let user_id = Uuid::new_v4();
+
+    let (status, json) = request_json(app.clone(), "GET", "/v1/index/history", Some(user_id), None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(json["generation"], 0);
+    assert_eq!(json["commits"], json!([]));
+
+    let mut generations = Vec::new();
+    for id in ["a", "b", "c"] {
+        let doc = json!({ "id": id, "title": "Receipt", "body": "Paid in full" });
+        let (_, json) = request_json(app.clone(), "PUT", "/v1/documents", Some(user_id), Some(doc)).await;
+        generations.push(json["generation"].clone());
+    }
+
+    // Only the latest INDEX_HISTORY_MAX commits are kept, newest first
+    let (_, json) = request_json(app.clone(), "GET", "/v1/index/history", Some(user_id), None).await;
+    assert_eq!(json["generation"], generations[2]);
+    let commits = json["commits"].as_array().unwrap();
+    assert_eq!(commits.len(), 2);
+    assert_eq!(commits[0]["generation"], generations[2]);
+    assert_eq!(commits[1]["generation"], generations[1]);
+    assert_eq!(commits[0]["operations"], 1);
+
+    let (_, json) = request_json(app.clone(), "GET", "/v1/index/history?limit=1", Some(user_id), None).await;
+    assert_eq!(json["commits"].as_array().unwrap().len(), 1);
+
+    for uri in ["/v1/index/history?limit=0", "/v1/index/history?limit=3"] {
+        let (status, _) = request_json(app.clone(), "GET", uri, Some(user_id), None).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY, "{}", uri);
+    }
+}
+
+#[tokio::test]
+async fn test_unknown_request_fields() {
+    let user_id = Uuid::new_v4();
+    let search = json!({ "query": "refund", "lmit": 1 });
+    let document = json!({ "title": "Refund", "body": "Refund of overpaid tax", "bost": 2.0 });
+
+    // Ignored by default
+    let (app, _temp_dir) = create_test_app();
+    let (status, _) = request_json(app.clone(), "PUT", "/v1/documents", Some(user_id), Some(document.clone())).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = request_json(app.clone(), "POST", "/v1/search", Some(user_id), Some(search.clone())).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (app, _temp_dir) = create_test_app_with_config(Config {
+        unknown_fields: UnknownFieldsMode::Reject,
+        ..Config::default()
+    });
+    let (status, json) = request_json(app.clone(), "PUT", "/v1/documents", Some(user_id), Some(document)).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(json["error"], "validation_error");
+    assert!(json["message"].as_str().unwrap().contains("'bost'"));
+    let (status, json) = request_json(app.clone(), "POST", "/v1/search", Some(user_id), Some(search)).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert!(json["message"].as_str().unwrap().contains("'lmit'"));
+
+    // Known fields, and custom metadata fields below the top level, are still accepted
+    let document = json!({
+        "title": "Refund",
+        "body": "Refund of overpaid tax",
+        "boost": 2.0,
+        "metadata": { "tags": ["tax"], "customer": "ACME" }
+    });
+    let (status, _) = request_json(app.clone(), "PUT", "/v1/documents", Some(user_id), Some(document)).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, json) = request_json(app.clone(), "POST", "/v1/search", Some(user_id), Some(json!({ "query": "refund", "limit": 1 }))).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(json["total"], 1);
+}
+
+/// Serve a test app on a local port, returning its base URL
+async fn spawn_server(config: Config) -> (String, TempDir) {
+    let options = ServerOptions::from_config(&config);
+    let (app, temp_dir) = create_test_app_with_config(config);
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { server::serve(listener, app, &options).await });
+    (format!("http://{}", addr), temp_dir)
+}
+
+#[tokio::test]
+async fn test_http2_negotiation() {
+    let h2_client = reqwest::Client::builder().http2_prior_knowledge().build().unwrap();
+    let http1_client = reqwest::Client::new();
+    let user_id = Uuid::new_v4();
+
+    let (base_url, _temp_dir) = spawn_server(Config {
+        http2_enabled: true,
+        http2_keep_alive_interval_secs: 10,
+        ..Config::default()
+    })
+    .await;
+    let response = h2_client
+        .post(format!("{}/v1/search", base_url))
+        .header("X-User-Id", user_id.to_string())
+        .json(&json!({ "query": "refund" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.version(), reqwest::Version::HTTP_2);
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+
+    // HTTP/1.1 clients keep working next to HTTP/2
+    let response = http1_client.get(format!("{}/health", base_url)).send().await.unwrap();
+    assert_eq!(response.version(), reqwest::Version::HTTP_11);
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+
+    // Disabled by default: the HTTP/2 preface isn't understood
+    let (base_url, _temp_dir) = spawn_server(Config::default()).await;
+    assert!(h2_client.get(format!("{}/health", base_url)).send().await.is_err());
+    let response = http1_client.get(format!("{}/health", base_url)).send().await.unwrap();
+    assert_eq!(response.version(), reqwest::Version::HTTP_11);
+}
+
+#[tokio::test]
+async fn test_search_columnar_format() {
+    let (app, _temp_dir) = create_test_app();
+    let user_id = Uuid::new_v4();
+
+    let docs = [
+        ("top", "Refund refund", "Refund of the refund overpayment"),
+        ("second", "Refund", "Refund claim for 2023"),
+        ("third", "Notice", "A refund may follow"),
+    ];
+    for (id, title, body) in docs {
+        let doc = json!({ "id": id, "title": title, "body": body });
+        let (status, _) = request_json(app.clone(), "PUT", "/v1/documents", Some(user_id), Some(doc)).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    let search = |body: Value| request_json(app.clone(), "POST", "/v1/search", Some(user_id), Some(body));
+    let (_, objects) = search(json!({ "query": "refund" })).await;
+    assert!(objects.get("columns").is_none());
+
+    let (status, json) = search(json!({ "query": "refund", "format": "columnar" })).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(json["results"], json!([]));
+    assert_eq!(json["total"], 3);
+
+    // Entry i of every column is the i-th result of the object format
+    let columns = &json["columns"];
+    let results = objects["results"].as_array().unwrap();
+    for key in ["ids", "titles", "scores", "created_ats"] {
+        assert_eq!(columns[key].as_array().unwrap().len(), results.len(), "{}", key);
+    }
+    for (i, result) in results.iter().enumerate() {
+        assert_eq!(columns["ids"][i], result["id"]);
+        assert_eq!(columns["titles"][i], result["title"]);
+        assert_eq!(columns["scores"][i], result["score"]);
+        assert_eq!(columns["created_ats"][i], result["created_at"]);
+    }
+    assert_eq!(columns["ids"], json!(["top", "second", "third"]));
+    let scores: Vec<f64> = columns["scores"].as_array().unwrap().iter().map(|s| s.as_f64().unwrap()).collect();
+    assert!(scores.windows(2).all(|pair| pair[0] >= pair[1]), "{:?}", scores);
+
+    let (_, json) = search(json!({ "query": "refund", "format": "columnar", "offset": 1, "limit": 1 })).await;
+    assert_eq!(json["columns"]["ids"], json!(["second"]));
+
+    let (status, _) = search(json!({ "query": "refund", "format": "columnar", "highlight": true })).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    let (status, _) = search(json!({ "query": "refund", "format": "table" })).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+}